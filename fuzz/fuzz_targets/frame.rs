@@ -0,0 +1,32 @@
+#![no_main]
+
+//! Feed arbitrary bytes to `Frame::check` and `Frame::decode`.
+//!
+//! Neither may panic, and decoding must not allocate more than the input
+//! justifies. Run with a malloc limit so oversized allocations are caught:
+//!
+//! cargo fuzz run frame -- -malloc_limit_mb=64
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use miniredis::frame::Frame;
+
+fuzz_target!(|data: &[u8]| {
+    let mut cursor = Cursor::new(data);
+    let checked = Frame::check(&mut cursor).map(|_| cursor.position());
+
+    cursor.set_position(0);
+    let decoded = Frame::decode(&mut cursor);
+
+    if let (Ok(end), Ok(frame)) = (checked, decoded) {
+        // both passes must agree on where the frame ends
+        assert_eq!(end, cursor.position());
+
+        // and whatever was decoded has to survive a round trip
+        let mut buf = Vec::new();
+        frame.encode(&mut buf);
+        let mut cursor = Cursor::new(&buf[..]);
+        assert_eq!(Frame::decode(&mut cursor).unwrap(), frame);
+    }
+});
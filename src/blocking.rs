@@ -0,0 +1,98 @@
+//! Clients blocked on keys, e.g. by `BLPOP`
+//!
+//! A blocked client parks a callback retrying its command, and waits on a
+//! oneshot channel for the reply. A write making one of its keys ready
+//! marks the key, and before the database lock is released the waiters of
+//! every ready key are served in the order they blocked, see
+//! `Database::serve_blocked`. Serving under the same lock as the write
+//! means no other client can take the element in between, and the oldest
+//! waiter always gets it first.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use tokio::sync::oneshot;
+
+use crate::{database::Database, frame::Frame};
+
+/// Retry a blocked command, `None` if it still has to wait
+pub type Serve = Box<dyn FnMut(&mut Database) -> Option<Frame> + Send>;
+
+pub(crate) struct Waiter {
+    pub(crate) keys: Vec<String>,
+    pub(crate) serve: Serve,
+    pub(crate) reply: oneshot::Sender<Frame>,
+}
+
+#[derive(Default)]
+pub(crate) struct Waiters {
+    next_id: u64,
+    waiters: HashMap<u64, Waiter>,
+    // the ids of the waiters on each key, oldest first
+    by_key: HashMap<String, VecDeque<u64>>,
+    ready: VecDeque<String>,
+    ready_set: HashSet<String>,
+}
+
+impl Waiters {
+    pub(crate) fn add(&mut self, keys: Vec<String>, serve: Serve) -> (u64, oneshot::Receiver<Frame>) {
+        let (tx, rx) = oneshot::channel();
+        let id = self.next_id;
+        self.next_id += 1;
+        for key in keys.iter() {
+            self.by_key.entry(key.clone()).or_default().push_back(id);
+        }
+        self.waiters.insert(id, Waiter { keys, serve, reply: tx });
+        (id, rx)
+    }
+
+    /// Forget waiter `id`, `false` if it is gone already, i.e. it was served
+    pub(crate) fn remove(&mut self, id: u64) -> bool {
+        match self.take(id) {
+            Some(waiter) => {
+                self.forget(id, &waiter.keys);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Take a waiter out to call `serve`, `put_back` if it has to wait on
+    pub(crate) fn take(&mut self, id: u64) -> Option<Waiter> {
+        self.waiters.remove(&id)
+    }
+
+    pub(crate) fn put_back(&mut self, id: u64, waiter: Waiter) {
+        self.waiters.insert(id, waiter);
+    }
+
+    /// Remove a served waiter from the queues of its keys
+    pub(crate) fn forget(&mut self, id: u64, keys: &[String]) {
+        for key in keys {
+            if let Some(queue) = self.by_key.get_mut(key) {
+                queue.retain(|&other| other != id);
+                if queue.is_empty() {
+                    self.by_key.remove(key);
+                }
+            }
+        }
+    }
+
+    /// Mark `key` ready if anyone waits on it
+    pub(crate) fn signal(&mut self, key: &str) {
+        if self.by_key.contains_key(key) && !self.ready_set.contains(key) {
+            self.ready_set.insert(key.to_string());
+            self.ready.push_back(key.to_string());
+        }
+    }
+
+    pub(crate) fn next_ready(&mut self) -> Option<String> {
+        let key = self.ready.pop_front()?;
+        self.ready_set.remove(&key);
+        Some(key)
+    }
+
+    /// The waiters on `key`, oldest first
+    pub(crate) fn queue(&self, key: &str) -> Vec<u64> {
+        self.by_key.get(key).map_or(vec![], |queue| queue.iter().copied().collect())
+    }
+}
@@ -1,3 +1,460 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures::Stream;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{
+    net::{TcpStream, ToSocketAddrs},
+    sync::{mpsc, oneshot},
+};
+
+use crate::{
+    cmd::{
+        Del, End, Get, HGetAll, HSet, LMove, LPop, LPush, LRange, PSubscribe, PUnsubscribe, Publish, RPop, RPush,
+        SPublish, SSubscribe, SUnsubscribe, Set, Subscribe, Unsubscribe,
+    },
+    connection::Connection,
+    convert,
+    frame::Frame,
+    pubsub::Message,
+    slot::key_slot,
+};
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// A single connection to a miniredis server
+///
+/// Every method sends one command and waits for its reply.
 pub struct Client {
-    // single connection or connection pool per client?
-}
\ No newline at end of file
+    connection: Connection,
+    // to reconnect to, see `Subscriber`
+    addr: SocketAddr,
+}
+
+impl Client {
+    pub async fn connect<T: ToSocketAddrs>(addr: T) -> Result<Client, Error> {
+        let stream = TcpStream::connect(addr).await?;
+        Ok(Client {
+            addr: stream.peer_addr()?,
+            connection: Connection::new(stream)?,
+        })
+    }
+
+    pub async fn get(&mut self, key: &str) -> Result<Option<Bytes>, Error> {
+        self.bulk_or_null(Get::new(key).into_frame()).await
+    }
+
+    pub async fn set(&mut self, key: &str, value: Bytes) -> Result<(), Error> {
+        match self.request(Set::new(key, value).into_frame()).await? {
+            Frame::Simple(s) if s == "OK" => Ok(()),
+            frame => Err(unexpected(frame)),
+        }
+    }
+
+    /// Delete `keys`, returns how many of them existed
+    pub async fn del(&mut self, keys: &[&str]) -> Result<u64, Error> {
+        match self.request(Del::new(keys).into_frame()).await? {
+            Frame::Integer(n) => Ok(n.try_into()?),
+            frame => Err(unexpected(frame)),
+        }
+    }
+
+    /// Push `elements` to the head of the list at `key`, returns its new length
+    pub async fn lpush(&mut self, key: &str, elements: Vec<Bytes>) -> Result<u64, Error> {
+        match self.request(LPush::new(key, elements).into_frame()).await? {
+            Frame::Integer(n) => Ok(n.try_into()?),
+            frame => Err(unexpected(frame)),
+        }
+    }
+
+    /// Push `elements` to the tail of the list at `key`, returns its new length
+    pub async fn rpush(&mut self, key: &str, elements: Vec<Bytes>) -> Result<u64, Error> {
+        match self.request(RPush::new(key, elements).into_frame()).await? {
+            Frame::Integer(n) => Ok(n.try_into()?),
+            frame => Err(unexpected(frame)),
+        }
+    }
+
+    pub async fn lpop(&mut self, key: &str) -> Result<Option<Bytes>, Error> {
+        self.bulk_or_null(LPop::new(key, None).into_frame()).await
+    }
+
+    pub async fn rpop(&mut self, key: &str) -> Result<Option<Bytes>, Error> {
+        self.bulk_or_null(RPop::new(key, None).into_frame()).await
+    }
+
+    pub async fn lrange(&mut self, key: &str, start: i64, stop: i64) -> Result<Vec<Bytes>, Error> {
+        match self.request(LRange::new(key, start, stop).into_frame()).await? {
+            Frame::Array(frames) => frames
+                .into_iter()
+                .map(|frame| match frame {
+                    Frame::Bulk(bs) => Ok(bs),
+                    frame => Err(unexpected(frame)),
+                })
+                .collect(),
+            frame => Err(unexpected(frame)),
+        }
+    }
+
+    /// Move an element between two lists, `None` if `source` is empty
+    pub async fn lmove(&mut self, source: &str, destination: &str, from: End, to: End) -> Result<Option<Bytes>, Error> {
+        self.bulk_or_null(LMove::new(source, destination, from, to).into_frame()).await
+    }
+
+    /// `GET` a value and deserialize it, see `convert` for the mapping
+    pub async fn get_as<T: DeserializeOwned>(&mut self, key: &str) -> Result<Option<T>, Error> {
+        match self.request(Get::new(key).into_frame()).await? {
+            Frame::Null => Ok(None),
+            frame => Ok(Some(convert::from_frame(frame)?)),
+        }
+    }
+
+    /// Serialize `value` into a single bulk string and `SET` it
+    pub async fn set_as<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        match convert::to_frame(value)? {
+            Frame::Bulk(bs) => self.set(key, bs).await,
+            _ => Err("value does not serialize to a single string".into()),
+        }
+    }
+
+    /// Store the fields of `value` in the hash at `key`, returns the number
+    /// of fields that were added
+    pub async fn hset_struct<T: Serialize + ?Sized>(
+        &mut self,
+        key: &str,
+        value: &T,
+    ) -> Result<u64, Error> {
+        let fields = match convert::to_frame(value)? {
+            Frame::Array(fields) => fields,
+            _ => return Err("value does not serialize to fields and values".into()),
+        };
+
+        let mut hset = HSet::new(key);
+        let mut fields = fields.into_iter();
+        while let (Some(field), Some(value)) = (fields.next(), fields.next()) {
+            match (field, value) {
+                (Frame::Bulk(field), Frame::Bulk(value)) => hset = hset.field(field, value),
+                // a hash has no nulls, leaving the field out is the closest match
+                (_, Frame::Null) => continue,
+                _ => return Err("nested values cannot be stored in a hash".into()),
+            }
+        }
+
+        match self.request(hset.into_frame()).await? {
+            Frame::Integer(n) => Ok(n.try_into()?),
+            frame => Err(unexpected(frame)),
+        }
+    }
+
+    /// `HGETALL` the hash at `key` and deserialize it, `None` if there is no hash
+    pub async fn hgetall_as<T: DeserializeOwned>(&mut self, key: &str) -> Result<Option<T>, Error> {
+        match self.request(HGetAll::new(key).into_frame()).await? {
+            Frame::Array(fields) if fields.is_empty() => Ok(None),
+            frame => Ok(Some(convert::from_frame(frame)?)),
+        }
+    }
+
+    /// Publish `message` on `channel`, returns how many clients received it
+    pub async fn publish(&mut self, channel: &str, message: Bytes) -> Result<u64, Error> {
+        match self.request(Publish::new(channel, message).into_frame()).await? {
+            Frame::Integer(n) => Ok(n.try_into()?),
+            frame => Err(unexpected(frame)),
+        }
+    }
+
+    /// Publish `message` on the shard channel `channel`
+    pub async fn spublish(&mut self, channel: &str, message: Bytes) -> Result<u64, Error> {
+        match self.request(SPublish::new(channel, message).into_frame()).await? {
+            Frame::Integer(n) => Ok(n.try_into()?),
+            frame => Err(unexpected(frame)),
+        }
+    }
+
+    /// Subscribe to `channels`, the connection then only receives
+    /// messages, see `Subscriber`
+    pub async fn subscribe(self, channels: &[&str]) -> Result<Subscriber, Error> {
+        let mut subscriber = Subscriber::new(self);
+        subscriber.subscribe(channels).await?;
+        Ok(subscriber)
+    }
+
+    /// Subscribe to the channels matching `patterns`
+    pub async fn psubscribe(self, patterns: &[&str]) -> Result<Subscriber, Error> {
+        let mut subscriber = Subscriber::new(self);
+        subscriber.psubscribe(patterns).await?;
+        Ok(subscriber)
+    }
+
+    /// Subscribe to the shard channels `channels`, which must hash to the
+    /// same slot
+    pub async fn ssubscribe(self, channels: &[&str]) -> Result<Subscriber, Error> {
+        let mut subscriber = Subscriber::new(self);
+        subscriber.ssubscribe(channels).await?;
+        Ok(subscriber)
+    }
+
+    async fn bulk_or_null(&mut self, frame: Frame) -> Result<Option<Bytes>, Error> {
+        match self.request(frame).await? {
+            Frame::Bulk(bs) => Ok(Some(bs)),
+            Frame::Null => Ok(None),
+            frame => Err(unexpected(frame)),
+        }
+    }
+
+    async fn request(&mut self, frame: Frame) -> Result<Frame, Error> {
+        self.connection.write_frame(frame).await?;
+        match self.connection.read_frame().await? {
+            Frame::Error(msg) => Err(msg.into()),
+            frame => Ok(frame),
+        }
+    }
+}
+
+/// longest wait between two attempts to reconnect a `Subscriber`
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// A client in subscriber mode, the `Stream` of the messages it receives
+///
+/// The connection is owned by a background task, which reads the messages
+/// and sends the changes of subscriptions. If the connection drops, the
+/// task reconnects, backing off up to `MAX_RECONNECT_DELAY`, and subscribes
+/// again to whatever the server had confirmed. Messages published in the
+/// meantime are lost. Received messages are queued until they are read
+/// from the stream. The task ends with the `Subscriber`.
+pub struct Subscriber {
+    changes: mpsc::UnboundedSender<Change>,
+    messages: mpsc::UnboundedReceiver<Message>,
+}
+
+/// A change of subscriptions, to be confirmed once per channel
+struct Change {
+    frame: Frame,
+    count: usize,
+    done: oneshot::Sender<Result<(), Error>>,
+}
+
+/// The confirmations a change still waits for, `done` is `None` when the
+/// task subscribes again after reconnecting
+struct Pending {
+    count: usize,
+    done: Option<oneshot::Sender<Result<(), Error>>>,
+}
+
+/// What the server confirmed
+#[derive(Default)]
+struct Subscribed {
+    channels: HashSet<Bytes>,
+    patterns: HashSet<Bytes>,
+    shard_channels: HashSet<Bytes>,
+}
+
+impl Subscribed {
+    /// Apply the confirmation `kind name`, e.g. `subscribe news`
+    fn confirm(&mut self, kind: &[u8], name: Bytes) {
+        match kind {
+            b"subscribe" => self.channels.insert(name),
+            b"unsubscribe" => self.channels.remove(&name),
+            b"psubscribe" => self.patterns.insert(name),
+            b"punsubscribe" => self.patterns.remove(&name),
+            b"ssubscribe" => self.shard_channels.insert(name),
+            b"sunsubscribe" => self.shard_channels.remove(&name),
+            _ => false,
+        };
+    }
+
+    /// The requests subscribing to everything again, with the number of
+    /// confirmations of each. Shard channels go by slot, the server refuses
+    /// channels of different slots together.
+    fn requests(&self) -> Vec<(Frame, usize)> {
+        let names = |set: &HashSet<Bytes>| set.iter().map(|name| String::from_utf8_lossy(name).into_owned()).collect::<Vec<_>>();
+        let mut requests = vec![];
+        let channels = names(&self.channels);
+        if !channels.is_empty() {
+            requests.push((Subscribe::new(&as_strs(&channels)).into_frame(), channels.len()));
+        }
+        let patterns = names(&self.patterns);
+        if !patterns.is_empty() {
+            requests.push((PSubscribe::new(&as_strs(&patterns)).into_frame(), patterns.len()));
+        }
+        let mut by_slot: HashMap<u16, Vec<String>> = HashMap::new();
+        for channel in names(&self.shard_channels) {
+            by_slot.entry(key_slot(channel.as_bytes())).or_default().push(channel);
+        }
+        for channels in by_slot.values() {
+            requests.push((SSubscribe::new(&as_strs(channels)).into_frame(), channels.len()));
+        }
+        requests
+    }
+}
+
+impl Subscriber {
+    fn new(client: Client) -> Self {
+        let (changes, changes_rx) = mpsc::unbounded_channel();
+        let (messages_tx, messages) = mpsc::unbounded_channel();
+        tokio::spawn(run_subscriber(client, changes_rx, messages_tx));
+        Subscriber { changes, messages }
+    }
+
+    /// Subscribe to more channels
+    pub async fn subscribe(&mut self, channels: &[&str]) -> Result<(), Error> {
+        self.change(Subscribe::new(channels).into_frame(), channels.len()).await
+    }
+
+    pub async fn unsubscribe(&mut self, channels: &[&str]) -> Result<(), Error> {
+        self.change(Unsubscribe::new(channels).into_frame(), channels.len()).await
+    }
+
+    pub async fn psubscribe(&mut self, patterns: &[&str]) -> Result<(), Error> {
+        self.change(PSubscribe::new(patterns).into_frame(), patterns.len()).await
+    }
+
+    pub async fn punsubscribe(&mut self, patterns: &[&str]) -> Result<(), Error> {
+        self.change(PUnsubscribe::new(patterns).into_frame(), patterns.len()).await
+    }
+
+    pub async fn ssubscribe(&mut self, channels: &[&str]) -> Result<(), Error> {
+        self.change(SSubscribe::new(channels).into_frame(), channels.len()).await
+    }
+
+    pub async fn sunsubscribe(&mut self, channels: &[&str]) -> Result<(), Error> {
+        self.change(SUnsubscribe::new(channels).into_frame(), channels.len()).await
+    }
+
+    /// Send a change to the task and wait until the server confirmed it.
+    /// An empty list changes nothing, it would mean every channel to an
+    /// unsubscription.
+    async fn change(&mut self, frame: Frame, count: usize) -> Result<(), Error> {
+        if count == 0 {
+            return Ok(());
+        }
+        let (done, confirmed) = oneshot::channel();
+        self.changes.send(Change { frame, count, done }).map_err(|_| "subscriber task ended")?;
+        confirmed.await.map_err(|_| "subscriber task ended")?
+    }
+}
+
+impl Stream for Subscriber {
+    type Item = Message;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        self.messages.poll_recv(cx)
+    }
+}
+
+/// The task behind a `Subscriber`, until the subscriber is dropped
+async fn run_subscriber(
+    client: Client,
+    mut changes: mpsc::UnboundedReceiver<Change>,
+    messages: mpsc::UnboundedSender<Message>,
+) {
+    let Client { mut connection, addr } = client;
+    let mut subscribed = Subscribed::default();
+    let mut pending: VecDeque<Pending> = VecDeque::new();
+    loop {
+        let lost = tokio::select! {
+            change = changes.recv() => {
+                let Some(change) = change else { return };
+                let sent = match connection.write_frame(change.frame).await {
+                    Ok(_) => connection.flush().await,
+                    Err(e) => Err(e),
+                };
+                pending.push_back(Pending { count: change.count, done: Some(change.done) });
+                sent.is_err()
+            }
+            frame = connection.read_frame() => match frame {
+                Ok(frame) => {
+                    match Message::from_frame(frame.clone()) {
+                        Some(message) => {
+                            if messages.send(message).is_err() {
+                                return;
+                            }
+                        }
+                        None => confirm(frame, &mut subscribed, &mut pending),
+                    }
+                    false
+                }
+                Err(_) => true,
+            },
+            _ = messages.closed() => return,
+        };
+        if lost {
+            for pending in pending.drain(..) {
+                if let Some(done) = pending.done {
+                    let _ = done.send(Err("connection lost".into()));
+                }
+            }
+            connection = match reconnect(addr, &subscribed, &mut pending, &messages).await {
+                Some(connection) => connection,
+                None => return,
+            };
+        }
+    }
+}
+
+/// Count a reply to a change against the oldest pending one
+fn confirm(frame: Frame, subscribed: &mut Subscribed, pending: &mut VecDeque<Pending>) {
+    let Some(front) = pending.front_mut() else { return };
+    match frame {
+        Frame::Error(msg) => {
+            // the whole change is refused
+            if let Some(done) = pending.pop_front().and_then(|pending| pending.done) {
+                let _ = done.send(Err(msg.into()));
+            }
+        }
+        Frame::Array(frames) | Frame::Push(frames) => {
+            if let [Frame::Bulk(kind), Frame::Bulk(name), ..] = &frames[..] {
+                subscribed.confirm(kind, name.clone());
+            }
+            front.count -= 1;
+            if front.count == 0 {
+                if let Some(done) = pending.pop_front().and_then(|pending| pending.done) {
+                    let _ = done.send(Ok(()));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Connect to `addr` again and send the requests restoring `subscribed`,
+/// `None` if the subscriber is dropped in the meantime
+async fn reconnect(
+    addr: SocketAddr,
+    subscribed: &Subscribed,
+    pending: &mut VecDeque<Pending>,
+    messages: &mpsc::UnboundedSender<Message>,
+) -> Option<Connection> {
+    let mut delay = Duration::from_millis(50);
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = messages.closed() => return None,
+        }
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        let Ok(stream) = TcpStream::connect(addr).await else { continue };
+        let Ok(mut connection) = Connection::new(stream) else { continue };
+        let requests = subscribed.requests();
+        let mut sent = true;
+        for (frame, _) in requests.iter() {
+            sent &= connection.write_frame(frame.clone()).await.is_ok();
+        }
+        if sent && connection.flush().await.is_ok() {
+            pending.extend(requests.into_iter().map(|(_, count)| Pending { count, done: None }));
+            return Some(connection);
+        }
+    }
+}
+
+fn as_strs(names: &[String]) -> Vec<&str> {
+    names.iter().map(String::as_str).collect()
+}
+
+fn unexpected(frame: Frame) -> Error {
+    format!("unexpected reply {:?}", frame).into()
+}
@@ -0,0 +1,749 @@
+//! Bitmap commands
+//!
+//! A bitmap is a plain string, bit `0` being the most significant bit of
+//! the first byte. Reading past the end reads zeros, and writing past it
+//! grows the string with zero bytes, up to `MAX_STRING_LEN`.
+
+use bytes::{Bytes, BytesMut};
+
+use super::{normalize_range, MAX_STRING_LEN};
+use crate::{
+    database::Database,
+    frame::{Error, Frame, Parse},
+    notify::Events,
+};
+
+const MAX_BITS: u64 = MAX_STRING_LEN as u64 * 8;
+
+fn key_frame(name: &'static str, key: String) -> Frame {
+    let mut frame = Frame::new_array_frame();
+    frame.push_bulk(Bytes::from(name));
+    frame.push_bulk(Bytes::from(key));
+    frame
+}
+
+/// A bit offset, which must stay within the largest string
+fn parse_offset(bs: &[u8]) -> Result<u64, Error> {
+    match std::str::from_utf8(bs).ok().and_then(|s| s.parse::<u64>().ok()) {
+        Some(offset) if offset < MAX_BITS => Ok(offset),
+        _ => Err("bit offset is not an integer or out of range".into()),
+    }
+}
+
+fn get_bit(bs: &[u8], offset: u64) -> u8 {
+    match bs.get((offset / 8) as usize) {
+        Some(byte) => (byte >> (7 - offset % 8)) & 1,
+        None => 0,
+    }
+}
+
+/// Set a bit within `bs`, which must be long enough
+fn set_bit(bs: &mut [u8], offset: u64, bit: u8) {
+    let mask = 1 << (7 - offset % 8);
+    let byte = &mut bs[(offset / 8) as usize];
+    if bit == 1 {
+        *byte |= mask;
+    } else {
+        *byte &= !mask;
+    }
+}
+
+/// `len` bytes of the string at `key` or more, padded with zero bytes
+fn grown(db: &Database, key: &str, len: usize) -> Result<BytesMut, Frame> {
+    let current = db.get(key).map_err(Frame::from)?.unwrap_or_default();
+    let mut value = BytesMut::from(&current[..]);
+    if value.len() < len {
+        value.resize(len, 0);
+    }
+    Ok(value)
+}
+
+/// What the `start` and `end` of `BITCOUNT` and `BITPOS` count
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BitUnit {
+    Byte,
+    Bit,
+}
+
+impl BitUnit {
+    fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        match it.next_string()?.to_lowercase().as_str() {
+            "byte" => Ok(BitUnit::Byte),
+            "bit" => Ok(BitUnit::Bit),
+            _ => Err("syntax error".into()),
+        }
+    }
+
+    fn push_arg(self, frame: &mut Frame) {
+        if self == BitUnit::Bit {
+            frame.push_bulk(Bytes::from("bit"));
+        }
+    }
+
+    /// The first and last bit of an inclusive range in this unit, where
+    /// negative indexes count from the end
+    fn bits(self, start: i64, end: i64, len: usize) -> Option<(u64, u64)> {
+        match self {
+            BitUnit::Byte => {
+                let (start, end) = normalize_range(start, end, len)?;
+                Some((start as u64 * 8, end as u64 * 8 + 7))
+            }
+            BitUnit::Bit => {
+                let (start, end) = normalize_range(start, end, len * 8)?;
+                Some((start as u64, end as u64))
+            }
+        }
+    }
+}
+
+/// `SETBIT key offset value`, replies with the bit that was there
+#[derive(Debug)]
+pub struct SetBit {
+    key: String,
+    offset: u64,
+    bit: u8,
+}
+
+impl SetBit {
+    pub fn new(key: &str, offset: u64, bit: bool) -> Self {
+        SetBit { key: key.to_string(), offset, bit: bit as u8 }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = key_frame("setbit", self.key);
+        frame.push_bulk(Bytes::from(self.offset.to_string()));
+        frame.push_bulk(Bytes::from(self.bit.to_string()));
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let offset = parse_offset(&it.next_bytes()?)?;
+        let bit = match &it.next_bytes()?[..] {
+            b"0" => 0,
+            b"1" => 1,
+            _ => return Err("bit is not an integer or out of range".into()),
+        };
+        Ok(SetBit { key, offset, bit })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let mut value = match grown(db, &self.key, (self.offset / 8) as usize + 1) {
+            Ok(value) => value,
+            Err(e) => return e,
+        };
+        let old = get_bit(&value, self.offset);
+        set_bit(&mut value, self.offset, self.bit);
+        db.update(self.key.clone(), value.freeze());
+        db.notify(Events::STRING, "setbit", &self.key);
+        Frame::Integer(old as i64)
+    }
+}
+
+/// `GETBIT key offset`
+#[derive(Debug)]
+pub struct GetBit {
+    key: String,
+    offset: u64,
+}
+
+impl GetBit {
+    pub fn new(key: &str, offset: u64) -> Self {
+        GetBit { key: key.to_string(), offset }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = key_frame("getbit", self.key);
+        frame.push_bulk(Bytes::from(self.offset.to_string()));
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(GetBit { key, offset: parse_offset(&it.next_bytes()?)? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.get(&self.key) {
+            Ok(value) => Frame::Integer(get_bit(&value.unwrap_or_default(), self.offset) as i64),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// `BITCOUNT key [start end [BYTE|BIT]]`, the number of set bits
+#[derive(Debug)]
+pub struct BitCount {
+    key: String,
+    range: Option<(i64, i64, BitUnit)>,
+}
+
+impl BitCount {
+    pub fn new(key: &str) -> Self {
+        BitCount { key: key.to_string(), range: None }
+    }
+
+    /// Count the bytes from `start` to `end` only
+    pub fn range(mut self, start: i64, end: i64) -> Self {
+        self.range = Some((start, end, BitUnit::Byte));
+        self
+    }
+
+    /// Count the bits from `start` to `end` only
+    pub fn bit_range(mut self, start: i64, end: i64) -> Self {
+        self.range = Some((start, end, BitUnit::Bit));
+        self
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = key_frame("bitcount", self.key);
+        if let Some((start, end, unit)) = self.range {
+            frame.push_bulk(Bytes::from(start.to_string()));
+            frame.push_bulk(Bytes::from(end.to_string()));
+            unit.push_arg(&mut frame);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let mut bitcount = BitCount::new(&it.next_string()?);
+        if it.has_next() {
+            let start = it.next_int()?;
+            if !it.has_next() {
+                return Err("syntax error".into());
+            }
+            let end = it.next_int()?;
+            let unit = if it.has_next() { BitUnit::from_frame(it)? } else { BitUnit::Byte };
+            bitcount.range = Some((start, end, unit));
+        }
+        Ok(bitcount)
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let value = match db.get(&self.key) {
+            Ok(value) => value.unwrap_or_default(),
+            Err(e) => return e.into(),
+        };
+        let (start, end) = match self.range {
+            Some((start, end, unit)) => match unit.bits(start, end, value.len()) {
+                Some(bits) => bits,
+                None => return Frame::Integer(0),
+            },
+            None if value.is_empty() => return Frame::Integer(0),
+            None => (0, value.len() as u64 * 8 - 1),
+        };
+
+        let mut count = 0;
+        let mut offset = start;
+        while offset <= end {
+            // whole bytes at once
+            if offset.is_multiple_of(8) && offset + 7 <= end {
+                count += value[(offset / 8) as usize].count_ones() as i64;
+                offset += 8;
+            } else {
+                count += get_bit(&value, offset) as i64;
+                offset += 1;
+            }
+        }
+        Frame::Integer(count)
+    }
+}
+
+/// `BITPOS key bit [start [end [BYTE|BIT]]]`, the offset of the first bit
+/// set to `bit`, or `-1`
+///
+/// Without an `end`, looking for a clear bit in a string of set bits finds
+/// the bit right after the string, as if it was padded with zeros.
+#[derive(Debug)]
+pub struct BitPos {
+    key: String,
+    bit: u8,
+    start: Option<i64>,
+    end: Option<i64>,
+    unit: BitUnit,
+}
+
+impl BitPos {
+    pub fn new(key: &str, bit: bool) -> Self {
+        BitPos { key: key.to_string(), bit: bit as u8, start: None, end: None, unit: BitUnit::Byte }
+    }
+
+    pub fn start(mut self, start: i64) -> Self {
+        self.start = Some(start);
+        self
+    }
+
+    /// Needs a `start`
+    pub fn end(mut self, end: i64) -> Self {
+        self.end = Some(end);
+        self
+    }
+
+    /// Count `start` and `end` in bits rather than bytes
+    pub fn bits(mut self) -> Self {
+        self.unit = BitUnit::Bit;
+        self
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = key_frame("bitpos", self.key);
+        frame.push_bulk(Bytes::from(self.bit.to_string()));
+        if let Some(start) = self.start {
+            frame.push_bulk(Bytes::from(start.to_string()));
+            if let Some(end) = self.end {
+                frame.push_bulk(Bytes::from(end.to_string()));
+                self.unit.push_arg(&mut frame);
+            }
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let bit = match it.next_int()? {
+            0 => false,
+            1 => true,
+            _ => return Err("The bit argument must be 1 or 0.".into()),
+        };
+        let mut bitpos = BitPos::new(&key, bit);
+        if it.has_next() {
+            bitpos.start = Some(it.next_int()?);
+        }
+        if it.has_next() {
+            bitpos.end = Some(it.next_int()?);
+        }
+        if it.has_next() {
+            bitpos.unit = BitUnit::from_frame(it)?;
+        }
+        Ok(bitpos)
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let value = match db.get(&self.key) {
+            Ok(Some(value)) => value,
+            // a missing key is an empty string, so all zeros
+            Ok(None) => return Frame::Integer(if self.bit == 1 { -1 } else { 0 }),
+            Err(e) => return e.into(),
+        };
+        let range = match self.unit {
+            BitUnit::Byte => value.len(),
+            BitUnit::Bit => value.len() * 8,
+        };
+        let start = self.start.unwrap_or(0);
+        let end = self.end.unwrap_or(range as i64 - 1);
+        let (start, end) = match self.unit.bits(start, end, value.len()) {
+            Some(bits) => bits,
+            None => return Frame::Integer(-1),
+        };
+
+        // bytes with no bit of interest are skipped whole
+        let skip = if self.bit == 1 { 0 } else { 0xff };
+        let mut offset = start;
+        while offset <= end {
+            if offset.is_multiple_of(8) && offset + 7 <= end && value[(offset / 8) as usize] == skip {
+                offset += 8;
+                continue;
+            }
+            if get_bit(&value, offset) == self.bit {
+                return Frame::Integer(offset as i64);
+            }
+            offset += 1;
+        }
+        if self.bit == 0 && self.end.is_none() {
+            return Frame::Integer(end as i64 + 1);
+        }
+        Frame::Integer(-1)
+    }
+}
+
+/// The operation of `BITOP`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bitwise {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+/// `BITOP AND|OR|XOR|NOT destkey key [key ...]`
+///
+/// Stores the result at `destkey`, as long as the longest source, which
+/// shorter ones and missing keys are padded with zeros up to. Replies with
+/// its length, and an empty result deletes `destkey`.
+#[derive(Debug)]
+pub struct BitOp {
+    op: Bitwise,
+    dest: String,
+    keys: Vec<String>,
+}
+
+impl BitOp {
+    pub fn new(op: Bitwise, dest: &str, keys: &[&str]) -> Self {
+        BitOp { op, dest: dest.to_string(), keys: keys.iter().map(|key| key.to_string()).collect() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::new_array_frame();
+        frame.push_bulk(Bytes::from("bitop"));
+        frame.push_bulk(Bytes::from(match self.op {
+            Bitwise::And => "and",
+            Bitwise::Or => "or",
+            Bitwise::Xor => "xor",
+            Bitwise::Not => "not",
+        }));
+        frame.push_bulk(Bytes::from(self.dest));
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let op = match it.next_string()?.to_lowercase().as_str() {
+            "and" => Bitwise::And,
+            "or" => Bitwise::Or,
+            "xor" => Bitwise::Xor,
+            "not" => Bitwise::Not,
+            _ => return Err("syntax error".into()),
+        };
+        let dest = it.next_string()?;
+        let mut keys = vec![it.next_string()?];
+        while it.has_next() {
+            keys.push(it.next_string()?);
+        }
+        if op == Bitwise::Not && keys.len() != 1 {
+            return Err("BITOP NOT must be called with a single source key.".into());
+        }
+        Ok(BitOp { op, dest, keys })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let mut sources = Vec::with_capacity(self.keys.len());
+        for key in &self.keys {
+            match db.get(key) {
+                Ok(value) => sources.push(value.unwrap_or_default()),
+                Err(e) => return e.into(),
+            }
+        }
+        let len = sources.iter().map(|source| source.len()).max().unwrap_or(0);
+        if len == 0 {
+            if db.remove(&self.dest).is_some() {
+                db.notify(Events::GENERIC, "del", &self.dest);
+            }
+            return Frame::Integer(0);
+        }
+
+        let byte = |source: &Bytes, i: usize| source.get(i).copied().unwrap_or(0);
+        let mut result = BytesMut::with_capacity(len);
+        for i in 0..len {
+            let mut bytes = sources.iter().map(|source| byte(source, i));
+            let first = bytes.next().unwrap();
+            result.extend_from_slice(&[match self.op {
+                Bitwise::And => bytes.fold(first, |acc, b| acc & b),
+                Bitwise::Or => bytes.fold(first, |acc, b| acc | b),
+                Bitwise::Xor => bytes.fold(first, |acc, b| acc ^ b),
+                Bitwise::Not => !first,
+            }]);
+        }
+        db.set(self.dest.clone(), result.freeze());
+        db.notify(Events::STRING, "set", &self.dest);
+        Frame::Integer(len as i64)
+    }
+}
+
+/// The integer type of a `BITFIELD` field, `i1` to `i64` or `u1` to `u63`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitType {
+    signed: bool,
+    bits: u32,
+}
+
+impl BitType {
+    /// `None` unless `1 <= bits <= 64`
+    pub fn signed(bits: u32) -> Option<Self> {
+        (1..=64).contains(&bits).then_some(BitType { signed: true, bits })
+    }
+
+    /// `None` unless `1 <= bits <= 63`, a `u64` would not fit in a reply
+    pub fn unsigned(bits: u32) -> Option<Self> {
+        (1..=63).contains(&bits).then_some(BitType { signed: false, bits })
+    }
+
+    fn parse(bs: &[u8]) -> Result<Self, Error> {
+        let bits = std::str::from_utf8(&bs[1.min(bs.len())..]).ok().and_then(|s| s.parse().ok());
+        let ty = match (bs.first(), bits) {
+            (Some(b'i' | b'I'), Some(bits)) => BitType::signed(bits),
+            (Some(b'u' | b'U'), Some(bits)) => BitType::unsigned(bits),
+            _ => None,
+        };
+        ty.ok_or_else(|| {
+            "Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.".into()
+        })
+    }
+
+    fn min(self) -> i128 {
+        if self.signed {
+            -(1 << (self.bits - 1))
+        } else {
+            0
+        }
+    }
+
+    fn max(self) -> i128 {
+        if self.signed {
+            (1 << (self.bits - 1)) - 1
+        } else {
+            (1 << self.bits) - 1
+        }
+    }
+
+    /// `value` as this type, `None` if it overflows with `Overflow::Fail`
+    fn fit(self, value: i128, overflow: Overflow) -> Option<i64> {
+        let fitted = if (self.min()..=self.max()).contains(&value) {
+            value
+        } else {
+            match overflow {
+                Overflow::Wrap => {
+                    let modulus = 1i128 << self.bits;
+                    let wrapped = value.rem_euclid(modulus);
+                    if wrapped > self.max() {
+                        wrapped - modulus
+                    } else {
+                        wrapped
+                    }
+                }
+                Overflow::Sat => value.clamp(self.min(), self.max()),
+                Overflow::Fail => return None,
+            }
+        };
+        Some(fitted as i64)
+    }
+
+    fn read(self, bs: &[u8], offset: u64) -> i64 {
+        let mut value: u64 = 0;
+        for i in 0..self.bits as u64 {
+            value = (value << 1) | get_bit(bs, offset + i) as u64;
+        }
+        if self.signed && self.bits < 64 && value >> (self.bits - 1) == 1 {
+            // sign extension
+            value |= u64::MAX << self.bits;
+        }
+        value as i64
+    }
+
+    fn write(self, bs: &mut [u8], offset: u64, value: i64) {
+        for i in 0..self.bits as u64 {
+            let bit = (value as u64 >> (self.bits as u64 - 1 - i)) & 1;
+            set_bit(bs, offset + i, bit as u8);
+        }
+    }
+}
+
+impl std::fmt::Display for BitType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", if self.signed { 'i' } else { 'u' }, self.bits)
+    }
+}
+
+/// What `SET` and `INCRBY` of `BITFIELD` do with a value out of the range
+/// of the field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Keep the lowest bits, the default
+    Wrap,
+    /// Saturate to the smallest or largest value
+    Sat,
+    /// Do nothing and reply nil
+    Fail,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FieldOp {
+    Get(BitType, u64),
+    Set(BitType, u64, i64),
+    IncrBy(BitType, u64, i64),
+    Overflow(Overflow),
+}
+
+/// `BITFIELD key [GET type offset] [SET type offset value]
+/// [INCRBY type offset increment] [OVERFLOW WRAP|SAT|FAIL] ...`
+///
+/// Replies with a value per `GET`, `SET` and `INCRBY`: the value, the
+/// previous value and the new value. `OVERFLOW` applies to the operations
+/// after it. An offset prefixed with `#` counts fields of the type rather
+/// than bits. `BITFIELD_RO` only takes `GET`s.
+#[derive(Debug)]
+pub struct BitField {
+    key: String,
+    ops: Vec<FieldOp>,
+    read_only: bool,
+}
+
+impl BitField {
+    pub fn new(key: &str) -> Self {
+        BitField { key: key.to_string(), ops: vec![], read_only: false }
+    }
+
+    /// `BITFIELD_RO`, which only `get`s
+    pub fn read_only(key: &str) -> Self {
+        BitField { key: key.to_string(), ops: vec![], read_only: true }
+    }
+
+    pub fn get(mut self, ty: BitType, offset: u64) -> Self {
+        self.ops.push(FieldOp::Get(ty, offset));
+        self
+    }
+
+    pub fn set(mut self, ty: BitType, offset: u64, value: i64) -> Self {
+        self.ops.push(FieldOp::Set(ty, offset, value));
+        self
+    }
+
+    pub fn incr_by(mut self, ty: BitType, offset: u64, increment: i64) -> Self {
+        self.ops.push(FieldOp::IncrBy(ty, offset, increment));
+        self
+    }
+
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.ops.push(FieldOp::Overflow(overflow));
+        self
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = key_frame(if self.read_only { "bitfield_ro" } else { "bitfield" }, self.key);
+        for op in self.ops {
+            let args = match op {
+                FieldOp::Get(ty, offset) => vec!["get".to_string(), ty.to_string(), offset.to_string()],
+                FieldOp::Set(ty, offset, value) => {
+                    vec!["set".to_string(), ty.to_string(), offset.to_string(), value.to_string()]
+                }
+                FieldOp::IncrBy(ty, offset, increment) => {
+                    vec!["incrby".to_string(), ty.to_string(), offset.to_string(), increment.to_string()]
+                }
+                FieldOp::Overflow(overflow) => {
+                    let overflow = match overflow {
+                        Overflow::Wrap => "wrap",
+                        Overflow::Sat => "sat",
+                        Overflow::Fail => "fail",
+                    };
+                    vec!["overflow".to_string(), overflow.to_string()]
+                }
+            };
+            for arg in args {
+                frame.push_bulk(Bytes::from(arg));
+            }
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse, read_only: bool) -> Result<Self, Error> {
+        let mut bitfield = BitField::new(&it.next_string()?);
+        bitfield.read_only = read_only;
+        while it.has_next() {
+            let op = match it.next_string()?.to_lowercase().as_str() {
+                "get" => {
+                    let (ty, offset) = field_from_frame(it)?;
+                    FieldOp::Get(ty, offset)
+                }
+                "set" if !read_only => {
+                    let (ty, offset) = field_from_frame(it)?;
+                    FieldOp::Set(ty, offset, it.next_int()?)
+                }
+                "incrby" if !read_only => {
+                    let (ty, offset) = field_from_frame(it)?;
+                    FieldOp::IncrBy(ty, offset, it.next_int()?)
+                }
+                "overflow" if !read_only => FieldOp::Overflow(match it.next_string()?.to_lowercase().as_str() {
+                    "wrap" => Overflow::Wrap,
+                    "sat" => Overflow::Sat,
+                    "fail" => Overflow::Fail,
+                    _ => return Err("Invalid OVERFLOW type specified".into()),
+                }),
+                _ if read_only => return Err("BITFIELD_RO only supports the GET subcommand".into()),
+                _ => return Err("syntax error".into()),
+            };
+            bitfield.ops.push(op);
+        }
+        Ok(bitfield)
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        // the string is grown to fit every field written, even one that
+        // overflows with FAIL
+        let written = self.ops.iter().filter_map(|op| match op {
+            FieldOp::Set(ty, offset, _) | FieldOp::IncrBy(ty, offset, _) => Some(offset + ty.bits as u64),
+            _ => None,
+        });
+        let len = written.max().map(|end| end.div_ceil(8) as usize);
+        let mut value = match grown(db, &self.key, len.unwrap_or(0)) {
+            Ok(value) => value,
+            Err(e) => return e,
+        };
+
+        let mut overflow = Overflow::Wrap;
+        let mut replies = vec![];
+        for op in &self.ops {
+            let reply = match *op {
+                FieldOp::Get(ty, offset) => Some(ty.read(&value, offset)),
+                FieldOp::Set(ty, offset, new) => ty.fit(new as i128, overflow).map(|new| {
+                    let old = ty.read(&value, offset);
+                    ty.write(&mut value, offset, new);
+                    old
+                }),
+                FieldOp::IncrBy(ty, offset, increment) => {
+                    let old = ty.read(&value, offset);
+                    ty.fit(old as i128 + increment as i128, overflow).inspect(|new| ty.write(&mut value, offset, *new))
+                }
+                FieldOp::Overflow(mode) => {
+                    overflow = mode;
+                    continue;
+                }
+            };
+            replies.push(reply.map_or(Frame::Null, Frame::Integer));
+        }
+        if len.is_some() {
+            db.update(self.key.clone(), value.freeze());
+            db.notify(Events::STRING, "setbit", &self.key);
+        }
+        Frame::Array(replies)
+    }
+}
+
+/// A `type offset` pair, the offset in bits or in fields of the type when
+/// prefixed with `#`
+fn field_from_frame(it: &mut dyn Parse) -> Result<(BitType, u64), Error> {
+    let ty = BitType::parse(&it.next_bytes()?)?;
+    let offset = it.next_bytes()?;
+    let offset = match &offset[..] {
+        [b'#', index @ ..] => parse_offset(index)?.saturating_mul(ty.bits as u64),
+        offset => parse_offset(offset)?,
+    };
+    if offset + ty.bits as u64 > MAX_BITS {
+        return Err("bit offset is not an integer or out of range".into());
+    }
+    Ok((ty, offset))
+}
+
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[test]
+fn test_bit_types() {
+    let u8 = BitType::unsigned(8).unwrap();
+    assert_eq!(u8.fit(256, Overflow::Wrap), Some(0));
+    assert_eq!(u8.fit(-1, Overflow::Sat), Some(0));
+    assert_eq!(u8.fit(300, Overflow::Fail), None);
+    let i4 = BitType::signed(4).unwrap();
+    assert_eq!(i4.fit(8, Overflow::Wrap), Some(-8));
+    assert_eq!(i4.fit(-9, Overflow::Sat), Some(-8));
+    let i64 = BitType::signed(64).unwrap();
+    assert_eq!(i64.fit(i64::MAX as i128 + 1, Overflow::Wrap), Some(i64::MIN));
+    assert!(BitType::unsigned(64).is_none());
+
+    // fields need not be aligned on bytes
+    let mut bs = [0u8; 3];
+    i4.write(&mut bs, 6, -3);
+    assert_eq!(bs, [0b0000_0011, 0b0100_0000, 0]);
+    assert_eq!(i4.read(&bs, 6), -3);
+    assert_eq!(BitType::unsigned(4).unwrap().read(&bs, 6), 13);
+}
@@ -0,0 +1,286 @@
+//! Blocking list commands
+//!
+//! `execute` never waits: it pops if it can and replies nil otherwise. That
+//! nil is a null array, except for `BLMOVE` which replies a null bulk
+//! string. It is also the reply when the timeout is over, and inside a
+//! transaction. The handler parks a client on a nil reply, see
+//! `Request::blocking_keys` and `crate::blocking`.
+
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use super::list::{lmove, End};
+use crate::{
+    database::Database,
+    frame::{Error, Frame, Parse},
+    notify::Events,
+};
+
+fn timeout_from_frame(it: &mut dyn Parse) -> Result<Option<Duration>, Error> {
+    parse_timeout(&it.next_string()?)
+}
+
+/// A timeout in seconds, as a float, `0` to wait forever
+fn parse_timeout(s: &str) -> Result<Option<Duration>, Error> {
+    let secs = match s.parse::<f64>() {
+        Ok(secs) if secs.is_finite() => secs,
+        _ => return Err("timeout is not a float or out of range".into()),
+    };
+    if secs < 0.0 {
+        return Err("timeout is negative".into());
+    }
+    if secs == 0.0 {
+        return Ok(None);
+    }
+    match Duration::try_from_secs_f64(secs) {
+        Ok(timeout) => Ok(Some(timeout)),
+        Err(_) => Err("timeout is out of range".into()),
+    }
+}
+
+fn timeout_bulk(timeout: Option<Duration>) -> Bytes {
+    Bytes::from(timeout.map_or(0.0, |timeout| timeout.as_secs_f64()).to_string())
+}
+
+/// Pop from the first of `keys` holding a list, `[key, element]`
+fn pop_first(db: &mut Database, keys: &[String], end: End, count: Option<usize>) -> Frame {
+    for key in keys {
+        let list = match db.get_list_mut(key) {
+            Ok(Some(list)) => list,
+            Ok(None) => continue,
+            Err(e) => return e.into(),
+        };
+        let popped = match count {
+            None => Frame::Bulk(end.pop(list).unwrap()),
+            Some(count) => {
+                let n = count.min(list.len());
+                Frame::Array((0..n).filter_map(|_| end.pop(list)).map(Frame::Bulk).collect())
+            }
+        };
+        db.notify(Events::LIST, end.pop_event(), key);
+        db.remove_if_empty(key);
+        return Frame::Array(vec![Frame::Bulk(Bytes::from(key.clone())), popped]);
+    }
+    Frame::NullArray
+}
+
+/// `BLPOP` and `BRPOP` take the same arguments
+fn keys_and_timeout(it: &mut dyn Parse) -> Result<(Vec<String>, Option<Duration>), Error> {
+    let mut keys = vec![it.next_string()?];
+    // the last argument is the timeout, and there has to be one
+    let mut last = it.next_string()?;
+    while it.has_next() {
+        keys.push(last);
+        last = it.next_string()?;
+    }
+    Ok((keys, parse_timeout(&last)?))
+}
+
+fn keys_frame(name: &'static str, keys: Vec<String>, timeout: Option<Duration>) -> Frame {
+    let mut frame = Frame::new_array_frame();
+    frame.push_bulk(Bytes::from(name));
+    for key in keys {
+        frame.push_bulk(Bytes::from(key));
+    }
+    frame.push_bulk(timeout_bulk(timeout));
+    frame
+}
+
+/// `BLPOP key [key ...] timeout`, replies with the key and the element
+#[derive(Debug, Clone)]
+pub struct BLPop {
+    keys: Vec<String>,
+    timeout: Option<Duration>,
+}
+
+impl BLPop {
+    /// A `None` timeout waits forever
+    pub fn new(keys: &[&str], timeout: Option<Duration>) -> Self {
+        BLPop { keys: keys.iter().map(|key| key.to_string()).collect(), timeout }
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn into_frame(self) -> Frame {
+        keys_frame("blpop", self.keys, self.timeout)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let (keys, timeout) = keys_and_timeout(it)?;
+        Ok(BLPop { keys, timeout })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        pop_first(db, &self.keys, End::Left, None)
+    }
+}
+
+/// `BRPOP key [key ...] timeout`
+#[derive(Debug, Clone)]
+pub struct BRPop {
+    keys: Vec<String>,
+    timeout: Option<Duration>,
+}
+
+impl BRPop {
+    /// A `None` timeout waits forever
+    pub fn new(keys: &[&str], timeout: Option<Duration>) -> Self {
+        BRPop { keys: keys.iter().map(|key| key.to_string()).collect(), timeout }
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn into_frame(self) -> Frame {
+        keys_frame("brpop", self.keys, self.timeout)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let (keys, timeout) = keys_and_timeout(it)?;
+        Ok(BRPop { keys, timeout })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        pop_first(db, &self.keys, End::Right, None)
+    }
+}
+
+/// `BLMOVE source destination LEFT|RIGHT LEFT|RIGHT timeout`
+#[derive(Debug, Clone)]
+pub struct BLMove {
+    // a single key, as a slice for `keys`
+    source: [String; 1],
+    destination: String,
+    from: End,
+    to: End,
+    timeout: Option<Duration>,
+}
+
+impl BLMove {
+    /// A `None` timeout waits forever
+    pub fn new(source: &str, destination: &str, from: End, to: End, timeout: Option<Duration>) -> Self {
+        BLMove {
+            source: [source.to_string()],
+            destination: destination.to_string(),
+            from,
+            to,
+            timeout,
+        }
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.source
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let [source] = self.source;
+        let mut frame = Frame::new_array_frame();
+        frame.push_bulk(Bytes::from("blmove"));
+        frame.push_bulk(Bytes::from(source));
+        frame.push_bulk(Bytes::from(self.destination));
+        frame.push_bulk(Bytes::from(self.from.as_str()));
+        frame.push_bulk(Bytes::from(self.to.as_str()));
+        frame.push_bulk(timeout_bulk(self.timeout));
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let source = it.next_string()?;
+        let destination = it.next_string()?;
+        let from = End::from_frame(it)?;
+        let to = End::from_frame(it)?;
+        Ok(BLMove::new(&source, &destination, from, to, timeout_from_frame(it)?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        lmove(db, &self.source[0], &self.destination, self.from, self.to)
+    }
+}
+
+/// `BLMPOP timeout numkeys key [key ...] LEFT|RIGHT [COUNT count]`,
+/// replies with the key and an array of up to `count` elements
+#[derive(Debug, Clone)]
+pub struct BLMPop {
+    keys: Vec<String>,
+    end: End,
+    count: usize,
+    timeout: Option<Duration>,
+}
+
+impl BLMPop {
+    /// A `None` timeout waits forever
+    pub fn new(keys: &[&str], end: End, count: usize, timeout: Option<Duration>) -> Self {
+        BLMPop {
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+            end,
+            count,
+            timeout,
+        }
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::new_array_frame();
+        frame.push_bulk(Bytes::from("blmpop"));
+        frame.push_bulk(timeout_bulk(self.timeout));
+        frame.push_bulk(Bytes::from(self.keys.len().to_string()));
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key));
+        }
+        frame.push_bulk(Bytes::from(self.end.as_str()));
+        frame.push_bulk(Bytes::from("count"));
+        frame.push_bulk(Bytes::from(self.count.to_string()));
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let timeout = timeout_from_frame(it)?;
+        let numkeys = match it.next_int()? {
+            n if n > 0 => n,
+            _ => return Err("numkeys should be greater than 0".into()),
+        };
+        let mut keys = vec![];
+        for _ in 0..numkeys {
+            keys.push(it.next_string()?);
+        }
+        let end = End::from_frame(it)?;
+        let mut count = 1;
+        if it.has_next() {
+            if it.next_string()?.to_lowercase() != "count" {
+                return Err("syntax error".into());
+            }
+            count = match it.next_int()? {
+                n if n > 0 => n as usize,
+                _ => return Err("count should be greater than 0".into()),
+            };
+        }
+        Ok(BLMPop { keys, end, count, timeout })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        pop_first(db, &self.keys, self.end, Some(self.count))
+    }
+}
@@ -0,0 +1,203 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use super::{glob_match, Error};
+use crate::{
+    database::{self, Database},
+    frame::{Frame, Parse},
+    notify::Events,
+};
+
+/// A tunable `CONFIG GET` and `CONFIG SET` know of, with how to read it
+/// from and write it to the database config
+struct Parameter {
+    name: &'static str,
+    get: fn(&database::Config) -> String,
+    set: fn(&mut database::Config, &str) -> Result<(), &'static str>,
+}
+
+const NOT_INT: &str = "argument couldn't be parsed into an integer";
+
+fn parse_usize(value: &str) -> Result<usize, &'static str> {
+    value.parse().map_err(|_| NOT_INT)
+}
+
+const PARAMETERS: &[Parameter] = &[
+    Parameter {
+        name: "hash-max-listpack-entries",
+        get: |config| config.hash_limits.max_entries.to_string(),
+        set: |config, value| {
+            config.hash_limits.max_entries = parse_usize(value)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "hash-max-listpack-value",
+        get: |config| config.hash_limits.max_value.to_string(),
+        set: |config, value| {
+            config.hash_limits.max_value = parse_usize(value)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "hll-sparse-max-bytes",
+        get: |config| config.hll_sparse_max_bytes.to_string(),
+        set: |config, value| {
+            config.hll_sparse_max_bytes = parse_usize(value)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "lua-time-limit",
+        get: |config| config.lua_time_limit.as_millis().to_string(),
+        set: |config, value| {
+            config.lua_time_limit = Duration::from_millis(value.parse().map_err(|_| NOT_INT)?);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "notify-keyspace-events",
+        get: |config| config.notify_keyspace_events.to_string(),
+        set: |config, value| {
+            let events = Events::parse(value).ok_or("Invalid event class character. Use 'Ag$lshzxeKEtmdn'.")?;
+            config.notify_keyspace_events = events;
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "pubsub-buffer-limit",
+        get: |config| config.pubsub_buffer_limit.unwrap_or(0).to_string(),
+        set: |config, value| {
+            // 0 for no limit
+            config.pubsub_buffer_limit = Some(parse_usize(value)?).filter(|limit| *limit > 0);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "stream-bulk-threshold",
+        get: |config| config.stream_bulk_threshold.unwrap_or(0).to_string(),
+        set: |config, value| {
+            // 0 turns streaming off
+            config.stream_bulk_threshold = Some(parse_usize(value)?).filter(|threshold| *threshold > 0);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "set-max-intset-entries",
+        get: |config| config.set_max_intset_entries.to_string(),
+        set: |config, value| {
+            config.set_max_intset_entries = parse_usize(value)?;
+            Ok(())
+        },
+    },
+];
+
+fn parameter(name: &str) -> Option<&'static Parameter> {
+    PARAMETERS.iter().find(|parameter| parameter.name.eq_ignore_ascii_case(name))
+}
+
+/// `CONFIG GET parameter [parameter ...]` and
+/// `CONFIG SET parameter value [parameter value ...]`
+#[derive(Debug)]
+pub enum Config {
+    /// the parameters matching any of the glob patterns
+    Get(Vec<Bytes>),
+    /// all set, or none if a value is invalid
+    Set(Vec<(String, String)>),
+}
+
+impl Config {
+    pub fn get(patterns: &[&str]) -> Self {
+        Config::Get(patterns.iter().map(|pattern| Bytes::copy_from_slice(pattern.as_bytes())).collect())
+    }
+
+    pub fn set(parameter: impl ToString, value: impl ToString) -> Self {
+        Config::Set(vec![(parameter.to_string(), value.to_string())])
+    }
+
+    /// Set one more parameter, after a `set`
+    pub fn and(mut self, parameter: impl ToString, value: impl ToString) -> Self {
+        if let Config::Set(pairs) = &mut self {
+            pairs.push((parameter.to_string(), value.to_string()));
+        }
+        self
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::new_array_frame();
+        frame.push_bulk(Bytes::from("config"));
+        match self {
+            Config::Get(patterns) => {
+                frame.push_bulk(Bytes::from("get"));
+                for pattern in patterns {
+                    frame.push_bulk(pattern);
+                }
+            }
+            Config::Set(pairs) => {
+                frame.push_bulk(Bytes::from("set"));
+                for (parameter, value) in pairs {
+                    frame.push_bulk(Bytes::from(parameter));
+                    frame.push_bulk(Bytes::from(value));
+                }
+            }
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let subcommand = it.next_string()?.to_lowercase();
+        match subcommand.as_str() {
+            "get" => {
+                let mut patterns = vec![it.next_bytes()?];
+                while it.has_next() {
+                    patterns.push(it.next_bytes()?);
+                }
+                Ok(Config::Get(patterns))
+            }
+            "set" => {
+                let mut pairs = vec![(it.next_string()?, it.next_string()?)];
+                while it.has_next() {
+                    pairs.push((it.next_string()?, it.next_string()?));
+                }
+                Ok(Config::Set(pairs))
+            }
+            _ => Err(format!("unknown subcommand '{subcommand}'. Try CONFIG HELP.").into()),
+        }
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match self {
+            Config::Get(patterns) => {
+                let mut frame = Frame::new_array_frame();
+                let matching = PARAMETERS.iter().filter(|parameter| {
+                    let name = parameter.name.as_bytes();
+                    patterns.iter().any(|pattern| glob_match(&pattern.to_ascii_lowercase(), name))
+                });
+                for parameter in matching {
+                    frame.push_bulk(Bytes::from(parameter.name));
+                    frame.push_bulk(Bytes::from((parameter.get)(db.config())));
+                }
+                frame
+            }
+            Config::Set(pairs) => {
+                // on a copy, so that an invalid value changes nothing
+                let mut config = db.config().clone();
+                for (name, value) in pairs {
+                    let Some(parameter) = parameter(name) else {
+                        return Frame::Error(format!(
+                            "ERR Unknown option or number of arguments for CONFIG SET - '{name}'"
+                        ));
+                    };
+                    if let Err(reason) = (parameter.set)(&mut config, value) {
+                        return Frame::Error(format!(
+                            "ERR CONFIG SET failed (possibly related to argument '{name}') - {reason}"
+                        ));
+                    }
+                }
+                db.set_config(config);
+                Frame::Simple("OK".to_string())
+            }
+        }
+    }
+}
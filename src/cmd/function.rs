@@ -0,0 +1,313 @@
+//! Function commands, see `function`
+//!
+//! Like the scripting commands they run against the `Scripts` of the
+//! server as well as the database, through `Request::script`.
+
+use bytes::Bytes;
+
+use super::glob_match;
+use crate::{
+    database::Database,
+    frame::{Error, Frame, Parse},
+    function::{self, Library},
+    script::Scripts,
+};
+
+/// What `FUNCTION RESTORE` does with the libraries already loaded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RestorePolicy {
+    /// fail if one has the name of a restored library
+    #[default]
+    Append,
+    /// delete them all first
+    Flush,
+    /// restored libraries take the place of those of the same name
+    Replace,
+}
+
+/// `FUNCTION LOAD [REPLACE] code`, `FUNCTION LIST [LIBRARYNAME pattern]
+/// [WITHCODE]`, `FUNCTION DELETE library`, `FUNCTION DUMP`,
+/// `FUNCTION RESTORE payload [FLUSH | APPEND | REPLACE]` and
+/// `FUNCTION FLUSH [ASYNC | SYNC]`
+#[derive(Debug)]
+pub enum Function {
+    /// replies with the name of the library
+    Load { code: Bytes, replace: bool },
+    List { pattern: Option<Bytes>, with_code: bool },
+    Delete(String),
+    /// replies with a payload for `RESTORE`
+    Dump,
+    /// all the libraries of the payload are loaded, or none
+    Restore { payload: Bytes, policy: RestorePolicy },
+    Flush,
+}
+
+impl Function {
+    pub fn load(code: &str) -> Self {
+        Function::Load { code: Bytes::copy_from_slice(code.as_bytes()), replace: false }
+    }
+
+    /// Replace the library of the same name, after a `load`
+    pub fn replace(mut self) -> Self {
+        if let Function::Load { replace, .. } = &mut self {
+            *replace = true;
+        }
+        self
+    }
+
+    pub fn list() -> Self {
+        Function::List { pattern: None, with_code: false }
+    }
+
+    /// Only the libraries matching the glob `pattern`, after a `list`
+    pub fn library_name(mut self, name: &str) -> Self {
+        if let Function::List { pattern, .. } = &mut self {
+            *pattern = Some(Bytes::copy_from_slice(name.as_bytes()));
+        }
+        self
+    }
+
+    /// With the code of the libraries, after a `list`
+    pub fn with_code(mut self) -> Self {
+        if let Function::List { with_code, .. } = &mut self {
+            *with_code = true;
+        }
+        self
+    }
+
+    pub fn delete(library: &str) -> Self {
+        Function::Delete(library.to_string())
+    }
+
+    pub fn restore(payload: Bytes, policy: RestorePolicy) -> Self {
+        Function::Restore { payload, policy }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::new_array_frame();
+        frame.push_bulk(Bytes::from("function"));
+        match self {
+            Function::Load { code, replace } => {
+                frame.push_bulk(Bytes::from("load"));
+                if replace {
+                    frame.push_bulk(Bytes::from("replace"));
+                }
+                frame.push_bulk(code);
+            }
+            Function::List { pattern, with_code } => {
+                frame.push_bulk(Bytes::from("list"));
+                if let Some(pattern) = pattern {
+                    frame.push_bulk(Bytes::from("libraryname"));
+                    frame.push_bulk(pattern);
+                }
+                if with_code {
+                    frame.push_bulk(Bytes::from("withcode"));
+                }
+            }
+            Function::Delete(library) => {
+                frame.push_bulk(Bytes::from("delete"));
+                frame.push_bulk(Bytes::from(library));
+            }
+            Function::Dump => frame.push_bulk(Bytes::from("dump")),
+            Function::Restore { payload, policy } => {
+                frame.push_bulk(Bytes::from("restore"));
+                frame.push_bulk(payload);
+                frame.push_bulk(Bytes::from(match policy {
+                    RestorePolicy::Append => "append",
+                    RestorePolicy::Flush => "flush",
+                    RestorePolicy::Replace => "replace",
+                }));
+            }
+            Function::Flush => frame.push_bulk(Bytes::from("flush")),
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let subcommand = it.next_string()?.to_lowercase();
+        match subcommand.as_str() {
+            "load" => {
+                let mut code = it.next_bytes()?;
+                let replace = it.has_next() && code.eq_ignore_ascii_case(b"replace");
+                if replace {
+                    code = it.next_bytes()?;
+                }
+                Ok(Function::Load { code, replace })
+            }
+            "list" => {
+                let (mut pattern, mut with_code) = (None, false);
+                while it.has_next() {
+                    match it.next_string()?.to_lowercase().as_str() {
+                        "withcode" => with_code = true,
+                        "libraryname" if pattern.is_none() => pattern = Some(it.next_bytes()?),
+                        _ => return Err("syntax error".into()),
+                    }
+                }
+                Ok(Function::List { pattern, with_code })
+            }
+            "delete" => Ok(Function::Delete(it.next_string()?)),
+            "dump" => Ok(Function::Dump),
+            "restore" => {
+                let payload = it.next_bytes()?;
+                let policy = if it.has_next() {
+                    match it.next_string()?.to_lowercase().as_str() {
+                        "append" => RestorePolicy::Append,
+                        "flush" => RestorePolicy::Flush,
+                        "replace" => RestorePolicy::Replace,
+                        _ => return Err("Wrong restore policy given, value should be either FLUSH, APPEND or REPLACE.".into()),
+                    }
+                } else {
+                    RestorePolicy::default()
+                };
+                Ok(Function::Restore { payload, policy })
+            }
+            "flush" => {
+                // always synchronous
+                if it.has_next() {
+                    let mode = it.next_string()?.to_lowercase();
+                    if mode != "async" && mode != "sync" {
+                        return Err("FUNCTION FLUSH only supports SYNC|ASYNC option".into());
+                    }
+                }
+                Ok(Function::Flush)
+            }
+            _ => Err(format!("unknown subcommand '{subcommand}'. Try FUNCTION HELP.").into()),
+        }
+    }
+
+    pub fn execute(&self, db: &mut Database, scripts: &Scripts) -> Frame {
+        match self {
+            Function::Load { code, replace } => {
+                let added = scripts
+                    .compile_library(db, code)
+                    .and_then(|library| {
+                        let name = library.name.clone();
+                        db.functions_mut().add(library, *replace).map(|_| name)
+                    });
+                match added {
+                    Ok(name) => Frame::Bulk(Bytes::from(name)),
+                    Err(e) => Frame::Error(format!("ERR {e}")),
+                }
+            }
+            Function::List { pattern, with_code } => {
+                let matching = db.functions().iter().filter(|library| match pattern {
+                    Some(pattern) => glob_match(pattern, library.name.as_bytes()),
+                    None => true,
+                });
+                Frame::Array(matching.map(|library| list_entry(library, *with_code)).collect())
+            }
+            Function::Delete(library) => match db.functions_mut().delete(library) {
+                true => Frame::Simple("OK".to_string()),
+                false => Frame::Error("ERR Library not found".to_string()),
+            },
+            Function::Dump => Frame::Bulk(db.functions().dump()),
+            Function::Restore { payload, policy } => {
+                let Some(codes) = function::restore(payload) else {
+                    return Frame::Error("ERR payload version or checksum are wrong".to_string());
+                };
+                // on a copy, so that an invalid library changes nothing
+                let mut libraries = db.functions().clone();
+                if *policy == RestorePolicy::Flush {
+                    libraries.flush();
+                }
+                for code in codes {
+                    let added = scripts
+                        .compile_library(db, &code)
+                        .and_then(|library| libraries.add(library, *policy == RestorePolicy::Replace));
+                    if let Err(e) = added {
+                        return Frame::Error(format!("ERR {e}"));
+                    }
+                }
+                *db.functions_mut() = libraries;
+                Frame::Simple("OK".to_string())
+            }
+            Function::Flush => {
+                db.functions_mut().flush();
+                Frame::Simple("OK".to_string())
+            }
+        }
+    }
+}
+
+/// A library the way `FUNCTION LIST` shows it
+fn list_entry(library: &Library, with_code: bool) -> Frame {
+    let bulk = |s: &str| Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()));
+    let functions = library.functions.iter().map(|function| {
+        Frame::Array(vec![
+            bulk("name"),
+            bulk(&function.name),
+            bulk("description"),
+            Frame::Null,
+            bulk("flags"),
+            Frame::Array(function.flags.iter().map(|flag| bulk(flag)).collect()),
+        ])
+    });
+    let mut entry = vec![
+        bulk("library_name"),
+        bulk(&library.name),
+        bulk("engine"),
+        bulk("LUA"),
+        bulk("functions"),
+        Frame::Array(functions.collect()),
+    ];
+    if with_code {
+        entry.push(bulk("library_code"));
+        entry.push(Frame::Bulk(library.code.clone()));
+    }
+    Frame::Array(entry)
+}
+
+/// `FCALL function numkeys [key ...] [arg ...]` and `FCALL_RO`, which only
+/// runs functions flagged `no-writes`. The function gets the keys and the
+/// args as its two arguments.
+#[derive(Debug)]
+pub struct FCall {
+    function: String,
+    keys: Vec<String>,
+    args: Vec<Bytes>,
+    read_only: bool,
+}
+
+impl FCall {
+    pub fn new(function: &str, keys: &[&str], args: Vec<Bytes>) -> Self {
+        FCall {
+            function: function.to_string(),
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+            args,
+            read_only: false,
+        }
+    }
+
+    /// `FCALL_RO`
+    pub fn read_only(function: &str, keys: &[&str], args: Vec<Bytes>) -> Self {
+        FCall { read_only: true, ..FCall::new(function, keys, args) }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::new_array_frame();
+        frame.push_bulk(Bytes::from(if self.read_only { "fcall_ro" } else { "fcall" }));
+        frame.push_bulk(Bytes::from(self.function));
+        frame.push_bulk(Bytes::from(self.keys.len().to_string()));
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key));
+        }
+        for arg in self.args {
+            frame.push_bulk(arg);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse, read_only: bool) -> Result<Self, Error> {
+        let function = it.next_string()?;
+        let (keys, args) = super::script::keys_and_args_from_frame(it)?;
+        Ok(FCall { function, keys, args, read_only })
+    }
+
+    pub fn execute(&self, db: &mut Database, scripts: &Scripts) -> Frame {
+        scripts.fcall(db, &self.function, &self.keys, &self.args, self.read_only)
+    }
+}
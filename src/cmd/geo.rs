@@ -0,0 +1,675 @@
+//! Geospatial commands
+//!
+//! Places are members of a sorted set scored by their geohash, see
+//! `types::geo`, so the sorted set commands work on them too. Distances
+//! are replied with 4 decimals, in the unit of the request.
+
+use bytes::Bytes;
+
+use super::ZAdd;
+use crate::{
+    database::{Database, Value},
+    frame::{Error, Frame, Parse},
+    notify::Events,
+    types::{
+        geo::{self, Shape},
+        zset::ScoreRange,
+        ZSet,
+    },
+};
+
+fn cmd_frame(name: &'static str, key: String) -> Frame {
+    let mut frame = Frame::new_array_frame();
+    frame.push_bulk(Bytes::from(name));
+    frame.push_bulk(Bytes::from(key));
+    frame
+}
+
+fn float_bulk(x: f64) -> Frame {
+    Frame::Bulk(Bytes::from(x.to_string()))
+}
+
+fn members_from_frame(it: &mut dyn Parse) -> Result<Vec<Bytes>, Error> {
+    let mut members = vec![];
+    while it.has_next() {
+        members.push(it.next_bytes()?);
+    }
+    Ok(members)
+}
+
+fn invalid_coordinates(lon: f64, lat: f64) -> Frame {
+    Frame::Error(format!("ERR invalid longitude,latitude pair {:.6},{:.6}", lon, lat))
+}
+
+/// The unit of a distance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    M,
+    Km,
+    Ft,
+    Mi,
+}
+
+impl Unit {
+    fn meters(self) -> f64 {
+        match self {
+            Unit::M => 1.0,
+            Unit::Km => 1000.0,
+            Unit::Ft => 0.3048,
+            Unit::Mi => 1609.34,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Unit::M => "m",
+            Unit::Km => "km",
+            Unit::Ft => "ft",
+            Unit::Mi => "mi",
+        }
+    }
+
+    fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        match it.next_string()?.to_lowercase().as_str() {
+            "m" => Ok(Unit::M),
+            "km" => Ok(Unit::Km),
+            "ft" => Ok(Unit::Ft),
+            "mi" => Ok(Unit::Mi),
+            _ => Err("unsupported unit provided. please use M, KM, FT, MI".into()),
+        }
+    }
+}
+
+/// `GEOADD key [NX|XX] [CH] longitude latitude member [longitude latitude member ...]`,
+/// replies with the number of new places, or of changed places too with `CH`
+#[derive(Debug, Default)]
+pub struct GeoAdd {
+    key: String,
+    nx: bool,
+    xx: bool,
+    ch: bool,
+    places: Vec<(f64, f64, Bytes)>,
+}
+
+impl GeoAdd {
+    pub fn new(key: &str) -> Self {
+        GeoAdd { key: key.to_string(), ..GeoAdd::default() }
+    }
+
+    pub fn place(mut self, lon: f64, lat: f64, member: Bytes) -> Self {
+        self.places.push((lon, lat, member));
+        self
+    }
+
+    /// Only add new places
+    pub fn nx(mut self) -> Self {
+        self.nx = true;
+        self
+    }
+
+    /// Only move existing places
+    pub fn xx(mut self) -> Self {
+        self.xx = true;
+        self
+    }
+
+    pub fn ch(mut self) -> Self {
+        self.ch = true;
+        self
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("geoadd", self.key);
+        for (set, flag) in [(self.nx, "nx"), (self.xx, "xx"), (self.ch, "ch")] {
+            if set {
+                frame.push_bulk(Bytes::from(flag));
+            }
+        }
+        for (lon, lat, member) in self.places {
+            frame.push_bulk(Bytes::from(lon.to_string()));
+            frame.push_bulk(Bytes::from(lat.to_string()));
+            frame.push_bulk(member);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let mut geoadd = GeoAdd::new(&it.next_string()?);
+        let mut args = vec![];
+        while it.has_next() {
+            args.push(it.next_bytes()?);
+        }
+        // the flags come first
+        let flags = args.iter().take_while(|arg| {
+            let arg = String::from_utf8_lossy(arg).to_lowercase();
+            match arg.as_str() {
+                "nx" => geoadd.nx = true,
+                "xx" => geoadd.xx = true,
+                "ch" => geoadd.ch = true,
+                _ => return false,
+            }
+            true
+        });
+        let flags = flags.count();
+        let places = &args[flags..];
+        if places.is_empty() || places.len() % 3 != 0 {
+            return Err("syntax error".into());
+        }
+        if geoadd.nx && geoadd.xx {
+            return Err("XX and NX options at the same time are not compatible".into());
+        }
+        let float = |bs: &Bytes| match std::str::from_utf8(bs).ok().and_then(|s| s.parse::<f64>().ok()) {
+            Some(x) if !x.is_nan() => Ok(x),
+            _ => Err::<f64, Error>("value is not a valid float".into()),
+        };
+        for place in places.chunks(3) {
+            geoadd.places.push((float(&place[0])?, float(&place[1])?, place[2].clone()));
+        }
+        Ok(geoadd)
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let mut zadd = ZAdd::new(&self.key);
+        for (lon, lat, member) in &self.places {
+            if !geo::is_valid(*lon, *lat) {
+                return invalid_coordinates(*lon, *lat);
+            }
+            zadd = zadd.member(geo::encode(*lon, *lat) as f64, member.clone());
+        }
+        if self.nx {
+            zadd = zadd.nx();
+        }
+        if self.xx {
+            zadd = zadd.xx();
+        }
+        if self.ch {
+            zadd = zadd.ch();
+        }
+        zadd.execute(db)
+    }
+}
+
+/// `GEODIST key member1 member2 [M|KM|FT|MI]`, nil if a place is missing
+#[derive(Debug)]
+pub struct GeoDist {
+    key: String,
+    members: (Bytes, Bytes),
+    unit: Unit,
+}
+
+impl GeoDist {
+    pub fn new(key: &str, member1: Bytes, member2: Bytes, unit: Unit) -> Self {
+        GeoDist { key: key.to_string(), members: (member1, member2), unit }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("geodist", self.key);
+        frame.push_bulk(self.members.0);
+        frame.push_bulk(self.members.1);
+        frame.push_bulk(Bytes::from(self.unit.name()));
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let member1 = it.next_bytes()?;
+        let member2 = it.next_bytes()?;
+        let unit = if it.has_next() { Unit::from_frame(it)? } else { Unit::M };
+        Ok(GeoDist::new(&key, member1, member2, unit))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let zset = match db.get_zset(&self.key) {
+            Ok(Some(zset)) => zset,
+            Ok(None) => return Frame::Null,
+            Err(e) => return e.into(),
+        };
+        let (Some(score1), Some(score2)) = (zset.score(&self.members.0), zset.score(&self.members.1)) else {
+            return Frame::Null;
+        };
+        let (lon1, lat1) = geo::decode(score1 as u64);
+        let (lon2, lat2) = geo::decode(score2 as u64);
+        let distance = geo::distance(lon1, lat1, lon2, lat2) / self.unit.meters();
+        Frame::Bulk(Bytes::from(format!("{:.4}", distance)))
+    }
+}
+
+/// `GEOPOS key [member ...]`, the `[longitude, latitude]` of each place, or
+/// nil for a missing one
+#[derive(Debug)]
+pub struct GeoPos {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+impl GeoPos {
+    pub fn new(key: &str, members: Vec<Bytes>) -> Self {
+        GeoPos { key: key.to_string(), members }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("geopos", self.key);
+        for member in self.members {
+            frame.push_bulk(member);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(GeoPos { key, members: members_from_frame(it)? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let zset = match db.get_zset(&self.key) {
+            Ok(zset) => zset,
+            Err(e) => return e.into(),
+        };
+        let positions = self.members.iter().map(|member| match zset.and_then(|zset| zset.score(member)) {
+            Some(score) => {
+                let (lon, lat) = geo::decode(score as u64);
+                Frame::Array(vec![float_bulk(lon), float_bulk(lat)])
+            }
+            None => Frame::Null,
+        });
+        Frame::Array(positions.collect())
+    }
+}
+
+/// `GEOHASH key [member ...]`, the standard 11 characters geohash of each
+/// place, or nil for a missing one
+#[derive(Debug)]
+pub struct GeoHash {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+impl GeoHash {
+    pub fn new(key: &str, members: Vec<Bytes>) -> Self {
+        GeoHash { key: key.to_string(), members }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("geohash", self.key);
+        for member in self.members {
+            frame.push_bulk(member);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(GeoHash { key, members: members_from_frame(it)? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let zset = match db.get_zset(&self.key) {
+            Ok(zset) => zset,
+            Err(e) => return e.into(),
+        };
+        let hashes = self.members.iter().map(|member| match zset.and_then(|zset| zset.score(member)) {
+            Some(score) => Frame::Bulk(Bytes::from(geo::to_base32(score as u64))),
+            None => Frame::Null,
+        });
+        Frame::Array(hashes.collect())
+    }
+}
+
+/// The center of a search
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoFrom {
+    /// `FROMMEMBER member`
+    Member(Bytes),
+    /// `FROMLONLAT longitude latitude`
+    LonLat(f64, f64),
+}
+
+/// The area of a search
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoBy {
+    /// `BYRADIUS radius unit`
+    Radius(f64, Unit),
+    /// `BYBOX width height unit`
+    Box(f64, f64, Unit),
+}
+
+impl GeoBy {
+    fn unit(self) -> Unit {
+        match self {
+            GeoBy::Radius(_, unit) | GeoBy::Box(_, _, unit) => unit,
+        }
+    }
+
+    fn shape(self) -> Shape {
+        let meters = self.unit().meters();
+        match self {
+            GeoBy::Radius(radius, _) => Shape::Radius(radius * meters),
+            GeoBy::Box(width, height, _) => Shape::Box { width: width * meters, height: height * meters },
+        }
+    }
+}
+
+/// The order of the places found, by distance from the center
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+struct Found {
+    member: Bytes,
+    hash: u64,
+    // in meters
+    distance: f64,
+}
+
+/// `GEOSEARCH key FROMMEMBER member|FROMLONLAT longitude latitude
+/// BYRADIUS radius unit|BYBOX width height unit [ASC|DESC] [COUNT count [ANY]]
+/// [WITHCOORD] [WITHDIST] [WITHHASH]`
+///
+/// Replies with the places in the area, each with its distance, geohash
+/// and coordinates if asked for. `COUNT` without `ANY` returns the closest
+/// places, with `ANY` the first ones found, quicker.
+#[derive(Debug)]
+pub struct GeoSearch {
+    key: String,
+    from: GeoFrom,
+    by: GeoBy,
+    order: Option<Order>,
+    count: Option<(usize, bool)>,
+    with_coord: bool,
+    with_dist: bool,
+    with_hash: bool,
+}
+
+impl GeoSearch {
+    pub fn new(key: &str, from: GeoFrom, by: GeoBy) -> Self {
+        GeoSearch {
+            key: key.to_string(),
+            from,
+            by,
+            order: None,
+            count: None,
+            with_coord: false,
+            with_dist: false,
+            with_hash: false,
+        }
+    }
+
+    pub fn order(mut self, order: Order) -> Self {
+        self.order = Some(order);
+        self
+    }
+
+    /// At most `count` places, any of them if `any`
+    pub fn count(mut self, count: usize, any: bool) -> Self {
+        self.count = Some((count, any));
+        self
+    }
+
+    pub fn with_coord(mut self) -> Self {
+        self.with_coord = true;
+        self
+    }
+
+    pub fn with_dist(mut self) -> Self {
+        self.with_dist = true;
+        self
+    }
+
+    pub fn with_hash(mut self) -> Self {
+        self.with_hash = true;
+        self
+    }
+
+    fn push_args(self, frame: &mut Frame) {
+        match self.from {
+            GeoFrom::Member(member) => {
+                frame.push_bulk(Bytes::from("frommember"));
+                frame.push_bulk(member);
+            }
+            GeoFrom::LonLat(lon, lat) => {
+                frame.push_bulk(Bytes::from("fromlonlat"));
+                frame.push_bulk(Bytes::from(lon.to_string()));
+                frame.push_bulk(Bytes::from(lat.to_string()));
+            }
+        }
+        let mut args = vec![];
+        match self.by {
+            GeoBy::Radius(radius, unit) => args.extend(["byradius".to_string(), radius.to_string(), unit.name().into()]),
+            GeoBy::Box(width, height, unit) => {
+                args.extend(["bybox".to_string(), width.to_string(), height.to_string(), unit.name().into()])
+            }
+        }
+        match self.order {
+            Some(Order::Asc) => args.push("asc".to_string()),
+            Some(Order::Desc) => args.push("desc".to_string()),
+            None => {}
+        }
+        if let Some((count, any)) = self.count {
+            args.extend(["count".to_string(), count.to_string()]);
+            if any {
+                args.push("any".to_string());
+            }
+        }
+        for (set, flag) in [(self.with_coord, "withcoord"), (self.with_dist, "withdist"), (self.with_hash, "withhash")] {
+            if set {
+                args.push(flag.to_string());
+            }
+        }
+        for arg in args {
+            frame.push_bulk(Bytes::from(arg));
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("geosearch", self.key.clone());
+        self.push_args(&mut frame);
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(GeoSearch::args_from_frame(key, it, false)?.0)
+    }
+
+    /// The arguments after the key, and whether they include `STOREDIST`,
+    /// which only a `GEOSEARCHSTORE`, `store`, takes
+    fn args_from_frame(key: String, it: &mut dyn Parse, store: bool) -> Result<(Self, bool), Error> {
+        let mut from = None;
+        let mut by = None;
+        let mut search = GeoSearch::new(&key, GeoFrom::LonLat(0.0, 0.0), GeoBy::Radius(0.0, Unit::M));
+        let mut any = false;
+        let mut store_dist = false;
+        while it.has_next() {
+            match it.next_string()?.to_lowercase().as_str() {
+                "frommember" if from.is_none() => from = Some(GeoFrom::Member(it.next_bytes()?)),
+                "fromlonlat" if from.is_none() => {
+                    let lon = it.next_float()?;
+                    from = Some(GeoFrom::LonLat(lon, it.next_float()?));
+                }
+                "frommember" | "fromlonlat" => {
+                    return Err("exactly one of FROMMEMBER or FROMLONLAT can be specified for geosearch".into())
+                }
+                "byradius" if by.is_none() => {
+                    let radius = it.next_float()?;
+                    if radius < 0.0 {
+                        return Err("radius cannot be negative".into());
+                    }
+                    by = Some(GeoBy::Radius(radius, Unit::from_frame(it)?));
+                }
+                "bybox" if by.is_none() => {
+                    let width = it.next_float()?;
+                    let height = it.next_float()?;
+                    if width < 0.0 || height < 0.0 {
+                        return Err("height or width cannot be negative".into());
+                    }
+                    by = Some(GeoBy::Box(width, height, Unit::from_frame(it)?));
+                }
+                "byradius" | "bybox" => {
+                    return Err("exactly one of BYRADIUS and BYBOX can be specified for geosearch".into())
+                }
+                "asc" => search.order = Some(Order::Asc),
+                "desc" => search.order = Some(Order::Desc),
+                "count" => match it.next_int()? {
+                    count if count > 0 => search.count = Some((count as usize, false)),
+                    _ => return Err("COUNT must be > 0".into()),
+                },
+                "any" => any = true,
+                "withcoord" if !store => search.with_coord = true,
+                "withdist" if !store => search.with_dist = true,
+                "withhash" if !store => search.with_hash = true,
+                "storedist" if store => store_dist = true,
+                _ => return Err("syntax error".into()),
+            }
+        }
+        search.from = from.ok_or("exactly one of FROMMEMBER or FROMLONLAT can be specified for geosearch")?;
+        search.by = by.ok_or("exactly one of BYRADIUS and BYBOX can be specified for geosearch")?;
+        match (&mut search.count, any) {
+            (Some((_, any_flag)), true) => *any_flag = true,
+            (None, true) => return Err("the ANY argument requires COUNT argument".into()),
+            _ => {}
+        }
+        Ok((search, store_dist))
+    }
+
+    /// The places found, ordered and limited, or the error to reply
+    fn search(&self, zset: &ZSet) -> Result<Vec<Found>, Frame> {
+        let center = match &self.from {
+            GeoFrom::LonLat(lon, lat) if !geo::is_valid(*lon, *lat) => return Err(invalid_coordinates(*lon, *lat)),
+            GeoFrom::LonLat(lon, lat) => (*lon, *lat),
+            GeoFrom::Member(member) => match zset.score(member) {
+                Some(score) => geo::decode(score as u64),
+                None => return Err(Frame::Error("ERR could not decode requested zset member".to_string())),
+            },
+        };
+        let shape = self.by.shape();
+        let (limit, any) = self.count.unwrap_or((usize::MAX, false));
+
+        let mut found = vec![];
+        'ranges: for (min, max) in geo::search_ranges(center.0, center.1, shape) {
+            let range = ScoreRange { min: min as f64, min_exclusive: false, max: max as f64, max_exclusive: true };
+            let ranks = zset.score_ranks(&range);
+            for (member, score) in zset.iter_from(ranks.start, false).take(ranks.len()) {
+                let (lon, lat) = geo::decode(score as u64);
+                if let Some(distance) = shape.distance_within(center, lon, lat) {
+                    found.push(Found { member: member.clone(), hash: score as u64, distance });
+                    if any && found.len() == limit {
+                        break 'ranges;
+                    }
+                }
+            }
+        }
+
+        // COUNT alone gives the closest places
+        let order = match self.order {
+            None if self.count.is_some() && !any => Some(Order::Asc),
+            order => order,
+        };
+        match order {
+            Some(Order::Asc) => found.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+            Some(Order::Desc) => found.sort_by(|a, b| b.distance.total_cmp(&a.distance)),
+            None => {}
+        }
+        found.truncate(limit);
+        Ok(found)
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let found = match db.get_zset(&self.key) {
+            Ok(Some(zset)) => match self.search(zset) {
+                Ok(found) => found,
+                Err(e) => return e,
+            },
+            Ok(None) => return Frame::Array(vec![]),
+            Err(e) => return e.into(),
+        };
+        let unit = self.by.unit().meters();
+        let places = found.into_iter().map(|place| {
+            if !(self.with_dist || self.with_hash || self.with_coord) {
+                return Frame::Bulk(place.member);
+            }
+            let mut reply = vec![Frame::Bulk(place.member)];
+            if self.with_dist {
+                reply.push(Frame::Bulk(Bytes::from(format!("{:.4}", place.distance / unit))));
+            }
+            if self.with_hash {
+                reply.push(Frame::Integer(place.hash as i64));
+            }
+            if self.with_coord {
+                let (lon, lat) = geo::decode(place.hash);
+                reply.push(Frame::Array(vec![float_bulk(lon), float_bulk(lat)]));
+            }
+            Frame::Array(reply)
+        });
+        Frame::Array(places.collect())
+    }
+}
+
+/// `GEOSEARCHSTORE destination source ... [STOREDIST]`, the search of
+/// `GEOSEARCH` stored as a sorted set of places, or scored by distance with
+/// `STOREDIST`, replies with its size
+#[derive(Debug)]
+pub struct GeoSearchStore {
+    destination: String,
+    search: GeoSearch,
+    store_dist: bool,
+}
+
+impl GeoSearchStore {
+    /// The `with_*` options of `search` are ignored
+    pub fn new(destination: &str, search: GeoSearch) -> Self {
+        GeoSearchStore { destination: destination.to_string(), search, store_dist: false }
+    }
+
+    pub fn store_dist(mut self) -> Self {
+        self.store_dist = true;
+        self
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("geosearchstore", self.destination);
+        frame.push_bulk(Bytes::from(self.search.key.clone()));
+        let search = GeoSearch { with_coord: false, with_dist: false, with_hash: false, ..self.search };
+        search.push_args(&mut frame);
+        if self.store_dist {
+            frame.push_bulk(Bytes::from("storedist"));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let destination = it.next_string()?;
+        let key = it.next_string()?;
+        let (search, store_dist) = GeoSearch::args_from_frame(key, it, true)?;
+        Ok(GeoSearchStore { destination, search, store_dist })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let found = match db.get_zset(&self.search.key) {
+            Ok(Some(zset)) => match self.search.search(zset) {
+                Ok(found) => found,
+                Err(e) => return e,
+            },
+            Ok(None) => vec![],
+            Err(e) => return e.into(),
+        };
+        let unit = self.search.by.unit().meters();
+        let mut result = ZSet::default();
+        for place in found {
+            let score = if self.store_dist { place.distance / unit } else { place.hash as f64 };
+            result.insert(place.member, score);
+        }
+
+        let len = result.len();
+        if result.is_empty() {
+            if db.remove(&self.destination).is_some() {
+                db.notify(Events::GENERIC, "del", &self.destination);
+            }
+        } else {
+            db.insert(self.destination.clone(), Value::ZSet(result), None);
+            db.notify(Events::ZSET, "geosearchstore", &self.destination);
+        }
+        Frame::Integer(len as i64)
+    }
+}
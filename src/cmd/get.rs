@@ -1,5 +1,8 @@
-use crate::{frame::{Error, Frame, Parse}, connection::Connection, database::Database};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{frame::{Error, Frame, Parse}, connection::Connection, database::Database, notify::Events};
 use bytes::Bytes;
+use tokio::time::Instant;
 
 #[derive(Debug)]
 pub struct Get {
@@ -20,20 +23,252 @@ impl Get {
 
     /// only visiable in `cmd` module
     /// TODO: currently it returns frame::Error, should use command Error
-    pub(super) fn from_frame(it: &mut impl Parse) -> Result<Self, Error> {
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
         Ok(Get::new(&it.next_string()?))
     }
 
-    pub async fn apply(&self, db: &Database, conn: &mut Connection) -> Result<(), super::Error> {
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.get(&self.key) {
+            Ok(Some(bs)) => Frame::Bulk(bs),
+            Ok(None) => Frame::Null,
+            Err(e) => e.into(),
+        }
+    }
+
+    pub async fn apply(&self, db: &mut Database, conn: &mut Connection) -> Result<(), super::Error> {
         // generic behavior handling without knowing underlying storage and connection?
-        /// interfaces for send command, receive command and get data
-        /// 
-        /// 
+        // interfaces for send command, receive command and get data
+        conn.write_frame(self.execute(db)).await?;
+        Ok(())
+    }
+}
+
+/// `MGET key [key ...]`, a `GET` for every key under a single lock
+#[derive(Debug)]
+pub struct MGet {
+    gets: Vec<Get>,
+}
+
+impl MGet {
+    pub fn new(keys: &[&str]) -> Self {
+        MGet { gets: keys.iter().map(|key| Get::new(key)).collect() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::new_array_frame();
+        frame.push_bulk(Bytes::from("mget"));
+        for get in self.gets {
+            frame.push_bulk(Bytes::from(get.key));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let mut gets = vec![Get::from_frame(it)?];
+        while it.has_next() {
+            gets.push(Get::from_frame(it)?);
+        }
+        Ok(MGet { gets })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        // a key of another type reads as missing rather than failing the batch
+        let frames = self.gets.iter().map(|get| match db.get(&get.key) {
+            Ok(Some(bs)) => Frame::Bulk(bs),
+            _ => Frame::Null,
+        });
+        Frame::Array(frames.collect())
+    }
+}
+
+/// `GETSET key value`, set a new value and reply with the old one
+#[derive(Debug)]
+pub struct GetSet {
+    key: String,
+    value: Bytes,
+}
+
+impl GetSet {
+    pub fn new(key: &str, value: Bytes) -> Self {
+        GetSet { key: key.to_string(), value }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::new_array_frame();
+        frame.push_bulk(Bytes::from("getset"));
+        frame.push_bulk(Bytes::from(self.key));
+        frame.push_bulk(self.value);
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(GetSet::new(&key, it.next_bytes()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        // the type is checked before anything is overwritten
+        let old = match db.get(&self.key) {
+            Ok(old) => old,
+            Err(e) => return e.into(),
+        };
+        db.set(self.key.clone(), self.value.clone());
+        db.notify(Events::STRING, "set", &self.key);
+        match old {
+            Some(bs) => Frame::Bulk(bs),
+            None => Frame::Null,
+        }
+    }
+}
+
+/// `GETDEL key`, reply with the value and delete the key
+#[derive(Debug)]
+pub struct GetDel {
+    key: String,
+}
+
+impl GetDel {
+    pub fn new(key: &str) -> Self {
+        GetDel { key: key.to_string() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::new_array_frame();
+        frame.push_bulk(Bytes::from("getdel"));
+        frame.push_bulk(Bytes::from(self.key));
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(GetDel::new(&it.next_string()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
         match db.get(&self.key) {
-            Some(bs) => conn.write_frame(Frame::Bulk(bs)).await?,
-            None => conn.write_frame(Frame::Null).await?,
+            Ok(Some(bs)) => {
+                db.remove(&self.key);
+                db.notify(Events::GENERIC, "del", &self.key);
+                Frame::Bulk(bs)
+            }
+            Ok(None) => Frame::Null,
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// How `GETEX` changes the time to live of a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expiry {
+    /// `EX`, seconds from now
+    Ex(u64),
+    /// `PX`, milliseconds from now
+    Px(u64),
+    /// `EXAT`, unix time in seconds
+    ExAt(u64),
+    /// `PXAT`, unix time in milliseconds
+    PxAt(u64),
+    /// `PERSIST`, remove the time to live
+    Persist,
+}
+
+impl Expiry {
+    /// The deadline as an `Instant`, `None` for `Persist`
+    pub fn deadline(&self) -> Option<Instant> {
+        let now = Instant::now();
+        let unix_now = || SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        // a unix time in the past is turned into `now`, the key expires right away
+        let at = |unix: Duration| now + unix.saturating_sub(unix_now());
+        match *self {
+            Expiry::Ex(secs) => Some(now + Duration::from_secs(secs)),
+            Expiry::Px(ms) => Some(now + Duration::from_millis(ms)),
+            Expiry::ExAt(secs) => Some(at(Duration::from_secs(secs))),
+            Expiry::PxAt(ms) => Some(at(Duration::from_millis(ms))),
+            Expiry::Persist => None,
+        }
+    }
+
+    fn push_args(&self, frame: &mut Frame) {
+        let (name, n) = match *self {
+            Expiry::Ex(n) => ("ex", n),
+            Expiry::Px(n) => ("px", n),
+            Expiry::ExAt(n) => ("exat", n),
+            Expiry::PxAt(n) => ("pxat", n),
+            Expiry::Persist => return frame.push_bulk(Bytes::from("persist")),
         };
-        Ok(())
-        
+        frame.push_bulk(Bytes::from(name));
+        frame.push_bulk(Bytes::from(n.to_string()));
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let option = it.next_string()?.to_lowercase();
+        if option == "persist" {
+            return Ok(Expiry::Persist);
+        }
+
+        // same upper bound as redis, so the deadline can not overflow
+        const MAX_MS: i64 = i64::MAX / 1000 / 2;
+        let n = it.next_int()?;
+        let in_range = |max: i64| if n > 0 && n <= max { Ok(n as u64) } else { Err(Error::from("invalid expire time")) };
+        match option.as_str() {
+            "ex" => Ok(Expiry::Ex(in_range(MAX_MS / 1000)?)),
+            "px" => Ok(Expiry::Px(in_range(MAX_MS)?)),
+            "exat" => Ok(Expiry::ExAt(in_range(MAX_MS / 1000)?)),
+            "pxat" => Ok(Expiry::PxAt(in_range(MAX_MS)?)),
+            _ => Err("syntax error".into()),
+        }
+    }
+}
+
+/// `GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds | PERSIST]`
+#[derive(Debug)]
+pub struct GetEx {
+    key: String,
+    expiry: Option<Expiry>,
+}
+
+impl GetEx {
+    pub fn new(key: &str, expiry: Option<Expiry>) -> Self {
+        GetEx { key: key.to_string(), expiry }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::new_array_frame();
+        frame.push_bulk(Bytes::from("getex"));
+        frame.push_bulk(Bytes::from(self.key));
+        if let Some(expiry) = self.expiry {
+            expiry.push_args(&mut frame);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let expiry = if it.has_next() { Some(Expiry::from_frame(it)?) } else { None };
+        // at most one option, `EX 10 PX 10` is a syntax error as in redis
+        if it.has_next() {
+            return Err("syntax error".into());
+        }
+        Ok(GetEx::new(&key, expiry))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let value = match db.get(&self.key) {
+            Ok(Some(bs)) => bs,
+            Ok(None) => return Frame::Null,
+            Err(e) => return e.into(),
+        };
+        match self.expiry {
+            Some(Expiry::Persist) if db.expires_at(&self.key).is_some() => {
+                db.set_expires_at(&self.key, None);
+                db.notify(Events::GENERIC, "persist", &self.key);
+            }
+            Some(Expiry::Persist) => {}
+            Some(expiry) => {
+                db.set_expires_at(&self.key, expiry.deadline());
+                db.notify(Events::GENERIC, "expire", &self.key);
+            }
+            None => {}
+        }
+        Frame::Bulk(value)
     }
 }
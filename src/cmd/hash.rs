@@ -0,0 +1,554 @@
+//! Hash commands
+//!
+//! See `types::hash` for the encodings. A hash that loses its last field is
+//! removed.
+
+use bytes::Bytes;
+use rand::seq::index;
+
+use super::random_count;
+use crate::{
+    database::Database,
+    frame::{Error, Frame, Parse},
+    notify::Events,
+};
+
+fn cmd_frame(name: &'static str, key: String) -> Frame {
+    let mut frame = Frame::new_array_frame();
+    frame.push_bulk(Bytes::from(name));
+    frame.push_bulk(Bytes::from(key));
+    frame
+}
+
+fn fields_from_frame(it: &mut dyn Parse) -> Result<Vec<Bytes>, Error> {
+    let mut fields = vec![it.next_bytes()?];
+    while it.has_next() {
+        fields.push(it.next_bytes()?);
+    }
+    Ok(fields)
+}
+
+/// `HSET key field value [field value ...]`, replies with the number of new fields
+#[derive(Debug, Default)]
+pub struct HSet {
+    key: String,
+    pairs: Vec<(Bytes, Bytes)>,
+}
+
+impl HSet {
+    pub fn new(key: &str) -> Self {
+        HSet { key: key.to_string(), pairs: vec![] }
+    }
+
+    pub fn field(mut self, field: Bytes, value: Bytes) -> Self {
+        self.pairs.push((field, value));
+        self
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("hset", self.key);
+        for (field, value) in self.pairs {
+            frame.push_bulk(field);
+            frame.push_bulk(value);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let mut hset = HSet::new(&it.next_string()?);
+        loop {
+            let field = it.next_bytes()?;
+            hset.pairs.push((field, it.next_bytes()?));
+            if !it.has_next() {
+                return Ok(hset);
+            }
+        }
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let limits = db.config().hash_limits;
+        let hash = match db.hash_entry(&self.key) {
+            Ok(hash) => hash,
+            Err(e) => return e.into(),
+        };
+        let added = self
+            .pairs
+            .iter()
+            .filter(|(field, value)| hash.insert(field.clone(), value.clone(), &limits))
+            .count();
+        db.notify(Events::HASH, "hset", &self.key);
+        Frame::Integer(added as i64)
+    }
+}
+
+/// `HSETNX key field value`, set `field` only if it does not exist
+#[derive(Debug)]
+pub struct HSetNx {
+    key: String,
+    field: Bytes,
+    value: Bytes,
+}
+
+impl HSetNx {
+    pub fn new(key: &str, field: Bytes, value: Bytes) -> Self {
+        HSetNx { key: key.to_string(), field, value }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("hsetnx", self.key);
+        frame.push_bulk(self.field);
+        frame.push_bulk(self.value);
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let field = it.next_bytes()?;
+        Ok(HSetNx::new(&key, field, it.next_bytes()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let limits = db.config().hash_limits;
+        let hash = match db.hash_entry(&self.key) {
+            Ok(hash) => hash,
+            Err(e) => return e.into(),
+        };
+        if hash.contains(&self.field) {
+            return Frame::Integer(0);
+        }
+        hash.insert(self.field.clone(), self.value.clone(), &limits);
+        db.notify(Events::HASH, "hset", &self.key);
+        Frame::Integer(1)
+    }
+}
+
+/// `HGET key field`
+#[derive(Debug)]
+pub struct HGet {
+    key: String,
+    field: Bytes,
+}
+
+impl HGet {
+    pub fn new(key: &str, field: Bytes) -> Self {
+        HGet { key: key.to_string(), field }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("hget", self.key);
+        frame.push_bulk(self.field);
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(HGet::new(&key, it.next_bytes()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.get_hash(&self.key) {
+            Ok(hash) => hash.and_then(|hash| hash.get(&self.field)).map_or(Frame::Null, |v| Frame::Bulk(v.clone())),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// `HMGET key field [field ...]`, nil for every missing field
+#[derive(Debug)]
+pub struct HMGet {
+    key: String,
+    fields: Vec<Bytes>,
+}
+
+impl HMGet {
+    pub fn new(key: &str, fields: Vec<Bytes>) -> Self {
+        HMGet { key: key.to_string(), fields }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("hmget", self.key);
+        for field in self.fields {
+            frame.push_bulk(field);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(HMGet::new(&key, fields_from_frame(it)?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let hash = match db.get_hash(&self.key) {
+            Ok(hash) => hash,
+            Err(e) => return e.into(),
+        };
+        let values = self.fields.iter().map(|field| {
+            hash.and_then(|hash| hash.get(field)).map_or(Frame::Null, |v| Frame::Bulk(v.clone()))
+        });
+        Frame::Array(values.collect())
+    }
+}
+
+/// `HGETALL key`, fields and values, flattened
+#[derive(Debug)]
+pub struct HGetAll {
+    key: String,
+}
+
+impl HGetAll {
+    pub fn new(key: &str) -> Self {
+        HGetAll { key: key.to_string() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        cmd_frame("hgetall", self.key)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(HGetAll::new(&it.next_string()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.get_hash(&self.key) {
+            Ok(Some(hash)) => Frame::Array(
+                hash.iter()
+                    .flat_map(|(f, v)| [Frame::Bulk(f.clone()), Frame::Bulk(v.clone())])
+                    .collect(),
+            ),
+            Ok(None) => Frame::Array(vec![]),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// `HDEL key field [field ...]`, replies with the number of fields removed
+#[derive(Debug)]
+pub struct HDel {
+    key: String,
+    fields: Vec<Bytes>,
+}
+
+impl HDel {
+    pub fn new(key: &str, fields: Vec<Bytes>) -> Self {
+        HDel { key: key.to_string(), fields }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("hdel", self.key);
+        for field in self.fields {
+            frame.push_bulk(field);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(HDel::new(&key, fields_from_frame(it)?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let hash = match db.get_hash_mut(&self.key) {
+            Ok(Some(hash)) => hash,
+            Ok(None) => return Frame::Integer(0),
+            Err(e) => return e.into(),
+        };
+        let removed = self.fields.iter().filter(|field| hash.remove(field).is_some()).count();
+        if removed > 0 {
+            db.notify(Events::HASH, "hdel", &self.key);
+        }
+        db.remove_if_empty(&self.key);
+        Frame::Integer(removed as i64)
+    }
+}
+
+/// `HINCRBY key field increment`
+#[derive(Debug)]
+pub struct HIncrBy {
+    key: String,
+    field: Bytes,
+    increment: i64,
+}
+
+impl HIncrBy {
+    pub fn new(key: &str, field: Bytes, increment: i64) -> Self {
+        HIncrBy { key: key.to_string(), field, increment }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("hincrby", self.key);
+        frame.push_bulk(self.field);
+        frame.push_bulk(Bytes::from(self.increment.to_string()));
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let field = it.next_bytes()?;
+        Ok(HIncrBy::new(&key, field, it.next_int()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let limits = db.config().hash_limits;
+        let hash = match db.hash_entry(&self.key) {
+            Ok(hash) => hash,
+            Err(e) => return e.into(),
+        };
+        let current = match hash.get(&self.field) {
+            Some(bs) => match std::str::from_utf8(bs).ok().and_then(|s| s.parse::<i64>().ok()) {
+                Some(n) => n,
+                None => return Frame::Error("ERR hash value is not an integer".to_string()),
+            },
+            None => 0,
+        };
+        let n = match current.checked_add(self.increment) {
+            Some(n) => n,
+            None => {
+                db.remove_if_empty(&self.key);
+                return Frame::Error("ERR increment or decrement would overflow".to_string());
+            }
+        };
+        hash.insert(self.field.clone(), Bytes::from(n.to_string()), &limits);
+        db.notify(Events::HASH, "hincrby", &self.key);
+        Frame::Integer(n)
+    }
+}
+
+/// `HINCRBYFLOAT key field increment`
+#[derive(Debug)]
+pub struct HIncrByFloat {
+    key: String,
+    field: Bytes,
+    increment: f64,
+}
+
+impl HIncrByFloat {
+    pub fn new(key: &str, field: Bytes, increment: f64) -> Self {
+        HIncrByFloat { key: key.to_string(), field, increment }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("hincrbyfloat", self.key);
+        frame.push_bulk(self.field);
+        frame.push_bulk(Bytes::from(self.increment.to_string()));
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let field = it.next_bytes()?;
+        Ok(HIncrByFloat::new(&key, field, it.next_float()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let limits = db.config().hash_limits;
+        let hash = match db.hash_entry(&self.key) {
+            Ok(hash) => hash,
+            Err(e) => return e.into(),
+        };
+        let current = match hash.get(&self.field) {
+            Some(bs) => match std::str::from_utf8(bs).ok().and_then(|s| s.parse::<f64>().ok()) {
+                Some(n) if n.is_finite() => n,
+                _ => return Frame::Error("ERR hash value is not a float".to_string()),
+            },
+            None => 0.0,
+        };
+        let n = current + self.increment;
+        if !n.is_finite() {
+            db.remove_if_empty(&self.key);
+            return Frame::Error("ERR increment would produce NaN or Infinity".to_string());
+        }
+        let value = Bytes::from(n.to_string());
+        hash.insert(self.field.clone(), value.clone(), &limits);
+        db.notify(Events::HASH, "hincrbyfloat", &self.key);
+        Frame::Bulk(value)
+    }
+}
+
+/// `HEXISTS key field`
+#[derive(Debug)]
+pub struct HExists {
+    key: String,
+    field: Bytes,
+}
+
+impl HExists {
+    pub fn new(key: &str, field: Bytes) -> Self {
+        HExists { key: key.to_string(), field }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("hexists", self.key);
+        frame.push_bulk(self.field);
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(HExists::new(&key, it.next_bytes()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.get_hash(&self.key) {
+            Ok(hash) => Frame::Integer(hash.is_some_and(|hash| hash.contains(&self.field)) as i64),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// `HKEYS key`
+#[derive(Debug)]
+pub struct HKeys {
+    key: String,
+}
+
+impl HKeys {
+    pub fn new(key: &str) -> Self {
+        HKeys { key: key.to_string() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        cmd_frame("hkeys", self.key)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(HKeys::new(&it.next_string()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.get_hash(&self.key) {
+            Ok(Some(hash)) => Frame::Array(hash.iter().map(|(f, _)| Frame::Bulk(f.clone())).collect()),
+            Ok(None) => Frame::Array(vec![]),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// `HVALS key`
+#[derive(Debug)]
+pub struct HVals {
+    key: String,
+}
+
+impl HVals {
+    pub fn new(key: &str) -> Self {
+        HVals { key: key.to_string() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        cmd_frame("hvals", self.key)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(HVals::new(&it.next_string()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.get_hash(&self.key) {
+            Ok(Some(hash)) => Frame::Array(hash.iter().map(|(_, v)| Frame::Bulk(v.clone())).collect()),
+            Ok(None) => Frame::Array(vec![]),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// `HLEN key`
+#[derive(Debug)]
+pub struct HLen {
+    key: String,
+}
+
+impl HLen {
+    pub fn new(key: &str) -> Self {
+        HLen { key: key.to_string() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        cmd_frame("hlen", self.key)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(HLen::new(&it.next_string()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.get_hash(&self.key) {
+            Ok(hash) => Frame::Integer(hash.map_or(0, |hash| hash.len() as i64)),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// `HRANDFIELD key [count [WITHVALUES]]`
+///
+/// A positive `count` returns distinct fields, as many as there are at
+/// most, a negative one returns `-count` fields that may repeat.
+#[derive(Debug)]
+pub struct HRandField {
+    key: String,
+    count: Option<i64>,
+    with_values: bool,
+}
+
+impl HRandField {
+    pub fn new(key: &str, count: Option<i64>, with_values: bool) -> Self {
+        HRandField { key: key.to_string(), count, with_values }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("hrandfield", self.key);
+        if let Some(count) = self.count {
+            frame.push_bulk(Bytes::from(count.to_string()));
+            if self.with_values {
+                frame.push_bulk(Bytes::from("withvalues"));
+            }
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        if !it.has_next() {
+            return Ok(HRandField::new(&key, None, false));
+        }
+        let count = random_count(it)?;
+        let with_values = if it.has_next() {
+            if it.next_string()?.to_lowercase() != "withvalues" {
+                return Err("syntax error".into());
+            }
+            true
+        } else {
+            false
+        };
+        Ok(HRandField::new(&key, Some(count), with_values))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let hash = match db.get_hash(&self.key) {
+            Ok(Some(hash)) => hash,
+            Ok(None) if self.count.is_some() => return Frame::Array(vec![]),
+            Ok(None) => return Frame::Null,
+            Err(e) => return e.into(),
+        };
+        let count = match self.count {
+            Some(count) => count,
+            None => return Frame::Bulk(hash.random().unwrap().0.clone()),
+        };
+
+        let picked: Vec<(&Bytes, &Bytes)> = if count < 0 {
+            (0..count.unsigned_abs()).map(|_| hash.random().unwrap()).collect()
+        } else {
+            let pairs: Vec<_> = hash.iter().collect();
+            let n = (count as usize).min(pairs.len());
+            index::sample(&mut rand::rng(), pairs.len(), n).into_iter().map(|i| pairs[i]).collect()
+        };
+        let mut frames = Vec::with_capacity(picked.len() * if self.with_values { 2 } else { 1 });
+        for (field, value) in picked {
+            frames.push(Frame::Bulk(field.clone()));
+            if self.with_values {
+                frames.push(Frame::Bulk(value.clone()));
+            }
+        }
+        Frame::Array(frames)
+    }
+}
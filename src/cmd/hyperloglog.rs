@@ -0,0 +1,193 @@
+//! HyperLogLog commands
+//!
+//! See `types::hyperloglog`. A HyperLogLog is a string, so `GET` and `SET`
+//! move it around like any other, and a string that is not one is refused.
+
+use bytes::Bytes;
+
+use crate::{
+    database::Database,
+    frame::{Error, Frame, Parse},
+    notify::Events,
+    types::hyperloglog::{self, HyperLogLog, Invalid, REGISTERS},
+};
+
+fn keys_frame(name: &'static str, keys: Vec<String>) -> Frame {
+    let mut frame = Frame::new_array_frame();
+    frame.push_bulk(Bytes::from(name));
+    for key in keys {
+        frame.push_bulk(Bytes::from(key));
+    }
+    frame
+}
+
+fn keys_from_frame(it: &mut dyn Parse) -> Result<Vec<String>, Error> {
+    let mut keys = vec![it.next_string()?];
+    while it.has_next() {
+        keys.push(it.next_string()?);
+    }
+    Ok(keys)
+}
+
+/// The HyperLogLog at `key`, the reply is the error to send
+fn load(db: &Database, key: &str) -> Result<Option<HyperLogLog>, Frame> {
+    let bs = match db.get(key) {
+        Ok(Some(bs)) => bs,
+        Ok(None) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    match HyperLogLog::from_bytes(&bs) {
+        Ok(hll) => Ok(Some(hll)),
+        Err(Invalid::NotHll) => Err(Frame::Error("WRONGTYPE Key is not a valid HyperLogLog string value.".to_string())),
+        Err(Invalid::Corrupted) => Err(Frame::Error("INVALIDOBJ Corrupted HLL object detected".to_string())),
+    }
+}
+
+/// `PFADD key [element ...]`, replies `1` if the estimated cardinality may
+/// have changed, which includes creating the key
+#[derive(Debug)]
+pub struct PfAdd {
+    key: String,
+    elements: Vec<Bytes>,
+}
+
+impl PfAdd {
+    pub fn new(key: &str, elements: Vec<Bytes>) -> Self {
+        PfAdd { key: key.to_string(), elements }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = keys_frame("pfadd", vec![self.key]);
+        for element in self.elements {
+            frame.push_bulk(element);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let mut elements = vec![];
+        while it.has_next() {
+            elements.push(it.next_bytes()?);
+        }
+        Ok(PfAdd { key, elements })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let (mut hll, created) = match load(db, &self.key) {
+            Ok(Some(hll)) => (hll, false),
+            Ok(None) => (HyperLogLog::default(), true),
+            Err(e) => return e,
+        };
+        let sparse_max = db.config().hll_sparse_max_bytes;
+        let changed = hll.add(self.elements.iter().map(|element| &element[..]), sparse_max);
+        if !(changed || created) {
+            return Frame::Integer(0);
+        }
+        db.update(self.key.clone(), hll.into_bytes());
+        db.notify(Events::STRING, "pfadd", &self.key);
+        Frame::Integer(1)
+    }
+}
+
+/// `PFCOUNT key [key ...]`, the estimated cardinality of the union of the
+/// HyperLogLogs, which are merged on the fly
+///
+/// With a single key the cardinality is cached in the value itself, until
+/// the next `PFADD` that changes it.
+#[derive(Debug)]
+pub struct PfCount {
+    keys: Vec<String>,
+}
+
+impl PfCount {
+    pub fn new(keys: &[&str]) -> Self {
+        PfCount { keys: keys.iter().map(|key| key.to_string()).collect() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        keys_frame("pfcount", self.keys)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(PfCount { keys: keys_from_frame(it)? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        if let [key] = &self.keys[..] {
+            let mut hll = match load(db, key) {
+                Ok(Some(hll)) => hll,
+                Ok(None) => return Frame::Integer(0),
+                Err(e) => return e,
+            };
+            if let Some(count) = hll.cached_count() {
+                return Frame::Integer(count as i64);
+            }
+            let count = hll.count();
+            db.update(key.clone(), hll.into_bytes());
+            return Frame::Integer(count as i64);
+        }
+
+        let mut registers = vec![0; REGISTERS];
+        for key in &self.keys {
+            match load(db, key) {
+                Ok(Some(hll)) => hll.merge_into(&mut registers),
+                Ok(None) => {}
+                Err(e) => return e,
+            }
+        }
+        Frame::Integer(hyperloglog::estimate(&registers) as i64)
+    }
+}
+
+/// `PFMERGE destkey [sourcekey ...]`, stores the union of the HyperLogLogs,
+/// `destkey` included if it exists
+///
+/// The result is dense if any of them is, sparse otherwise as long as it
+/// fits within `hll-sparse-max-bytes`.
+#[derive(Debug)]
+pub struct PfMerge {
+    dest: String,
+    keys: Vec<String>,
+}
+
+impl PfMerge {
+    pub fn new(dest: &str, keys: &[&str]) -> Self {
+        PfMerge { dest: dest.to_string(), keys: keys.iter().map(|key| key.to_string()).collect() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut keys = vec![self.dest];
+        keys.extend(self.keys);
+        keys_frame("pfmerge", keys)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let dest = it.next_string()?;
+        let mut keys = vec![];
+        while it.has_next() {
+            keys.push(it.next_string()?);
+        }
+        Ok(PfMerge { dest, keys })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let mut registers = vec![0; REGISTERS];
+        let mut dense = false;
+        for key in std::iter::once(&self.dest).chain(&self.keys) {
+            match load(db, key) {
+                Ok(Some(hll)) => {
+                    hll.merge_into(&mut registers);
+                    dense |= !hll.is_sparse();
+                }
+                Ok(None) => {}
+                Err(e) => return e,
+            }
+        }
+        let sparse_max = if dense { 0 } else { db.config().hll_sparse_max_bytes };
+        let hll = HyperLogLog::from_registers(&registers, sparse_max);
+        db.update(self.dest.clone(), hll.into_bytes());
+        db.notify(Events::STRING, "pfadd", &self.dest);
+        Frame::Simple("OK".to_string())
+    }
+}
@@ -0,0 +1,337 @@
+//! Generic key commands, they work on a key whatever the type of its value
+
+use bytes::Bytes;
+
+use crate::{
+    database::Database,
+    frame::{Error, Frame, Parse},
+    notify::Events,
+};
+
+fn keys_frame(name: &'static str, keys: Vec<String>) -> Frame {
+    let mut frame = Frame::new_array_frame();
+    frame.push_bulk(Bytes::from(name));
+    for key in keys {
+        frame.push_bulk(Bytes::from(key));
+    }
+    frame
+}
+
+/// one or more keys, as taken by `DEL`, `EXISTS` and the like
+fn keys_from_frame(it: &mut dyn Parse) -> Result<Vec<String>, Error> {
+    let mut keys = vec![it.next_string()?];
+    while it.has_next() {
+        keys.push(it.next_string()?);
+    }
+    Ok(keys)
+}
+
+/// `DEL key [key ...]`, replies with the number of keys removed
+#[derive(Debug)]
+pub struct Del {
+    keys: Vec<String>,
+}
+
+impl Del {
+    pub fn new(keys: &[&str]) -> Self {
+        Del { keys: keys.iter().map(|key| key.to_string()).collect() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        keys_frame("del", self.keys)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(Del { keys: keys_from_frame(it)? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let mut removed = 0;
+        for key in self.keys.iter() {
+            if db.remove(key).is_some() {
+                db.notify(Events::GENERIC, "del", key);
+                removed += 1;
+            }
+        }
+        Frame::Integer(removed)
+    }
+}
+
+/// `UNLINK key [key ...]`, a `DEL` that frees large values in the background
+#[derive(Debug)]
+pub struct Unlink {
+    keys: Vec<String>,
+}
+
+impl Unlink {
+    pub fn new(keys: &[&str]) -> Self {
+        Unlink { keys: keys.iter().map(|key| key.to_string()).collect() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        keys_frame("unlink", self.keys)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(Unlink { keys: keys_from_frame(it)? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let mut removed = 0;
+        for key in self.keys.iter() {
+            if db.unlink(key) {
+                db.notify(Events::GENERIC, "del", key);
+                removed += 1;
+            }
+        }
+        Frame::Integer(removed)
+    }
+}
+
+/// `EXISTS key [key ...]`, a key given twice is counted twice
+#[derive(Debug)]
+pub struct Exists {
+    keys: Vec<String>,
+}
+
+impl Exists {
+    pub fn new(keys: &[&str]) -> Self {
+        Exists { keys: keys.iter().map(|key| key.to_string()).collect() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        keys_frame("exists", self.keys)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(Exists { keys: keys_from_frame(it)? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let found = self.keys.iter().filter(|key| db.contains(key)).count();
+        Frame::Integer(found as i64)
+    }
+}
+
+/// `TOUCH key [key ...]`, replies with the number of keys that exist
+#[derive(Debug)]
+pub struct Touch {
+    keys: Vec<String>,
+}
+
+impl Touch {
+    pub fn new(keys: &[&str]) -> Self {
+        Touch { keys: keys.iter().map(|key| key.to_string()).collect() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        keys_frame("touch", self.keys)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(Touch { keys: keys_from_frame(it)? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        // there is no access time to update yet, so this is `EXISTS`
+        // without counting duplicates twice
+        let mut keys: Vec<&String> = self.keys.iter().collect();
+        keys.sort();
+        keys.dedup();
+        let found = keys.into_iter().filter(|key| db.contains(key)).count();
+        Frame::Integer(found as i64)
+    }
+}
+
+/// `TYPE key`, `none` for a missing key
+#[derive(Debug)]
+pub struct Type {
+    key: String,
+}
+
+impl Type {
+    pub fn new(key: &str) -> Self {
+        Type { key: key.to_string() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        keys_frame("type", vec![self.key])
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(Type::new(&it.next_string()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let name = db.get_value(&self.key).map_or("none", |value| value.type_name());
+        Frame::Simple(name.to_string())
+    }
+}
+
+/// `RENAME key newkey`, the value keeps its time to live
+#[derive(Debug)]
+pub struct Rename {
+    key: String,
+    newkey: String,
+}
+
+impl Rename {
+    pub fn new(key: &str, newkey: &str) -> Self {
+        Rename { key: key.to_string(), newkey: newkey.to_string() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        keys_frame("rename", vec![self.key, self.newkey])
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(Rename::new(&key, &it.next_string()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        if !db.contains(&self.key) {
+            return no_such_key();
+        }
+        if self.key != self.newkey {
+            let (value, expires_at) = db.take(&self.key).unwrap();
+            // whatever was at `newkey` is replaced, its time to live included
+            db.insert(self.newkey.clone(), value, expires_at);
+            renamed(db, &self.key, &self.newkey);
+        }
+        Frame::Simple("OK".to_string())
+    }
+}
+
+/// `RENAMENX key newkey`, a `RENAME` only if `newkey` does not exist
+#[derive(Debug)]
+pub struct RenameNx {
+    key: String,
+    newkey: String,
+}
+
+impl RenameNx {
+    pub fn new(key: &str, newkey: &str) -> Self {
+        RenameNx { key: key.to_string(), newkey: newkey.to_string() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        keys_frame("renamenx", vec![self.key, self.newkey])
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(RenameNx::new(&key, &it.next_string()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        if !db.contains(&self.key) {
+            return no_such_key();
+        }
+        // renaming a key to itself counts as `newkey` existing
+        if db.contains(&self.newkey) {
+            return Frame::Integer(0);
+        }
+        let (value, expires_at) = db.take(&self.key).unwrap();
+        db.insert(self.newkey.clone(), value, expires_at);
+        renamed(db, &self.key, &self.newkey);
+        Frame::Integer(1)
+    }
+}
+
+/// `COPY source destination [DB destination-db] [REPLACE]`
+///
+/// There is a single database, so `DB` only accepts `0`.
+#[derive(Debug)]
+pub struct Copy {
+    source: String,
+    destination: String,
+    replace: bool,
+}
+
+impl Copy {
+    pub fn new(source: &str, destination: &str, replace: bool) -> Self {
+        Copy {
+            source: source.to_string(),
+            destination: destination.to_string(),
+            replace,
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = keys_frame("copy", vec![self.source, self.destination]);
+        if self.replace {
+            frame.push_bulk(Bytes::from("replace"));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let source = it.next_string()?;
+        let destination = it.next_string()?;
+        let mut replace = false;
+        while it.has_next() {
+            match it.next_string()?.to_lowercase().as_str() {
+                "replace" => replace = true,
+                "db" => {
+                    if it.next_int()? != 0 {
+                        return Err("DB index is out of range".into());
+                    }
+                }
+                _ => return Err("syntax error".into()),
+            }
+        }
+        Ok(Copy::new(&source, &destination, replace))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        if self.source == self.destination {
+            return Frame::Error("ERR source and destination objects are the same".to_string());
+        }
+        let value = match db.get_value(&self.source) {
+            Some(value) => value.clone(),
+            None => return Frame::Integer(0),
+        };
+        if !self.replace && db.contains(&self.destination) {
+            return Frame::Integer(0);
+        }
+        let expires_at = db.expires_at(&self.source);
+        db.insert(self.destination.clone(), value, expires_at);
+        db.notify(Events::GENERIC, "copy_to", &self.destination);
+        Frame::Integer(1)
+    }
+}
+
+/// `RANDOMKEY`, a nil reply if the database is empty
+#[derive(Debug, Default)]
+pub struct RandomKey;
+
+impl RandomKey {
+    pub fn new() -> Self {
+        RandomKey
+    }
+
+    pub fn into_frame(self) -> Frame {
+        keys_frame("randomkey", vec![])
+    }
+
+    pub(super) fn from_frame(_it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(RandomKey)
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.random_key() {
+            Some(key) => Frame::Bulk(Bytes::from(key)),
+            None => Frame::Null,
+        }
+    }
+}
+
+fn renamed(db: &mut Database, key: &str, newkey: &str) {
+    db.notify(Events::GENERIC, "rename_from", key);
+    db.notify(Events::GENERIC, "rename_to", newkey);
+}
+
+fn no_such_key() -> Frame {
+    Frame::Error("ERR no such key".to_string())
+}
@@ -0,0 +1,744 @@
+//! List commands
+//!
+//! A list is a `VecDeque`, pushing and popping at either end is O(1) and
+//! indexing is O(1) too. Indexes may be negative and count from the tail,
+//! `-1` is the last element. A list that becomes empty is removed.
+
+use std::collections::VecDeque;
+
+use bytes::Bytes;
+
+use super::normalize_range;
+use crate::{
+    database::Database,
+    frame::{Error, Frame, Parse},
+    notify::Events,
+};
+
+/// One end of a list, `LEFT` is the head
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum End {
+    Left,
+    Right,
+}
+
+impl End {
+    pub(super) fn as_str(&self) -> &'static str {
+        match self {
+            End::Left => "left",
+            End::Right => "right",
+        }
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        match it.next_string()?.to_lowercase().as_str() {
+            "left" => Ok(End::Left),
+            "right" => Ok(End::Right),
+            _ => Err("syntax error".into()),
+        }
+    }
+
+    pub(super) fn push(&self, list: &mut VecDeque<Bytes>, element: Bytes) {
+        match self {
+            End::Left => list.push_front(element),
+            End::Right => list.push_back(element),
+        }
+    }
+
+    /// The keyspace event of a push to this end
+    pub(super) fn push_event(&self) -> &'static str {
+        match self {
+            End::Left => "lpush",
+            End::Right => "rpush",
+        }
+    }
+
+    /// The keyspace event of a pop from this end
+    pub(super) fn pop_event(&self) -> &'static str {
+        match self {
+            End::Left => "lpop",
+            End::Right => "rpop",
+        }
+    }
+
+    pub(super) fn pop(&self, list: &mut VecDeque<Bytes>) -> Option<Bytes> {
+        match self {
+            End::Left => list.pop_front(),
+            End::Right => list.pop_back(),
+        }
+    }
+}
+
+fn cmd_frame(name: &'static str, key: String) -> Frame {
+    let mut frame = Frame::new_array_frame();
+    frame.push_bulk(Bytes::from(name));
+    frame.push_bulk(Bytes::from(key));
+    frame
+}
+
+fn int_bulk(n: i64) -> Bytes {
+    Bytes::from(n.to_string())
+}
+
+/// A negative index counts from the tail, `None` if it is out of range
+fn normalize_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+fn push(db: &mut Database, key: &str, elements: &[Bytes], end: End) -> Frame {
+    let list = match db.list_entry(key) {
+        Ok(list) => list,
+        Err(e) => return e.into(),
+    };
+    for element in elements {
+        end.push(list, element.clone());
+    }
+    let len = list.len();
+    db.notify(Events::LIST, end.push_event(), key);
+    Frame::Integer(len as i64)
+}
+
+fn elements_from_frame(it: &mut dyn Parse) -> Result<Vec<Bytes>, Error> {
+    let mut elements = vec![it.next_bytes()?];
+    while it.has_next() {
+        elements.push(it.next_bytes()?);
+    }
+    Ok(elements)
+}
+
+/// `LPUSH key element [element ...]`, elements are pushed one after the
+/// other, so they end up in reverse order at the head
+#[derive(Debug)]
+pub struct LPush {
+    key: String,
+    elements: Vec<Bytes>,
+}
+
+impl LPush {
+    pub fn new(key: &str, elements: Vec<Bytes>) -> Self {
+        LPush { key: key.to_string(), elements }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("lpush", self.key);
+        for element in self.elements {
+            frame.push_bulk(element);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(LPush::new(&key, elements_from_frame(it)?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        push(db, &self.key, &self.elements, End::Left)
+    }
+}
+
+/// `RPUSH key element [element ...]`
+#[derive(Debug)]
+pub struct RPush {
+    key: String,
+    elements: Vec<Bytes>,
+}
+
+impl RPush {
+    pub fn new(key: &str, elements: Vec<Bytes>) -> Self {
+        RPush { key: key.to_string(), elements }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("rpush", self.key);
+        for element in self.elements {
+            frame.push_bulk(element);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(RPush::new(&key, elements_from_frame(it)?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        push(db, &self.key, &self.elements, End::Right)
+    }
+}
+
+/// Without `count` the reply is a single element, with it an array, and
+/// nil of the same kind for a missing key
+fn pop(db: &mut Database, key: &str, count: Option<u64>, end: End) -> Frame {
+    let list = match db.get_list_mut(key) {
+        Ok(Some(list)) => list,
+        Ok(None) if count.is_some() => return Frame::NullArray,
+        Ok(None) => return Frame::Null,
+        Err(e) => return e.into(),
+    };
+    let reply = match count {
+        None => end.pop(list).map_or(Frame::Null, Frame::Bulk),
+        Some(count) => {
+            let n = (count as usize).min(list.len());
+            Frame::Array((0..n).filter_map(|_| end.pop(list)).map(Frame::Bulk).collect())
+        }
+    };
+    // the list exists, so it is empty only for a count of 0
+    if count != Some(0) {
+        db.notify(Events::LIST, end.pop_event(), key);
+    }
+    db.remove_if_empty(key);
+    reply
+}
+
+fn count_from_frame(it: &mut dyn Parse) -> Result<Option<u64>, Error> {
+    if !it.has_next() {
+        return Ok(None);
+    }
+    match it.next_int()? {
+        n if n >= 0 => Ok(Some(n as u64)),
+        _ => Err("value is out of range, must be positive".into()),
+    }
+}
+
+/// `LPOP key [count]`
+#[derive(Debug)]
+pub struct LPop {
+    key: String,
+    count: Option<u64>,
+}
+
+impl LPop {
+    pub fn new(key: &str, count: Option<u64>) -> Self {
+        LPop { key: key.to_string(), count }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("lpop", self.key);
+        if let Some(count) = self.count {
+            frame.push_bulk(Bytes::from(count.to_string()));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(LPop::new(&key, count_from_frame(it)?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        pop(db, &self.key, self.count, End::Left)
+    }
+}
+
+/// `RPOP key [count]`
+#[derive(Debug)]
+pub struct RPop {
+    key: String,
+    count: Option<u64>,
+}
+
+impl RPop {
+    pub fn new(key: &str, count: Option<u64>) -> Self {
+        RPop { key: key.to_string(), count }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("rpop", self.key);
+        if let Some(count) = self.count {
+            frame.push_bulk(Bytes::from(count.to_string()));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(RPop::new(&key, count_from_frame(it)?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        pop(db, &self.key, self.count, End::Right)
+    }
+}
+
+/// `LLEN key`
+#[derive(Debug)]
+pub struct LLen {
+    key: String,
+}
+
+impl LLen {
+    pub fn new(key: &str) -> Self {
+        LLen { key: key.to_string() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        cmd_frame("llen", self.key)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(LLen::new(&it.next_string()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.get_list(&self.key) {
+            Ok(list) => Frame::Integer(list.map_or(0, |list| list.len() as i64)),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// `LRANGE key start stop`, both ends inclusive
+#[derive(Debug)]
+pub struct LRange {
+    key: String,
+    start: i64,
+    stop: i64,
+}
+
+impl LRange {
+    pub fn new(key: &str, start: i64, stop: i64) -> Self {
+        LRange { key: key.to_string(), start, stop }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("lrange", self.key);
+        frame.push_bulk(int_bulk(self.start));
+        frame.push_bulk(int_bulk(self.stop));
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let start = it.next_int()?;
+        Ok(LRange::new(&key, start, it.next_int()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let list = match db.get_list(&self.key) {
+            Ok(Some(list)) => list,
+            Ok(None) => return Frame::Array(vec![]),
+            Err(e) => return e.into(),
+        };
+        match normalize_range(self.start, self.stop, list.len()) {
+            Some((start, end)) => Frame::Array(list.range(start..=end).cloned().map(Frame::Bulk).collect()),
+            None => Frame::Array(vec![]),
+        }
+    }
+}
+
+/// `LINDEX key index`
+#[derive(Debug)]
+pub struct LIndex {
+    key: String,
+    index: i64,
+}
+
+impl LIndex {
+    pub fn new(key: &str, index: i64) -> Self {
+        LIndex { key: key.to_string(), index }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("lindex", self.key);
+        frame.push_bulk(int_bulk(self.index));
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(LIndex::new(&key, it.next_int()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let list = match db.get_list(&self.key) {
+            Ok(Some(list)) => list,
+            Ok(None) => return Frame::Null,
+            Err(e) => return e.into(),
+        };
+        match normalize_index(self.index, list.len()) {
+            Some(i) => Frame::Bulk(list[i].clone()),
+            None => Frame::Null,
+        }
+    }
+}
+
+/// `LSET key index element`
+#[derive(Debug)]
+pub struct LSet {
+    key: String,
+    index: i64,
+    element: Bytes,
+}
+
+impl LSet {
+    pub fn new(key: &str, index: i64, element: Bytes) -> Self {
+        LSet { key: key.to_string(), index, element }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("lset", self.key);
+        frame.push_bulk(int_bulk(self.index));
+        frame.push_bulk(self.element);
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let index = it.next_int()?;
+        Ok(LSet::new(&key, index, it.next_bytes()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let list = match db.get_list_mut(&self.key) {
+            Ok(Some(list)) => list,
+            Ok(None) => return Frame::Error("ERR no such key".to_string()),
+            Err(e) => return e.into(),
+        };
+        match normalize_index(self.index, list.len()) {
+            Some(i) => {
+                list[i] = self.element.clone();
+                db.notify(Events::LIST, "lset", &self.key);
+                Frame::Simple("OK".to_string())
+            }
+            None => Frame::Error("ERR index out of range".to_string()),
+        }
+    }
+}
+
+/// `LREM key count element`, removes `count` occurrences from the head,
+/// from the tail if `count` is negative, or all of them if it is `0`
+#[derive(Debug)]
+pub struct LRem {
+    key: String,
+    count: i64,
+    element: Bytes,
+}
+
+impl LRem {
+    pub fn new(key: &str, count: i64, element: Bytes) -> Self {
+        LRem { key: key.to_string(), count, element }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("lrem", self.key);
+        frame.push_bulk(int_bulk(self.count));
+        frame.push_bulk(self.element);
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let count = it.next_int()?;
+        Ok(LRem::new(&key, count, it.next_bytes()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let list = match db.get_list_mut(&self.key) {
+            Ok(Some(list)) => list,
+            Ok(None) => return Frame::Integer(0),
+            Err(e) => return e.into(),
+        };
+        let limit = if self.count == 0 { usize::MAX } else { self.count.unsigned_abs() as usize };
+        let matches: Vec<usize> = if self.count < 0 {
+            let mut matches: Vec<usize> = (0..list.len()).rev().filter(|&i| list[i] == self.element).take(limit).collect();
+            matches.reverse();
+            matches
+        } else {
+            (0..list.len()).filter(|&i| list[i] == self.element).take(limit).collect()
+        };
+
+        // keep the elements in place, one pass instead of a `remove` per match
+        let mut matches = matches.iter().peekable();
+        let mut i = 0;
+        list.retain(|_| {
+            let keep = matches.next_if_eq(&&i).is_none();
+            i += 1;
+            keep
+        });
+        let removed = i - list.len();
+        if removed > 0 {
+            db.notify(Events::LIST, "lrem", &self.key);
+        }
+        db.remove_if_empty(&self.key);
+        Frame::Integer(removed as i64)
+    }
+}
+
+/// `LTRIM key start stop`, keep only the elements in the range
+#[derive(Debug)]
+pub struct LTrim {
+    key: String,
+    start: i64,
+    stop: i64,
+}
+
+impl LTrim {
+    pub fn new(key: &str, start: i64, stop: i64) -> Self {
+        LTrim { key: key.to_string(), start, stop }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("ltrim", self.key);
+        frame.push_bulk(int_bulk(self.start));
+        frame.push_bulk(int_bulk(self.stop));
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let start = it.next_int()?;
+        Ok(LTrim::new(&key, start, it.next_int()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let list = match db.get_list_mut(&self.key) {
+            Ok(Some(list)) => list,
+            Ok(None) => return Frame::Simple("OK".to_string()),
+            Err(e) => return e.into(),
+        };
+        match normalize_range(self.start, self.stop, list.len()) {
+            Some((start, end)) => {
+                list.truncate(end + 1);
+                list.drain(..start);
+            }
+            None => list.clear(),
+        }
+        db.notify(Events::LIST, "ltrim", &self.key);
+        db.remove_if_empty(&self.key);
+        Frame::Simple("OK".to_string())
+    }
+}
+
+/// `LINSERT key BEFORE|AFTER pivot element`
+#[derive(Debug)]
+pub struct LInsert {
+    key: String,
+    before: bool,
+    pivot: Bytes,
+    element: Bytes,
+}
+
+impl LInsert {
+    pub fn new(key: &str, before: bool, pivot: Bytes, element: Bytes) -> Self {
+        LInsert { key: key.to_string(), before, pivot, element }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("linsert", self.key);
+        frame.push_bulk(Bytes::from(if self.before { "before" } else { "after" }));
+        frame.push_bulk(self.pivot);
+        frame.push_bulk(self.element);
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let before = match it.next_string()?.to_lowercase().as_str() {
+            "before" => true,
+            "after" => false,
+            _ => return Err("syntax error".into()),
+        };
+        let pivot = it.next_bytes()?;
+        Ok(LInsert::new(&key, before, pivot, it.next_bytes()?))
+    }
+
+    /// The new length, `-1` if there is no `pivot` and `0` if there is no list
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let list = match db.get_list_mut(&self.key) {
+            Ok(Some(list)) => list,
+            Ok(None) => return Frame::Integer(0),
+            Err(e) => return e.into(),
+        };
+        match list.iter().position(|element| *element == self.pivot) {
+            Some(i) => {
+                list.insert(if self.before { i } else { i + 1 }, self.element.clone());
+                let len = list.len();
+                db.notify(Events::LIST, "linsert", &self.key);
+                Frame::Integer(len as i64)
+            }
+            None => Frame::Integer(-1),
+        }
+    }
+}
+
+/// `LPOS key element [RANK rank] [COUNT num-matches] [MAXLEN len]`
+#[derive(Debug)]
+pub struct LPos {
+    key: String,
+    element: Bytes,
+    rank: i64,
+    count: Option<u64>,
+    maxlen: u64,
+}
+
+impl LPos {
+    pub fn new(key: &str, element: Bytes) -> Self {
+        LPos { key: key.to_string(), element, rank: 1, count: None, maxlen: 0 }
+    }
+
+    /// Skip the first `rank - 1` matches, a negative rank searches from the tail
+    pub fn rank(mut self, rank: i64) -> Self {
+        self.rank = rank;
+        self
+    }
+
+    /// Reply with up to `count` positions, `0` for all of them
+    pub fn count(mut self, count: u64) -> Self {
+        self.count = Some(count);
+        self
+    }
+
+    /// Compare at most `maxlen` elements, `0` for no limit
+    pub fn maxlen(mut self, maxlen: u64) -> Self {
+        self.maxlen = maxlen;
+        self
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("lpos", self.key);
+        frame.push_bulk(self.element);
+        if self.rank != 1 {
+            frame.push_bulk(Bytes::from("rank"));
+            frame.push_bulk(int_bulk(self.rank));
+        }
+        if let Some(count) = self.count {
+            frame.push_bulk(Bytes::from("count"));
+            frame.push_bulk(Bytes::from(count.to_string()));
+        }
+        if self.maxlen != 0 {
+            frame.push_bulk(Bytes::from("maxlen"));
+            frame.push_bulk(Bytes::from(self.maxlen.to_string()));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let mut lpos = LPos::new(&key, it.next_bytes()?);
+        while it.has_next() {
+            match it.next_string()?.to_lowercase().as_str() {
+                "rank" => match it.next_int()? {
+                    0 => return Err("RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the last match".into()),
+                    // `-i64::MIN` would overflow
+                    i64::MIN => return Err("value is out of range".into()),
+                    rank => lpos.rank = rank,
+                },
+                "count" => match it.next_int()? {
+                    n if n >= 0 => lpos.count = Some(n as u64),
+                    _ => return Err("COUNT can't be negative".into()),
+                },
+                "maxlen" => match it.next_int()? {
+                    n if n >= 0 => lpos.maxlen = n as u64,
+                    _ => return Err("MAXLEN can't be negative".into()),
+                },
+                _ => return Err("syntax error".into()),
+            }
+        }
+        Ok(lpos)
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let list = match db.get_list(&self.key) {
+            Ok(Some(list)) => list,
+            Ok(None) if self.count.is_some() => return Frame::Array(vec![]),
+            Ok(None) => return Frame::Null,
+            Err(e) => return e.into(),
+        };
+
+        let maxlen = if self.maxlen == 0 { list.len() } else { (self.maxlen as usize).min(list.len()) };
+        let indexes: Box<dyn Iterator<Item = usize>> = if self.rank > 0 {
+            Box::new(0..maxlen)
+        } else {
+            Box::new((list.len() - maxlen..list.len()).rev())
+        };
+        let wanted = match self.count {
+            None => 1,
+            Some(0) => usize::MAX,
+            Some(count) => count as usize,
+        };
+        let found: Vec<Frame> = indexes
+            .filter(|&i| list[i] == self.element)
+            .skip(self.rank.unsigned_abs() as usize - 1)
+            .take(wanted)
+            .map(|i| Frame::Integer(i as i64))
+            .collect();
+
+        match self.count {
+            Some(_) => Frame::Array(found),
+            None => found.into_iter().next().unwrap_or(Frame::Null),
+        }
+    }
+}
+
+/// `LMOVE source destination LEFT|RIGHT LEFT|RIGHT`, pop an element from
+/// one end of `source` and push it to one end of `destination`
+#[derive(Debug)]
+pub struct LMove {
+    source: String,
+    destination: String,
+    from: End,
+    to: End,
+}
+
+impl LMove {
+    pub fn new(source: &str, destination: &str, from: End, to: End) -> Self {
+        LMove {
+            source: source.to_string(),
+            destination: destination.to_string(),
+            from,
+            to,
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("lmove", self.source);
+        frame.push_bulk(Bytes::from(self.destination));
+        frame.push_bulk(Bytes::from(self.from.as_str()));
+        frame.push_bulk(Bytes::from(self.to.as_str()));
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let source = it.next_string()?;
+        let destination = it.next_string()?;
+        let from = End::from_frame(it)?;
+        Ok(LMove::new(&source, &destination, from, End::from_frame(it)?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        lmove(db, &self.source, &self.destination, self.from, self.to)
+    }
+}
+
+/// Also used by `BLMOVE` once `source` has an element
+pub(super) fn lmove(db: &mut Database, source: &str, destination: &str, from: End, to: End) -> Frame {
+    // both types are checked before anything is popped
+    if let Err(e) = db.get_list(destination) {
+        return e.into();
+    }
+    let element = match db.get_list_mut(source) {
+        Ok(Some(list)) => from.pop(list).unwrap(),
+        Ok(None) => return Frame::Null,
+        Err(e) => return e.into(),
+    };
+    db.notify(Events::LIST, from.pop_event(), source);
+    db.remove_if_empty(source);
+    to.push(db.list_entry(destination).unwrap(), element.clone());
+    db.notify(Events::LIST, to.push_event(), destination);
+    Frame::Bulk(element)
+}
+
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[test]
+fn test_normalize_index() {
+    assert_eq!(normalize_index(0, 3), Some(0));
+    assert_eq!(normalize_index(-1, 3), Some(2));
+    assert_eq!(normalize_index(-3, 3), Some(0));
+    assert_eq!(normalize_index(-4, 3), None);
+    assert_eq!(normalize_index(3, 3), None);
+    assert_eq!(normalize_index(0, 0), None);
+}
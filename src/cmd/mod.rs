@@ -1,10 +1,64 @@
 mod get;
+use std::time::Duration;
+
 use bytes::Bytes;
-pub use get::Get;
+pub use get::{Expiry, Get, GetDel, GetEx, GetSet, MGet};
 mod set;
-pub use set::Set;
+pub use set::{MSet, MSetNx, Set};
+mod string;
+pub use string::{Append, Decr, DecrBy, GetRange, Incr, IncrBy, IncrByFloat, SetRange, Strlen};
+mod bitmap;
+pub use bitmap::{BitCount, BitField, BitOp, BitPos, BitType, Bitwise, GetBit, Overflow, SetBit};
+mod hyperloglog;
+pub use hyperloglog::{PfAdd, PfCount, PfMerge};
+mod keyspace;
+pub use keyspace::{Copy, Del, Exists, RandomKey, Rename, RenameNx, Touch, Type, Unlink};
+mod scan;
+pub use scan::{HScan, Keys, SScan, Scan, ZScan};
+pub(crate) use scan::glob_match;
+mod list;
+pub use list::{End, LIndex, LInsert, LLen, LMove, LPop, LPos, LPush, LRange, LRem, LSet, LTrim, RPop, RPush};
+mod blocking;
+pub use blocking::{BLMPop, BLMove, BLPop, BRPop};
+mod hash;
+pub use hash::{HDel, HExists, HGet, HGetAll, HIncrBy, HIncrByFloat, HKeys, HLen, HMGet, HRandField, HSet, HSetNx, HVals};
+mod sets;
+pub use sets::{
+    SAdd, SCard, SDiff, SDiffStore, SInter, SInterCard, SInterStore, SIsMember, SMIsMember, SMembers, SPop, SRandMember,
+    SRem, SUnion, SUnionStore,
+};
+mod zset;
+pub use zset::{
+    Aggregate, ZAdd, ZCard, ZCount, ZIncrBy, ZLexCount, ZMScore, ZPop, ZRange, ZRangeBy, ZRank, ZRem, ZRemRange, ZScore,
+    ZStore,
+};
+mod geo;
+pub use geo::{GeoAdd, GeoBy, GeoDist, GeoFrom, GeoHash, GeoPos, GeoSearch, GeoSearchStore, Order, Unit};
+mod stream;
+pub use stream::{
+    ReadFrom, XAck, XAdd, XAutoClaim, XClaim, XDel, XGroup, XLen, XPending, XRange, XRead, XReadGroup, XTrim,
+};
+mod config;
+pub use config::Config;
+mod pubsub;
+pub use pubsub::{
+    Hello, PSubscribe, PUnsubscribe, Ping, PubSub, Publish, Quit, SPublish, SSubscribe, SUnsubscribe, Subscribe,
+    Unsubscribe,
+};
+mod transaction;
+pub use transaction::{Discard, Exec, Multi, Unwatch, Watch};
+mod script;
+pub use script::{Eval, EvalSha, Script};
+mod function;
+pub use function::{FCall, Function, RestorePolicy};
 
-use crate::frame::{Frame, Parse};
+use crate::{
+    connection::Connection,
+    database::Database,
+    frame::{Frame, Parse},
+    pubsub::Subscriptions,
+    script::Scripts,
+};
 
 // #[derive(Debug)]
 // pub enum Error {
@@ -14,6 +68,9 @@ use crate::frame::{Frame, Parse};
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
 
+/// same limit as `proto-max-bulk-len` in redis, no command grows a string beyond it
+pub(crate) const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
 /// unify the command type
 /// Why?
 /// because we do not know what commands it is before parsing a frame
@@ -23,17 +80,621 @@ type Error = Box<dyn std::error::Error + Send + Sync>;
 pub enum Request {
     Get(Get),
     Set(Set),
+    Incr(Incr),
+    Decr(Decr),
+    IncrBy(IncrBy),
+    DecrBy(DecrBy),
+    IncrByFloat(IncrByFloat),
+    Append(Append),
+    Strlen(Strlen),
+    GetRange(GetRange),
+    SetRange(SetRange),
+    SetBit(SetBit),
+    GetBit(GetBit),
+    BitCount(BitCount),
+    BitPos(BitPos),
+    BitOp(BitOp),
+    BitField(BitField),
+    PfAdd(PfAdd),
+    PfCount(PfCount),
+    PfMerge(PfMerge),
+    MGet(MGet),
+    MSet(MSet),
+    MSetNx(MSetNx),
+    GetSet(GetSet),
+    GetDel(GetDel),
+    GetEx(GetEx),
+    Del(Del),
+    Unlink(Unlink),
+    Exists(Exists),
+    Type(Type),
+    Rename(Rename),
+    RenameNx(RenameNx),
+    Copy(Copy),
+    Touch(Touch),
+    RandomKey(RandomKey),
+    Scan(Scan),
+    HScan(HScan),
+    SScan(SScan),
+    ZScan(ZScan),
+    Keys(Keys),
+    LPush(LPush),
+    RPush(RPush),
+    LPop(LPop),
+    RPop(RPop),
+    LLen(LLen),
+    LRange(LRange),
+    LIndex(LIndex),
+    LSet(LSet),
+    LRem(LRem),
+    LTrim(LTrim),
+    LInsert(LInsert),
+    LPos(LPos),
+    LMove(LMove),
+    BLPop(BLPop),
+    BRPop(BRPop),
+    BLMove(BLMove),
+    BLMPop(BLMPop),
+    HSet(HSet),
+    HSetNx(HSetNx),
+    HGet(HGet),
+    HMGet(HMGet),
+    HGetAll(HGetAll),
+    HDel(HDel),
+    HIncrBy(HIncrBy),
+    HIncrByFloat(HIncrByFloat),
+    HExists(HExists),
+    HKeys(HKeys),
+    HVals(HVals),
+    HLen(HLen),
+    HRandField(HRandField),
+    SAdd(SAdd),
+    SRem(SRem),
+    SMembers(SMembers),
+    SIsMember(SIsMember),
+    SMIsMember(SMIsMember),
+    SCard(SCard),
+    SPop(SPop),
+    SRandMember(SRandMember),
+    SInter(SInter),
+    SInterStore(SInterStore),
+    SInterCard(SInterCard),
+    SUnion(SUnion),
+    SUnionStore(SUnionStore),
+    SDiff(SDiff),
+    SDiffStore(SDiffStore),
+    ZAdd(ZAdd),
+    ZIncrBy(ZIncrBy),
+    ZRem(ZRem),
+    ZCard(ZCard),
+    ZScore(ZScore),
+    ZMScore(ZMScore),
+    ZRank(ZRank),
+    ZCount(ZCount),
+    ZLexCount(ZLexCount),
+    ZRange(ZRange),
+    ZRemRange(ZRemRange),
+    ZPop(ZPop),
+    ZStore(ZStore),
+    GeoAdd(GeoAdd),
+    GeoDist(GeoDist),
+    GeoPos(GeoPos),
+    GeoHash(GeoHash),
+    GeoSearch(GeoSearch),
+    GeoSearchStore(GeoSearchStore),
+    XAdd(XAdd),
+    XLen(XLen),
+    XRange(XRange),
+    XDel(XDel),
+    XTrim(XTrim),
+    XRead(XRead),
+    XReadGroup(XReadGroup),
+    XGroup(XGroup),
+    XAck(XAck),
+    XPending(XPending),
+    XClaim(XClaim),
+    XAutoClaim(XAutoClaim),
+    Subscribe(Subscribe),
+    Unsubscribe(Unsubscribe),
+    PSubscribe(PSubscribe),
+    PUnsubscribe(PUnsubscribe),
+    SSubscribe(SSubscribe),
+    SUnsubscribe(SUnsubscribe),
+    Publish(Publish),
+    SPublish(SPublish),
+    PubSub(PubSub),
+    Ping(Ping),
+    Quit(Quit),
+    Hello(Hello),
+    Config(Config),
+    Multi(Multi),
+    Exec(Exec),
+    Discard(Discard),
+    Watch(Watch),
+    Unwatch(Unwatch),
+    Eval(Eval),
+    EvalSha(EvalSha),
+    Script(Script),
+    Function(Function),
+    FCall(FCall),
 }
 
 impl Request {
     pub fn from_frame(frame: Frame) -> Result<Request, Error> {
-        let mut it = frame.into_iterator();
-        match it.next_string()?.to_lowercase() {
-            cmd if cmd == "get" => Ok(Request::Get(Get::from_frame(&mut it)?)),
-            cmd if cmd == "set" => Ok(Request::Set(Set::from_frame(&mut it)?)),
-            _ => panic!("unknown command"),
+        let mut it = match frame {
+            Frame::Array(_) => frame.into_iterator(),
+            _ => return Err("protocol error; expected an array of bulk strings".into()),
+        };
+        let name = it.next_string()?.to_lowercase();
+        let req = match name.as_str() {
+            "get" => Request::Get(Get::from_frame(&mut it)?),
+            "set" => Request::Set(Set::from_frame(&mut it)?),
+            "incr" => Request::Incr(Incr::from_frame(&mut it)?),
+            "decr" => Request::Decr(Decr::from_frame(&mut it)?),
+            "incrby" => Request::IncrBy(IncrBy::from_frame(&mut it)?),
+            "decrby" => Request::DecrBy(DecrBy::from_frame(&mut it)?),
+            "incrbyfloat" => Request::IncrByFloat(IncrByFloat::from_frame(&mut it)?),
+            "append" => Request::Append(Append::from_frame(&mut it)?),
+            "strlen" => Request::Strlen(Strlen::from_frame(&mut it)?),
+            "getrange" => Request::GetRange(GetRange::from_frame(&mut it)?),
+            "setrange" => Request::SetRange(SetRange::from_frame(&mut it)?),
+            "setbit" => Request::SetBit(SetBit::from_frame(&mut it)?),
+            "getbit" => Request::GetBit(GetBit::from_frame(&mut it)?),
+            "bitcount" => Request::BitCount(BitCount::from_frame(&mut it)?),
+            "bitpos" => Request::BitPos(BitPos::from_frame(&mut it)?),
+            "bitop" => Request::BitOp(BitOp::from_frame(&mut it)?),
+            "bitfield" => Request::BitField(BitField::from_frame(&mut it, false)?),
+            "bitfield_ro" => Request::BitField(BitField::from_frame(&mut it, true)?),
+            "pfadd" => Request::PfAdd(PfAdd::from_frame(&mut it)?),
+            "pfcount" => Request::PfCount(PfCount::from_frame(&mut it)?),
+            "pfmerge" => Request::PfMerge(PfMerge::from_frame(&mut it)?),
+            "mget" => Request::MGet(MGet::from_frame(&mut it)?),
+            "mset" => Request::MSet(MSet::from_frame(&mut it)?),
+            "msetnx" => Request::MSetNx(MSetNx::from_frame(&mut it)?),
+            "getset" => Request::GetSet(GetSet::from_frame(&mut it)?),
+            "getdel" => Request::GetDel(GetDel::from_frame(&mut it)?),
+            "getex" => Request::GetEx(GetEx::from_frame(&mut it)?),
+            "del" => Request::Del(Del::from_frame(&mut it)?),
+            "unlink" => Request::Unlink(Unlink::from_frame(&mut it)?),
+            "exists" => Request::Exists(Exists::from_frame(&mut it)?),
+            "type" => Request::Type(Type::from_frame(&mut it)?),
+            "rename" => Request::Rename(Rename::from_frame(&mut it)?),
+            "renamenx" => Request::RenameNx(RenameNx::from_frame(&mut it)?),
+            "copy" => Request::Copy(Copy::from_frame(&mut it)?),
+            "touch" => Request::Touch(Touch::from_frame(&mut it)?),
+            "randomkey" => Request::RandomKey(RandomKey::from_frame(&mut it)?),
+            "scan" => Request::Scan(Scan::from_frame(&mut it)?),
+            "hscan" => Request::HScan(HScan::from_frame(&mut it)?),
+            "sscan" => Request::SScan(SScan::from_frame(&mut it)?),
+            "zscan" => Request::ZScan(ZScan::from_frame(&mut it)?),
+            "keys" => Request::Keys(Keys::from_frame(&mut it)?),
+            "lpush" => Request::LPush(LPush::from_frame(&mut it)?),
+            "rpush" => Request::RPush(RPush::from_frame(&mut it)?),
+            "lpop" => Request::LPop(LPop::from_frame(&mut it)?),
+            "rpop" => Request::RPop(RPop::from_frame(&mut it)?),
+            "llen" => Request::LLen(LLen::from_frame(&mut it)?),
+            "lrange" => Request::LRange(LRange::from_frame(&mut it)?),
+            "lindex" => Request::LIndex(LIndex::from_frame(&mut it)?),
+            "lset" => Request::LSet(LSet::from_frame(&mut it)?),
+            "lrem" => Request::LRem(LRem::from_frame(&mut it)?),
+            "ltrim" => Request::LTrim(LTrim::from_frame(&mut it)?),
+            "linsert" => Request::LInsert(LInsert::from_frame(&mut it)?),
+            "lpos" => Request::LPos(LPos::from_frame(&mut it)?),
+            "lmove" => Request::LMove(LMove::from_frame(&mut it)?),
+            "blpop" => Request::BLPop(BLPop::from_frame(&mut it)?),
+            "brpop" => Request::BRPop(BRPop::from_frame(&mut it)?),
+            "blmove" => Request::BLMove(BLMove::from_frame(&mut it)?),
+            "blmpop" => Request::BLMPop(BLMPop::from_frame(&mut it)?),
+            "hset" => Request::HSet(HSet::from_frame(&mut it)?),
+            "hsetnx" => Request::HSetNx(HSetNx::from_frame(&mut it)?),
+            "hget" => Request::HGet(HGet::from_frame(&mut it)?),
+            "hmget" => Request::HMGet(HMGet::from_frame(&mut it)?),
+            "hgetall" => Request::HGetAll(HGetAll::from_frame(&mut it)?),
+            "hdel" => Request::HDel(HDel::from_frame(&mut it)?),
+            "hincrby" => Request::HIncrBy(HIncrBy::from_frame(&mut it)?),
+            "hincrbyfloat" => Request::HIncrByFloat(HIncrByFloat::from_frame(&mut it)?),
+            "hexists" => Request::HExists(HExists::from_frame(&mut it)?),
+            "hkeys" => Request::HKeys(HKeys::from_frame(&mut it)?),
+            "hvals" => Request::HVals(HVals::from_frame(&mut it)?),
+            "hlen" => Request::HLen(HLen::from_frame(&mut it)?),
+            "hrandfield" => Request::HRandField(HRandField::from_frame(&mut it)?),
+            "sadd" => Request::SAdd(SAdd::from_frame(&mut it)?),
+            "srem" => Request::SRem(SRem::from_frame(&mut it)?),
+            "smembers" => Request::SMembers(SMembers::from_frame(&mut it)?),
+            "sismember" => Request::SIsMember(SIsMember::from_frame(&mut it)?),
+            "smismember" => Request::SMIsMember(SMIsMember::from_frame(&mut it)?),
+            "scard" => Request::SCard(SCard::from_frame(&mut it)?),
+            "spop" => Request::SPop(SPop::from_frame(&mut it)?),
+            "srandmember" => Request::SRandMember(SRandMember::from_frame(&mut it)?),
+            "sinter" => Request::SInter(SInter::from_frame(&mut it)?),
+            "sinterstore" => Request::SInterStore(SInterStore::from_frame(&mut it)?),
+            "sintercard" => Request::SInterCard(SInterCard::from_frame(&mut it)?),
+            "sunion" => Request::SUnion(SUnion::from_frame(&mut it)?),
+            "sunionstore" => Request::SUnionStore(SUnionStore::from_frame(&mut it)?),
+            "sdiff" => Request::SDiff(SDiff::from_frame(&mut it)?),
+            "sdiffstore" => Request::SDiffStore(SDiffStore::from_frame(&mut it)?),
+            "zadd" => Request::ZAdd(ZAdd::from_frame(&mut it)?),
+            "zincrby" => Request::ZIncrBy(ZIncrBy::from_frame(&mut it)?),
+            "zrem" => Request::ZRem(ZRem::from_frame(&mut it)?),
+            "zcard" => Request::ZCard(ZCard::from_frame(&mut it)?),
+            "zscore" => Request::ZScore(ZScore::from_frame(&mut it)?),
+            "zmscore" => Request::ZMScore(ZMScore::from_frame(&mut it)?),
+            "zrank" => Request::ZRank(ZRank::from_frame(&mut it, false)?),
+            "zrevrank" => Request::ZRank(ZRank::from_frame(&mut it, true)?),
+            "zcount" => Request::ZCount(ZCount::from_frame(&mut it)?),
+            "zlexcount" => Request::ZLexCount(ZLexCount::from_frame(&mut it)?),
+            "zrange" => Request::ZRange(ZRange::from_frame(&mut it)?),
+            "zrevrange" => Request::ZRange(ZRange::from_rev_frame(&mut it)?),
+            "zrangebyscore" => Request::ZRange(ZRange::from_score_frame(&mut it, false)?),
+            "zrevrangebyscore" => Request::ZRange(ZRange::from_score_frame(&mut it, true)?),
+            "zrangebylex" => Request::ZRange(ZRange::from_lex_frame(&mut it, false)?),
+            "zrevrangebylex" => Request::ZRange(ZRange::from_lex_frame(&mut it, true)?),
+            "zremrangebyrank" => Request::ZRemRange(ZRemRange::from_frame(&mut it, "rank")?),
+            "zremrangebyscore" => Request::ZRemRange(ZRemRange::from_frame(&mut it, "score")?),
+            "zremrangebylex" => Request::ZRemRange(ZRemRange::from_frame(&mut it, "lex")?),
+            "zpopmin" => Request::ZPop(ZPop::from_frame(&mut it, false)?),
+            "zpopmax" => Request::ZPop(ZPop::from_frame(&mut it, true)?),
+            "zunionstore" => Request::ZStore(ZStore::from_frame(&mut it, false)?),
+            "zinterstore" => Request::ZStore(ZStore::from_frame(&mut it, true)?),
+            "geoadd" => Request::GeoAdd(GeoAdd::from_frame(&mut it)?),
+            "geodist" => Request::GeoDist(GeoDist::from_frame(&mut it)?),
+            "geopos" => Request::GeoPos(GeoPos::from_frame(&mut it)?),
+            "geohash" => Request::GeoHash(GeoHash::from_frame(&mut it)?),
+            "geosearch" => Request::GeoSearch(GeoSearch::from_frame(&mut it)?),
+            "geosearchstore" => Request::GeoSearchStore(GeoSearchStore::from_frame(&mut it)?),
+            "xadd" => Request::XAdd(XAdd::from_frame(&mut it)?),
+            "xlen" => Request::XLen(XLen::from_frame(&mut it)?),
+            "xrange" => Request::XRange(XRange::from_frame(&mut it, false)?),
+            "xrevrange" => Request::XRange(XRange::from_frame(&mut it, true)?),
+            "xdel" => Request::XDel(XDel::from_frame(&mut it)?),
+            "xtrim" => Request::XTrim(XTrim::from_frame(&mut it)?),
+            "xread" => Request::XRead(XRead::from_frame(&mut it)?),
+            "xreadgroup" => Request::XReadGroup(XReadGroup::from_frame(&mut it)?),
+            "xgroup" => Request::XGroup(XGroup::from_frame(&mut it)?),
+            "xack" => Request::XAck(XAck::from_frame(&mut it)?),
+            "xpending" => Request::XPending(XPending::from_frame(&mut it)?),
+            "xclaim" => Request::XClaim(XClaim::from_frame(&mut it)?),
+            "xautoclaim" => Request::XAutoClaim(XAutoClaim::from_frame(&mut it)?),
+            "subscribe" => Request::Subscribe(Subscribe::from_frame(&mut it)?),
+            "unsubscribe" => Request::Unsubscribe(Unsubscribe::from_frame(&mut it)?),
+            "psubscribe" => Request::PSubscribe(PSubscribe::from_frame(&mut it)?),
+            "punsubscribe" => Request::PUnsubscribe(PUnsubscribe::from_frame(&mut it)?),
+            "ssubscribe" => Request::SSubscribe(SSubscribe::from_frame(&mut it)?),
+            "sunsubscribe" => Request::SUnsubscribe(SUnsubscribe::from_frame(&mut it)?),
+            "publish" => Request::Publish(Publish::from_frame(&mut it)?),
+            "spublish" => Request::SPublish(SPublish::from_frame(&mut it)?),
+            "pubsub" => Request::PubSub(PubSub::from_frame(&mut it)?),
+            "ping" => Request::Ping(Ping::from_frame(&mut it)?),
+            "quit" => Request::Quit(Quit::from_frame(&mut it)?),
+            "hello" => Request::Hello(Hello::from_frame(&mut it)?),
+            "config" => Request::Config(Config::from_frame(&mut it)?),
+            "multi" => Request::Multi(Multi::from_frame(&mut it)?),
+            "exec" => Request::Exec(Exec::from_frame(&mut it)?),
+            "discard" => Request::Discard(Discard::from_frame(&mut it)?),
+            "watch" => Request::Watch(Watch::from_frame(&mut it)?),
+            "unwatch" => Request::Unwatch(Unwatch::from_frame(&mut it)?),
+            "eval" => Request::Eval(Eval::from_frame(&mut it)?),
+            "evalsha" => Request::EvalSha(EvalSha::from_frame(&mut it)?),
+            "script" => Request::Script(Script::from_frame(&mut it)?),
+            "function" => Request::Function(Function::from_frame(&mut it)?),
+            "fcall" => Request::FCall(FCall::from_frame(&mut it, false)?),
+            "fcall_ro" => Request::FCall(FCall::from_frame(&mut it, true)?),
+            _ => return Err(format!("unknown command '{}'", name).into()),
+        };
+        it.finish()?;
+        Ok(req)
+    }
+
+    /// Run the command against `db` and return the reply.
+    ///
+    /// It does not await, so the caller can hold the database lock for
+    /// the whole command and it is atomic, multi-key commands included.
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match self {
+            Request::Get(cmd) => cmd.execute(db),
+            Request::Set(cmd) => cmd.execute(db),
+            Request::Incr(cmd) => cmd.execute(db),
+            Request::Decr(cmd) => cmd.execute(db),
+            Request::IncrBy(cmd) => cmd.execute(db),
+            Request::DecrBy(cmd) => cmd.execute(db),
+            Request::IncrByFloat(cmd) => cmd.execute(db),
+            Request::Append(cmd) => cmd.execute(db),
+            Request::Strlen(cmd) => cmd.execute(db),
+            Request::GetRange(cmd) => cmd.execute(db),
+            Request::SetRange(cmd) => cmd.execute(db),
+            Request::SetBit(cmd) => cmd.execute(db),
+            Request::GetBit(cmd) => cmd.execute(db),
+            Request::BitCount(cmd) => cmd.execute(db),
+            Request::BitPos(cmd) => cmd.execute(db),
+            Request::BitOp(cmd) => cmd.execute(db),
+            Request::BitField(cmd) => cmd.execute(db),
+            Request::PfAdd(cmd) => cmd.execute(db),
+            Request::PfCount(cmd) => cmd.execute(db),
+            Request::PfMerge(cmd) => cmd.execute(db),
+            Request::MGet(cmd) => cmd.execute(db),
+            Request::MSet(cmd) => cmd.execute(db),
+            Request::MSetNx(cmd) => cmd.execute(db),
+            Request::GetSet(cmd) => cmd.execute(db),
+            Request::GetDel(cmd) => cmd.execute(db),
+            Request::GetEx(cmd) => cmd.execute(db),
+            Request::Del(cmd) => cmd.execute(db),
+            Request::Unlink(cmd) => cmd.execute(db),
+            Request::Exists(cmd) => cmd.execute(db),
+            Request::Type(cmd) => cmd.execute(db),
+            Request::Rename(cmd) => cmd.execute(db),
+            Request::RenameNx(cmd) => cmd.execute(db),
+            Request::Copy(cmd) => cmd.execute(db),
+            Request::Touch(cmd) => cmd.execute(db),
+            Request::RandomKey(cmd) => cmd.execute(db),
+            Request::Scan(cmd) => cmd.execute(db),
+            Request::HScan(cmd) => cmd.execute(db),
+            Request::SScan(cmd) => cmd.execute(db),
+            Request::ZScan(cmd) => cmd.execute(db),
+            Request::Keys(cmd) => cmd.execute(db),
+            Request::LPush(cmd) => cmd.execute(db),
+            Request::RPush(cmd) => cmd.execute(db),
+            Request::LPop(cmd) => cmd.execute(db),
+            Request::RPop(cmd) => cmd.execute(db),
+            Request::LLen(cmd) => cmd.execute(db),
+            Request::LRange(cmd) => cmd.execute(db),
+            Request::LIndex(cmd) => cmd.execute(db),
+            Request::LSet(cmd) => cmd.execute(db),
+            Request::LRem(cmd) => cmd.execute(db),
+            Request::LTrim(cmd) => cmd.execute(db),
+            Request::LInsert(cmd) => cmd.execute(db),
+            Request::LPos(cmd) => cmd.execute(db),
+            Request::LMove(cmd) => cmd.execute(db),
+            Request::BLPop(cmd) => cmd.execute(db),
+            Request::BRPop(cmd) => cmd.execute(db),
+            Request::BLMove(cmd) => cmd.execute(db),
+            Request::BLMPop(cmd) => cmd.execute(db),
+            Request::HSet(cmd) => cmd.execute(db),
+            Request::HSetNx(cmd) => cmd.execute(db),
+            Request::HGet(cmd) => cmd.execute(db),
+            Request::HMGet(cmd) => cmd.execute(db),
+            Request::HGetAll(cmd) => cmd.execute(db),
+            Request::HDel(cmd) => cmd.execute(db),
+            Request::HIncrBy(cmd) => cmd.execute(db),
+            Request::HIncrByFloat(cmd) => cmd.execute(db),
+            Request::HExists(cmd) => cmd.execute(db),
+            Request::HKeys(cmd) => cmd.execute(db),
+            Request::HVals(cmd) => cmd.execute(db),
+            Request::HLen(cmd) => cmd.execute(db),
+            Request::HRandField(cmd) => cmd.execute(db),
+            Request::SAdd(cmd) => cmd.execute(db),
+            Request::SRem(cmd) => cmd.execute(db),
+            Request::SMembers(cmd) => cmd.execute(db),
+            Request::SIsMember(cmd) => cmd.execute(db),
+            Request::SMIsMember(cmd) => cmd.execute(db),
+            Request::SCard(cmd) => cmd.execute(db),
+            Request::SPop(cmd) => cmd.execute(db),
+            Request::SRandMember(cmd) => cmd.execute(db),
+            Request::SInter(cmd) => cmd.execute(db),
+            Request::SInterStore(cmd) => cmd.execute(db),
+            Request::SInterCard(cmd) => cmd.execute(db),
+            Request::SUnion(cmd) => cmd.execute(db),
+            Request::SUnionStore(cmd) => cmd.execute(db),
+            Request::SDiff(cmd) => cmd.execute(db),
+            Request::SDiffStore(cmd) => cmd.execute(db),
+            Request::ZAdd(cmd) => cmd.execute(db),
+            Request::ZIncrBy(cmd) => cmd.execute(db),
+            Request::ZRem(cmd) => cmd.execute(db),
+            Request::ZCard(cmd) => cmd.execute(db),
+            Request::ZScore(cmd) => cmd.execute(db),
+            Request::ZMScore(cmd) => cmd.execute(db),
+            Request::ZRank(cmd) => cmd.execute(db),
+            Request::ZCount(cmd) => cmd.execute(db),
+            Request::ZLexCount(cmd) => cmd.execute(db),
+            Request::ZRange(cmd) => cmd.execute(db),
+            Request::ZRemRange(cmd) => cmd.execute(db),
+            Request::ZPop(cmd) => cmd.execute(db),
+            Request::ZStore(cmd) => cmd.execute(db),
+            Request::GeoAdd(cmd) => cmd.execute(db),
+            Request::GeoDist(cmd) => cmd.execute(db),
+            Request::GeoPos(cmd) => cmd.execute(db),
+            Request::GeoHash(cmd) => cmd.execute(db),
+            Request::GeoSearch(cmd) => cmd.execute(db),
+            Request::GeoSearchStore(cmd) => cmd.execute(db),
+            Request::XAdd(cmd) => cmd.execute(db),
+            Request::XLen(cmd) => cmd.execute(db),
+            Request::XRange(cmd) => cmd.execute(db),
+            Request::XDel(cmd) => cmd.execute(db),
+            Request::XTrim(cmd) => cmd.execute(db),
+            Request::XRead(cmd) => cmd.execute(db),
+            Request::XReadGroup(cmd) => cmd.execute(db),
+            Request::XGroup(cmd) => cmd.execute(db),
+            Request::XAck(cmd) => cmd.execute(db),
+            Request::XPending(cmd) => cmd.execute(db),
+            Request::XClaim(cmd) => cmd.execute(db),
+            Request::XAutoClaim(cmd) => cmd.execute(db),
+            Request::Publish(cmd) => cmd.execute(db),
+            Request::SPublish(cmd) => cmd.execute(db),
+            Request::PubSub(cmd) => cmd.execute(db),
+            Request::Ping(cmd) => cmd.execute(db),
+            Request::Quit(cmd) => cmd.execute(db),
+            Request::Config(cmd) => cmd.execute(db),
+            // they change the state of the connection, see `server::Handler`
+            Request::Subscribe(_)
+            | Request::Unsubscribe(_)
+            | Request::PSubscribe(_)
+            | Request::PUnsubscribe(_)
+            | Request::SSubscribe(_)
+            | Request::SUnsubscribe(_)
+            | Request::Hello(_)
+            | Request::Multi(_)
+            | Request::Exec(_)
+            | Request::Discard(_)
+            | Request::Watch(_)
+            | Request::Unwatch(_)
+            | Request::Eval(_)
+            | Request::EvalSha(_)
+            | Request::Script(_)
+            | Request::Function(_)
+            | Request::FCall(_) => Frame::Error("ERR command not allowed here".to_string()),
+        }
+    }
+
+    /// Run a scripting command, `None` for any other command
+    pub fn script(&self, db: &mut Database, scripts: &Scripts) -> Option<Frame> {
+        match self {
+            Request::Eval(cmd) => Some(cmd.execute(db, scripts)),
+            Request::EvalSha(cmd) => Some(cmd.execute(db, scripts)),
+            Request::Script(cmd) => Some(cmd.execute(scripts)),
+            Request::Function(cmd) => Some(cmd.execute(db, scripts)),
+            Request::FCall(cmd) => Some(cmd.execute(db, scripts)),
+            _ => None,
+        }
+    }
+
+    /// Whether the command may modify the dataset, what read-only scripts
+    /// and `no-writes` functions may not call
+    pub fn is_write(&self) -> bool {
+        if let Request::FCall(cmd) = self {
+            return !cmd.is_read_only();
+        }
+        matches!(
+            self,
+            Request::Set(_)
+                | Request::Incr(_)
+                | Request::Decr(_)
+                | Request::IncrBy(_)
+                | Request::DecrBy(_)
+                | Request::IncrByFloat(_)
+                | Request::Append(_)
+                | Request::SetRange(_)
+                | Request::SetBit(_)
+                | Request::BitOp(_)
+                | Request::BitField(_)
+                | Request::PfAdd(_)
+                | Request::PfMerge(_)
+                | Request::MSet(_)
+                | Request::MSetNx(_)
+                | Request::GetSet(_)
+                | Request::GetDel(_)
+                | Request::GetEx(_)
+                | Request::Del(_)
+                | Request::Unlink(_)
+                | Request::Rename(_)
+                | Request::RenameNx(_)
+                | Request::Copy(_)
+                | Request::LPush(_)
+                | Request::RPush(_)
+                | Request::LPop(_)
+                | Request::RPop(_)
+                | Request::LSet(_)
+                | Request::LRem(_)
+                | Request::LTrim(_)
+                | Request::LInsert(_)
+                | Request::LMove(_)
+                | Request::BLPop(_)
+                | Request::BRPop(_)
+                | Request::BLMove(_)
+                | Request::BLMPop(_)
+                | Request::HSet(_)
+                | Request::HSetNx(_)
+                | Request::HDel(_)
+                | Request::HIncrBy(_)
+                | Request::HIncrByFloat(_)
+                | Request::SAdd(_)
+                | Request::SRem(_)
+                | Request::SPop(_)
+                | Request::SInterStore(_)
+                | Request::SUnionStore(_)
+                | Request::SDiffStore(_)
+                | Request::ZAdd(_)
+                | Request::ZIncrBy(_)
+                | Request::ZRem(_)
+                | Request::ZRemRange(_)
+                | Request::ZPop(_)
+                | Request::ZStore(_)
+                | Request::GeoAdd(_)
+                | Request::GeoSearchStore(_)
+                | Request::XAdd(_)
+                | Request::XDel(_)
+                | Request::XTrim(_)
+                | Request::XReadGroup(_)
+                | Request::XGroup(_)
+                | Request::XAck(_)
+                | Request::XClaim(_)
+                | Request::XAutoClaim(_)
+                | Request::Function(_)
+        )
+    }
+
+    /// Run a command changing the subscriptions of the client, it replies
+    /// with one frame per channel. `None` for any other command.
+    pub fn subscription(&self, db: &mut Database, subscriptions: &mut Subscriptions, resp3: bool) -> Option<Vec<Frame>> {
+        match self {
+            Request::Subscribe(cmd) => Some(cmd.execute(db, subscriptions, resp3)),
+            Request::Unsubscribe(cmd) => Some(cmd.execute(db, subscriptions, resp3)),
+            Request::PSubscribe(cmd) => Some(cmd.execute(db, subscriptions, resp3)),
+            Request::PUnsubscribe(cmd) => Some(cmd.execute(db, subscriptions, resp3)),
+            Request::SSubscribe(cmd) => Some(cmd.execute(db, subscriptions, resp3)),
+            Request::SUnsubscribe(cmd) => Some(cmd.execute(db, subscriptions, resp3)),
+            _ => None,
+        }
+    }
+
+    /// For a command that may block, the keys it waits on and for how
+    /// long, `None` to wait forever. The handler parks the client if
+    /// `execute` replies nil.
+    pub fn blocking_keys(&self) -> Option<(&[String], Option<Duration>)> {
+        match self {
+            Request::BLPop(cmd) => Some((cmd.keys(), cmd.timeout())),
+            Request::BRPop(cmd) => Some((cmd.keys(), cmd.timeout())),
+            Request::BLMove(cmd) => Some((cmd.keys(), cmd.timeout())),
+            Request::BLMPop(cmd) => Some((cmd.keys(), cmd.timeout())),
+            Request::XRead(cmd) => cmd.timeout().map(|timeout| (cmd.keys(), timeout)),
+            Request::XReadGroup(cmd) => cmd.timeout().map(|timeout| (cmd.keys(), timeout)),
+            _ => None,
+        }
+    }
+
+    /// Fix what a blocking command reads to the dataset as it is when the
+    /// client blocks, before `execute` is retried on wakeups
+    pub fn pin(&mut self, db: &Database) {
+        if let Request::XRead(cmd) = self {
+            cmd.pin(db);
         }
     }
+
+    pub async fn apply(&self, db: &mut Database, conn: &mut Connection) -> Result<(), Error> {
+        conn.write_frame(self.execute(db)).await?;
+        Ok(())
+    }
+}
+
+/// Turn redis style inclusive `start`/`end` indexes, where negative ones
+/// count from the end, into an inclusive range of `0..len`.
+/// `None` if the range is empty.
+pub(crate) fn normalize_range(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let end = if end < 0 { len + end } else { end.min(len - 1) };
+    if start > end || start >= len {
+        return None;
+    }
+    Some((start as usize, end as usize))
+}
+
+/// Most elements a negative count of `HRANDFIELD` or `SRANDMEMBER` may ask
+/// for. Elements repeat then, so the size of the value does not bound the
+/// reply, which is built whole before it is sent.
+///
+/// A deliberate difference with redis, which takes counts down to
+/// `-LONG_MAX / 2` and writes such a reply out as it goes, until its output
+/// buffer limit disconnects the client. Here the whole reply would have to
+/// fit in memory first.
+const MAX_RANDOM_REPEATS: u64 = 1024 * 1024;
+
+/// The count of `HRANDFIELD` and `SRANDMEMBER`
+pub(crate) fn random_count(it: &mut dyn Parse) -> Result<i64, crate::frame::Error> {
+    let count = it.next_int()?;
+    // the range redis takes, and the cap of a negative count
+    if count.unsigned_abs() > i64::MAX as u64 / 2 || (count < 0 && count.unsigned_abs() > MAX_RANDOM_REPEATS) {
+        return Err("value is out of range".into());
+    }
+    Ok(count)
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -44,4 +705,15 @@ pub enum Response {
     NULL,
 }
 
-
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[test]
+fn test_normalize_range() {
+    assert_eq!(normalize_range(0, -1, 5), Some((0, 4)));
+    assert_eq!(normalize_range(-3, -2, 5), Some((2, 3)));
+    assert_eq!(normalize_range(-100, 100, 5), Some((0, 4)));
+    assert_eq!(normalize_range(3, 1, 5), None);
+    assert_eq!(normalize_range(5, 10, 5), None);
+    assert_eq!(normalize_range(0, -1, 0), None);
+}
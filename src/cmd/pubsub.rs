@@ -0,0 +1,503 @@
+//! Pub/sub and connection commands
+//!
+//! The subscriptions of a client belong to its connection, so the
+//! `SUBSCRIBE` family runs against the `Subscriptions` of the handler as
+//! well as the broker of the database, and replies with one frame per
+//! channel. See `pubsub`.
+
+use bytes::Bytes;
+
+use crate::{
+    database::Database,
+    frame::{Error, Frame, Parse},
+    pubsub::{push_frame, Subscriptions},
+    slot::key_slot,
+};
+
+fn names_frame(name: &'static str, names: Vec<Bytes>) -> Frame {
+    let mut frame = Frame::new_array_frame();
+    frame.push_bulk(Bytes::from(name));
+    for name in names {
+        frame.push_bulk(name);
+    }
+    frame
+}
+
+fn names_from_frame(it: &mut dyn Parse) -> Result<Vec<Bytes>, Error> {
+    let mut names = vec![];
+    while it.has_next() {
+        names.push(it.next_bytes()?);
+    }
+    Ok(names)
+}
+
+fn to_bytes(names: &[&str]) -> Vec<Bytes> {
+    names.iter().map(|name| Bytes::copy_from_slice(name.as_bytes())).collect()
+}
+
+/// Shard channels taken together must be in the same slot, like the keys
+/// of a command in redis cluster
+fn same_slot(channels: &[Bytes]) -> Result<(), Frame> {
+    match channels.split_first() {
+        Some((first, rest)) if rest.iter().any(|channel| key_slot(channel) != key_slot(first)) => {
+            Err(Frame::Error("CROSSSLOT Keys in request don't hash to the same slot".to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// `kind name count`, the confirmation of a change of subscription
+fn confirmation(kind: &'static str, name: Option<Bytes>, count: usize, resp3: bool) -> Frame {
+    let name = name.map_or(Frame::Null, Frame::Bulk);
+    push_frame(vec![Frame::Bulk(Bytes::from(kind)), name, Frame::Integer(count as i64)], resp3)
+}
+
+/// `SUBSCRIBE channel [channel ...]`, the client then only receives
+/// messages until it unsubscribes from everything, see `server::Handler`
+#[derive(Debug)]
+pub struct Subscribe {
+    channels: Vec<Bytes>,
+}
+
+impl Subscribe {
+    pub fn new(channels: &[&str]) -> Self {
+        Subscribe { channels: to_bytes(channels) }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        names_frame("subscribe", self.channels)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let channels = vec![it.next_bytes()?];
+        Ok(Subscribe { channels: [channels, names_from_frame(it)?].concat() })
+    }
+
+    pub fn execute(&self, db: &mut Database, subscriptions: &mut Subscriptions, resp3: bool) -> Vec<Frame> {
+        self.channels
+            .iter()
+            .map(|channel| {
+                let count = subscriptions.subscribe(db.broker_mut(), channel.clone());
+                confirmation("subscribe", Some(channel.clone()), count, resp3)
+            })
+            .collect()
+    }
+}
+
+/// `UNSUBSCRIBE [channel ...]`, from every channel if none is given
+#[derive(Debug)]
+pub struct Unsubscribe {
+    channels: Vec<Bytes>,
+}
+
+impl Unsubscribe {
+    pub fn new(channels: &[&str]) -> Self {
+        Unsubscribe { channels: to_bytes(channels) }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        names_frame("unsubscribe", self.channels)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(Unsubscribe { channels: names_from_frame(it)? })
+    }
+
+    pub fn execute(&self, db: &mut Database, subscriptions: &mut Subscriptions, resp3: bool) -> Vec<Frame> {
+        let channels = match &self.channels[..] {
+            [] => subscriptions.channels(),
+            channels => channels.to_vec(),
+        };
+        if channels.is_empty() {
+            return vec![confirmation("unsubscribe", None, subscriptions.count(), resp3)];
+        }
+        channels
+            .into_iter()
+            .map(|channel| {
+                let count = subscriptions.unsubscribe(db.broker_mut(), &channel);
+                confirmation("unsubscribe", Some(channel), count, resp3)
+            })
+            .collect()
+    }
+}
+
+/// `PSUBSCRIBE pattern [pattern ...]`, with the glob patterns of `KEYS`
+#[derive(Debug)]
+pub struct PSubscribe {
+    patterns: Vec<Bytes>,
+}
+
+impl PSubscribe {
+    pub fn new(patterns: &[&str]) -> Self {
+        PSubscribe { patterns: to_bytes(patterns) }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        names_frame("psubscribe", self.patterns)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let patterns = vec![it.next_bytes()?];
+        Ok(PSubscribe { patterns: [patterns, names_from_frame(it)?].concat() })
+    }
+
+    pub fn execute(&self, db: &mut Database, subscriptions: &mut Subscriptions, resp3: bool) -> Vec<Frame> {
+        self.patterns
+            .iter()
+            .map(|pattern| {
+                let count = subscriptions.psubscribe(db.broker_mut(), pattern.clone());
+                confirmation("psubscribe", Some(pattern.clone()), count, resp3)
+            })
+            .collect()
+    }
+}
+
+/// `PUNSUBSCRIBE [pattern ...]`, from every pattern if none is given
+#[derive(Debug)]
+pub struct PUnsubscribe {
+    patterns: Vec<Bytes>,
+}
+
+impl PUnsubscribe {
+    pub fn new(patterns: &[&str]) -> Self {
+        PUnsubscribe { patterns: to_bytes(patterns) }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        names_frame("punsubscribe", self.patterns)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(PUnsubscribe { patterns: names_from_frame(it)? })
+    }
+
+    pub fn execute(&self, db: &mut Database, subscriptions: &mut Subscriptions, resp3: bool) -> Vec<Frame> {
+        let patterns = match &self.patterns[..] {
+            [] => subscriptions.patterns(),
+            patterns => patterns.to_vec(),
+        };
+        if patterns.is_empty() {
+            return vec![confirmation("punsubscribe", None, subscriptions.count(), resp3)];
+        }
+        patterns
+            .into_iter()
+            .map(|pattern| {
+                let count = subscriptions.punsubscribe(db.broker_mut(), &pattern);
+                confirmation("punsubscribe", Some(pattern), count, resp3)
+            })
+            .collect()
+    }
+}
+
+/// `SSUBSCRIBE shardchannel [shardchannel ...]`, the channels must hash to
+/// the same slot. The count in the replies is of shard channels only.
+#[derive(Debug)]
+pub struct SSubscribe {
+    channels: Vec<Bytes>,
+}
+
+impl SSubscribe {
+    pub fn new(channels: &[&str]) -> Self {
+        SSubscribe { channels: to_bytes(channels) }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        names_frame("ssubscribe", self.channels)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let channels = vec![it.next_bytes()?];
+        Ok(SSubscribe { channels: [channels, names_from_frame(it)?].concat() })
+    }
+
+    pub fn execute(&self, db: &mut Database, subscriptions: &mut Subscriptions, resp3: bool) -> Vec<Frame> {
+        if let Err(e) = same_slot(&self.channels) {
+            return vec![e];
+        }
+        self.channels
+            .iter()
+            .map(|channel| {
+                let count = subscriptions.ssubscribe(db.broker_mut(), channel.clone());
+                confirmation("ssubscribe", Some(channel.clone()), count, resp3)
+            })
+            .collect()
+    }
+}
+
+/// `SUNSUBSCRIBE [shardchannel ...]`, from every shard channel if none is
+/// given
+#[derive(Debug)]
+pub struct SUnsubscribe {
+    channels: Vec<Bytes>,
+}
+
+impl SUnsubscribe {
+    pub fn new(channels: &[&str]) -> Self {
+        SUnsubscribe { channels: to_bytes(channels) }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        names_frame("sunsubscribe", self.channels)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(SUnsubscribe { channels: names_from_frame(it)? })
+    }
+
+    pub fn execute(&self, db: &mut Database, subscriptions: &mut Subscriptions, resp3: bool) -> Vec<Frame> {
+        if let Err(e) = same_slot(&self.channels) {
+            return vec![e];
+        }
+        let channels = match &self.channels[..] {
+            [] => subscriptions.shard_channels(),
+            channels => channels.to_vec(),
+        };
+        if channels.is_empty() {
+            return vec![confirmation("sunsubscribe", None, subscriptions.shard_count(), resp3)];
+        }
+        channels
+            .into_iter()
+            .map(|channel| {
+                let count = subscriptions.sunsubscribe(db.broker_mut(), &channel);
+                confirmation("sunsubscribe", Some(channel), count, resp3)
+            })
+            .collect()
+    }
+}
+
+/// `PUBLISH channel message`, replies with the number of clients that
+/// received it
+#[derive(Debug)]
+pub struct Publish {
+    channel: Bytes,
+    message: Bytes,
+}
+
+impl Publish {
+    pub fn new(channel: &str, message: Bytes) -> Self {
+        Publish { channel: Bytes::copy_from_slice(channel.as_bytes()), message }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        names_frame("publish", vec![self.channel, self.message])
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(Publish { channel: it.next_bytes()?, message: it.next_bytes()? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        Frame::Integer(db.broker().publish(&self.channel, &self.message) as i64)
+    }
+}
+
+/// `SPUBLISH shardchannel message`, replies with the number of clients
+/// that received it
+#[derive(Debug)]
+pub struct SPublish {
+    channel: Bytes,
+    message: Bytes,
+}
+
+impl SPublish {
+    pub fn new(channel: &str, message: Bytes) -> Self {
+        SPublish { channel: Bytes::copy_from_slice(channel.as_bytes()), message }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        names_frame("spublish", vec![self.channel, self.message])
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(SPublish { channel: it.next_bytes()?, message: it.next_bytes()? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        Frame::Integer(db.broker().spublish(&self.channel, &self.message) as i64)
+    }
+}
+
+/// `PUBSUB CHANNELS [pattern]`, `PUBSUB NUMSUB [channel ...]`,
+/// `PUBSUB NUMPAT`, `PUBSUB SHARDCHANNELS [pattern]` and
+/// `PUBSUB SHARDNUMSUB [shardchannel ...]`
+#[derive(Debug)]
+pub enum PubSub {
+    /// the channels with subscribers, matching the pattern if any
+    Channels(Option<Bytes>),
+    /// the number of subscribers of each channel, patterns aside
+    NumSub(Vec<Bytes>),
+    /// the number of patterns with subscribers
+    NumPat,
+    /// the shard channels with subscribers, matching the pattern if any
+    ShardChannels(Option<Bytes>),
+    /// the number of subscribers of each shard channel
+    ShardNumSub(Vec<Bytes>),
+}
+
+impl PubSub {
+    pub fn into_frame(self) -> Frame {
+        match self {
+            PubSub::Channels(pattern) => names_frame("pubsub", [Bytes::from("channels")].into_iter().chain(pattern).collect()),
+            PubSub::NumSub(channels) => names_frame("pubsub", [vec![Bytes::from("numsub")], channels].concat()),
+            PubSub::NumPat => names_frame("pubsub", vec![Bytes::from("numpat")]),
+            PubSub::ShardChannels(pattern) => {
+                names_frame("pubsub", [Bytes::from("shardchannels")].into_iter().chain(pattern).collect())
+            }
+            PubSub::ShardNumSub(channels) => names_frame("pubsub", [vec![Bytes::from("shardnumsub")], channels].concat()),
+        }
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let subcommand = it.next_string()?.to_lowercase();
+        match subcommand.as_str() {
+            "channels" if it.has_next() => Ok(PubSub::Channels(Some(it.next_bytes()?))),
+            "channels" => Ok(PubSub::Channels(None)),
+            "numsub" => Ok(PubSub::NumSub(names_from_frame(it)?)),
+            "numpat" => Ok(PubSub::NumPat),
+            "shardchannels" if it.has_next() => Ok(PubSub::ShardChannels(Some(it.next_bytes()?))),
+            "shardchannels" => Ok(PubSub::ShardChannels(None)),
+            "shardnumsub" => Ok(PubSub::ShardNumSub(names_from_frame(it)?)),
+            _ => Err(format!("unknown subcommand '{subcommand}'. Try PUBSUB HELP.").into()),
+        }
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let broker = db.broker();
+        let counts = |channels: &[Bytes], count: &dyn Fn(&[u8]) -> usize| {
+            let counts = channels.iter().flat_map(|channel| {
+                [Frame::Bulk(channel.clone()), Frame::Integer(count(channel) as i64)]
+            });
+            Frame::Array(counts.collect())
+        };
+        match self {
+            PubSub::Channels(pattern) => {
+                Frame::Array(broker.channels(pattern.as_deref()).into_iter().map(Frame::Bulk).collect())
+            }
+            PubSub::NumSub(channels) => counts(channels, &|channel| broker.num_subscribers(channel)),
+            PubSub::NumPat => Frame::Integer(broker.num_patterns() as i64),
+            PubSub::ShardChannels(pattern) => {
+                Frame::Array(broker.shard_channels(pattern.as_deref()).into_iter().map(Frame::Bulk).collect())
+            }
+            PubSub::ShardNumSub(channels) => counts(channels, &|channel| broker.num_shard_subscribers(channel)),
+        }
+    }
+}
+
+/// `PING [message]`, replies `PONG`, or the message
+#[derive(Debug, Default)]
+pub struct Ping {
+    message: Option<Bytes>,
+}
+
+impl Ping {
+    pub fn new(message: Option<Bytes>) -> Self {
+        Ping { message }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        names_frame("ping", self.message.into_iter().collect())
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let message = if it.has_next() { Some(it.next_bytes()?) } else { None };
+        Ok(Ping { message })
+    }
+
+    pub fn execute(&self, _db: &mut Database) -> Frame {
+        match &self.message {
+            Some(message) => Frame::Bulk(message.clone()),
+            None => Frame::Simple("PONG".to_string()),
+        }
+    }
+
+    /// The reply to a subscribed RESP2 client, `pong message`, so it can
+    /// be told apart from the messages
+    pub fn subscribed_reply(&self) -> Frame {
+        let message = self.message.clone().unwrap_or_default();
+        Frame::Array(vec![Frame::Bulk(Bytes::from("pong")), Frame::Bulk(message)])
+    }
+}
+
+/// `QUIT`, the server replies `OK` and closes the connection
+#[derive(Debug, Default)]
+pub struct Quit;
+
+impl Quit {
+    pub fn new() -> Self {
+        Quit
+    }
+
+    pub fn into_frame(self) -> Frame {
+        names_frame("quit", vec![])
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        // arguments are ignored
+        while it.has_next() {
+            it.next_bytes()?;
+        }
+        Ok(Quit)
+    }
+
+    pub fn execute(&self, _db: &mut Database) -> Frame {
+        Frame::Simple("OK".to_string())
+    }
+}
+
+/// `HELLO [protover]`, switches the connection to RESP2 or RESP3 and
+/// replies with the properties of the server
+///
+/// Under RESP3 only out of band data uses the new types, as push frames,
+/// replies keep their RESP2 encoding.
+#[derive(Debug, Default)]
+pub struct Hello {
+    protover: Option<i64>,
+}
+
+impl Hello {
+    pub fn new(protover: Option<i64>) -> Self {
+        Hello { protover }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        names_frame("hello", self.protover.map(|n| Bytes::from(n.to_string())).into_iter().collect())
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let protover = if it.has_next() {
+            Some(it.next_int().map_err(|_| "Protocol version is not an integer or out of range")?)
+        } else {
+            None
+        };
+        Ok(Hello { protover })
+    }
+
+    /// Reply and update `resp3`, the protocol of the connection
+    pub fn execute(&self, resp3: &mut bool) -> Frame {
+        match self.protover {
+            Some(2) => *resp3 = false,
+            Some(3) => *resp3 = true,
+            Some(_) => return Frame::Error("NOPROTO unsupported protocol version".to_string()),
+            None => {}
+        }
+        let proto = if *resp3 { 3 } else { 2 };
+        let fields = vec![
+            Frame::Bulk(Bytes::from("server")),
+            Frame::Bulk(Bytes::from("miniredis")),
+            Frame::Bulk(Bytes::from("version")),
+            Frame::Bulk(Bytes::from(env!("CARGO_PKG_VERSION"))),
+            Frame::Bulk(Bytes::from("proto")),
+            Frame::Integer(proto),
+            Frame::Bulk(Bytes::from("mode")),
+            Frame::Bulk(Bytes::from("standalone")),
+            Frame::Bulk(Bytes::from("role")),
+            Frame::Bulk(Bytes::from("master")),
+        ];
+        // a map under RESP3, its keys and values in turn under RESP2
+        if *resp3 {
+            Frame::Map(fields)
+        } else {
+            Frame::Array(fields)
+        }
+    }
+}
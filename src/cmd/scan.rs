@@ -0,0 +1,384 @@
+//! `KEYS` and the cursor based `SCAN` family
+//!
+//! A cursor is the position in the hash table to continue from, see
+//! `dict` for why every element present for the whole iteration is
+//! returned. `MATCH` filters with the same glob patterns as `KEYS`, after
+//! the elements are read, so a call may return fewer than `COUNT` of them,
+//! or none at all, before the iteration ends with a cursor of `0`.
+
+use bytes::Bytes;
+
+use crate::{
+    database::Database,
+    frame::{Error, Frame, Parse},
+};
+
+/// same default as redis
+const DEFAULT_COUNT: usize = 10;
+
+/// Whether `s` matches the glob `pattern`, with the syntax of redis:
+/// `*`, `?`, `[abc]`, `[^abc]`, `[a-z]` and `\` to escape.
+pub(crate) fn glob_match(pattern: &[u8], s: &[u8]) -> bool {
+    let (mut p, mut i) = (0, 0);
+    // where to resume after the last `*` when the rest fails to match,
+    // trying one more byte under the star each time
+    let mut star: Option<(usize, usize)> = None;
+    while i < s.len() {
+        let next = match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p + 1, i));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => Some(p + 1),
+            Some(b'[') => match_class(pattern, p + 1, s[i]),
+            Some(b'\\') if p + 1 < pattern.len() => (pattern[p + 1] == s[i]).then_some(p + 2),
+            Some(&c) => (c == s[i]).then_some(p + 1),
+            None => None,
+        };
+        match (next, star) {
+            (Some(next), _) => {
+                p = next;
+                i += 1;
+            }
+            (None, Some((after_star, matched))) => {
+                p = after_star;
+                i = matched + 1;
+                star = Some((after_star, i));
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Match `c` against the class starting at `pattern[p]`, right after the
+/// `[`. Returns the position after the closing `]` if it matches. An
+/// unterminated class ends with the pattern.
+fn match_class(pattern: &[u8], mut p: usize, c: u8) -> Option<usize> {
+    let negate = pattern.get(p) == Some(&b'^');
+    if negate {
+        p += 1;
+    }
+    let mut matched = false;
+    while p < pattern.len() && pattern[p] != b']' {
+        if pattern[p] == b'\\' && p + 1 < pattern.len() {
+            matched |= pattern[p + 1] == c;
+            p += 2;
+        } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' && pattern[p + 2] != b']' {
+            let (lo, hi) = (pattern[p].min(pattern[p + 2]), pattern[p].max(pattern[p + 2]));
+            matched |= lo <= c && c <= hi;
+            p += 3;
+        } else {
+            matched |= pattern[p] == c;
+            p += 1;
+        }
+    }
+    (matched != negate).then_some((p + 1).min(pattern.len()))
+}
+
+/// `[MATCH pattern] [COUNT count]` shared by the scan commands
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ScanArgs {
+    cursor: u64,
+    pattern: Option<Bytes>,
+    count: usize,
+}
+
+impl ScanArgs {
+    fn new(cursor: u64) -> Self {
+        ScanArgs { cursor, pattern: None, count: DEFAULT_COUNT }
+    }
+
+    fn matches(&self, s: &[u8]) -> bool {
+        self.pattern.as_ref().is_none_or(|pattern| glob_match(pattern, s))
+    }
+
+    fn push_args(&self, frame: &mut Frame) {
+        frame.push_bulk(Bytes::from(self.cursor.to_string()));
+        if let Some(pattern) = &self.pattern {
+            frame.push_bulk(Bytes::from("match"));
+            frame.push_bulk(pattern.clone());
+        }
+        if self.count != DEFAULT_COUNT {
+            frame.push_bulk(Bytes::from("count"));
+            frame.push_bulk(Bytes::from(self.count.to_string()));
+        }
+    }
+
+    fn parse_cursor(it: &mut dyn Parse) -> Result<u64, Error> {
+        it.next_string()?.parse().map_err(|_| "invalid cursor".into())
+    }
+
+    /// Parse one option, `false` if `option` is not `MATCH` or `COUNT`
+    fn parse_option(&mut self, option: &str, it: &mut dyn Parse) -> Result<bool, Error> {
+        match option {
+            "match" => self.pattern = Some(it.next_bytes()?),
+            "count" => {
+                self.count = match it.next_int()? {
+                    n if n >= 1 => n as usize,
+                    _ => return Err("syntax error".into()),
+                }
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// The arguments of the `HSCAN`-like commands, which have no `TYPE`
+    fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let mut args = ScanArgs::new(ScanArgs::parse_cursor(it)?);
+        while it.has_next() {
+            let option = it.next_string()?.to_lowercase();
+            if !args.parse_option(&option, it)? {
+                return Err("syntax error".into());
+            }
+        }
+        Ok(args)
+    }
+}
+
+fn scan_reply(cursor: u64, items: Vec<Bytes>) -> Frame {
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from(cursor.to_string())),
+        Frame::Array(items.into_iter().map(Frame::Bulk).collect()),
+    ])
+}
+
+/// `SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]`
+#[derive(Debug)]
+pub struct Scan {
+    args: ScanArgs,
+    type_name: Option<String>,
+}
+
+impl Scan {
+    pub fn new(cursor: u64) -> Self {
+        Scan { args: ScanArgs::new(cursor), type_name: None }
+    }
+
+    pub fn pattern(mut self, pattern: &str) -> Self {
+        self.args.pattern = Some(Bytes::from(pattern.to_string()));
+        self
+    }
+
+    pub fn count(mut self, count: usize) -> Self {
+        self.args.count = count;
+        self
+    }
+
+    /// Only return keys of this type, named as `TYPE` replies
+    pub fn type_name(mut self, type_name: &str) -> Self {
+        self.type_name = Some(type_name.to_lowercase());
+        self
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::new_array_frame();
+        frame.push_bulk(Bytes::from("scan"));
+        self.args.push_args(&mut frame);
+        if let Some(type_name) = self.type_name {
+            frame.push_bulk(Bytes::from("type"));
+            frame.push_bulk(Bytes::from(type_name));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let mut scan = Scan::new(ScanArgs::parse_cursor(it)?);
+        while it.has_next() {
+            let option = it.next_string()?.to_lowercase();
+            if option == "type" {
+                scan.type_name = Some(it.next_string()?.to_lowercase());
+            } else if !scan.args.parse_option(&option, it)? {
+                return Err("syntax error".into());
+            }
+        }
+        Ok(scan)
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let mut keys = Vec::new();
+        let cursor = db.scan(self.args.cursor, self.args.count, |key, value| {
+            let type_matches = self.type_name.as_deref().is_none_or(|name| name == value.type_name());
+            if type_matches && self.args.matches(key.as_bytes()) {
+                keys.push(Bytes::from(key.to_string()));
+            }
+        });
+        scan_reply(cursor, keys)
+    }
+}
+
+/// The commands scanning the elements of a single value
+macro_rules! scan_value_cmd {
+    ($(#[$doc:meta])* $name:ident, $cmd:literal) => {
+        $(#[$doc])*
+        #[derive(Debug)]
+        pub struct $name {
+            key: String,
+            args: ScanArgs,
+        }
+
+        impl $name {
+            pub fn new(key: &str, cursor: u64) -> Self {
+                $name { key: key.to_string(), args: ScanArgs::new(cursor) }
+            }
+
+            pub fn pattern(mut self, pattern: &str) -> Self {
+                self.args.pattern = Some(Bytes::from(pattern.to_string()));
+                self
+            }
+
+            pub fn count(mut self, count: usize) -> Self {
+                self.args.count = count;
+                self
+            }
+
+            pub fn into_frame(self) -> Frame {
+                let mut frame = Frame::new_array_frame();
+                frame.push_bulk(Bytes::from($cmd));
+                frame.push_bulk(Bytes::from(self.key));
+                self.args.push_args(&mut frame);
+                frame
+            }
+
+            pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+                let key = it.next_string()?;
+                Ok($name { key, args: ScanArgs::from_frame(it)? })
+            }
+        }
+    };
+}
+
+scan_value_cmd!(
+    /// `HSCAN key cursor [MATCH pattern] [COUNT count]`, replies with fields and values
+    HScan,
+    "hscan"
+);
+scan_value_cmd!(
+    /// `SSCAN key cursor [MATCH pattern] [COUNT count]`
+    SScan,
+    "sscan"
+);
+scan_value_cmd!(
+    /// `ZSCAN key cursor [MATCH pattern] [COUNT count]`, replies with members and scores
+    ZScan,
+    "zscan"
+);
+
+impl HScan {
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let hash = match db.get_hash(&self.key) {
+            Ok(Some(hash)) => hash,
+            Ok(None) => return scan_reply(0, vec![]),
+            Err(e) => return e.into(),
+        };
+        let mut items = Vec::new();
+        let cursor = hash.scan(self.args.cursor, self.args.count, |field, value| {
+            if self.args.matches(field) {
+                items.push(field.clone());
+                items.push(value.clone());
+            }
+        });
+        scan_reply(cursor, items)
+    }
+}
+
+impl SScan {
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let set = match db.get_set(&self.key) {
+            Ok(Some(set)) => set,
+            Ok(None) => return scan_reply(0, vec![]),
+            Err(e) => return e.into(),
+        };
+        let mut members = Vec::new();
+        let cursor = set.scan(self.args.cursor, self.args.count, |member| {
+            if self.args.matches(&member) {
+                members.push(member);
+            }
+        });
+        scan_reply(cursor, members)
+    }
+}
+
+impl ZScan {
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let zset = match db.get_zset(&self.key) {
+            Ok(Some(zset)) => zset,
+            Ok(None) => return scan_reply(0, vec![]),
+            Err(e) => return e.into(),
+        };
+        let mut items = Vec::new();
+        let cursor = zset.scan(self.args.cursor, self.args.count, |member, score| {
+            if self.args.matches(member) {
+                items.push(member.clone());
+                items.push(Bytes::from(score.to_string()));
+            }
+        });
+        scan_reply(cursor, items)
+    }
+}
+
+/// `KEYS pattern`, every key matching at once, prefer `SCAN`
+#[derive(Debug)]
+pub struct Keys {
+    pattern: Bytes,
+}
+
+impl Keys {
+    pub fn new(pattern: &str) -> Self {
+        Keys { pattern: Bytes::from(pattern.to_string()) }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::new_array_frame();
+        frame.push_bulk(Bytes::from("keys"));
+        frame.push_bulk(self.pattern);
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(Keys { pattern: it.next_bytes()? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let keys = db
+            .keys()
+            .filter(|key| glob_match(&self.pattern, key.as_bytes()))
+            .map(|key| Frame::Bulk(Bytes::from(key.clone())))
+            .collect();
+        Frame::Array(keys)
+    }
+}
+
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[test]
+fn test_glob_match() {
+    let cases: &[(&str, &str, bool)] = &[
+        ("*", "", true),
+        ("*", "anything", true),
+        ("h?llo", "hello", true),
+        ("h?llo", "hllo", false),
+        ("h*llo", "heeeello", true),
+        ("h*llo", "hello world", false),
+        ("h[ae]llo", "hallo", true),
+        ("h[ae]llo", "hillo", false),
+        ("h[^e]llo", "hallo", true),
+        ("h[^e]llo", "hello", false),
+        ("h[a-b]llo", "hbllo", true),
+        ("h[b-a]llo", "hallo", true),
+        ("h[a-b]llo", "hcllo", false),
+        ("h\\*llo", "h*llo", true),
+        ("h\\*llo", "hello", false),
+        ("user:*:name", "user:42:name", true),
+        ("user:*:name", "user:42:email", false),
+        ("*a*b", "xaxxaxb", true),
+        ("a*", "b", false),
+        ("[abc", "b", true),
+    ];
+    for &(pattern, s, expected) in cases {
+        assert_eq!(glob_match(pattern.as_bytes(), s.as_bytes()), expected, "{} ~ {}", pattern, s);
+    }
+}
@@ -0,0 +1,194 @@
+//! Scripting commands
+//!
+//! They run against the `Scripts` of the server as well as the database,
+//! the handler calls them through `Request::script`. See `script`.
+
+use bytes::Bytes;
+
+use crate::{
+    database::Database,
+    frame::{Error, Frame, Parse},
+    script::Scripts,
+};
+
+fn script_frame(name: &'static str, script: Bytes, keys: Vec<String>, args: Vec<Bytes>) -> Frame {
+    let mut frame = Frame::new_array_frame();
+    frame.push_bulk(Bytes::from(name));
+    frame.push_bulk(script);
+    frame.push_bulk(Bytes::from(keys.len().to_string()));
+    for key in keys {
+        frame.push_bulk(Bytes::from(key));
+    }
+    for arg in args {
+        frame.push_bulk(arg);
+    }
+    frame
+}
+
+/// `numkeys key [key ...] arg [arg ...]`, the rest of `EVAL`, `EVALSHA`
+/// and `FCALL`
+pub(super) fn keys_and_args_from_frame(it: &mut dyn Parse) -> Result<(Vec<String>, Vec<Bytes>), Error> {
+    let numkeys = it.next_int()?;
+    if numkeys < 0 {
+        return Err("Number of keys can't be negative".into());
+    }
+    let mut keys = vec![];
+    for _ in 0..numkeys {
+        if !it.has_next() {
+            return Err("Number of keys can't be greater than number of args".into());
+        }
+        keys.push(it.next_string()?);
+    }
+    let mut args = vec![];
+    while it.has_next() {
+        args.push(it.next_bytes()?);
+    }
+    Ok((keys, args))
+}
+
+/// `EVAL script numkeys [key ...] [arg ...]`, the keys and args are the
+/// `KEYS` and `ARGV` tables of the script
+#[derive(Debug)]
+pub struct Eval {
+    script: Bytes,
+    keys: Vec<String>,
+    args: Vec<Bytes>,
+}
+
+impl Eval {
+    pub fn new(script: &str, keys: &[&str], args: Vec<Bytes>) -> Self {
+        Eval {
+            script: Bytes::copy_from_slice(script.as_bytes()),
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+            args,
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        script_frame("eval", self.script, self.keys, self.args)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let script = it.next_bytes()?;
+        let (keys, args) = keys_and_args_from_frame(it)?;
+        Ok(Eval { script, keys, args })
+    }
+
+    pub fn execute(&self, db: &mut Database, scripts: &Scripts) -> Frame {
+        scripts.eval(db, &self.script, &self.keys, &self.args)
+    }
+}
+
+/// `EVALSHA sha1 numkeys [key ...] [arg ...]`, an `EVAL` of a cached script
+#[derive(Debug)]
+pub struct EvalSha {
+    sha: String,
+    keys: Vec<String>,
+    args: Vec<Bytes>,
+}
+
+impl EvalSha {
+    pub fn new(sha: &str, keys: &[&str], args: Vec<Bytes>) -> Self {
+        EvalSha { sha: sha.to_string(), keys: keys.iter().map(|key| key.to_string()).collect(), args }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        script_frame("evalsha", Bytes::from(self.sha), self.keys, self.args)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let sha = it.next_string()?;
+        let (keys, args) = keys_and_args_from_frame(it)?;
+        Ok(EvalSha { sha, keys, args })
+    }
+
+    pub fn execute(&self, db: &mut Database, scripts: &Scripts) -> Frame {
+        scripts.eval_sha(db, &self.sha, &self.keys, &self.args)
+    }
+}
+
+/// `SCRIPT LOAD script`, `SCRIPT EXISTS sha1 [sha1 ...]`,
+/// `SCRIPT FLUSH [ASYNC|SYNC]` and `SCRIPT KILL`
+#[derive(Debug)]
+pub enum Script {
+    /// replies with the SHA1 to pass to `EVALSHA`
+    Load(Bytes),
+    /// `1` or `0` for each SHA1
+    Exists(Vec<String>),
+    Flush,
+    /// stops the script running for another client, see `Scripts::kill`
+    Kill,
+}
+
+impl Script {
+    pub fn load(script: &str) -> Self {
+        Script::Load(Bytes::copy_from_slice(script.as_bytes()))
+    }
+
+    pub fn exists(shas: &[&str]) -> Self {
+        Script::Exists(shas.iter().map(|sha| sha.to_string()).collect())
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::new_array_frame();
+        frame.push_bulk(Bytes::from("script"));
+        match self {
+            Script::Load(script) => {
+                frame.push_bulk(Bytes::from("load"));
+                frame.push_bulk(script);
+            }
+            Script::Exists(shas) => {
+                frame.push_bulk(Bytes::from("exists"));
+                for sha in shas {
+                    frame.push_bulk(Bytes::from(sha));
+                }
+            }
+            Script::Flush => frame.push_bulk(Bytes::from("flush")),
+            Script::Kill => frame.push_bulk(Bytes::from("kill")),
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let subcommand = it.next_string()?.to_lowercase();
+        match subcommand.as_str() {
+            "load" => Ok(Script::Load(it.next_bytes()?)),
+            "exists" => {
+                let mut shas = vec![it.next_string()?];
+                while it.has_next() {
+                    shas.push(it.next_string()?);
+                }
+                Ok(Script::Exists(shas))
+            }
+            "flush" => {
+                // always synchronous
+                if it.has_next() {
+                    let mode = it.next_string()?.to_lowercase();
+                    if mode != "async" && mode != "sync" {
+                        return Err("SCRIPT FLUSH only support SYNC|ASYNC option".into());
+                    }
+                }
+                Ok(Script::Flush)
+            }
+            "kill" => Ok(Script::Kill),
+            _ => Err(format!("unknown subcommand '{subcommand}'. Try SCRIPT HELP.").into()),
+        }
+    }
+
+    pub fn execute(&self, scripts: &Scripts) -> Frame {
+        match self {
+            Script::Load(script) => match scripts.load(script) {
+                Ok(sha) => Frame::Bulk(Bytes::from(sha)),
+                Err(error) => error,
+            },
+            Script::Exists(shas) => {
+                Frame::Array(shas.iter().map(|sha| Frame::Integer(scripts.exists(sha) as i64)).collect())
+            }
+            Script::Flush => {
+                scripts.flush();
+                Frame::Simple("OK".to_string())
+            }
+            Script::Kill => scripts.kill(),
+        }
+    }
+}
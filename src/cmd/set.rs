@@ -1,6 +1,6 @@
 use bytes::Bytes;
 
-use crate::{frame::{Error, Frame, Parse}, database::Database, connection::Connection};
+use crate::{frame::{Error, Frame, Parse}, database::Database, connection::Connection, notify::Events};
 #[derive(Debug)]
 pub struct Set {
     key: String,
@@ -26,18 +26,94 @@ impl Set {
         let value = it.next_bytes()?;
         Ok(Set::new(&key, value))
     }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        // the reply is the same for an exisiting key and a new key
+        db.set(self.key.clone(), self.value.clone());
+        db.notify(Events::STRING, "set", &self.key);
+        Frame::Simple("OK".to_string())
+    }
+
     pub async fn apply(&self, db: &mut Database, conn: &mut Connection) -> Result<(), super::Error> {
         // generic behavior handling without knowing underlying storage and connection?
-        /// interfaces for send command, receive command and get data
-        /// 
-        /// 
-        match db.set(self.key.clone(), self.value.clone()) {
-            // exisiting key
-            Some(_bs) => conn.write_frame(Frame::Simple("OK".to_string())).await?,
-            // new key
-            None => conn.write_frame(Frame::Simple("OK".to_string())).await?,
-        };
+        // interfaces for send command, receive command and get data
+        conn.write_frame(self.execute(db)).await?;
         Ok(())
-        
     }
 }
+
+/// `MSET key value [key value ...]`, a `SET` for every pair under a single lock
+#[derive(Debug)]
+pub struct MSet {
+    sets: Vec<Set>,
+}
+
+impl MSet {
+    pub fn new(sets: Vec<Set>) -> Self {
+        MSet { sets }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        into_frame("mset", self.sets)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(MSet::new(sets_from_frame(it)?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        for set in self.sets.iter() {
+            set.execute(db);
+        }
+        Frame::Simple("OK".to_string())
+    }
+}
+
+/// `MSETNX key value [key value ...]`, like `MSET` but sets nothing at all
+/// if any of the keys exists
+#[derive(Debug)]
+pub struct MSetNx {
+    sets: Vec<Set>,
+}
+
+impl MSetNx {
+    pub fn new(sets: Vec<Set>) -> Self {
+        MSetNx { sets }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        into_frame("msetnx", self.sets)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(MSetNx::new(sets_from_frame(it)?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        if self.sets.iter().any(|set| db.contains(&set.key)) {
+            return Frame::Integer(0);
+        }
+        for set in self.sets.iter() {
+            set.execute(db);
+        }
+        Frame::Integer(1)
+    }
+}
+
+fn into_frame(name: &'static str, sets: Vec<Set>) -> Frame {
+    let mut frame = Frame::new_array_frame();
+    frame.push_bulk(Bytes::from(name));
+    for set in sets {
+        frame.push_bulk(Bytes::from(set.key));
+        frame.push_bulk(set.value);
+    }
+    frame
+}
+
+fn sets_from_frame(it: &mut dyn Parse) -> Result<Vec<Set>, Error> {
+    let mut sets = vec![Set::from_frame(it)?];
+    while it.has_next() {
+        sets.push(Set::from_frame(it)?);
+    }
+    Ok(sets)
+}
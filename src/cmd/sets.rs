@@ -0,0 +1,585 @@
+//! Set commands
+//!
+//! See `types::set` for the encodings. A set that loses its last member is
+//! removed. The algebra commands treat a missing key as an empty set, and
+//! fail if any key holds something else than a set.
+
+use bytes::Bytes;
+
+use super::random_count;
+use crate::{
+    database::{Database, Value, WrongType},
+    frame::{Error, Frame, Parse},
+    notify::Events,
+    types::Set,
+};
+
+fn cmd_frame(name: &'static str, key: String) -> Frame {
+    let mut frame = Frame::new_array_frame();
+    frame.push_bulk(Bytes::from(name));
+    frame.push_bulk(Bytes::from(key));
+    frame
+}
+
+fn members_from_frame(it: &mut dyn Parse) -> Result<Vec<Bytes>, Error> {
+    let mut members = vec![it.next_bytes()?];
+    while it.has_next() {
+        members.push(it.next_bytes()?);
+    }
+    Ok(members)
+}
+
+fn members_reply(members: impl Iterator<Item = Bytes>) -> Frame {
+    Frame::Array(members.map(Frame::Bulk).collect())
+}
+
+/// `SADD key member [member ...]`, replies with the number of new members
+#[derive(Debug)]
+pub struct SAdd {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+impl SAdd {
+    pub fn new(key: &str, members: Vec<Bytes>) -> Self {
+        SAdd { key: key.to_string(), members }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("sadd", self.key);
+        for member in self.members {
+            frame.push_bulk(member);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(SAdd { key, members: members_from_frame(it)? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let max_intset = db.config().set_max_intset_entries;
+        let set = match db.set_entry(&self.key) {
+            Ok(set) => set,
+            Err(e) => return e.into(),
+        };
+        let added = self.members.iter().filter(|member| set.insert((*member).clone(), max_intset)).count();
+        if added > 0 {
+            db.notify(Events::SET, "sadd", &self.key);
+        }
+        Frame::Integer(added as i64)
+    }
+}
+
+/// `SREM key member [member ...]`, replies with the number of removed members
+#[derive(Debug)]
+pub struct SRem {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+impl SRem {
+    pub fn new(key: &str, members: Vec<Bytes>) -> Self {
+        SRem { key: key.to_string(), members }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("srem", self.key);
+        for member in self.members {
+            frame.push_bulk(member);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(SRem { key, members: members_from_frame(it)? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let set = match db.get_set_mut(&self.key) {
+            Ok(Some(set)) => set,
+            Ok(None) => return Frame::Integer(0),
+            Err(e) => return e.into(),
+        };
+        let removed = self.members.iter().filter(|member| set.remove(member)).count();
+        if removed > 0 {
+            db.notify(Events::SET, "srem", &self.key);
+        }
+        db.remove_if_empty(&self.key);
+        Frame::Integer(removed as i64)
+    }
+}
+
+/// `SMEMBERS key`
+#[derive(Debug)]
+pub struct SMembers {
+    key: String,
+}
+
+impl SMembers {
+    pub fn new(key: &str) -> Self {
+        SMembers { key: key.to_string() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        cmd_frame("smembers", self.key)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(SMembers { key: it.next_string()? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.get_set(&self.key) {
+            Ok(Some(set)) => members_reply(set.iter()),
+            Ok(None) => Frame::Array(vec![]),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// `SISMEMBER key member`
+#[derive(Debug)]
+pub struct SIsMember {
+    key: String,
+    member: Bytes,
+}
+
+impl SIsMember {
+    pub fn new(key: &str, member: Bytes) -> Self {
+        SIsMember { key: key.to_string(), member }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("sismember", self.key);
+        frame.push_bulk(self.member);
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(SIsMember { key, member: it.next_bytes()? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.get_set(&self.key) {
+            Ok(set) => Frame::Integer(set.is_some_and(|set| set.contains(&self.member)) as i64),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// `SMISMEMBER key member [member ...]`, replies with `1` or `0` for each member
+#[derive(Debug)]
+pub struct SMIsMember {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+impl SMIsMember {
+    pub fn new(key: &str, members: Vec<Bytes>) -> Self {
+        SMIsMember { key: key.to_string(), members }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("smismember", self.key);
+        for member in self.members {
+            frame.push_bulk(member);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(SMIsMember { key, members: members_from_frame(it)? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let set = match db.get_set(&self.key) {
+            Ok(set) => set,
+            Err(e) => return e.into(),
+        };
+        let contains = |member: &Bytes| set.is_some_and(|set| set.contains(member));
+        Frame::Array(self.members.iter().map(|member| Frame::Integer(contains(member) as i64)).collect())
+    }
+}
+
+/// `SCARD key`, the number of members
+#[derive(Debug)]
+pub struct SCard {
+    key: String,
+}
+
+impl SCard {
+    pub fn new(key: &str) -> Self {
+        SCard { key: key.to_string() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        cmd_frame("scard", self.key)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(SCard { key: it.next_string()? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.get_set(&self.key) {
+            Ok(set) => Frame::Integer(set.map_or(0, |set| set.len()) as i64),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// `SPOP key [count]`, removes random members. Without a count the reply
+/// is one member or nil, with one it is an array.
+#[derive(Debug)]
+pub struct SPop {
+    key: String,
+    count: Option<u64>,
+}
+
+impl SPop {
+    pub fn new(key: &str, count: Option<u64>) -> Self {
+        SPop { key: key.to_string(), count }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("spop", self.key);
+        if let Some(count) = self.count {
+            frame.push_bulk(Bytes::from(count.to_string()));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        if !it.has_next() {
+            return Ok(SPop::new(&key, None));
+        }
+        match it.next_int()? {
+            n if n >= 0 => Ok(SPop::new(&key, Some(n as u64))),
+            _ => Err("value is out of range, must be positive".into()),
+        }
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let set = match db.get_set_mut(&self.key) {
+            Ok(Some(set)) => set,
+            Ok(None) if self.count.is_some() => return Frame::Array(vec![]),
+            Ok(None) => return Frame::Null,
+            Err(e) => return e.into(),
+        };
+        let popped = match self.count {
+            None => {
+                let member = set.random().unwrap();
+                set.remove(&member);
+                Frame::Bulk(member)
+            }
+            Some(count) => {
+                let members = set.random_distinct(count.min(usize::MAX as u64) as usize);
+                for member in members.iter() {
+                    set.remove(member);
+                }
+                members_reply(members.into_iter())
+            }
+        };
+        if self.count != Some(0) {
+            db.notify(Events::SET, "spop", &self.key);
+        }
+        db.remove_if_empty(&self.key);
+        popped
+    }
+}
+
+/// `SRANDMEMBER key [count]`, like `SPOP` without removing anything. A
+/// negative `count` returns `-count` members that may repeat.
+#[derive(Debug)]
+pub struct SRandMember {
+    key: String,
+    count: Option<i64>,
+}
+
+impl SRandMember {
+    pub fn new(key: &str, count: Option<i64>) -> Self {
+        SRandMember { key: key.to_string(), count }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("srandmember", self.key);
+        if let Some(count) = self.count {
+            frame.push_bulk(Bytes::from(count.to_string()));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        if !it.has_next() {
+            return Ok(SRandMember::new(&key, None));
+        }
+        let count = random_count(it)?;
+        Ok(SRandMember::new(&key, Some(count)))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let set = match db.get_set(&self.key) {
+            Ok(Some(set)) => set,
+            Ok(None) if self.count.is_some() => return Frame::Array(vec![]),
+            Ok(None) => return Frame::Null,
+            Err(e) => return e.into(),
+        };
+        match self.count {
+            None => Frame::Bulk(set.random().unwrap()),
+            Some(count) if count < 0 => {
+                members_reply((0..count.unsigned_abs()).map(|_| set.random().unwrap()))
+            }
+            Some(count) => members_reply(set.random_distinct(count as usize).into_iter()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Inter,
+    Union,
+    Diff,
+}
+
+/// The sets at `keys`, `None` for a missing key
+fn load_sets<'a>(db: &'a Database, keys: &[String]) -> Result<Vec<Option<&'a Set>>, WrongType> {
+    keys.iter().map(|key| db.get_set(key)).collect()
+}
+
+/// The members of all of `sets`, smallest set first so each candidate is
+/// looked up in the bigger ones. Stops after `limit` members.
+fn intersect(sets: &[Option<&Set>], limit: usize) -> Vec<Bytes> {
+    // a missing key is an empty set
+    let mut sets: Vec<&Set> = match sets.iter().copied().collect::<Option<_>>() {
+        Some(sets) => sets,
+        None => return vec![],
+    };
+    sets.sort_by_key(|set| set.len());
+    let (smallest, others) = match sets.split_first() {
+        Some(split) => split,
+        None => return vec![],
+    };
+    smallest
+        .iter()
+        .filter(|member| others.iter().all(|set| set.contains(member)))
+        .take(limit)
+        .collect()
+}
+
+fn apply(op: Op, sets: &[Option<&Set>], max_intset: usize) -> Set {
+    let mut result = Set::default();
+    match op {
+        Op::Inter => {
+            for member in intersect(sets, usize::MAX) {
+                result.insert(member, max_intset);
+            }
+        }
+        Op::Union => {
+            for set in sets.iter().flatten() {
+                for member in set.iter() {
+                    result.insert(member, max_intset);
+                }
+            }
+        }
+        Op::Diff => {
+            if let Some((Some(first), others)) = sets.split_first() {
+                for member in first.iter() {
+                    if !others.iter().flatten().any(|set| set.contains(&member)) {
+                        result.insert(member, max_intset);
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+fn keys_from_frame(it: &mut dyn Parse) -> Result<Vec<String>, Error> {
+    let mut keys = vec![it.next_string()?];
+    while it.has_next() {
+        keys.push(it.next_string()?);
+    }
+    Ok(keys)
+}
+
+/// The commands replying with the result of `$op` on the sets at `keys`,
+/// and the ones storing it at `destination`
+macro_rules! set_op_cmds {
+    ($(#[$doc:meta])* $name:ident, $cmd:literal, $(#[$store_doc:meta])* $store:ident, $store_cmd:literal, $op:expr) => {
+        $(#[$doc])*
+        #[derive(Debug)]
+        pub struct $name {
+            keys: Vec<String>,
+        }
+
+        impl $name {
+            pub fn new(keys: &[&str]) -> Self {
+                $name { keys: keys.iter().map(|key| key.to_string()).collect() }
+            }
+
+            pub fn into_frame(self) -> Frame {
+                let mut frame = Frame::new_array_frame();
+                frame.push_bulk(Bytes::from($cmd));
+                for key in self.keys {
+                    frame.push_bulk(Bytes::from(key));
+                }
+                frame
+            }
+
+            pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+                Ok($name { keys: keys_from_frame(it)? })
+            }
+
+            pub fn execute(&self, db: &mut Database) -> Frame {
+                let sets = match load_sets(db, &self.keys) {
+                    Ok(sets) => sets,
+                    Err(e) => return e.into(),
+                };
+                // the reply is never stored, no need for the compact encoding
+                members_reply(apply($op, &sets, 0).iter())
+            }
+        }
+
+        $(#[$store_doc])*
+        #[derive(Debug)]
+        pub struct $store {
+            destination: String,
+            keys: Vec<String>,
+        }
+
+        impl $store {
+            pub fn new(destination: &str, keys: &[&str]) -> Self {
+                $store {
+                    destination: destination.to_string(),
+                    keys: keys.iter().map(|key| key.to_string()).collect(),
+                }
+            }
+
+            pub fn into_frame(self) -> Frame {
+                let mut frame = cmd_frame($store_cmd, self.destination);
+                for key in self.keys {
+                    frame.push_bulk(Bytes::from(key));
+                }
+                frame
+            }
+
+            pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+                let destination = it.next_string()?;
+                Ok($store { destination, keys: keys_from_frame(it)? })
+            }
+
+            pub fn execute(&self, db: &mut Database) -> Frame {
+                let max_intset = db.config().set_max_intset_entries;
+                let result = match load_sets(db, &self.keys) {
+                    Ok(sets) => apply($op, &sets, max_intset),
+                    Err(e) => return e.into(),
+                };
+                let len = result.len();
+                // an empty result deletes the destination, like any empty set
+                if result.is_empty() {
+                    if db.remove(&self.destination).is_some() {
+                        db.notify(Events::GENERIC, "del", &self.destination);
+                    }
+                } else {
+                    db.insert(self.destination.clone(), Value::Set(result), None);
+                    db.notify(Events::SET, $store_cmd, &self.destination);
+                }
+                Frame::Integer(len as i64)
+            }
+        }
+    };
+}
+
+set_op_cmds!(
+    /// `SINTER key [key ...]`, the members in every set
+    SInter,
+    "sinter",
+    /// `SINTERSTORE destination key [key ...]`, replies with the size of the result
+    SInterStore,
+    "sinterstore",
+    Op::Inter
+);
+set_op_cmds!(
+    /// `SUNION key [key ...]`, the members in any set
+    SUnion,
+    "sunion",
+    /// `SUNIONSTORE destination key [key ...]`, replies with the size of the result
+    SUnionStore,
+    "sunionstore",
+    Op::Union
+);
+set_op_cmds!(
+    /// `SDIFF key [key ...]`, the members of the first set in none of the others
+    SDiff,
+    "sdiff",
+    /// `SDIFFSTORE destination key [key ...]`, replies with the size of the result
+    SDiffStore,
+    "sdiffstore",
+    Op::Diff
+);
+
+/// `SINTERCARD numkeys key [key ...] [LIMIT limit]`, the size of the
+/// intersection, counting stops at `limit` unless it is `0`
+#[derive(Debug)]
+pub struct SInterCard {
+    keys: Vec<String>,
+    limit: usize,
+}
+
+impl SInterCard {
+    pub fn new(keys: &[&str], limit: usize) -> Self {
+        SInterCard { keys: keys.iter().map(|key| key.to_string()).collect(), limit }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::new_array_frame();
+        frame.push_bulk(Bytes::from("sintercard"));
+        frame.push_bulk(Bytes::from(self.keys.len().to_string()));
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key));
+        }
+        if self.limit > 0 {
+            frame.push_bulk(Bytes::from("limit"));
+            frame.push_bulk(Bytes::from(self.limit.to_string()));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let numkeys = match it.next_int()? {
+            n if n > 0 => n,
+            _ => return Err("numkeys should be greater than 0".into()),
+        };
+        let mut keys = vec![];
+        for _ in 0..numkeys {
+            keys.push(it.next_string()?);
+        }
+        let mut limit = 0;
+        if it.has_next() {
+            if it.next_string()?.to_lowercase() != "limit" {
+                return Err("syntax error".into());
+            }
+            limit = match it.next_int()? {
+                n if n >= 0 => n as usize,
+                _ => return Err("LIMIT can't be negative".into()),
+            };
+        }
+        Ok(SInterCard { keys, limit })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let sets = match load_sets(db, &self.keys) {
+            Ok(sets) => sets,
+            Err(e) => return e.into(),
+        };
+        let limit = if self.limit == 0 { usize::MAX } else { self.limit };
+        Frame::Integer(intersect(&sets, limit).len() as i64)
+    }
+}
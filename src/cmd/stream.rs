@@ -0,0 +1,1382 @@
+//! Stream commands
+//!
+//! See `types::stream`. Times are unix milliseconds: auto generated IDs
+//! are based on them, and so are the idle times of pending entries. An
+//! entry is replied as its ID and the array of its fields and values.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+
+use crate::{
+    database::Database,
+    frame::{Error, Frame, Parse},
+    notify::Events,
+    types::stream::{Claim, Fields, StreamId, Trim},
+};
+
+const INVALID_ID: &str = "Invalid stream ID specified as stream command argument";
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as u64)
+}
+
+fn cmd_frame(name: &'static str, key: String) -> Frame {
+    let mut frame = Frame::new_array_frame();
+    frame.push_bulk(Bytes::from(name));
+    frame.push_bulk(Bytes::from(key));
+    frame
+}
+
+fn id_bulk(id: StreamId) -> Bytes {
+    Bytes::from(id.to_string())
+}
+
+fn parse_id(bs: &[u8], default_seq: u64) -> Result<StreamId, Error> {
+    StreamId::parse(bs, default_seq).ok_or_else(|| INVALID_ID.into())
+}
+
+/// The start of an interval, `-` for the first ID, `(` in front to exclude it
+fn parse_start(bs: &[u8]) -> Result<StreamId, Error> {
+    match bs {
+        b"-" => Ok(StreamId::MIN),
+        b"+" => Ok(StreamId::MAX),
+        [b'(', id @ ..] => parse_id(id, 0)?.next().ok_or_else(|| "invalid start ID for the interval".into()),
+        id => parse_id(id, 0),
+    }
+}
+
+/// The end of an interval, `+` for the last ID, `(` in front to exclude it
+fn parse_end(bs: &[u8]) -> Result<StreamId, Error> {
+    match bs {
+        b"-" => Ok(StreamId::MIN),
+        b"+" => Ok(StreamId::MAX),
+        [b'(', id @ ..] => parse_id(id, u64::MAX)?.prev().ok_or_else(|| "invalid end ID for the interval".into()),
+        id => parse_id(id, u64::MAX),
+    }
+}
+
+fn entry_frame(id: StreamId, fields: Option<&Fields>) -> Frame {
+    let fields = match fields {
+        Some(fields) => {
+            Frame::Array(fields.iter().flat_map(|(f, v)| [Frame::Bulk(f.clone()), Frame::Bulk(v.clone())]).collect())
+        }
+        // deleted since it was delivered
+        None => Frame::Null,
+    };
+    Frame::Array(vec![Frame::Bulk(id_bulk(id)), fields])
+}
+
+fn no_group(key: &str, group: &[u8], cmd: &str) -> Frame {
+    Frame::Error(format!(
+        "NOGROUP No such key '{}' or consumer group '{}'{}",
+        key,
+        String::from_utf8_lossy(group),
+        cmd
+    ))
+}
+
+/// `COUNT count`, `0` meaning no limit
+fn count_from_frame(it: &mut dyn Parse) -> Result<usize, Error> {
+    match it.next_int()? {
+        0 => Ok(usize::MAX),
+        n if n > 0 => Ok(n as usize),
+        _ => Err("value is out of range, must be positive".into()),
+    }
+}
+
+/// `BLOCK milliseconds`, `0` to wait forever
+fn block_from_frame(it: &mut dyn Parse) -> Result<Option<Duration>, Error> {
+    match it.next_int()? {
+        0 => Ok(None),
+        n if n > 0 => Ok(Some(Duration::from_millis(n as u64))),
+        _ => Err("timeout is negative".into()),
+    }
+}
+
+/// `MAXLEN|MINID [=|~] threshold [LIMIT count]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TrimArgs {
+    trim: Trim,
+    // `~` trims exactly too, but allows a `LIMIT`
+    approx: bool,
+    limit: Option<usize>,
+}
+
+impl TrimArgs {
+    /// After `MAXLEN` or `MINID`, as `by`
+    fn from_frame(by: &str, it: &mut dyn Parse) -> Result<Self, Error> {
+        let mut threshold = it.next_bytes()?;
+        let mut approx = false;
+        if threshold[..] == b"="[..] || threshold[..] == b"~"[..] {
+            approx = threshold[..] == b"~"[..];
+            threshold = it.next_bytes()?;
+        }
+        let trim = if by == "maxlen" {
+            match std::str::from_utf8(&threshold).ok().and_then(|s| s.parse::<i64>().ok()) {
+                Some(n) if n >= 0 => Trim::MaxLen(n as u64),
+                Some(_) => return Err("The MAXLEN argument must be >= 0.".into()),
+                None => return Err("value is not an integer or out of range".into()),
+            }
+        } else {
+            Trim::MinId(parse_id(&threshold, 0)?)
+        };
+        Ok(TrimArgs { trim, approx, limit: None })
+    }
+
+    /// `LIMIT count` right after the threshold
+    fn limit_from_frame(&mut self, it: &mut dyn Parse) -> Result<(), Error> {
+        if !self.approx {
+            return Err("syntax error, LIMIT cannot be used without the special ~ option".into());
+        }
+        self.limit = match it.next_int()? {
+            0 => None,
+            n if n > 0 => Some(n as usize),
+            _ => return Err("The LIMIT argument must be >= 0.".into()),
+        };
+        Ok(())
+    }
+
+    fn push_args(&self, frame: &mut Frame) {
+        let (by, threshold) = match self.trim {
+            Trim::MaxLen(max) => ("maxlen", max.to_string()),
+            Trim::MinId(id) => ("minid", id.to_string()),
+        };
+        frame.push_bulk(Bytes::from(by));
+        frame.push_bulk(Bytes::from(if self.approx { "~" } else { "=" }));
+        frame.push_bulk(Bytes::from(threshold));
+        if let Some(limit) = self.limit {
+            frame.push_bulk(Bytes::from("limit"));
+            frame.push_bulk(Bytes::from(limit.to_string()));
+        }
+    }
+}
+
+/// The ID `XADD` gives an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AddId {
+    /// `*`
+    Auto,
+    /// `ms-*`
+    AutoSeq(u64),
+    Explicit(StreamId),
+}
+
+/// `XADD key [NOMKSTREAM] [MAXLEN|MINID [=|~] threshold [LIMIT count]] *|id field value [field value ...]`,
+/// replies with the ID of the new entry
+#[derive(Debug)]
+pub struct XAdd {
+    key: String,
+    nomkstream: bool,
+    trim: Option<TrimArgs>,
+    id: AddId,
+    fields: Fields,
+}
+
+impl XAdd {
+    /// An entry with an auto generated ID
+    pub fn new(key: &str) -> Self {
+        XAdd { key: key.to_string(), nomkstream: false, trim: None, id: AddId::Auto, fields: vec![] }
+    }
+
+    pub fn id(mut self, id: StreamId) -> Self {
+        self.id = AddId::Explicit(id);
+        self
+    }
+
+    pub fn field(mut self, field: Bytes, value: Bytes) -> Self {
+        self.fields.push((field, value));
+        self
+    }
+
+    /// Do not create the stream if it is missing, reply nil instead
+    pub fn nomkstream(mut self) -> Self {
+        self.nomkstream = true;
+        self
+    }
+
+    /// Evict the oldest entries past `max`, or the entries before an ID
+    pub fn trim(mut self, trim: Trim) -> Self {
+        self.trim = Some(TrimArgs { trim, approx: false, limit: None });
+        self
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("xadd", self.key);
+        if self.nomkstream {
+            frame.push_bulk(Bytes::from("nomkstream"));
+        }
+        if let Some(trim) = self.trim {
+            trim.push_args(&mut frame);
+        }
+        frame.push_bulk(match self.id {
+            AddId::Auto => Bytes::from("*"),
+            AddId::AutoSeq(ms) => Bytes::from(format!("{}-*", ms)),
+            AddId::Explicit(id) => id_bulk(id),
+        });
+        for (field, value) in self.fields {
+            frame.push_bulk(field);
+            frame.push_bulk(value);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let mut xadd = XAdd::new(&it.next_string()?);
+        let id = loop {
+            let arg = it.next_bytes()?;
+            match String::from_utf8_lossy(&arg).to_lowercase().as_str() {
+                "nomkstream" => xadd.nomkstream = true,
+                by @ ("maxlen" | "minid") => xadd.trim = Some(TrimArgs::from_frame(by, it)?),
+                "limit" => match xadd.trim.as_mut() {
+                    Some(trim) => trim.limit_from_frame(it)?,
+                    None => return Err("syntax error".into()),
+                },
+                _ => break arg,
+            }
+        };
+        xadd.id = match &id[..] {
+            b"*" => AddId::Auto,
+            [ms @ .., b'-', b'*'] => match std::str::from_utf8(ms).ok().and_then(|ms| ms.parse().ok()) {
+                Some(ms) => AddId::AutoSeq(ms),
+                None => return Err(INVALID_ID.into()),
+            },
+            id => AddId::Explicit(parse_id(id, 0)?),
+        };
+        loop {
+            let field = it.next_bytes()?;
+            xadd.fields.push((field, it.next_bytes()?));
+            if !it.has_next() {
+                break;
+            }
+        }
+        Ok(xadd)
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        // find the ID before creating the stream, a missing stream is
+        // created only if the entry can be added
+        let last_id = match db.get_stream(&self.key) {
+            Ok(Some(stream)) => stream.last_id(),
+            Ok(None) if self.nomkstream => return Frame::Null,
+            Ok(None) => StreamId::MIN,
+            Err(e) => return e.into(),
+        };
+        let id = match self.id {
+            AddId::Auto => {
+                let ms = now_ms();
+                if ms > last_id.ms {
+                    Some(StreamId::new(ms, 0))
+                } else {
+                    last_id.next()
+                }
+            }
+            AddId::AutoSeq(ms) if ms == last_id.ms => last_id.next().filter(|id| id.ms == ms),
+            AddId::AutoSeq(ms) if ms > last_id.ms => Some(StreamId::new(ms, 0)),
+            AddId::AutoSeq(_) => Some(StreamId::MIN),
+            AddId::Explicit(id) => Some(id),
+        };
+        let id = match id {
+            Some(id) if id == StreamId::MIN => {
+                return Frame::Error("ERR The ID specified in XADD must be greater than 0-0".to_string())
+            }
+            Some(id) if id > last_id => id,
+            Some(_) => {
+                return Frame::Error(
+                    "ERR The ID specified in XADD is equal or smaller than the target stream top item".to_string(),
+                )
+            }
+            None if self.id == AddId::Auto => {
+                return Frame::Error(
+                    "ERR The stream has exhausted the last possible ID, unable to add more items".to_string(),
+                )
+            }
+            None => {
+                return Frame::Error(
+                    "ERR The ID specified in XADD is equal or smaller than the target stream top item".to_string(),
+                )
+            }
+        };
+
+        let stream = db.stream_entry(&self.key).unwrap();
+        stream.insert(id, self.fields.clone());
+        let trimmed = self.trim.map_or(0, |trim| stream.trim(trim.trim, trim.limit));
+        db.notify(Events::STREAM, "xadd", &self.key);
+        if trimmed > 0 {
+            db.notify(Events::STREAM, "xtrim", &self.key);
+        }
+        Frame::Bulk(id_bulk(id))
+    }
+}
+
+/// `XLEN key`, the number of entries
+#[derive(Debug)]
+pub struct XLen {
+    key: String,
+}
+
+impl XLen {
+    pub fn new(key: &str) -> Self {
+        XLen { key: key.to_string() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        cmd_frame("xlen", self.key)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(XLen { key: it.next_string()? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.get_stream(&self.key) {
+            Ok(stream) => Frame::Integer(stream.map_or(0, |stream| stream.len()) as i64),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// `XRANGE key start end [COUNT count]`, or `XREVRANGE key end start
+/// [COUNT count]` for the entries from the last one down
+#[derive(Debug)]
+pub struct XRange {
+    key: String,
+    start: StreamId,
+    end: StreamId,
+    count: Option<usize>,
+    rev: bool,
+}
+
+impl XRange {
+    pub fn new(key: &str, start: StreamId, end: StreamId) -> Self {
+        XRange { key: key.to_string(), start, end, count: None, rev: false }
+    }
+
+    pub fn count(mut self, count: usize) -> Self {
+        self.count = Some(count);
+        self
+    }
+
+    /// `XREVRANGE`
+    pub fn rev(mut self) -> Self {
+        self.rev = true;
+        self
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame(if self.rev { "xrevrange" } else { "xrange" }, self.key);
+        let (first, second) = if self.rev { (self.end, self.start) } else { (self.start, self.end) };
+        frame.push_bulk(id_bulk(first));
+        frame.push_bulk(id_bulk(second));
+        if let Some(count) = self.count {
+            frame.push_bulk(Bytes::from("count"));
+            frame.push_bulk(Bytes::from(count.to_string()));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse, rev: bool) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let first = it.next_bytes()?;
+        let second = it.next_bytes()?;
+        let (start, end) = if rev { (second, first) } else { (first, second) };
+        let mut xrange = XRange::new(&key, parse_start(&start)?, parse_end(&end)?);
+        xrange.rev = rev;
+        if it.has_next() {
+            if it.next_string()?.to_lowercase() != "count" {
+                return Err("syntax error".into());
+            }
+            // a negative count is no entries at all
+            xrange.count = Some(usize::try_from(it.next_int()?).unwrap_or(0));
+        }
+        Ok(xrange)
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let stream = match db.get_stream(&self.key) {
+            Ok(Some(stream)) => stream,
+            Ok(None) => return Frame::Array(vec![]),
+            Err(e) => return e.into(),
+        };
+        let count = self.count.unwrap_or(usize::MAX);
+        let range = stream.range(self.start, self.end);
+        let entries: Vec<Frame> = if self.rev {
+            range.rev().take(count).map(|(id, fields)| entry_frame(*id, Some(fields))).collect()
+        } else {
+            range.take(count).map(|(id, fields)| entry_frame(*id, Some(fields))).collect()
+        };
+        Frame::Array(entries)
+    }
+}
+
+/// `XDEL key id [id ...]`, replies with the number of deleted entries
+#[derive(Debug)]
+pub struct XDel {
+    key: String,
+    ids: Vec<StreamId>,
+}
+
+impl XDel {
+    pub fn new(key: &str, ids: Vec<StreamId>) -> Self {
+        XDel { key: key.to_string(), ids }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("xdel", self.key);
+        for id in self.ids {
+            frame.push_bulk(id_bulk(id));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let mut ids = vec![parse_id(&it.next_bytes()?, 0)?];
+        while it.has_next() {
+            ids.push(parse_id(&it.next_bytes()?, 0)?);
+        }
+        Ok(XDel { key, ids })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let deleted = match db.get_stream_mut(&self.key) {
+            Ok(Some(stream)) => self.ids.iter().filter(|id| stream.remove(**id)).count(),
+            Ok(None) => 0,
+            Err(e) => return e.into(),
+        };
+        if deleted > 0 {
+            db.notify(Events::STREAM, "xdel", &self.key);
+        }
+        Frame::Integer(deleted as i64)
+    }
+}
+
+/// `XTRIM key MAXLEN|MINID [=|~] threshold [LIMIT count]`, replies with the
+/// number of evicted entries
+#[derive(Debug)]
+pub struct XTrim {
+    key: String,
+    trim: TrimArgs,
+}
+
+impl XTrim {
+    pub fn new(key: &str, trim: Trim) -> Self {
+        XTrim { key: key.to_string(), trim: TrimArgs { trim, approx: false, limit: None } }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("xtrim", self.key);
+        self.trim.push_args(&mut frame);
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let by = it.next_string()?.to_lowercase();
+        if by != "maxlen" && by != "minid" {
+            return Err("syntax error".into());
+        }
+        let mut trim = TrimArgs::from_frame(&by, it)?;
+        if it.has_next() {
+            if it.next_string()?.to_lowercase() != "limit" {
+                return Err("syntax error".into());
+            }
+            trim.limit_from_frame(it)?;
+        }
+        Ok(XTrim { key, trim })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let trimmed = match db.get_stream_mut(&self.key) {
+            Ok(Some(stream)) => stream.trim(self.trim.trim, self.trim.limit),
+            Ok(None) => 0,
+            Err(e) => return e.into(),
+        };
+        if trimmed > 0 {
+            db.notify(Events::STREAM, "xtrim", &self.key);
+        }
+        Frame::Integer(trimmed as i64)
+    }
+}
+
+/// Where `XREAD` reads a stream from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadFrom {
+    /// `$`, the entries added from now on
+    Last,
+    /// the entries after an ID
+    After(StreamId),
+}
+
+/// `XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]`
+///
+/// Replies with each stream that has entries after its ID, and those
+/// entries, or a null array if none has. With `BLOCK` it waits for an entry
+/// instead, see `Request::blocking_keys`.
+#[derive(Debug)]
+pub struct XRead {
+    keys: Vec<String>,
+    from: Vec<ReadFrom>,
+    count: usize,
+    block: Option<Option<Duration>>,
+}
+
+impl XRead {
+    pub fn new(streams: &[(&str, ReadFrom)]) -> Self {
+        XRead {
+            keys: streams.iter().map(|(key, _)| key.to_string()).collect(),
+            from: streams.iter().map(|(_, from)| *from).collect(),
+            count: usize::MAX,
+            block: None,
+        }
+    }
+
+    pub fn count(mut self, count: usize) -> Self {
+        self.count = count;
+        self
+    }
+
+    /// Wait for entries, forever if `timeout` is `None`
+    pub fn block(mut self, timeout: Option<Duration>) -> Self {
+        self.block = Some(timeout);
+        self
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// `None` if it does not block, `Some(None)` if it blocks forever
+    pub fn timeout(&self) -> Option<Option<Duration>> {
+        self.block
+    }
+
+    /// Resolve `$` to the last ID, so that a blocked `XREAD` retried later
+    /// still reads the entries added after it blocked
+    pub(super) fn pin(&mut self, db: &Database) {
+        for (key, from) in self.keys.iter().zip(self.from.iter_mut()) {
+            if *from == ReadFrom::Last {
+                let last_id = db.get_stream(key).ok().flatten().map_or(StreamId::MIN, |stream| stream.last_id());
+                *from = ReadFrom::After(last_id);
+            }
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::new_array_frame();
+        frame.push_bulk(Bytes::from("xread"));
+        if self.count != usize::MAX {
+            frame.push_bulk(Bytes::from("count"));
+            frame.push_bulk(Bytes::from(self.count.to_string()));
+        }
+        if let Some(timeout) = self.block {
+            frame.push_bulk(Bytes::from("block"));
+            frame.push_bulk(Bytes::from(timeout.map_or(0, |timeout| timeout.as_millis()).to_string()));
+        }
+        frame.push_bulk(Bytes::from("streams"));
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key));
+        }
+        for from in self.from {
+            frame.push_bulk(match from {
+                ReadFrom::Last => Bytes::from("$"),
+                ReadFrom::After(id) => id_bulk(id),
+            });
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let mut xread = XRead::new(&[]);
+        loop {
+            match it.next_string()?.to_lowercase().as_str() {
+                "count" => xread.count = count_from_frame(it)?,
+                "block" => xread.block = Some(block_from_frame(it)?),
+                "streams" => break,
+                _ => return Err("syntax error".into()),
+            }
+        }
+        let args = streams_from_frame(it, "xread")?;
+        let (keys, ids) = args.split_at(args.len() / 2);
+        xread.keys = keys.iter().map(|key| String::from_utf8_lossy(key).into_owned()).collect();
+        for id in ids {
+            xread.from.push(match &id[..] {
+                b"$" => ReadFrom::Last,
+                id => ReadFrom::After(parse_id(id, 0)?),
+            });
+        }
+        Ok(xread)
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let mut streams = vec![];
+        for (key, from) in self.keys.iter().zip(self.from.iter()) {
+            let stream = match db.get_stream(key) {
+                Ok(Some(stream)) => stream,
+                Ok(None) => continue,
+                Err(e) => return e.into(),
+            };
+            let start = match from {
+                ReadFrom::Last => continue,
+                ReadFrom::After(id) => match id.next() {
+                    Some(start) => start,
+                    None => continue,
+                },
+            };
+            let entries: Vec<Frame> = stream
+                .range(start, StreamId::MAX)
+                .take(self.count)
+                .map(|(id, fields)| entry_frame(*id, Some(fields)))
+                .collect();
+            if !entries.is_empty() {
+                streams.push(Frame::Array(vec![Frame::Bulk(Bytes::from(key.clone())), Frame::Array(entries)]));
+            }
+        }
+        if streams.is_empty() {
+            Frame::NullArray
+        } else {
+            Frame::Array(streams)
+        }
+    }
+}
+
+/// The `key [key ...] id [id ...]` after `STREAMS`, as many IDs as keys
+fn streams_from_frame(it: &mut dyn Parse, cmd: &str) -> Result<Vec<Bytes>, Error> {
+    let mut args = vec![];
+    while it.has_next() {
+        args.push(it.next_bytes()?);
+    }
+    if args.is_empty() || args.len() % 2 != 0 {
+        return Err(format!(
+            "Unbalanced '{}' list of streams: for each stream key an ID or '$' must be specified.",
+            cmd
+        )
+        .into());
+    }
+    Ok(args)
+}
+
+/// `XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK] STREAMS key [key ...] id [id ...]`
+///
+/// An ID of `>` reads the entries never delivered to the group, adding
+/// them to the pending entries of `consumer` unless `NOACK`. Any other ID
+/// reads back the pending entries of `consumer` after it.
+#[derive(Debug)]
+pub struct XReadGroup {
+    group: Bytes,
+    consumer: Bytes,
+    keys: Vec<String>,
+    // `None` for `>`
+    after: Vec<Option<StreamId>>,
+    count: usize,
+    block: Option<Option<Duration>>,
+    noack: bool,
+}
+
+impl XReadGroup {
+    /// `None` as the ID of a stream stands for `>`
+    pub fn new(group: Bytes, consumer: Bytes, streams: &[(&str, Option<StreamId>)]) -> Self {
+        XReadGroup {
+            group,
+            consumer,
+            keys: streams.iter().map(|(key, _)| key.to_string()).collect(),
+            after: streams.iter().map(|(_, after)| *after).collect(),
+            count: usize::MAX,
+            block: None,
+            noack: false,
+        }
+    }
+
+    pub fn count(mut self, count: usize) -> Self {
+        self.count = count;
+        self
+    }
+
+    /// Wait for new entries, forever if `timeout` is `None`
+    pub fn block(mut self, timeout: Option<Duration>) -> Self {
+        self.block = Some(timeout);
+        self
+    }
+
+    pub fn noack(mut self) -> Self {
+        self.noack = true;
+        self
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// `None` if it does not block, `Some(None)` if it blocks forever
+    pub fn timeout(&self) -> Option<Option<Duration>> {
+        self.block
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::new_array_frame();
+        frame.push_bulk(Bytes::from("xreadgroup"));
+        frame.push_bulk(Bytes::from("group"));
+        frame.push_bulk(self.group);
+        frame.push_bulk(self.consumer);
+        if self.count != usize::MAX {
+            frame.push_bulk(Bytes::from("count"));
+            frame.push_bulk(Bytes::from(self.count.to_string()));
+        }
+        if let Some(timeout) = self.block {
+            frame.push_bulk(Bytes::from("block"));
+            frame.push_bulk(Bytes::from(timeout.map_or(0, |timeout| timeout.as_millis()).to_string()));
+        }
+        if self.noack {
+            frame.push_bulk(Bytes::from("noack"));
+        }
+        frame.push_bulk(Bytes::from("streams"));
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key));
+        }
+        for after in self.after {
+            frame.push_bulk(after.map_or(Bytes::from(">"), id_bulk));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        if it.next_string()?.to_lowercase() != "group" {
+            return Err("syntax error".into());
+        }
+        let group = it.next_bytes()?;
+        let mut xreadgroup = XReadGroup::new(group, it.next_bytes()?, &[]);
+        loop {
+            match it.next_string()?.to_lowercase().as_str() {
+                "count" => xreadgroup.count = count_from_frame(it)?,
+                "block" => xreadgroup.block = Some(block_from_frame(it)?),
+                "noack" => xreadgroup.noack = true,
+                "streams" => break,
+                _ => return Err("syntax error".into()),
+            }
+        }
+        let args = streams_from_frame(it, "xreadgroup")?;
+        let (keys, ids) = args.split_at(args.len() / 2);
+        xreadgroup.keys = keys.iter().map(|key| String::from_utf8_lossy(key).into_owned()).collect();
+        for id in ids {
+            xreadgroup.after.push(match &id[..] {
+                b">" => None,
+                id => Some(parse_id(id, 0)?),
+            });
+        }
+        Ok(xreadgroup)
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let now = now_ms();
+        let mut streams = vec![];
+        for (key, after) in self.keys.iter().zip(self.after.iter()) {
+            let stream = match db.get_stream_mut(key) {
+                Ok(Some(stream)) => stream,
+                Ok(None) => return no_group(key, &self.group, " in XREADGROUP with GROUP option"),
+                Err(e) => return e.into(),
+            };
+            let entries: Vec<Frame> = match after {
+                None => match stream.read_group(&self.group, &self.consumer, self.count, self.noack, now) {
+                    Some(entries) => entries.iter().map(|(id, fields)| entry_frame(*id, Some(fields))).collect(),
+                    None => return no_group(key, &self.group, " in XREADGROUP with GROUP option"),
+                },
+                Some(after) => match stream.read_history(&self.group, &self.consumer, *after, self.count, now) {
+                    Some(entries) => entries.iter().map(|(id, fields)| entry_frame(*id, fields.as_ref())).collect(),
+                    None => return no_group(key, &self.group, " in XREADGROUP with GROUP option"),
+                },
+            };
+            // the history of a stream is replied even if it is empty
+            if !entries.is_empty() || after.is_some() {
+                streams.push(Frame::Array(vec![Frame::Bulk(Bytes::from(key.clone())), Frame::Array(entries)]));
+            }
+        }
+        if streams.is_empty() {
+            Frame::NullArray
+        } else {
+            Frame::Array(streams)
+        }
+    }
+}
+
+/// `XGROUP CREATE|SETID|DESTROY|CREATECONSUMER|DELCONSUMER key group ...`
+#[derive(Debug)]
+pub enum XGroup {
+    /// `XGROUP CREATE key group id|$ [MKSTREAM]`, `None` for `$`
+    Create { key: String, group: Bytes, id: Option<StreamId>, mkstream: bool },
+    /// `XGROUP SETID key group id|$`, `None` for `$`
+    SetId { key: String, group: Bytes, id: Option<StreamId> },
+    /// `XGROUP DESTROY key group`
+    Destroy { key: String, group: Bytes },
+    /// `XGROUP CREATECONSUMER key group consumer`
+    CreateConsumer { key: String, group: Bytes, consumer: Bytes },
+    /// `XGROUP DELCONSUMER key group consumer`, replies with the number of
+    /// pending entries the consumer had
+    DelConsumer { key: String, group: Bytes, consumer: Bytes },
+}
+
+impl XGroup {
+    pub fn into_frame(self) -> Frame {
+        let mut frame = Frame::new_array_frame();
+        frame.push_bulk(Bytes::from("xgroup"));
+        let id_or_last = |id: Option<StreamId>| id.map_or(Bytes::from("$"), id_bulk);
+        let args = match self {
+            XGroup::Create { key, group, id, mkstream } => {
+                let mut args = vec![Bytes::from("create"), Bytes::from(key), group, id_or_last(id)];
+                if mkstream {
+                    args.push(Bytes::from("mkstream"));
+                }
+                args
+            }
+            XGroup::SetId { key, group, id } => vec![Bytes::from("setid"), Bytes::from(key), group, id_or_last(id)],
+            XGroup::Destroy { key, group } => vec![Bytes::from("destroy"), Bytes::from(key), group],
+            XGroup::CreateConsumer { key, group, consumer } => {
+                vec![Bytes::from("createconsumer"), Bytes::from(key), group, consumer]
+            }
+            XGroup::DelConsumer { key, group, consumer } => {
+                vec![Bytes::from("delconsumer"), Bytes::from(key), group, consumer]
+            }
+        };
+        for arg in args {
+            frame.push_bulk(arg);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let subcommand = it.next_string()?.to_lowercase();
+        let key = it.next_string()?;
+        let group = it.next_bytes()?;
+        let id_or_last = |bs: Bytes| match &bs[..] {
+            b"$" => Ok(None),
+            id => parse_id(id, 0).map(Some),
+        };
+        Ok(match subcommand.as_str() {
+            "create" => {
+                let id = id_or_last(it.next_bytes()?)?;
+                let mut mkstream = false;
+                while it.has_next() {
+                    match it.next_string()?.to_lowercase().as_str() {
+                        "mkstream" => mkstream = true,
+                        // the lag of a group is not tracked, accept it anyway
+                        "entriesread" => {
+                            it.next_int()?;
+                        }
+                        _ => return Err("syntax error".into()),
+                    }
+                }
+                XGroup::Create { key, group, id, mkstream }
+            }
+            "setid" => {
+                let id = id_or_last(it.next_bytes()?)?;
+                if it.has_next() {
+                    if it.next_string()?.to_lowercase() != "entriesread" {
+                        return Err("syntax error".into());
+                    }
+                    it.next_int()?;
+                }
+                XGroup::SetId { key, group, id }
+            }
+            "destroy" => XGroup::Destroy { key, group },
+            "createconsumer" => XGroup::CreateConsumer { key, group, consumer: it.next_bytes()? },
+            "delconsumer" => XGroup::DelConsumer { key, group, consumer: it.next_bytes()? },
+            _ => return Err(format!("unknown subcommand '{}'. Try XGROUP HELP.", subcommand).into()),
+        })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let (key, group) = match self {
+            XGroup::Create { key, group, .. }
+            | XGroup::SetId { key, group, .. }
+            | XGroup::Destroy { key, group }
+            | XGroup::CreateConsumer { key, group, .. }
+            | XGroup::DelConsumer { key, group, .. } => (key, group),
+        };
+        let stream = match db.get_stream(key) {
+            Ok(Some(_)) => db.get_stream_mut(key).unwrap().unwrap(),
+            Ok(None) => match self {
+                XGroup::Create { mkstream: true, .. } => db.stream_entry(key).unwrap(),
+                _ => {
+                    return Frame::Error(
+                        "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to \
+                         use the MKSTREAM option to create an empty stream automatically."
+                            .to_string(),
+                    )
+                }
+            },
+            Err(e) => return e.into(),
+        };
+        let last_id = stream.last_id();
+        let reply = match self {
+            XGroup::Create { id, .. } => {
+                if stream.create_group(group.clone(), id.unwrap_or(last_id)) {
+                    Frame::Simple("OK".to_string())
+                } else {
+                    Frame::Error("BUSYGROUP Consumer Group name already exists".to_string())
+                }
+            }
+            XGroup::Destroy { .. } => Frame::Integer(stream.destroy_group(group) as i64),
+            XGroup::SetId { id, .. } => match stream.group_mut(group) {
+                Some(g) => {
+                    g.last_delivered = id.unwrap_or(last_id);
+                    Frame::Simple("OK".to_string())
+                }
+                None => no_group(key, group, " in XGROUP SETID"),
+            },
+            XGroup::CreateConsumer { consumer, .. } => match stream.group_mut(group) {
+                Some(g) => Frame::Integer(g.create_consumer(consumer, now_ms()) as i64),
+                None => no_group(key, group, " for CREATECONSUMER"),
+            },
+            XGroup::DelConsumer { consumer, .. } => match stream.group_mut(group) {
+                Some(g) => Frame::Integer(g.delete_consumer(consumer).unwrap_or(0) as i64),
+                None => no_group(key, group, " for DELCONSUMER"),
+            },
+        };
+        let (event, changed) = match (self, &reply) {
+            (XGroup::Create { .. }, Frame::Simple(_)) => ("xgroup-create", true),
+            (XGroup::SetId { .. }, Frame::Simple(_)) => ("xgroup-setid", true),
+            (XGroup::Destroy { .. }, Frame::Integer(n)) => ("xgroup-destroy", *n > 0),
+            (XGroup::CreateConsumer { .. }, Frame::Integer(n)) => ("xgroup-createconsumer", *n > 0),
+            // even if the consumer did not exist, same as redis
+            (XGroup::DelConsumer { .. }, Frame::Integer(_)) => ("xgroup-delconsumer", true),
+            _ => ("", false),
+        };
+        if changed {
+            db.notify(Events::STREAM, event, key);
+        }
+        reply
+    }
+}
+
+/// `XACK key group id [id ...]`, replies with the number of acknowledged entries
+#[derive(Debug)]
+pub struct XAck {
+    key: String,
+    group: Bytes,
+    ids: Vec<StreamId>,
+}
+
+impl XAck {
+    pub fn new(key: &str, group: Bytes, ids: Vec<StreamId>) -> Self {
+        XAck { key: key.to_string(), group, ids }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("xack", self.key);
+        frame.push_bulk(self.group);
+        for id in self.ids {
+            frame.push_bulk(id_bulk(id));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let group = it.next_bytes()?;
+        let mut ids = vec![parse_id(&it.next_bytes()?, 0)?];
+        while it.has_next() {
+            ids.push(parse_id(&it.next_bytes()?, 0)?);
+        }
+        Ok(XAck { key, group, ids })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let group = match db.get_stream_mut(&self.key) {
+            Ok(stream) => stream.and_then(|stream| stream.group_mut(&self.group)),
+            Err(e) => return e.into(),
+        };
+        match group {
+            Some(group) => Frame::Integer(self.ids.iter().filter(|id| group.ack(**id)).count() as i64),
+            None => Frame::Integer(0),
+        }
+    }
+}
+
+/// `XPENDING key group [[IDLE min-idle-time] start end count [consumer]]`
+///
+/// Without a range, replies with the number of pending entries, the
+/// smallest and largest pending IDs and how many each consumer has. With
+/// one, replies with the ID, consumer, idle time and delivery count of
+/// each pending entry in it.
+#[derive(Debug)]
+pub struct XPending {
+    key: String,
+    group: Bytes,
+    range: Option<PendingRange>,
+}
+
+#[derive(Debug)]
+struct PendingRange {
+    min_idle: u64,
+    start: StreamId,
+    end: StreamId,
+    count: usize,
+    consumer: Option<Bytes>,
+}
+
+impl XPending {
+    /// The summary form
+    pub fn new(key: &str, group: Bytes) -> Self {
+        XPending { key: key.to_string(), group, range: None }
+    }
+
+    /// The extended form, for up to `count` entries from `start` to `end`
+    pub fn range(mut self, start: StreamId, end: StreamId, count: usize) -> Self {
+        self.range = Some(PendingRange { min_idle: 0, start, end, count, consumer: None });
+        self
+    }
+
+    /// Only entries idle for `min_idle` milliseconds, with `range`
+    pub fn idle(mut self, min_idle: u64) -> Self {
+        if let Some(range) = self.range.as_mut() {
+            range.min_idle = min_idle;
+        }
+        self
+    }
+
+    /// Only the entries of `consumer`, with `range`
+    pub fn consumer(mut self, consumer: Bytes) -> Self {
+        if let Some(range) = self.range.as_mut() {
+            range.consumer = Some(consumer);
+        }
+        self
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("xpending", self.key);
+        frame.push_bulk(self.group);
+        if let Some(range) = self.range {
+            if range.min_idle > 0 {
+                frame.push_bulk(Bytes::from("idle"));
+                frame.push_bulk(Bytes::from(range.min_idle.to_string()));
+            }
+            frame.push_bulk(id_bulk(range.start));
+            frame.push_bulk(id_bulk(range.end));
+            frame.push_bulk(Bytes::from(range.count.to_string()));
+            if let Some(consumer) = range.consumer {
+                frame.push_bulk(consumer);
+            }
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let mut xpending = XPending::new(&key, it.next_bytes()?);
+        if !it.has_next() {
+            return Ok(xpending);
+        }
+        let mut min_idle = 0;
+        let mut start = it.next_bytes()?;
+        if start.eq_ignore_ascii_case(b"idle") {
+            min_idle = it.next_int()?.max(0) as u64;
+            start = it.next_bytes()?;
+        }
+        let start = parse_start(&start)?;
+        let end = parse_end(&it.next_bytes()?)?;
+        // a negative count is no entries at all
+        let count = usize::try_from(it.next_int()?).unwrap_or(0);
+        let consumer = if it.has_next() { Some(it.next_bytes()?) } else { None };
+        xpending.range = Some(PendingRange { min_idle, start, end, count, consumer });
+        Ok(xpending)
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let group = match db.get_stream(&self.key) {
+            Ok(stream) => match stream.and_then(|stream| stream.group(&self.group)) {
+                Some(group) => group,
+                None => return no_group(&self.key, &self.group, ""),
+            },
+            Err(e) => return e.into(),
+        };
+
+        let range = match &self.range {
+            Some(range) => range,
+            None => {
+                let mut pending = group.pending(StreamId::MIN, StreamId::MAX);
+                let first = match pending.next() {
+                    Some((id, _)) => *id,
+                    None => return Frame::Array(vec![Frame::Integer(0), Frame::Null, Frame::Null, Frame::Null]),
+                };
+                let last = pending.last().map_or(first, |(id, _)| *id);
+                let consumers = group
+                    .consumers()
+                    .filter(|(_, consumer)| consumer.pending_len() > 0)
+                    .map(|(name, consumer)| {
+                        let count = Frame::Bulk(Bytes::from(consumer.pending_len().to_string()));
+                        Frame::Array(vec![Frame::Bulk(name.clone()), count])
+                    })
+                    .collect();
+                return Frame::Array(vec![
+                    Frame::Integer(group.pending_len() as i64),
+                    Frame::Bulk(id_bulk(first)),
+                    Frame::Bulk(id_bulk(last)),
+                    Frame::Array(consumers),
+                ]);
+            }
+        };
+
+        let now = now_ms();
+        let entries = group
+            .pending(range.start, range.end)
+            .filter(|(_, pending)| range.consumer.as_ref().is_none_or(|consumer| *consumer == pending.consumer))
+            .filter(|(_, pending)| now.saturating_sub(pending.delivered_at) >= range.min_idle)
+            .take(range.count)
+            .map(|(id, pending)| {
+                Frame::Array(vec![
+                    Frame::Bulk(id_bulk(*id)),
+                    Frame::Bulk(pending.consumer.clone()),
+                    Frame::Integer(now.saturating_sub(pending.delivered_at) as i64),
+                    Frame::Integer(pending.deliveries as i64),
+                ])
+            })
+            .collect();
+        Frame::Array(entries)
+    }
+}
+
+/// `XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms]
+/// [TIME unix-time-milliseconds] [RETRYCOUNT count] [FORCE] [JUSTID]
+/// [LASTID lastid]`
+///
+/// Hands the pending entries idle for `min-idle-time` to `consumer` and
+/// replies with them, or with their IDs only with `JUSTID`.
+#[derive(Debug)]
+pub struct XClaim {
+    key: String,
+    group: Bytes,
+    consumer: Bytes,
+    min_idle: u64,
+    ids: Vec<StreamId>,
+    idle: Option<u64>,
+    time: Option<u64>,
+    retry_count: Option<u64>,
+    force: bool,
+    justid: bool,
+    last_id: Option<StreamId>,
+}
+
+impl XClaim {
+    pub fn new(key: &str, group: Bytes, consumer: Bytes, min_idle: u64, ids: Vec<StreamId>) -> Self {
+        XClaim {
+            key: key.to_string(),
+            group,
+            consumer,
+            min_idle,
+            ids,
+            idle: None,
+            time: None,
+            retry_count: None,
+            force: false,
+            justid: false,
+            last_id: None,
+        }
+    }
+
+    /// Set the idle time of the claimed entries instead of resetting it
+    pub fn idle(mut self, idle: u64) -> Self {
+        self.idle = Some(idle);
+        self
+    }
+
+    pub fn retry_count(mut self, count: u64) -> Self {
+        self.retry_count = Some(count);
+        self
+    }
+
+    /// Claim entries of the stream that are not pending too
+    pub fn force(mut self) -> Self {
+        self.force = true;
+        self
+    }
+
+    /// Reply with the IDs only, and do not count the claim as a delivery
+    pub fn justid(mut self) -> Self {
+        self.justid = true;
+        self
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("xclaim", self.key);
+        frame.push_bulk(self.group);
+        frame.push_bulk(self.consumer);
+        frame.push_bulk(Bytes::from(self.min_idle.to_string()));
+        for id in self.ids {
+            frame.push_bulk(id_bulk(id));
+        }
+        let options = [("idle", self.idle), ("time", self.time), ("retrycount", self.retry_count)];
+        for (name, value) in options {
+            if let Some(value) = value {
+                frame.push_bulk(Bytes::from(name));
+                frame.push_bulk(Bytes::from(value.to_string()));
+            }
+        }
+        if self.force {
+            frame.push_bulk(Bytes::from("force"));
+        }
+        if self.justid {
+            frame.push_bulk(Bytes::from("justid"));
+        }
+        if let Some(last_id) = self.last_id {
+            frame.push_bulk(Bytes::from("lastid"));
+            frame.push_bulk(id_bulk(last_id));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let group = it.next_bytes()?;
+        let consumer = it.next_bytes()?;
+        let min_idle = it.next_int()?.max(0) as u64;
+        let mut xclaim = XClaim::new(&key, group, consumer, min_idle, vec![parse_id(&it.next_bytes()?, 0)?]);
+        // the IDs, then the options
+        while it.has_next() {
+            let arg = it.next_bytes()?;
+            match String::from_utf8_lossy(&arg).to_lowercase().as_str() {
+                "idle" => xclaim.idle = Some(it.next_int()?.max(0) as u64),
+                "time" => xclaim.time = Some(it.next_int()?.max(0) as u64),
+                "retrycount" => xclaim.retry_count = Some(it.next_int()?.max(0) as u64),
+                "force" => xclaim.force = true,
+                "justid" => xclaim.justid = true,
+                "lastid" => xclaim.last_id = Some(parse_id(&it.next_bytes()?, 0)?),
+                _ if xclaim.idle.is_none() && xclaim.time.is_none() && xclaim.retry_count.is_none() => {
+                    xclaim.ids.push(parse_id(&arg, 0)?)
+                }
+                _ => return Err("syntax error".into()),
+            }
+        }
+        Ok(xclaim)
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let stream = match db.get_stream_mut(&self.key) {
+            Ok(Some(stream)) => stream,
+            Ok(None) => return no_group(&self.key, &self.group, ""),
+            Err(e) => return e.into(),
+        };
+        let now = now_ms();
+        let claim = Claim {
+            min_idle: self.min_idle,
+            delivered_at: match (self.idle, self.time) {
+                (Some(idle), _) => now.saturating_sub(idle),
+                (None, Some(time)) => time,
+                (None, None) => now,
+            },
+            deliveries: self.retry_count,
+            bump: !self.justid,
+            force: self.force,
+        };
+        let claimed = match stream.claim(&self.group, &self.consumer, &self.ids, &claim, now) {
+            Some(claimed) => claimed,
+            None => return no_group(&self.key, &self.group, ""),
+        };
+        if let Some(last_id) = self.last_id {
+            let group = stream.group_mut(&self.group).unwrap();
+            group.last_delivered = group.last_delivered.max(last_id);
+        }
+        let claimed = claimed.into_iter().map(|id| {
+            if self.justid {
+                Frame::Bulk(id_bulk(id))
+            } else {
+                entry_frame(id, stream.get(id))
+            }
+        });
+        Frame::Array(claimed.collect())
+    }
+}
+
+/// `XAUTOCLAIM key group consumer min-idle-time start [COUNT count] [JUSTID]`
+///
+/// Like `XCLAIM` on the pending entries from `start`, replies with the ID
+/// to continue from, `0-0` when done, the claimed entries and the IDs of
+/// the entries found deleted, which are dropped from the PEL.
+#[derive(Debug)]
+pub struct XAutoClaim {
+    key: String,
+    group: Bytes,
+    consumer: Bytes,
+    min_idle: u64,
+    start: StreamId,
+    count: usize,
+    justid: bool,
+}
+
+impl XAutoClaim {
+    pub fn new(key: &str, group: Bytes, consumer: Bytes, min_idle: u64, start: StreamId) -> Self {
+        XAutoClaim { key: key.to_string(), group, consumer, min_idle, start, count: 100, justid: false }
+    }
+
+    pub fn count(mut self, count: usize) -> Self {
+        self.count = count;
+        self
+    }
+
+    pub fn justid(mut self) -> Self {
+        self.justid = true;
+        self
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("xautoclaim", self.key);
+        frame.push_bulk(self.group);
+        frame.push_bulk(self.consumer);
+        frame.push_bulk(Bytes::from(self.min_idle.to_string()));
+        frame.push_bulk(id_bulk(self.start));
+        frame.push_bulk(Bytes::from("count"));
+        frame.push_bulk(Bytes::from(self.count.to_string()));
+        if self.justid {
+            frame.push_bulk(Bytes::from("justid"));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let group = it.next_bytes()?;
+        let consumer = it.next_bytes()?;
+        let min_idle = it.next_int()?.max(0) as u64;
+        let start = parse_start(&it.next_bytes()?)?;
+        let mut xautoclaim = XAutoClaim::new(&key, group, consumer, min_idle, start);
+        while it.has_next() {
+            match it.next_string()?.to_lowercase().as_str() {
+                "count" => {
+                    xautoclaim.count = match it.next_int()? {
+                        // the scan reads up to 10 times `count` entries
+                        n if n > 0 && n <= i64::MAX / 10 => n as usize,
+                        _ => return Err("COUNT must be > 0".into()),
+                    }
+                }
+                "justid" => xautoclaim.justid = true,
+                _ => return Err("syntax error".into()),
+            }
+        }
+        Ok(xautoclaim)
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let stream = match db.get_stream_mut(&self.key) {
+            Ok(Some(stream)) => stream,
+            Ok(None) => return no_group(&self.key, &self.group, ""),
+            Err(e) => return e.into(),
+        };
+        let now = now_ms();
+        let claim = Claim { min_idle: self.min_idle, delivered_at: now, deliveries: None, bump: !self.justid, force: false };
+        let (next, claimed, deleted) =
+            match stream.auto_claim(&self.group, &self.consumer, self.start, self.count, &claim, now) {
+                Some(result) => result,
+                None => return no_group(&self.key, &self.group, ""),
+            };
+        let claimed = claimed.into_iter().map(|id| {
+            if self.justid {
+                Frame::Bulk(id_bulk(id))
+            } else {
+                entry_frame(id, stream.get(id))
+            }
+        });
+        Frame::Array(vec![
+            Frame::Bulk(id_bulk(next)),
+            Frame::Array(claimed.collect()),
+            Frame::Array(deleted.into_iter().map(|id| Frame::Bulk(id_bulk(id))).collect()),
+        ])
+    }
+}
@@ -0,0 +1,348 @@
+//! String commands besides `GET` and `SET`
+//!
+//! Values are stored as `Bytes`, numbers are their decimal representation,
+//! the same way redis stores them. A missing key reads as an empty string,
+//! or as `0` for the counters.
+
+use bytes::{Bytes, BytesMut};
+
+use super::{normalize_range, MAX_STRING_LEN};
+use crate::{
+    database::Database,
+    frame::{Error, Frame, Parse},
+    notify::Events,
+    types::as_int,
+};
+
+fn key_frame(name: &'static str, key: String) -> Frame {
+    let mut frame = Frame::new_array_frame();
+    frame.push_bulk(Bytes::from(name));
+    frame.push_bulk(Bytes::from(key));
+    frame
+}
+
+fn incr_by(db: &mut Database, key: &str, delta: i64) -> Frame {
+    let current = match db.get(key) {
+        Ok(Some(bs)) => match as_int(&bs) {
+            Some(n) => n,
+            None => return Frame::Error("ERR value is not an integer or out of range".to_string()),
+        },
+        Ok(None) => 0,
+        Err(e) => return e.into(),
+    };
+    match current.checked_add(delta) {
+        Some(n) => {
+            db.update(key.to_string(), Bytes::from(n.to_string()));
+            // `INCR`, `DECR` and `DECRBY` too, same as in redis
+            db.notify(Events::STRING, "incrby", key);
+            Frame::Integer(n)
+        }
+        None => Frame::Error("ERR increment or decrement would overflow".to_string()),
+    }
+}
+
+#[derive(Debug)]
+pub struct Incr {
+    key: String,
+}
+
+impl Incr {
+    pub fn new(key: &str) -> Self {
+        Incr { key: key.to_string() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        key_frame("incr", self.key)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(Incr::new(&it.next_string()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        incr_by(db, &self.key, 1)
+    }
+}
+
+#[derive(Debug)]
+pub struct Decr {
+    key: String,
+}
+
+impl Decr {
+    pub fn new(key: &str) -> Self {
+        Decr { key: key.to_string() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        key_frame("decr", self.key)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(Decr::new(&it.next_string()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        incr_by(db, &self.key, -1)
+    }
+}
+
+#[derive(Debug)]
+pub struct IncrBy {
+    key: String,
+    increment: i64,
+}
+
+impl IncrBy {
+    pub fn new(key: &str, increment: i64) -> Self {
+        IncrBy { key: key.to_string(), increment }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = key_frame("incrby", self.key);
+        frame.push_bulk(Bytes::from(self.increment.to_string()));
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(IncrBy::new(&key, it.next_int()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        incr_by(db, &self.key, self.increment)
+    }
+}
+
+#[derive(Debug)]
+pub struct DecrBy {
+    key: String,
+    decrement: i64,
+}
+
+impl DecrBy {
+    pub fn new(key: &str, decrement: i64) -> Self {
+        DecrBy { key: key.to_string(), decrement }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = key_frame("decrby", self.key);
+        frame.push_bulk(Bytes::from(self.decrement.to_string()));
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(DecrBy::new(&key, it.next_int()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match self.decrement.checked_neg() {
+            Some(delta) => incr_by(db, &self.key, delta),
+            None => Frame::Error("ERR decrement would overflow".to_string()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct IncrByFloat {
+    key: String,
+    increment: f64,
+}
+
+impl IncrByFloat {
+    pub fn new(key: &str, increment: f64) -> Self {
+        IncrByFloat { key: key.to_string(), increment }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = key_frame("incrbyfloat", self.key);
+        frame.push_bulk(Bytes::from(self.increment.to_string()));
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(IncrByFloat::new(&key, it.next_float()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let current = match db.get(&self.key) {
+            Ok(Some(bs)) => match std::str::from_utf8(&bs).ok().and_then(|s| s.parse::<f64>().ok()) {
+                Some(n) if n.is_finite() => n,
+                _ => return Frame::Error("ERR value is not a valid float".to_string()),
+            },
+            Ok(None) => 0.0,
+            Err(e) => return e.into(),
+        };
+        let n = current + self.increment;
+        if !n.is_finite() {
+            return Frame::Error("ERR increment would produce NaN or Infinity".to_string());
+        }
+        // the shortest representation that reads back as the same number
+        let value = Bytes::from(n.to_string());
+        db.update(self.key.clone(), value.clone());
+        db.notify(Events::STRING, "incrbyfloat", &self.key);
+        Frame::Bulk(value)
+    }
+}
+
+#[derive(Debug)]
+pub struct Append {
+    key: String,
+    value: Bytes,
+}
+
+impl Append {
+    pub fn new(key: &str, value: Bytes) -> Self {
+        Append { key: key.to_string(), value }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = key_frame("append", self.key);
+        frame.push_bulk(self.value);
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(Append::new(&key, it.next_bytes()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let value = match db.get(&self.key) {
+            Ok(Some(current)) => {
+                if current.len() + self.value.len() > MAX_STRING_LEN {
+                    return Frame::Error("ERR string exceeds maximum allowed size".to_string());
+                }
+                let mut value = BytesMut::with_capacity(current.len() + self.value.len());
+                value.extend_from_slice(&current);
+                value.extend_from_slice(&self.value);
+                value.freeze()
+            }
+            Ok(None) => self.value.clone(),
+            Err(e) => return e.into(),
+        };
+        let len = value.len() as i64;
+        db.update(self.key.clone(), value);
+        db.notify(Events::STRING, "append", &self.key);
+        Frame::Integer(len)
+    }
+}
+
+#[derive(Debug)]
+pub struct Strlen {
+    key: String,
+}
+
+impl Strlen {
+    pub fn new(key: &str) -> Self {
+        Strlen { key: key.to_string() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        key_frame("strlen", self.key)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(Strlen::new(&it.next_string()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.get(&self.key) {
+            Ok(value) => Frame::Integer(value.map_or(0, |bs| bs.len() as i64)),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct GetRange {
+    key: String,
+    start: i64,
+    end: i64,
+}
+
+impl GetRange {
+    pub fn new(key: &str, start: i64, end: i64) -> Self {
+        GetRange { key: key.to_string(), start, end }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = key_frame("getrange", self.key);
+        frame.push_bulk(Bytes::from(self.start.to_string()));
+        frame.push_bulk(Bytes::from(self.end.to_string()));
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let start = it.next_int()?;
+        Ok(GetRange::new(&key, start, it.next_int()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let value = match db.get(&self.key) {
+            Ok(value) => value.unwrap_or_default(),
+            Err(e) => return e.into(),
+        };
+        match normalize_range(self.start, self.end, value.len()) {
+            // `Bytes::slice` shares the stored value instead of copying it
+            Some((start, end)) => Frame::Bulk(value.slice(start..=end)),
+            None => Frame::Bulk(Bytes::new()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SetRange {
+    key: String,
+    offset: i64,
+    value: Bytes,
+}
+
+impl SetRange {
+    pub fn new(key: &str, offset: i64, value: Bytes) -> Self {
+        SetRange { key: key.to_string(), offset, value }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = key_frame("setrange", self.key);
+        frame.push_bulk(Bytes::from(self.offset.to_string()));
+        frame.push_bulk(self.value);
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let offset = it.next_int()?;
+        Ok(SetRange::new(&key, offset, it.next_bytes()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let offset = match usize::try_from(self.offset) {
+            Ok(offset) if offset + self.value.len() <= MAX_STRING_LEN => offset,
+            _ => return Frame::Error("ERR offset is out of range".to_string()),
+        };
+        let current = match db.get(&self.key) {
+            Ok(current) => current,
+            Err(e) => return e.into(),
+        };
+        if self.value.is_empty() {
+            // nothing to write, and a missing key is not created
+            return Frame::Integer(current.map_or(0, |bs| bs.len() as i64));
+        }
+
+        let current = current.unwrap_or_default();
+        let mut value = BytesMut::from(&current[..]);
+        if value.len() < offset + self.value.len() {
+            // the gap is padded with zero bytes
+            value.resize(offset + self.value.len(), 0);
+        }
+        value[offset..offset + self.value.len()].copy_from_slice(&self.value);
+        let len = value.len() as i64;
+        db.update(self.key.clone(), value.freeze());
+        db.notify(Events::STRING, "setrange", &self.key);
+        Frame::Integer(len)
+    }
+}
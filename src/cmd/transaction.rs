@@ -0,0 +1,167 @@
+//! Transaction commands
+//!
+//! Like the `SUBSCRIBE` family they change the state of the connection,
+//! the handler runs them against its `Transaction` and `Watches`. See
+//! `transaction`.
+
+use bytes::Bytes;
+
+use crate::{
+    database::Database,
+    frame::{Error, Frame, Parse},
+    script::Scripts,
+    transaction::{Transaction, Watches},
+};
+
+fn ok() -> Frame {
+    Frame::Simple("OK".to_string())
+}
+
+fn name_frame(name: &'static str) -> Frame {
+    let mut frame = Frame::new_array_frame();
+    frame.push_bulk(Bytes::from(name));
+    frame
+}
+
+/// `MULTI`, the next commands are queued until `EXEC`
+#[derive(Debug, Default)]
+pub struct Multi;
+
+impl Multi {
+    pub fn new() -> Self {
+        Multi
+    }
+
+    pub fn into_frame(self) -> Frame {
+        name_frame("multi")
+    }
+
+    pub(super) fn from_frame(_it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(Multi)
+    }
+
+    pub fn execute(&self, transaction: &mut Option<Transaction>) -> Frame {
+        if transaction.is_some() {
+            return Frame::Error("ERR MULTI calls can not be nested".to_string());
+        }
+        *transaction = Some(Transaction::new());
+        ok()
+    }
+}
+
+/// `EXEC`, replies with the replies of the queued commands, nil if a
+/// watched key changed
+#[derive(Debug, Default)]
+pub struct Exec;
+
+impl Exec {
+    pub fn new() -> Self {
+        Exec
+    }
+
+    pub fn into_frame(self) -> Frame {
+        name_frame("exec")
+    }
+
+    pub(super) fn from_frame(_it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(Exec)
+    }
+
+    pub fn execute(
+        &self,
+        db: &mut Database,
+        transaction: Option<Transaction>,
+        watches: &mut Watches,
+        scripts: &Scripts,
+    ) -> Frame {
+        match transaction {
+            Some(transaction) => transaction.exec(db, watches, scripts),
+            None => Frame::Error("ERR EXEC without MULTI".to_string()),
+        }
+    }
+}
+
+/// `DISCARD`, drops the queued commands and unwatches every key
+#[derive(Debug, Default)]
+pub struct Discard;
+
+impl Discard {
+    pub fn new() -> Self {
+        Discard
+    }
+
+    pub fn into_frame(self) -> Frame {
+        name_frame("discard")
+    }
+
+    pub(super) fn from_frame(_it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(Discard)
+    }
+
+    pub fn execute(&self, db: &mut Database, transaction: &mut Option<Transaction>, watches: &mut Watches) -> Frame {
+        if transaction.take().is_none() {
+            return Frame::Error("ERR DISCARD without MULTI".to_string());
+        }
+        watches.clear(db);
+        ok()
+    }
+}
+
+/// `WATCH key [key ...]`, the next `EXEC` fails if any of the keys changes
+/// in the meantime
+#[derive(Debug)]
+pub struct Watch {
+    keys: Vec<String>,
+}
+
+impl Watch {
+    pub fn new(keys: &[&str]) -> Self {
+        Watch { keys: keys.iter().map(|key| key.to_string()).collect() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = name_frame("watch");
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let mut keys = vec![it.next_string()?];
+        while it.has_next() {
+            keys.push(it.next_string()?);
+        }
+        Ok(Watch { keys })
+    }
+
+    pub fn execute(&self, db: &mut Database, watches: &mut Watches) -> Frame {
+        for key in self.keys.iter() {
+            watches.watch(db, key);
+        }
+        ok()
+    }
+}
+
+/// `UNWATCH`, forgets every watched key
+#[derive(Debug, Default)]
+pub struct Unwatch;
+
+impl Unwatch {
+    pub fn new() -> Self {
+        Unwatch
+    }
+
+    pub fn into_frame(self) -> Frame {
+        name_frame("unwatch")
+    }
+
+    pub(super) fn from_frame(_it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(Unwatch)
+    }
+
+    pub fn execute(&self, db: &mut Database, watches: &mut Watches) -> Frame {
+        watches.clear(db);
+        ok()
+    }
+}
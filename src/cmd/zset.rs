@@ -0,0 +1,1095 @@
+//! Sorted set commands
+//!
+//! See `types::zset`. Scores are replied as bulk strings, in the shortest
+//! form that reads back as the same number. A sorted set that loses its
+//! last member is removed.
+
+use bytes::Bytes;
+
+use super::normalize_range;
+use crate::{
+    database::{Database, Value, WrongType},
+    frame::{Error, Frame, Parse},
+    notify::Events,
+    types::{
+        zset::{LexBound, LexRange, ScoreRange},
+        Set, ZSet,
+    },
+};
+
+fn cmd_frame(name: &'static str, key: String) -> Frame {
+    let mut frame = Frame::new_array_frame();
+    frame.push_bulk(Bytes::from(name));
+    frame.push_bulk(Bytes::from(key));
+    frame
+}
+
+fn score_bulk(score: f64) -> Frame {
+    Frame::Bulk(Bytes::from(score.to_string()))
+}
+
+fn members_from_frame(it: &mut dyn Parse) -> Result<Vec<Bytes>, Error> {
+    let mut members = vec![it.next_bytes()?];
+    while it.has_next() {
+        members.push(it.next_bytes()?);
+    }
+    Ok(members)
+}
+
+/// `min` or `max` of a score range, a float with a `(` in front to exclude it
+fn parse_score_bound(bs: &[u8]) -> Result<(f64, bool), Error> {
+    let (bs, exclusive) = match bs.strip_prefix(b"(") {
+        Some(bs) => (bs, true),
+        None => (bs, false),
+    };
+    match std::str::from_utf8(bs).ok().and_then(|s| s.parse::<f64>().ok()) {
+        Some(score) if !score.is_nan() => Ok((score, exclusive)),
+        _ => Err("min or max is not a float".into()),
+    }
+}
+
+fn score_range(min: &[u8], max: &[u8]) -> Result<ScoreRange, Error> {
+    let (min, min_exclusive) = parse_score_bound(min)?;
+    let (max, max_exclusive) = parse_score_bound(max)?;
+    Ok(ScoreRange { min, min_exclusive, max, max_exclusive })
+}
+
+fn score_bound_bulk(score: f64, exclusive: bool) -> Bytes {
+    Bytes::from(format!("{}{}", if exclusive { "(" } else { "" }, score))
+}
+
+fn score_range_from_frame(it: &mut dyn Parse) -> Result<ScoreRange, Error> {
+    let min = it.next_bytes()?;
+    score_range(&min, &it.next_bytes()?)
+}
+
+/// `-`, `+`, or a member with `[` in front to include it or `(` to exclude it
+fn parse_lex_bound(bs: Bytes) -> Result<LexBound, Error> {
+    match bs.first() {
+        Some(b'-') if bs.len() == 1 => Ok(LexBound::Min),
+        Some(b'+') if bs.len() == 1 => Ok(LexBound::Max),
+        Some(b'[') => Ok(LexBound::Inclusive(bs.slice(1..))),
+        Some(b'(') => Ok(LexBound::Exclusive(bs.slice(1..))),
+        _ => Err("min or max not valid string range item".into()),
+    }
+}
+
+fn lex_bound_bulk(bound: LexBound) -> Bytes {
+    let (prefix, member) = match bound {
+        LexBound::Min => return Bytes::from("-"),
+        LexBound::Max => return Bytes::from("+"),
+        LexBound::Inclusive(member) => (b'[', member),
+        LexBound::Exclusive(member) => (b'(', member),
+    };
+    let mut bs = Vec::with_capacity(member.len() + 1);
+    bs.push(prefix);
+    bs.extend_from_slice(&member);
+    Bytes::from(bs)
+}
+
+fn lex_range_from_frame(it: &mut dyn Parse) -> Result<LexRange, Error> {
+    let min = parse_lex_bound(it.next_bytes()?)?;
+    let max = parse_lex_bound(it.next_bytes()?)?;
+    Ok(LexRange { min, max })
+}
+
+/// `ZADD key [NX|XX] [GT|LT] [CH] [INCR] score member [score member ...]`
+///
+/// Replies with the number of new members, plus the number of updated
+/// ones with `CH`. With `INCR` it behaves like `ZINCRBY` and replies with
+/// the new score, or nil if the flags prevented the update.
+#[derive(Debug, Default)]
+pub struct ZAdd {
+    key: String,
+    nx: bool,
+    xx: bool,
+    gt: bool,
+    lt: bool,
+    ch: bool,
+    incr: bool,
+    members: Vec<(f64, Bytes)>,
+}
+
+impl ZAdd {
+    pub fn new(key: &str) -> Self {
+        ZAdd { key: key.to_string(), ..ZAdd::default() }
+    }
+
+    pub fn member(mut self, score: f64, member: Bytes) -> Self {
+        self.members.push((score, member));
+        self
+    }
+
+    /// Only add new members
+    pub fn nx(mut self) -> Self {
+        self.nx = true;
+        self
+    }
+
+    /// Only update existing members
+    pub fn xx(mut self) -> Self {
+        self.xx = true;
+        self
+    }
+
+    /// Only update a score if it grows
+    pub fn gt(mut self) -> Self {
+        self.gt = true;
+        self
+    }
+
+    /// Only update a score if it shrinks
+    pub fn lt(mut self) -> Self {
+        self.lt = true;
+        self
+    }
+
+    pub fn ch(mut self) -> Self {
+        self.ch = true;
+        self
+    }
+
+    pub fn incr(mut self) -> Self {
+        self.incr = true;
+        self
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("zadd", self.key);
+        let flags = [
+            (self.nx, "nx"),
+            (self.xx, "xx"),
+            (self.gt, "gt"),
+            (self.lt, "lt"),
+            (self.ch, "ch"),
+            (self.incr, "incr"),
+        ];
+        for (_, flag) in flags.into_iter().filter(|(set, _)| *set) {
+            frame.push_bulk(Bytes::from(flag));
+        }
+        for (score, member) in self.members {
+            frame.push_bulk(Bytes::from(score.to_string()));
+            frame.push_bulk(member);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let mut zadd = ZAdd::new(&it.next_string()?);
+        // the flags come first, then the first score
+        let first_score = loop {
+            let arg = it.next_string()?;
+            match arg.to_lowercase().as_str() {
+                "nx" => zadd.nx = true,
+                "xx" => zadd.xx = true,
+                "gt" => zadd.gt = true,
+                "lt" => zadd.lt = true,
+                "ch" => zadd.ch = true,
+                "incr" => zadd.incr = true,
+                _ => break arg,
+            }
+        };
+        if zadd.nx && zadd.xx {
+            return Err("XX and NX options at the same time are not compatible".into());
+        }
+        if (zadd.gt && zadd.lt) || (zadd.nx && (zadd.gt || zadd.lt)) {
+            return Err("GT, LT, and/or NX options at the same time are not compatible".into());
+        }
+
+        let mut score = first_score;
+        loop {
+            let parsed = match score.parse::<f64>() {
+                Ok(parsed) if !parsed.is_nan() => parsed,
+                _ => return Err("value is not a valid float".into()),
+            };
+            zadd.members.push((parsed, it.next_bytes()?));
+            if !it.has_next() {
+                break;
+            }
+            score = it.next_string()?;
+        }
+        if zadd.incr && zadd.members.len() > 1 {
+            return Err("INCR option supports a single increment-element pair".into());
+        }
+        Ok(zadd)
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let zset = match db.zset_entry(&self.key) {
+            Ok(zset) => zset,
+            Err(e) => return e.into(),
+        };
+        let mut added = 0;
+        let mut changed = 0;
+        // nil if the flags prevent the update
+        let mut incr_reply = Frame::Null;
+        for (score, member) in self.members.iter() {
+            match zset.score(member) {
+                None if self.xx => continue,
+                None => {
+                    zset.insert(member.clone(), *score);
+                    added += 1;
+                    incr_reply = score_bulk(*score);
+                }
+                Some(_) if self.nx => continue,
+                Some(current) => {
+                    let new = if self.incr { current + score } else { *score };
+                    if new.is_nan() {
+                        db.remove_if_empty(&self.key);
+                        return Frame::Error("ERR resulting score is not a number (NaN)".to_string());
+                    }
+                    if (self.gt && new <= current) || (self.lt && new >= current) {
+                        continue;
+                    }
+                    if new != current {
+                        zset.insert(member.clone(), new);
+                        changed += 1;
+                    }
+                    incr_reply = score_bulk(new);
+                }
+            }
+        }
+        if added + changed > 0 {
+            db.notify(Events::ZSET, if self.incr { "zincr" } else { "zadd" }, &self.key);
+        }
+        db.remove_if_empty(&self.key);
+        if self.incr {
+            incr_reply
+        } else {
+            Frame::Integer(added + if self.ch { changed } else { 0 })
+        }
+    }
+}
+
+/// `ZINCRBY key increment member`, replies with the new score
+#[derive(Debug)]
+pub struct ZIncrBy {
+    key: String,
+    increment: f64,
+    member: Bytes,
+}
+
+impl ZIncrBy {
+    pub fn new(key: &str, increment: f64, member: Bytes) -> Self {
+        ZIncrBy { key: key.to_string(), increment, member }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("zincrby", self.key);
+        frame.push_bulk(Bytes::from(self.increment.to_string()));
+        frame.push_bulk(self.member);
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let increment = it.next_float()?;
+        Ok(ZIncrBy::new(&key, increment, it.next_bytes()?))
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        ZAdd::new(&self.key).incr().member(self.increment, self.member.clone()).execute(db)
+    }
+}
+
+/// `ZREM key member [member ...]`, replies with the number of removed members
+#[derive(Debug)]
+pub struct ZRem {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+impl ZRem {
+    pub fn new(key: &str, members: Vec<Bytes>) -> Self {
+        ZRem { key: key.to_string(), members }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("zrem", self.key);
+        for member in self.members {
+            frame.push_bulk(member);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(ZRem { key, members: members_from_frame(it)? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let zset = match db.get_zset_mut(&self.key) {
+            Ok(Some(zset)) => zset,
+            Ok(None) => return Frame::Integer(0),
+            Err(e) => return e.into(),
+        };
+        let removed = self.members.iter().filter(|member| zset.remove(member)).count();
+        if removed > 0 {
+            db.notify(Events::ZSET, "zrem", &self.key);
+        }
+        db.remove_if_empty(&self.key);
+        Frame::Integer(removed as i64)
+    }
+}
+
+/// `ZCARD key`, the number of members
+#[derive(Debug)]
+pub struct ZCard {
+    key: String,
+}
+
+impl ZCard {
+    pub fn new(key: &str) -> Self {
+        ZCard { key: key.to_string() }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        cmd_frame("zcard", self.key)
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        Ok(ZCard { key: it.next_string()? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.get_zset(&self.key) {
+            Ok(zset) => Frame::Integer(zset.map_or(0, |zset| zset.len()) as i64),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// `ZSCORE key member`, nil if there is no such member
+#[derive(Debug)]
+pub struct ZScore {
+    key: String,
+    member: Bytes,
+}
+
+impl ZScore {
+    pub fn new(key: &str, member: Bytes) -> Self {
+        ZScore { key: key.to_string(), member }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("zscore", self.key);
+        frame.push_bulk(self.member);
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(ZScore { key, member: it.next_bytes()? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.get_zset(&self.key) {
+            Ok(zset) => zset.and_then(|zset| zset.score(&self.member)).map_or(Frame::Null, score_bulk),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// `ZMSCORE key member [member ...]`
+#[derive(Debug)]
+pub struct ZMScore {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+impl ZMScore {
+    pub fn new(key: &str, members: Vec<Bytes>) -> Self {
+        ZMScore { key: key.to_string(), members }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("zmscore", self.key);
+        for member in self.members {
+            frame.push_bulk(member);
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(ZMScore { key, members: members_from_frame(it)? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let zset = match db.get_zset(&self.key) {
+            Ok(zset) => zset,
+            Err(e) => return e.into(),
+        };
+        let score = |member: &Bytes| zset.and_then(|zset| zset.score(member)).map_or(Frame::Null, score_bulk);
+        Frame::Array(self.members.iter().map(score).collect())
+    }
+}
+
+/// `ZRANK key member`, or `ZREVRANK` counting from the highest score.
+/// Replies nil if there is no such member.
+#[derive(Debug)]
+pub struct ZRank {
+    key: String,
+    member: Bytes,
+    rev: bool,
+}
+
+impl ZRank {
+    pub fn new(key: &str, member: Bytes) -> Self {
+        ZRank { key: key.to_string(), member, rev: false }
+    }
+
+    /// `ZREVRANK`
+    pub fn rev(mut self) -> Self {
+        self.rev = true;
+        self
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame(if self.rev { "zrevrank" } else { "zrank" }, self.key);
+        frame.push_bulk(self.member);
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse, rev: bool) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(ZRank { key, member: it.next_bytes()?, rev })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.get_zset(&self.key) {
+            Ok(zset) => match zset.and_then(|zset| zset.rank(&self.member, self.rev)) {
+                Some(rank) => Frame::Integer(rank as i64),
+                None => Frame::Null,
+            },
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// `ZCOUNT key min max`, the number of members with a score in the range
+#[derive(Debug)]
+pub struct ZCount {
+    key: String,
+    range: ScoreRange,
+}
+
+impl ZCount {
+    pub fn new(key: &str, range: ScoreRange) -> Self {
+        ZCount { key: key.to_string(), range }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("zcount", self.key);
+        frame.push_bulk(score_bound_bulk(self.range.min, self.range.min_exclusive));
+        frame.push_bulk(score_bound_bulk(self.range.max, self.range.max_exclusive));
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(ZCount { key, range: score_range_from_frame(it)? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.get_zset(&self.key) {
+            Ok(zset) => Frame::Integer(zset.map_or(0, |zset| zset.score_ranks(&self.range).len()) as i64),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// `ZLEXCOUNT key min max`, the number of members in the range
+#[derive(Debug)]
+pub struct ZLexCount {
+    key: String,
+    range: LexRange,
+}
+
+impl ZLexCount {
+    pub fn new(key: &str, range: LexRange) -> Self {
+        ZLexCount { key: key.to_string(), range }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("zlexcount", self.key);
+        frame.push_bulk(lex_bound_bulk(self.range.min));
+        frame.push_bulk(lex_bound_bulk(self.range.max));
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        Ok(ZLexCount { key, range: lex_range_from_frame(it)? })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        match db.get_zset(&self.key) {
+            Ok(zset) => Frame::Integer(zset.map_or(0, |zset| zset.lex_ranks(&self.range).len()) as i64),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// What a `ZRANGE` selects the members by
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZRangeBy {
+    /// inclusive ranks, negative ones count from the end
+    Rank(i64, i64),
+    Score(ScoreRange),
+    Lex(LexRange),
+}
+
+impl ZRangeBy {
+    /// The first rank of the range and its length, ranks counting from the
+    /// highest score if `rev`
+    fn ranks(&self, zset: &ZSet, rev: bool) -> (usize, usize) {
+        let ranks = match self {
+            ZRangeBy::Rank(start, stop) => {
+                return normalize_range(*start, *stop, zset.len()).map_or((0, 0), |(start, stop)| (start, stop - start + 1))
+            }
+            ZRangeBy::Score(range) => zset.score_ranks(range),
+            ZRangeBy::Lex(range) => zset.lex_ranks(range),
+        };
+        let start = if rev { zset.len() - ranks.end } else { ranks.start };
+        (start, ranks.len())
+    }
+}
+
+/// `ZRANGE key start stop [BYSCORE|BYLEX] [REV] [LIMIT offset count] [WITHSCORES]`
+///
+/// With `REV` the members come from the highest score down, and for
+/// `BYSCORE` and `BYLEX` the range is given as `max min`. The older
+/// `ZREVRANGE`, `ZRANGEBYSCORE`, `ZREVRANGEBYSCORE`, `ZRANGEBYLEX` and
+/// `ZREVRANGEBYLEX` are parsed into a `ZRange` too.
+#[derive(Debug)]
+pub struct ZRange {
+    key: String,
+    by: ZRangeBy,
+    rev: bool,
+    limit: Option<(i64, i64)>,
+    with_scores: bool,
+}
+
+impl ZRange {
+    pub fn new(key: &str, by: ZRangeBy) -> Self {
+        ZRange { key: key.to_string(), by, rev: false, limit: None, with_scores: false }
+    }
+
+    pub fn rev(mut self) -> Self {
+        self.rev = true;
+        self
+    }
+
+    /// Skip `offset` members and reply with `count` at most, all the rest
+    /// if `count` is negative. Only for ranges by score or lex.
+    pub fn limit(mut self, offset: i64, count: i64) -> Self {
+        self.limit = Some((offset, count));
+        self
+    }
+
+    pub fn with_scores(mut self) -> Self {
+        self.with_scores = true;
+        self
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame("zrange", self.key);
+        let (start, stop, by) = match self.by {
+            ZRangeBy::Rank(start, stop) => {
+                (Bytes::from(start.to_string()), Bytes::from(stop.to_string()), None)
+            }
+            ZRangeBy::Score(range) => {
+                let min = score_bound_bulk(range.min, range.min_exclusive);
+                let max = score_bound_bulk(range.max, range.max_exclusive);
+                (min, max, Some("byscore"))
+            }
+            ZRangeBy::Lex(range) => (lex_bound_bulk(range.min), lex_bound_bulk(range.max), Some("bylex")),
+        };
+        // a reversed range is given from the top
+        let (start, stop) = if self.rev && by.is_some() { (stop, start) } else { (start, stop) };
+        frame.push_bulk(start);
+        frame.push_bulk(stop);
+        if let Some(by) = by {
+            frame.push_bulk(Bytes::from(by));
+        }
+        if self.rev {
+            frame.push_bulk(Bytes::from("rev"));
+        }
+        if let Some((offset, count)) = self.limit {
+            frame.push_bulk(Bytes::from("limit"));
+            frame.push_bulk(Bytes::from(offset.to_string()));
+            frame.push_bulk(Bytes::from(count.to_string()));
+        }
+        if self.with_scores {
+            frame.push_bulk(Bytes::from("withscores"));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let start = it.next_bytes()?;
+        let stop = it.next_bytes()?;
+        let mut by = "rank";
+        let mut zrange = ZRange::new(&key, ZRangeBy::Rank(0, 0));
+        while it.has_next() {
+            match it.next_string()?.to_lowercase().as_str() {
+                "byscore" => by = "score",
+                "bylex" => by = "lex",
+                "rev" => zrange.rev = true,
+                "limit" => zrange.limit = Some((it.next_int()?, it.next_int()?)),
+                "withscores" => zrange.with_scores = true,
+                _ => return Err("syntax error".into()),
+            }
+        }
+        if zrange.limit.is_some() && by == "rank" {
+            return Err("syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX".into());
+        }
+        if zrange.with_scores && by == "lex" {
+            return Err("syntax error, WITHSCORES not supported in combination with BYLEX".into());
+        }
+        let (min, max) = if zrange.rev && by != "rank" { (stop, start) } else { (start, stop) };
+        zrange.by = match by {
+            "score" => ZRangeBy::Score(score_range(&min, &max)?),
+            "lex" => ZRangeBy::Lex(LexRange { min: parse_lex_bound(min)?, max: parse_lex_bound(max)? }),
+            _ => ZRangeBy::Rank(bulk_int(&min)?, bulk_int(&max)?),
+        };
+        Ok(zrange)
+    }
+
+    /// `ZREVRANGE key start stop [WITHSCORES]`
+    pub(super) fn from_rev_frame(it: &mut dyn Parse) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let start = it.next_int()?;
+        let mut zrange = ZRange::new(&key, ZRangeBy::Rank(start, it.next_int()?)).rev();
+        if it.has_next() {
+            if it.next_string()?.to_lowercase() != "withscores" {
+                return Err("syntax error".into());
+            }
+            zrange.with_scores = true;
+        }
+        Ok(zrange)
+    }
+
+    /// `Z[REV]RANGEBYSCORE key min max [WITHSCORES] [LIMIT offset count]`,
+    /// `max` first if `rev`
+    pub(super) fn from_score_frame(it: &mut dyn Parse, rev: bool) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let first = it.next_bytes()?;
+        let second = it.next_bytes()?;
+        let range = if rev { score_range(&second, &first)? } else { score_range(&first, &second)? };
+        let mut zrange = ZRange::new(&key, ZRangeBy::Score(range));
+        zrange.rev = rev;
+        while it.has_next() {
+            match it.next_string()?.to_lowercase().as_str() {
+                "withscores" => zrange.with_scores = true,
+                "limit" => zrange.limit = Some((it.next_int()?, it.next_int()?)),
+                _ => return Err("syntax error".into()),
+            }
+        }
+        Ok(zrange)
+    }
+
+    /// `Z[REV]RANGEBYLEX key min max [LIMIT offset count]`, `max` first if `rev`
+    pub(super) fn from_lex_frame(it: &mut dyn Parse, rev: bool) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let first = parse_lex_bound(it.next_bytes()?)?;
+        let second = parse_lex_bound(it.next_bytes()?)?;
+        let (min, max) = if rev { (second, first) } else { (first, second) };
+        let mut zrange = ZRange::new(&key, ZRangeBy::Lex(LexRange { min, max }));
+        zrange.rev = rev;
+        if it.has_next() {
+            if it.next_string()?.to_lowercase() != "limit" {
+                return Err("syntax error".into());
+            }
+            zrange.limit = Some((it.next_int()?, it.next_int()?));
+        }
+        Ok(zrange)
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let zset = match db.get_zset(&self.key) {
+            Ok(Some(zset)) => zset,
+            Ok(None) => return Frame::Array(vec![]),
+            Err(e) => return e.into(),
+        };
+        let (mut start, mut len) = self.by.ranks(zset, self.rev);
+        if let Some((offset, count)) = self.limit {
+            // a negative offset selects nothing
+            let offset = usize::try_from(offset).unwrap_or(usize::MAX);
+            start = start.saturating_add(offset);
+            len = len.saturating_sub(offset);
+            if count >= 0 {
+                len = len.min(count as usize);
+            }
+        }
+        let mut frames = vec![];
+        for (member, score) in zset.iter_from(start, self.rev).take(len) {
+            frames.push(Frame::Bulk(member.clone()));
+            if self.with_scores {
+                frames.push(score_bulk(score));
+            }
+        }
+        Frame::Array(frames)
+    }
+}
+
+fn bulk_int(bs: &[u8]) -> Result<i64, Error> {
+    std::str::from_utf8(bs)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| "value is not an integer or out of range".into())
+}
+
+/// `ZREMRANGEBYRANK key start stop`, `ZREMRANGEBYSCORE key min max` or
+/// `ZREMRANGEBYLEX key min max`, replies with the number of removed members
+#[derive(Debug)]
+pub struct ZRemRange {
+    key: String,
+    by: ZRangeBy,
+}
+
+impl ZRemRange {
+    pub fn new(key: &str, by: ZRangeBy) -> Self {
+        ZRemRange { key: key.to_string(), by }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let (name, min, max) = match self.by {
+            ZRangeBy::Rank(start, stop) => {
+                ("zremrangebyrank", Bytes::from(start.to_string()), Bytes::from(stop.to_string()))
+            }
+            ZRangeBy::Score(range) => (
+                "zremrangebyscore",
+                score_bound_bulk(range.min, range.min_exclusive),
+                score_bound_bulk(range.max, range.max_exclusive),
+            ),
+            ZRangeBy::Lex(range) => ("zremrangebylex", lex_bound_bulk(range.min), lex_bound_bulk(range.max)),
+        };
+        let mut frame = cmd_frame(name, self.key);
+        frame.push_bulk(min);
+        frame.push_bulk(max);
+        frame
+    }
+
+    /// `by` is the `rank`, `score` or `lex` of the command name
+    pub(super) fn from_frame(it: &mut dyn Parse, by: &str) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let by = match by {
+            "score" => ZRangeBy::Score(score_range_from_frame(it)?),
+            "lex" => ZRangeBy::Lex(lex_range_from_frame(it)?),
+            _ => {
+                let start = it.next_int()?;
+                ZRangeBy::Rank(start, it.next_int()?)
+            }
+        };
+        Ok(ZRemRange { key, by })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let zset = match db.get_zset_mut(&self.key) {
+            Ok(Some(zset)) => zset,
+            Ok(None) => return Frame::Integer(0),
+            Err(e) => return e.into(),
+        };
+        let (start, len) = self.by.ranks(zset, false);
+        let members: Vec<Bytes> = zset.iter_from(start, false).take(len).map(|(member, _)| member.clone()).collect();
+        for member in members.iter() {
+            zset.remove(member);
+        }
+        if !members.is_empty() {
+            let event = match self.by {
+                ZRangeBy::Rank(..) => "zremrangebyrank",
+                ZRangeBy::Score(_) => "zremrangebyscore",
+                ZRangeBy::Lex(_) => "zremrangebylex",
+            };
+            db.notify(Events::ZSET, event, &self.key);
+        }
+        db.remove_if_empty(&self.key);
+        Frame::Integer(members.len() as i64)
+    }
+}
+
+/// `ZPOPMIN key [count]` or `ZPOPMAX key [count]`, removes the members with
+/// the lowest or highest scores and replies with them and their scores
+#[derive(Debug)]
+pub struct ZPop {
+    key: String,
+    count: Option<u64>,
+    max: bool,
+}
+
+impl ZPop {
+    /// `ZPOPMIN`
+    pub fn min(key: &str, count: Option<u64>) -> Self {
+        ZPop { key: key.to_string(), count, max: false }
+    }
+
+    /// `ZPOPMAX`
+    pub fn max(key: &str, count: Option<u64>) -> Self {
+        ZPop { key: key.to_string(), count, max: true }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame(if self.max { "zpopmax" } else { "zpopmin" }, self.key);
+        if let Some(count) = self.count {
+            frame.push_bulk(Bytes::from(count.to_string()));
+        }
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse, max: bool) -> Result<Self, Error> {
+        let key = it.next_string()?;
+        let mut count = None;
+        if it.has_next() {
+            count = match it.next_int()? {
+                n if n >= 0 => Some(n as u64),
+                _ => return Err("value is out of range, must be positive".into()),
+            };
+        }
+        Ok(ZPop { key, count, max })
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let zset = match db.get_zset_mut(&self.key) {
+            Ok(Some(zset)) => zset,
+            Ok(None) => return Frame::Array(vec![]),
+            Err(e) => return e.into(),
+        };
+        let count = self.count.map_or(1, |count| count.min(usize::MAX as u64) as usize);
+        let popped: Vec<(Bytes, f64)> =
+            zset.iter_from(0, self.max).take(count).map(|(member, score)| (member.clone(), score)).collect();
+        let mut frames = Vec::with_capacity(popped.len() * 2);
+        for (member, score) in popped {
+            zset.remove(&member);
+            frames.push(Frame::Bulk(member));
+            frames.push(score_bulk(score));
+        }
+        if !frames.is_empty() {
+            db.notify(Events::ZSET, if self.max { "zpopmax" } else { "zpopmin" }, &self.key);
+        }
+        db.remove_if_empty(&self.key);
+        Frame::Array(frames)
+    }
+}
+
+/// How `ZUNIONSTORE` and `ZINTERSTORE` combine the scores of a member
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Aggregate::Sum => "sum",
+            Aggregate::Min => "min",
+            Aggregate::Max => "max",
+        }
+    }
+
+    fn apply(&self, a: f64, b: f64) -> f64 {
+        match self {
+            // +inf and -inf add up to 0 rather than NaN
+            Aggregate::Sum => Some(a + b).filter(|sum| !sum.is_nan()).unwrap_or(0.0),
+            Aggregate::Min => a.min(b),
+            Aggregate::Max => a.max(b),
+        }
+    }
+}
+
+/// An input of `ZUNIONSTORE` or `ZINTERSTORE`, the members of a plain set
+/// have a score of `1`
+enum Source<'a> {
+    Set(&'a Set),
+    ZSet(&'a ZSet),
+}
+
+impl Source<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Source::Set(set) => set.len(),
+            Source::ZSet(zset) => zset.len(),
+        }
+    }
+
+    fn score(&self, member: &[u8]) -> Option<f64> {
+        match self {
+            Source::Set(set) => set.contains(member).then_some(1.0),
+            Source::ZSet(zset) => zset.score(member),
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (Bytes, f64)> + '_> {
+        match self {
+            Source::Set(set) => Box::new(set.iter().map(|member| (member, 1.0))),
+            Source::ZSet(zset) => Box::new(zset.iter().map(|(member, score)| (member.clone(), score))),
+        }
+    }
+}
+
+/// `ZUNIONSTORE` or `ZINTERSTORE destination numkeys key [key ...]
+/// [WEIGHTS weight [weight ...]] [AGGREGATE SUM|MIN|MAX]`, replies with
+/// the size of the result
+#[derive(Debug)]
+pub struct ZStore {
+    destination: String,
+    keys: Vec<String>,
+    weights: Option<Vec<f64>>,
+    aggregate: Aggregate,
+    inter: bool,
+}
+
+impl ZStore {
+    /// `ZUNIONSTORE`
+    pub fn union(destination: &str, keys: &[&str]) -> Self {
+        ZStore {
+            destination: destination.to_string(),
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+            weights: None,
+            aggregate: Aggregate::Sum,
+            inter: false,
+        }
+    }
+
+    /// `ZINTERSTORE`
+    pub fn inter(destination: &str, keys: &[&str]) -> Self {
+        ZStore { inter: true, ..ZStore::union(destination, keys) }
+    }
+
+    /// One weight for each key, the scores of a key are multiplied by its weight
+    pub fn weights(mut self, weights: Vec<f64>) -> Self {
+        self.weights = Some(weights);
+        self
+    }
+
+    pub fn aggregate(mut self, aggregate: Aggregate) -> Self {
+        self.aggregate = aggregate;
+        self
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frame = cmd_frame(if self.inter { "zinterstore" } else { "zunionstore" }, self.destination);
+        frame.push_bulk(Bytes::from(self.keys.len().to_string()));
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key));
+        }
+        if let Some(weights) = self.weights {
+            frame.push_bulk(Bytes::from("weights"));
+            for weight in weights {
+                frame.push_bulk(Bytes::from(weight.to_string()));
+            }
+        }
+        frame.push_bulk(Bytes::from("aggregate"));
+        frame.push_bulk(Bytes::from(self.aggregate.as_str()));
+        frame
+    }
+
+    pub(super) fn from_frame(it: &mut dyn Parse, inter: bool) -> Result<Self, Error> {
+        let destination = it.next_string()?;
+        let numkeys = it.next_int()?;
+        if numkeys <= 0 {
+            let name = if inter { "zinterstore" } else { "zunionstore" };
+            return Err(format!("at least 1 input key is needed for '{}' command", name).into());
+        }
+        let mut zstore = ZStore::union(&destination, &[]);
+        zstore.inter = inter;
+        for _ in 0..numkeys {
+            zstore.keys.push(it.next_string()?);
+        }
+        while it.has_next() {
+            match it.next_string()?.to_lowercase().as_str() {
+                "weights" => {
+                    let mut weights = vec![];
+                    for _ in 0..numkeys {
+                        match it.next_float() {
+                            Ok(weight) => weights.push(weight),
+                            Err(_) => return Err("weight value is not a float".into()),
+                        }
+                    }
+                    zstore.weights = Some(weights);
+                }
+                "aggregate" => {
+                    zstore.aggregate = match it.next_string()?.to_lowercase().as_str() {
+                        "sum" => Aggregate::Sum,
+                        "min" => Aggregate::Min,
+                        "max" => Aggregate::Max,
+                        _ => return Err("syntax error".into()),
+                    }
+                }
+                _ => return Err("syntax error".into()),
+            }
+        }
+        Ok(zstore)
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Frame {
+        let sources = match self.load(db) {
+            Ok(sources) => sources,
+            Err(e) => return e.into(),
+        };
+        let weight = |i: usize| self.weights.as_ref().map_or(1.0, |weights| weights[i]);
+        // 0 times infinity is 0, not NaN
+        let weighted = |score: f64, weight: f64| Some(score * weight).filter(|s| !s.is_nan()).unwrap_or(0.0);
+
+        let mut result = ZSet::default();
+        if self.inter {
+            // a missing key is an empty set, so the intersection is empty
+            let sources: Option<Vec<_>> =
+                sources.into_iter().enumerate().map(|(i, source)| source.map(|source| (weight(i), source))).collect();
+            if let Some(mut sources) = sources {
+                // start from the smallest set, each candidate is looked up in the others
+                sources.sort_by_key(|(_, source)| source.len());
+                let ((first_weight, first), others) = sources.split_first().unwrap();
+                'members: for (member, score) in first.iter() {
+                    let mut total = weighted(score, *first_weight);
+                    for (weight, other) in others {
+                        match other.score(&member) {
+                            Some(score) => total = self.aggregate.apply(total, weighted(score, *weight)),
+                            None => continue 'members,
+                        }
+                    }
+                    result.insert(member, total);
+                }
+            }
+        } else {
+            let sources = sources.iter().enumerate().filter_map(|(i, source)| source.as_ref().map(|source| (i, source)));
+            for (i, source) in sources {
+                for (member, score) in source.iter() {
+                    let score = weighted(score, weight(i));
+                    let score = match result.score(&member) {
+                        Some(total) => self.aggregate.apply(total, score),
+                        None => score,
+                    };
+                    result.insert(member, score);
+                }
+            }
+        }
+
+        let len = result.len();
+        if result.is_empty() {
+            if db.remove(&self.destination).is_some() {
+                db.notify(Events::GENERIC, "del", &self.destination);
+            }
+        } else {
+            db.insert(self.destination.clone(), Value::ZSet(result), None);
+            db.notify(Events::ZSET, if self.inter { "zinterstore" } else { "zunionstore" }, &self.destination);
+        }
+        Frame::Integer(len as i64)
+    }
+
+    fn load<'a>(&self, db: &'a Database) -> Result<Vec<Option<Source<'a>>>, WrongType> {
+        self.keys
+            .iter()
+            .map(|key| match db.get_value(key) {
+                None => Ok(None),
+                Some(Value::Set(set)) => Ok(Some(Source::Set(set))),
+                Some(Value::ZSet(zset)) => Ok(Some(Source::ZSet(zset))),
+                Some(_) => Err(WrongType),
+            })
+            .collect()
+    }
+}
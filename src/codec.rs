@@ -0,0 +1,118 @@
+use std::io::Cursor;
+
+use bytes::{Buf, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{
+    connection::Error,
+    frame::{self, Frame},
+};
+
+/// `tokio_util` codec for `Frame`
+///
+/// Plug it into `Framed`, `FramedRead` or `FramedWrite` to get a
+/// `Stream` of decoded frames and a `Sink` of frames to encode, so the
+/// framing composes with the usual stream combinators.
+///
+/// Both directions reuse `Frame::check`/`Frame::decode`/`Frame::encode`,
+/// so the codec and `Connection` always agree on the wire format.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FrameCodec;
+
+impl FrameCodec {
+    pub fn new() -> Self {
+        FrameCodec
+    }
+}
+
+impl Decoder for FrameCodec {
+    type Item = Frame;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, Error> {
+        if !src.has_remaining() {
+            return Ok(None);
+        }
+
+        let mut cursor = Cursor::new(src.as_ref());
+        match Frame::check(&mut cursor) {
+            Ok(_) => {
+                cursor.set_position(0);
+                let frame = Frame::decode(&mut cursor)?;
+                let len = cursor.position() as usize;
+                src.advance(len);
+                Ok(Some(frame))
+            }
+            // wait for more bytes from the underlying reader
+            Err(frame::Error::Incomplete) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl Encoder<Frame> for FrameCodec {
+    type Error = Error;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> Result<(), Error> {
+        frame.encode(dst);
+        Ok(())
+    }
+}
+
+impl Encoder<&Frame> for FrameCodec {
+    type Error = Error;
+
+    fn encode(&mut self, frame: &Frame, dst: &mut BytesMut) -> Result<(), Error> {
+        frame.encode(dst);
+        Ok(())
+    }
+}
+
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[test]
+fn test_decode_partial() {
+    let mut codec = FrameCodec::new();
+    let mut buf = BytesMut::from(&b"*2\r\n$3\r\nget\r\n$4\r\nna"[..]);
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+
+    buf.extend_from_slice(b"me\r\n+OK\r\n");
+    let frame = codec.decode(&mut buf).unwrap().unwrap();
+    assert_eq!(
+        frame,
+        Frame::Array(vec![Frame::Bulk("get".into()), Frame::Bulk("name".into())])
+    );
+    assert_eq!(codec.decode(&mut buf).unwrap().unwrap(), "OK");
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn test_decode_protocol_error() {
+    let mut codec = FrameCodec::new();
+    let mut buf = BytesMut::from(&b"?oops\r\n"[..]);
+    assert!(codec.decode(&mut buf).is_err());
+}
+
+#[test]
+fn test_framed_round_trip() {
+    use futures::{SinkExt, StreamExt};
+    use tokio_util::codec::Framed;
+
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(async {
+            let (client, server) = tokio::io::duplex(64);
+            let mut client = Framed::new(client, FrameCodec::new());
+            let mut server = Framed::new(server, FrameCodec::new());
+
+            let mut frame = Frame::new_array_frame();
+            frame.push_bulk("set".into());
+            frame.push_bulk("name".into());
+            frame.push_bulk("simon".into());
+            client.send(&frame).await.unwrap();
+
+            assert_eq!(server.next().await.unwrap().unwrap(), frame);
+        });
+}
@@ -1,19 +1,47 @@
-use std::{io::Cursor};
+use std::{
+    collections::VecDeque,
+    io::{Cursor, IoSlice},
+};
 
-use bytes::{Buf, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::TcpStream,
 };
 
-use crate::frame::{Frame, self};
+use crate::frame::{Frame, self, CRLF, FLAG_ARRAY, FLAG_BULK};
+
+/// same limit as `proto-max-bulk-len` in redis, a larger length header is
+/// rejected before anything is allocated for it
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+/// bulk payloads of at least this size are queued by reference instead of
+/// being copied into `write_buffer`
+const MAX_INLINE_BULK: usize = 4 * 1024;
+
+/// pending output above this size is flushed by `write_frame` itself
+const MAX_PENDING_WRITE: usize = 64 * 1024;
+
+/// at most this many buffers are handed to a single `write_vectored`
+const MAX_IOV: usize = 64;
+
+/// most bytes a streamed bulk string grows by for one read
+const STREAM_CHUNK: usize = 64 * 1024;
 
 /// network layer
 ///
+/// Written frames are buffered until `flush` is called, or until the read
+/// side has to wait for the peer, so pipelined replies go out together.
 pub struct Connection {
     stream: TcpStream,
     write_buffer: BytesMut,
+    // encoded output not yet written, in order, followed by `write_buffer`
+    write_queue: VecDeque<Bytes>,
     read_buffer: BytesMut,
+    // bulk strings of at least this size bypass the buffers, `None` disables streaming
+    stream_threshold: Option<usize>,
+    // the frame being streamed in
+    partial: PartialFrame,
 }
 
 #[derive(Debug)]
@@ -47,40 +75,170 @@ impl Connection {
         Ok(Connection {
             stream,
             write_buffer: BytesMut::with_capacity(1024),
+            write_queue: VecDeque::new(),
             read_buffer: BytesMut::with_capacity(1024),
+            stream_threshold: None,
+            partial: PartialFrame::default(),
         })
     }
 
+    /// Opt in to streaming of large bulk strings.
+    ///
+    /// A bulk string of at least `threshold` bytes is read from the socket
+    /// straight into its own buffer, which becomes the `Bytes` of the frame,
+    /// instead of growing `read_buffer` until the whole frame is there and
+    /// then copying it out. So peak memory stays around the size of the
+    /// value itself. Large bulks are always written from their `Bytes`, see
+    /// `write_frame`.
+    ///
+    /// Only bulk strings at the top level or inside a top level array, i.e.
+    /// the shapes of requests and of most replies, are streamed. The server
+    /// turns it on with `Config::stream_bulk_threshold`.
+    pub fn set_stream_threshold(&mut self, threshold: Option<usize>) {
+        self.stream_threshold = threshold;
+    }
+
+    /// Queue `frame` for writing, nothing is sent until `flush`.
+    ///
+    /// Bulk payloads of at least `MAX_INLINE_BULK` bytes are not copied,
+    /// their `Bytes` is handed to `write_vectored` as is.
     pub async fn write_frame(&mut self, frame: Frame) -> Result<usize, Error> {
-        let written = frame.encode(&mut self.write_buffer);
-        self.stream.write_all(self.write_buffer.as_ref()).await?;
-        self.write_buffer.advance(written);
+        let mut written = 0;
+        let mut pending = vec![&frame];
+        while let Some(frame) = pending.pop() {
+            match frame {
+                Frame::Bulk(bs) if bs.len() >= MAX_INLINE_BULK => {
+                    written += frame.encode_header(&mut self.write_buffer);
+                    self.write_queue.push_back(self.write_buffer.split().freeze());
+                    self.write_queue.push_back(bs.clone());
+                    self.write_buffer.put(CRLF);
+                    written += bs.len() + CRLF.len();
+                }
+                Frame::Array(arr) | Frame::Push(arr) | Frame::Map(arr) => {
+                    written += frame.encode_header(&mut self.write_buffer);
+                    pending.extend(arr.iter().rev());
+                }
+                _ => written += frame.encode(&mut self.write_buffer),
+            }
+        }
+
+        if self.pending_write() >= MAX_PENDING_WRITE {
+            self.flush().await?;
+        }
         Ok(written)
     }
 
+    /// Write everything queued by `write_frame` to the socket
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        if !self.write_buffer.is_empty() {
+            self.write_queue.push_back(self.write_buffer.split().freeze());
+        }
+
+        while !self.write_queue.is_empty() {
+            let slices: Vec<IoSlice> = self
+                .write_queue
+                .iter()
+                .take(MAX_IOV)
+                .map(|bs| IoSlice::new(bs))
+                .collect();
+            let mut n = self.stream.write_vectored(&slices).await?;
+            if n == 0 {
+                return Err(Error::IO("failed to write to socket".to_string()));
+            }
+
+            while n > 0 {
+                let front = self.write_queue.front_mut().unwrap();
+                if n < front.len() {
+                    front.advance(n);
+                    break;
+                }
+                n -= front.len();
+                self.write_queue.pop_front();
+            }
+        }
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    fn pending_write(&self) -> usize {
+        self.write_buffer.len() + self.write_queue.iter().map(Bytes::len).sum::<usize>()
+    }
+
     pub async fn read_frame(&mut self) -> Result<Frame, Error> {
-        if self.read_buffer.has_remaining() {
-            let mut cursor = Cursor::new(self.read_buffer.as_ref());
-            if Frame::check(&mut cursor).is_ok() {
-                cursor.set_position(0);
-                let frame = Frame::decode(&mut cursor)?;
-                let len = cursor.position() as usize;
-                self.read_buffer.advance(len);
+        match self.stream_threshold {
+            Some(threshold) => self.read_frame_streaming(threshold).await,
+            None => self.read_frame_buffered().await,
+        }
+    }
+
+    /// Cancel safe like the buffered read: whatever was consumed of an
+    /// unfinished frame is kept in `partial`, the only awaits are reads of
+    /// more bytes
+    async fn read_frame_streaming(&mut self, threshold: usize) -> Result<Frame, Error> {
+        loop {
+            if let Some(frame) = self.parse_streaming(threshold)? {
                 return Ok(frame);
             }
+            match self.partial.bulk.as_ref() {
+                Some((len, value)) if value.len() < *len => self.read_bulk().await?,
+                _ => self.fill_read_buffer().await?,
+            }
         }
+    }
 
+    /// Go on with the frame in `partial` with what `read_buffer` holds,
+    /// returns the frame once complete
+    fn parse_streaming(&mut self, threshold: usize) -> Result<Option<Frame>, Error> {
         loop {
-            let len = self.stream.read_buf(&mut self.read_buffer).await?;
-            if len == 0 {
-                if self.read_buffer.is_empty() {
-                    return Err(Error::Other("peer shutdown".to_string()));
-                } else {
-                    return Err(Error::IO("connection failure".to_string()));
+            if let Some((len, value)) = self.partial.bulk.as_mut() {
+                let buffered = (*len - value.len()).min(self.read_buffer.len());
+                value.extend_from_slice(&self.read_buffer.split_to(buffered));
+                if value.len() < *len || self.read_buffer.len() < CRLF.len() {
+                    return Ok(None);
+                }
+                if &self.read_buffer[..CRLF.len()] != CRLF {
+                    return Err(Error::Other("protocol error; bulk not terminated by CRLF".to_string()));
                 }
+                self.read_buffer.advance(CRLF.len());
+                let (_, value) = self.partial.bulk.take().unwrap();
+                match self.partial.push(Frame::Bulk(value.freeze())) {
+                    Some(frame) => return Ok(Some(frame)),
+                    None => continue,
+                }
+            }
+            if let Some(frames) = self.partial.complete_array() {
+                return Ok(Some(Frame::Array(frames)));
+            }
+            if self.read_buffer.is_empty() {
+                return Ok(None);
             }
-            println!("read: {}", len);
 
+            let top = self.partial.array.is_none();
+            match self.read_buffer[0] {
+                FLAG_ARRAY if top => {
+                    let Some((header, len)) = parse_length(&self.read_buffer)? else {
+                        return Ok(None);
+                    };
+                    self.read_buffer.advance(header);
+                    let Some(len) = len else {
+                        return Ok(Some(Frame::NullArray));
+                    };
+                    self.partial.array = Some((len, Vec::with_capacity(len.min(self.read_buffer.len() / 3))));
+                    continue;
+                }
+                FLAG_BULK => {
+                    if let Some((header, Some(len))) = parse_length(&self.read_buffer)? {
+                        if len >= threshold {
+                            self.read_buffer.advance(header);
+                            self.partial.bulk = Some((len, BytesMut::new()));
+                            continue;
+                        }
+                    }
+                }
+                _ => {}
+            }
+
+            // anything else is small, and decoded once it is all buffered
             let mut cursor = Cursor::new(self.read_buffer.as_ref());
             match Frame::check(&mut cursor) {
                 Ok(_) => {
@@ -88,20 +246,128 @@ impl Connection {
                     let frame = Frame::decode(&mut cursor)?;
                     let len = cursor.position() as usize;
                     self.read_buffer.advance(len);
-                    return Ok(frame);
+                    if let Some(frame) = self.partial.push(frame) {
+                        return Ok(Some(frame));
+                    }
+                }
+                Err(frame::Error::Incomplete) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Read the large bulk string in `partial` from the socket, straight
+    /// into its value. The value grows by `STREAM_CHUNK` at most, so a
+    /// length header alone allocates nothing.
+    async fn read_bulk(&mut self) -> Result<(), Error> {
+        self.flush_pending().await?;
+        let (len, value) = self.partial.bulk.as_mut().expect("a bulk string in progress");
+        let remaining = *len - value.len();
+        value.reserve(remaining.min(STREAM_CHUNK));
+        if self.stream.read_buf(&mut value.limit(remaining)).await? == 0 {
+            return Err(Error::IO("connection failure".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Read more bytes from the socket. Queued output is flushed first, the
+    /// peer may well be waiting for it before sending anything.
+    async fn fill_read_buffer(&mut self) -> Result<(), Error> {
+        self.flush_pending().await?;
+        if self.stream.read_buf(&mut self.read_buffer).await? == 0 {
+            if self.read_buffer.is_empty() {
+                return Err(Error::Other("peer shutdown".to_string()));
+            } else {
+                return Err(Error::IO("connection failure".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    async fn flush_pending(&mut self) -> Result<(), Error> {
+        if self.pending_write() > 0 {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn read_frame_buffered(&mut self) -> Result<Frame, Error> {
+        loop {
+            if self.read_buffer.has_remaining() {
+                let mut cursor = Cursor::new(self.read_buffer.as_ref());
+                match Frame::check(&mut cursor) {
+                    Ok(_) => {
+                        cursor.set_position(0);
+                        let frame = Frame::decode(&mut cursor)?;
+                        let len = cursor.position() as usize;
+                        self.read_buffer.advance(len);
+                        return Ok(frame);
+                    }
+                    Err(frame::Error::Incomplete) => {}
+                    Err(e) => return Err(e.into()),
                 }
-                Err(_) => continue,
             }
+            self.fill_read_buffer().await?;
         }
     }
 }
 
 
+/// The length of the `$<len>` or `*<len>` header at the start of `buf`, `None`
+/// for the null length `-1`, along with the size of the header. `None` if the
+/// header is not all there yet.
+fn parse_length(buf: &[u8]) -> Result<Option<(usize, Option<usize>)>, Error> {
+    let Some(end) = buf.windows(2).position(|w| w == CRLF) else {
+        return Ok(None);
+    };
+    let len = match &buf[1..end] {
+        b"-1" => None,
+        digits => match atoi::atoi::<usize>(digits) {
+            Some(len) if len <= MAX_BULK_LEN => Some(len),
+            _ => return Err(Error::Other("protocol error; invalid length".to_string())),
+        },
+    };
+    Ok(Some((end + CRLF.len(), len)))
+}
+
+/// What the streaming read consumed of a frame it did not finish, kept
+/// across calls to `read_frame`
+#[derive(Default)]
+struct PartialFrame {
+    // a top level array, its length and the elements read so far
+    array: Option<(usize, Vec<Frame>)>,
+    // a large bulk string, its length and the bytes read so far
+    bulk: Option<(usize, BytesMut)>,
+}
+
+impl PartialFrame {
+    /// Add `frame` to the array in progress, or return it if there is none
+    fn push(&mut self, frame: Frame) -> Option<Frame> {
+        match self.array.as_mut() {
+            Some((_, frames)) => {
+                frames.push(frame);
+                None
+            }
+            None => Some(frame),
+        }
+    }
+
+    /// The elements of the array in progress, once they are all read
+    fn complete_array(&mut self) -> Option<Vec<Frame>> {
+        match &self.array {
+            Some((len, frames)) if frames.len() == *len => self.array.take().map(|(_, frames)| frames),
+            _ => None,
+        }
+    }
+}
+
 //////////////////////////////
 /// Unit Test
 ////////////////////////////// 
+#[cfg(test)]
 use tokio::runtime;
 
+#[cfg(test)]
 fn new_runtime() -> runtime::Runtime {
     let rt = runtime::Builder::new_multi_thread()
         .enable_all()
@@ -151,3 +417,176 @@ fn test_pipeline() {
         }
     });
 }
+
+#[cfg(test)]
+async fn connection_pair() -> (Connection, Connection) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (client, server) = tokio::join!(TcpStream::connect(addr), listener.accept());
+    (
+        Connection::new(client.unwrap()).unwrap(),
+        Connection::new(server.unwrap().0).unwrap(),
+    )
+}
+
+#[test]
+fn test_stream_large_bulk() {
+    new_runtime().block_on(async {
+        let (mut client, mut server) = connection_pair().await;
+        client.set_stream_threshold(Some(4096));
+        server.set_stream_threshold(Some(4096));
+
+        let value = bytes::Bytes::from(vec![b'x'; 4 * 1024 * 1024]);
+        let mut frame = Frame::new_array_frame();
+        frame.push_bulk("set".into());
+        frame.push_bulk("name".into());
+        frame.push_bulk(value.clone());
+        let expected = frame.clone();
+
+        let writer = tokio::spawn(async move {
+            client.write_frame(frame).await.unwrap();
+            client.write_frame(Frame::Bulk(value)).await.unwrap();
+            client
+        });
+        assert_eq!(server.read_frame().await.unwrap(), expected);
+        // the value never went through the read buffer
+        assert!(server.read_buffer.capacity() < 64 * 1024);
+
+        let reply = server.read_frame().await.unwrap();
+        let mut client = writer.await.unwrap();
+        let reader = tokio::spawn(async move { client.read_frame().await.unwrap() });
+        server.write_frame(reply).await.unwrap();
+        assert_eq!(reader.await.unwrap(), expected.into_iterator().nth(2).unwrap());
+        assert!(server.write_buffer.capacity() < 64 * 1024);
+    });
+}
+
+#[test]
+fn test_stream_small_frames() {
+    new_runtime().block_on(async {
+        let (mut client, mut server) = connection_pair().await;
+        server.set_stream_threshold(Some(4096));
+
+        let frames = vec![
+            Frame::Array(vec![Frame::Bulk("get".into()), Frame::Bulk("name".into())]),
+            Frame::Simple("OK".to_string()),
+            Frame::Array(vec![Frame::Integer(1), Frame::Null, Frame::Array(vec![])]),
+            Frame::Null,
+        ];
+        for frame in frames.iter() {
+            client.write_frame(frame.clone()).await.unwrap();
+        }
+        client.flush().await.unwrap();
+        for frame in frames {
+            assert_eq!(server.read_frame().await.unwrap(), frame);
+        }
+    });
+}
+
+#[test]
+fn test_stream_cancelled_reads() {
+    use std::time::Duration;
+    use tokio::io::AsyncWriteExt;
+
+    new_runtime().block_on(async {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (client, server) = tokio::join!(TcpStream::connect(addr), listener.accept());
+        let mut client = client.unwrap();
+        let mut server = Connection::new(server.unwrap().0).unwrap();
+        server.set_stream_threshold(Some(4096));
+
+        let value = vec![b'x'; 100 * 1024];
+        let mut request = b"*3\r\n$3\r\nset\r\n$4\r\nname\r\n".to_vec();
+        request.extend_from_slice(format!("${}\r\n", value.len()).as_bytes());
+        request.extend_from_slice(&value);
+        request.extend_from_slice(b"\r\n*1\r\n$4\r\nping\r\n");
+        // in pieces, each read given up on before the next one arrives,
+        // like the `select!` of a handler does on a published message
+        for piece in request.chunks(7 * 1024 + 3) {
+            client.write_all(piece).await.unwrap();
+            let read = tokio::time::timeout(Duration::from_millis(20), server.read_frame()).await;
+            if let Ok(frame) = read {
+                let frame = frame.unwrap();
+                assert_eq!(frame.into_iterator().nth(2).unwrap(), Frame::Bulk(value.clone().into()));
+            }
+        }
+        let ping = Frame::Array(vec![Frame::Bulk("ping".into())]);
+        assert_eq!(server.read_frame().await.unwrap(), ping);
+    });
+}
+
+#[test]
+fn test_stream_grows_with_the_value() {
+    use std::time::Duration;
+    use tokio::io::AsyncWriteExt;
+
+    new_runtime().block_on(async {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (client, server) = tokio::join!(TcpStream::connect(addr), listener.accept());
+        let mut client = client.unwrap();
+        let mut server = Connection::new(server.unwrap().0).unwrap();
+        server.set_stream_threshold(Some(4096));
+
+        // a length header alone does not allocate the whole value
+        client.write_all(format!("*2\r\n$3\r\nget\r\n${MAX_BULK_LEN}\r\nabc").as_bytes()).await.unwrap();
+        let read = tokio::time::timeout(Duration::from_millis(50), server.read_frame()).await;
+        assert!(read.is_err());
+        let (len, value) = server.partial.bulk.as_ref().unwrap();
+        assert_eq!((*len, value.as_ref()), (MAX_BULK_LEN, &b"abc"[..]));
+        assert!(value.capacity() <= 2 * STREAM_CHUNK);
+    });
+}
+
+#[test]
+fn test_explicit_flush() {
+    use std::time::Duration;
+
+    new_runtime().block_on(async {
+        let (mut client, mut server) = connection_pair().await;
+        let value = bytes::Bytes::from(vec![b'x'; 16 * 1024]);
+
+        client.write_frame(Frame::Simple("OK".to_string())).await.unwrap();
+        client.write_frame(Frame::Bulk(value.clone())).await.unwrap();
+        // the large payload is queued as is, not copied
+        assert!(client.write_queue.iter().any(|bs| bs.as_ptr() == value.as_ptr()));
+        assert!(client.write_buffer.capacity() < value.len());
+
+        // nothing has been sent yet
+        let read = tokio::time::timeout(Duration::from_millis(50), server.read_frame()).await;
+        assert!(read.is_err());
+
+        client.flush().await.unwrap();
+        assert_eq!(client.pending_write(), 0);
+        assert_eq!(server.read_frame().await.unwrap(), "OK");
+        assert_eq!(server.read_frame().await.unwrap(), Frame::Bulk(value));
+    });
+}
+
+#[test]
+fn test_flush_before_read() {
+    new_runtime().block_on(async {
+        let (mut client, mut server) = connection_pair().await;
+
+        let echo = tokio::spawn(async move {
+            loop {
+                let frame = match server.read_frame().await {
+                    Ok(frame) => frame,
+                    Err(_) => return,
+                };
+                server.write_frame(frame).await.unwrap();
+            }
+        });
+
+        // pipelined requests go out together once the client waits for a reply
+        for i in 0..100 {
+            client.write_frame(Frame::Integer(i)).await.unwrap();
+        }
+        for i in 0..100 {
+            assert_eq!(client.read_frame().await.unwrap(), Frame::Integer(i));
+        }
+        drop(client);
+        echo.await.unwrap();
+    });
+}
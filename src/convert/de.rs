@@ -0,0 +1,340 @@
+use std::{str::FromStr, vec};
+
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+
+use super::Error;
+use crate::frame::Frame;
+
+/// Deserialize a `T` out of `frame`
+pub fn from_frame<T: DeserializeOwned>(frame: Frame) -> Result<T, Error> {
+    T::deserialize(Deserializer::new(frame))
+}
+
+/// serde `Deserializer` reading from a `Frame`, see the module docs for the mapping
+pub struct Deserializer {
+    frame: Frame,
+}
+
+impl Deserializer {
+    pub fn new(frame: Frame) -> Self {
+        Deserializer { frame }
+    }
+
+    /// The frame as text, numbers are parsed from it since redis sends
+    /// them as bulk strings
+    fn into_text(self) -> Result<String, Error> {
+        match self.frame {
+            Frame::Simple(s) => Ok(s),
+            Frame::Bulk(bs) => String::from_utf8(bs.to_vec())
+                .map_err(|_| Error::Mismatch("bulk string is not valid utf-8".to_string())),
+            Frame::Integer(n) => Ok(n.to_string()),
+            other => Err(unexpected(other, "a string")),
+        }
+    }
+
+    fn parse<T: FromStr>(self, what: &str) -> Result<T, Error> {
+        let text = self.into_text()?;
+        text.parse()
+            .map_err(|_| Error::Mismatch(format!("`{}` is not {}", text, what)))
+    }
+
+    fn into_array(self, what: &str) -> Result<Vec<Frame>, Error> {
+        match self.frame {
+            Frame::Array(frames) | Frame::Map(frames) => Ok(frames),
+            other => Err(unexpected(other, what)),
+        }
+    }
+}
+
+fn unexpected(frame: Frame, expected: &str) -> Error {
+    match frame {
+        // an error reply is reported as is rather than as a mismatch
+        Frame::Error(msg) => Error::Other(msg),
+        other => Error::Mismatch(format!("expected {}, got {:?}", expected, other)),
+    }
+}
+
+macro_rules! deserialize_number {
+    ($method:ident, $visit:ident, $what:expr) => {
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.$visit(self.parse($what)?)
+        }
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.frame {
+            Frame::Simple(s) => visitor.visit_string(s),
+            Frame::Bulk(bs) => match String::from_utf8(bs.to_vec()) {
+                Ok(s) => visitor.visit_string(s),
+                Err(e) => visitor.visit_byte_buf(e.into_bytes()),
+            },
+            Frame::Integer(n) => visitor.visit_i64(n),
+            Frame::Null | Frame::NullArray => visitor.visit_unit(),
+            Frame::Array(frames) | Frame::Push(frames) => visitor.visit_seq(SeqAccess::new(frames)),
+            Frame::Map(frames) => visitor.visit_map(MapAccess { frames: frames.into_iter() }),
+            Frame::Error(msg) => Err(Error::Other(msg)),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.into_text()?.as_str() {
+            "1" | "true" => visitor.visit_bool(true),
+            "0" | "false" => visitor.visit_bool(false),
+            other => Err(Error::Mismatch(format!("`{}` is not a boolean", other))),
+        }
+    }
+
+    deserialize_number!(deserialize_i8, visit_i8, "an i8");
+    deserialize_number!(deserialize_i16, visit_i16, "an i16");
+    deserialize_number!(deserialize_i32, visit_i32, "an i32");
+    deserialize_number!(deserialize_i64, visit_i64, "an i64");
+    deserialize_number!(deserialize_u8, visit_u8, "a u8");
+    deserialize_number!(deserialize_u16, visit_u16, "a u16");
+    deserialize_number!(deserialize_u32, visit_u32, "a u32");
+    deserialize_number!(deserialize_u64, visit_u64, "a u64");
+    deserialize_number!(deserialize_f32, visit_f32, "an f32");
+    deserialize_number!(deserialize_f64, visit_f64, "an f64");
+    deserialize_number!(deserialize_char, visit_char, "a char");
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_string(self.into_text()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.frame {
+            Frame::Bulk(bs) => visitor.visit_byte_buf(bs.to_vec()),
+            Frame::Simple(s) => visitor.visit_byte_buf(s.into_bytes()),
+            other => Err(unexpected(other, "bytes")),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.frame {
+            Frame::Null | Frame::NullArray => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.frame {
+            Frame::Null | Frame::NullArray => visitor.visit_unit(),
+            other => Err(unexpected(other, "null")),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let frames = self.into_array("an array")?;
+        visitor.visit_seq(SeqAccess::new(frames))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let frames = self.into_array("an array of keys and values")?;
+        if frames.len() % 2 != 0 {
+            return Err(Error::Mismatch(
+                "an array of keys and values has an even length".to_string(),
+            ));
+        }
+        visitor.visit_map(MapAccess {
+            frames: frames.into_iter(),
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let (variant, content) = match self.frame {
+            Frame::Array(frames) if frames.len() == 2 => {
+                let mut frames = frames.into_iter();
+                let variant = Deserializer::new(frames.next().unwrap()).into_text()?;
+                (variant, frames.next())
+            }
+            frame @ (Frame::Bulk(_) | Frame::Simple(_)) => {
+                (Deserializer::new(frame).into_text()?, None)
+            }
+            other => return Err(unexpected(other, "an enum variant")),
+        };
+        visitor.visit_enum(EnumAccess { variant, content })
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+}
+
+struct SeqAccess {
+    frames: vec::IntoIter<Frame>,
+}
+
+impl SeqAccess {
+    fn new(frames: Vec<Frame>) -> Self {
+        SeqAccess {
+            frames: frames.into_iter(),
+        }
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for SeqAccess {
+    type Error = Error;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.frames.next() {
+            Some(frame) => seed.deserialize(Deserializer::new(frame)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.frames.len())
+    }
+}
+
+/// Reads a flat array of alternating keys and values
+struct MapAccess {
+    frames: vec::IntoIter<Frame>,
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess {
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.frames.next() {
+            Some(frame) => seed.deserialize(Deserializer::new(frame)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        // `deserialize_map` made sure every key has a value
+        seed.deserialize(Deserializer::new(self.frames.next().unwrap()))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.frames.len() / 2)
+    }
+}
+
+struct EnumAccess {
+    variant: String,
+    content: Option<Frame>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = Error;
+    type Variant = VariantAccess;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, VariantAccess), Error> {
+        let variant: de::value::StringDeserializer<Error> = self.variant.into_deserializer();
+        let value = seed.deserialize(variant)?;
+        Ok((
+            value,
+            VariantAccess {
+                content: self.content,
+            },
+        ))
+    }
+}
+
+struct VariantAccess {
+    content: Option<Frame>,
+}
+
+impl VariantAccess {
+    fn content(self) -> Result<Deserializer, Error> {
+        self.content
+            .map(Deserializer::new)
+            .ok_or_else(|| Error::Mismatch("enum variant has no content".to_string()))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.content {
+            None => Ok(()),
+            Some(_) => Err(Error::Mismatch("unit variant has content".to_string())),
+        }
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self.content()?)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(self.content()?, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(self.content()?, visitor)
+    }
+}
@@ -0,0 +1,143 @@
+//! Conversion between Rust types and `Frame` through serde
+//!
+//! The mapping follows how redis itself stores data:
+//! - strings, bytes, numbers and booleans become bulk strings
+//! - `None` and `()` become null
+//! - sequences and tuples become arrays
+//! - maps and structs become flat arrays of alternating keys and values,
+//!   the shape taken by `HSET` arguments and returned by `HGETALL`
+//! - unit variants become their name, other variants an array of the name
+//!   and the content
+
+mod de;
+mod ser;
+
+pub use de::{from_frame, Deserializer};
+pub use ser::{to_frame, Serializer};
+
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// the frame does not have the shape the type expects
+    Mismatch(String),
+    Other(String),
+}
+
+impl std::error::Error for Error {}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Mismatch(msg) => write!(f, "frame mismatch: {}", msg),
+            Error::Other(msg) => msg.fmt(f),
+        }
+    }
+}
+
+impl serde::ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Other(msg.to_string())
+    }
+}
+
+impl serde::de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Other(msg.to_string())
+    }
+}
+
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[cfg(test)]
+use crate::frame::Frame;
+#[cfg(test)]
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Session {
+    user: String,
+    visits: u32,
+    admin: bool,
+    score: f64,
+    tag: Option<String>,
+}
+
+#[test]
+fn test_struct_round_trip() {
+    let session = Session {
+        user: "simon".to_string(),
+        visits: 3,
+        admin: false,
+        score: 1.5,
+        tag: None,
+    };
+
+    let frame = to_frame(&session).unwrap();
+    assert_eq!(
+        frame,
+        Frame::Array(vec![
+            Frame::Bulk("user".into()),
+            Frame::Bulk("simon".into()),
+            Frame::Bulk("visits".into()),
+            Frame::Bulk("3".into()),
+            Frame::Bulk("admin".into()),
+            Frame::Bulk("0".into()),
+            Frame::Bulk("score".into()),
+            Frame::Bulk("1.5".into()),
+            Frame::Bulk("tag".into()),
+            Frame::Null,
+        ])
+    );
+    assert_eq!(from_frame::<Session>(frame).unwrap(), session);
+}
+
+#[test]
+fn test_primitives_and_containers() {
+    use std::collections::BTreeMap;
+
+    assert_eq!(to_frame(&42i64).unwrap(), Frame::Bulk("42".into()));
+    assert_eq!(from_frame::<i64>(Frame::Bulk("-7".into())).unwrap(), -7);
+    assert_eq!(from_frame::<u64>(Frame::Integer(7)).unwrap(), 7);
+    assert_eq!(from_frame::<String>(Frame::Simple("OK".into())).unwrap(), "OK");
+    assert_eq!(from_frame::<Option<String>>(Frame::Null).unwrap(), None);
+
+    let list = vec!["a".to_string(), "b".to_string()];
+    let frame = to_frame(&list).unwrap();
+    assert_eq!(from_frame::<Vec<String>>(frame).unwrap(), list);
+
+    let mut map = BTreeMap::new();
+    map.insert("x".to_string(), 1u8);
+    map.insert("y".to_string(), 2u8);
+    let frame = to_frame(&map).unwrap();
+    assert_eq!(from_frame::<BTreeMap<String, u8>>(frame).unwrap(), map);
+
+    assert!(matches!(
+        from_frame::<u8>(Frame::Bulk("300".into())),
+        Err(Error::Mismatch(_))
+    ));
+    assert!(from_frame::<String>(Frame::Error("ERR boom".into())).is_err());
+}
+
+#[test]
+fn test_enum_round_trip() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Job {
+        Idle,
+        Retry(u8),
+        Move { from: String, to: String },
+    }
+
+    for job in [
+        Job::Idle,
+        Job::Retry(2),
+        Job::Move {
+            from: "a".to_string(),
+            to: "b".to_string(),
+        },
+    ] {
+        let frame = to_frame(&job).unwrap();
+        assert_eq!(from_frame::<Job>(frame).unwrap(), job);
+    }
+}
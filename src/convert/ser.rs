@@ -0,0 +1,302 @@
+use bytes::Bytes;
+use serde::{ser, Serialize};
+
+use super::Error;
+use crate::frame::Frame;
+
+/// Serialize `value` into a `Frame`
+pub fn to_frame<T: Serialize + ?Sized>(value: &T) -> Result<Frame, Error> {
+    value.serialize(Serializer)
+}
+
+/// serde `Serializer` that builds a `Frame`, see the module docs for the mapping
+pub struct Serializer;
+
+fn bulk(s: impl ToString) -> Frame {
+    Frame::Bulk(Bytes::from(s.to_string()))
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Frame;
+    type Error = Error;
+
+    type SerializeSeq = SerializeVec;
+    type SerializeTuple = SerializeVec;
+    type SerializeTupleStruct = SerializeVec;
+    type SerializeTupleVariant = SerializeVariant;
+    type SerializeMap = SerializeVec;
+    type SerializeStruct = SerializeVec;
+    type SerializeStructVariant = SerializeVariant;
+
+    fn serialize_bool(self, v: bool) -> Result<Frame, Error> {
+        Ok(bulk(if v { "1" } else { "0" }))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Frame, Error> {
+        Ok(bulk(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Frame, Error> {
+        Ok(bulk(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Frame, Error> {
+        Ok(bulk(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Frame, Error> {
+        Ok(bulk(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Frame, Error> {
+        Ok(bulk(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Frame, Error> {
+        Ok(bulk(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Frame, Error> {
+        Ok(bulk(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Frame, Error> {
+        Ok(bulk(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Frame, Error> {
+        Ok(bulk(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Frame, Error> {
+        Ok(bulk(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Frame, Error> {
+        Ok(bulk(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Frame, Error> {
+        Ok(bulk(v))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Frame, Error> {
+        Ok(Frame::Bulk(Bytes::copy_from_slice(v)))
+    }
+
+    fn serialize_none(self) -> Result<Frame, Error> {
+        Ok(Frame::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Frame, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Frame, Error> {
+        Ok(Frame::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Frame, Error> {
+        Ok(Frame::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Frame, Error> {
+        Ok(bulk(variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Frame, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Frame, Error> {
+        Ok(Frame::Array(vec![bulk(variant), value.serialize(self)?]))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeVec, Error> {
+        Ok(SerializeVec {
+            frames: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeVec, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SerializeVec, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeVariant, Error> {
+        Ok(SerializeVariant {
+            variant,
+            frames: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<SerializeVec, Error> {
+        self.serialize_seq(len.map(|len| len * 2))
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeVec, Error> {
+        self.serialize_seq(Some(len * 2))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeVariant, Error> {
+        Ok(SerializeVariant {
+            variant,
+            frames: Vec::with_capacity(len * 2),
+        })
+    }
+}
+
+/// Collects elements, and for maps and structs keys and values, into an array
+pub struct SerializeVec {
+    frames: Vec<Frame>,
+}
+
+impl ser::SerializeSeq for SerializeVec {
+    type Ok = Frame;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.frames.push(to_frame(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Frame, Error> {
+        Ok(Frame::Array(self.frames))
+    }
+}
+
+impl ser::SerializeTuple for SerializeVec {
+    type Ok = Frame;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Frame, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeVec {
+    type Ok = Frame;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Frame, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeMap for SerializeVec {
+    type Ok = Frame;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Frame, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeStruct for SerializeVec {
+    type Ok = Frame;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.frames.push(bulk(key));
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Frame, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+/// Like `SerializeVec`, but wraps the array with the name of the variant
+pub struct SerializeVariant {
+    variant: &'static str,
+    frames: Vec<Frame>,
+}
+
+impl SerializeVariant {
+    fn finish(self) -> Frame {
+        Frame::Array(vec![bulk(self.variant), Frame::Array(self.frames)])
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeVariant {
+    type Ok = Frame;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.frames.push(to_frame(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Frame, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeVariant {
+    type Ok = Frame;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.frames.push(bulk(key));
+        self.frames.push(to_frame(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Frame, Error> {
+        Ok(self.finish())
+    }
+}
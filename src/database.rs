@@ -1,26 +1,532 @@
-use std::collections::HashMap;
+use std::{collections::VecDeque, time::Duration};
 
 use bytes::Bytes;
+use tokio::time::Instant;
+
+use tokio::sync::{oneshot, watch};
+
+use crate::{
+    blocking::{Serve, Waiters},
+    dict::Dict,
+    frame::Frame,
+    function::Libraries,
+    notify::Events,
+    pubsub::Broker,
+    transaction::Versions,
+    types::{CompactLimits, Hash, Set, Stream, ZSet},
+};
+
+/// values freeing more allocations than this are dropped in the background
+/// by `unlink`, same as `LAZYFREE_THRESHOLD` in redis
+const LAZYFREE_THRESHOLD: usize = 64;
+
+/// keys looked at in a row by `expire_keys`, same as
+/// `ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP` in redis
+const EXPIRE_SAMPLE: usize = 20;
 
 pub struct Database {
-    // the value should be abstracted
-    store: HashMap<String, Bytes>,
+    store: Dict<String, Entry>,
+    blocked: Waiters,
+    // counts the writes to the keys clients WATCH
+    versions: Versions,
+    broker: Broker,
+    // the function libraries, part of the dataset like the keys
+    functions: Libraries,
+    config: Config,
+    // the config again, for handlers to read without locking the database
+    config_sender: watch::Sender<Config>,
+    // where `expire_keys` goes on from
+    expire_cursor: u64,
+}
+
+/// Tunables of the database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// when a hash leaves its compact encoding
+    pub hash_limits: CompactLimits,
+    /// most members of a set of integers in its compact encoding, same as
+    /// `set-max-intset-entries`
+    pub set_max_intset_entries: usize,
+    /// largest sparse HyperLogLog, header included, same as
+    /// `hll-sparse-max-bytes`
+    pub hll_sparse_max_bytes: usize,
+    /// the keyspace events published, see `notify`
+    pub notify_keyspace_events: Events,
+    /// how long a script runs before other clients are told the server is
+    /// busy, same as `lua-time-limit`
+    pub lua_time_limit: Duration,
+    /// bulk strings of requests at least this long are streamed into their
+    /// value rather than buffered whole, see `Connection::set_stream_threshold`.
+    /// Taken by new connections.
+    pub stream_bulk_threshold: Option<usize>,
+    /// most bytes of messages a subscriber may have waiting before it is
+    /// disconnected, see `Subscriptions::set_limit`. Taken by new connections.
+    pub pubsub_buffer_limit: Option<usize>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            hash_limits: CompactLimits::default(),
+            set_max_intset_entries: 512,
+            hll_sparse_max_bytes: 3000,
+            notify_keyspace_events: Events::NONE,
+            lua_time_limit: Duration::from_secs(5),
+            // `PROTO_MBULK_BIG_ARG` of redis
+            stream_bulk_threshold: Some(32 * 1024),
+            // the hard pubsub `client-output-buffer-limit` of redis
+            pubsub_buffer_limit: Some(32 * 1024 * 1024),
+        }
+    }
+}
+
+/// A value stored in the database, one variant per redis data type
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(Bytes),
+    List(VecDeque<Bytes>),
+    Hash(Hash),
+    Set(Set),
+    ZSet(ZSet),
+    Stream(Stream),
+}
+
+impl Value {
+    /// The name reported by `TYPE`
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::ZSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// A collection with no elements left, its key has to be removed
+    fn is_empty_collection(&self) -> bool {
+        match self {
+            Value::String(_) => false,
+            Value::List(list) => list.is_empty(),
+            Value::Hash(hash) => hash.is_empty(),
+            Value::Set(set) => set.is_empty(),
+            Value::ZSet(zset) => zset.is_empty(),
+            // an empty stream keeps its last ID and its groups
+            Value::Stream(_) => false,
+        }
+    }
+
+    /// Roughly the number of allocations freed when the value is dropped
+    fn free_effort(&self) -> usize {
+        match self {
+            Value::String(_) => 1,
+            Value::List(list) => list.len(),
+            Value::Hash(hash) => hash.len(),
+            Value::Set(set) => set.len(),
+            Value::ZSet(zset) => zset.len(),
+            Value::Stream(stream) => stream.len(),
+        }
+    }
+}
+
+/// The value at a key is of another type than the operation expects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WrongType;
+
+impl From<WrongType> for Frame {
+    fn from(_: WrongType) -> Frame {
+        Frame::Error("WRONGTYPE Operation against a key holding the wrong kind of value".to_string())
+    }
+}
+
+/// `get_*`, `get_*_mut` and `*_entry` accessors for a collection type
+macro_rules! typed_accessors {
+    ($variant:ident, $ty:ty, $get:ident, $get_mut:ident, $entry:ident) => {
+        pub fn $get(&self, key: &str) -> Result<Option<&$ty>, WrongType> {
+            match self.get_value(key) {
+                Some(Value::$variant(v)) => Ok(Some(v)),
+                Some(_) => Err(WrongType),
+                None => Ok(None),
+            }
+        }
+
+        pub fn $get_mut(&mut self, key: &str) -> Result<Option<&mut $ty>, WrongType> {
+            match self.get_value_mut(key) {
+                Some(Value::$variant(v)) => Ok(Some(v)),
+                Some(_) => Err(WrongType),
+                None => Ok(None),
+            }
+        }
+
+        /// Like the `_mut` accessor, but a missing key is created empty.
+        /// Call `remove_if_empty` if nothing ends up being added.
+        pub fn $entry(&mut self, key: &str) -> Result<&mut $ty, WrongType> {
+            // whatever is added next may be what a blocked client waits for
+            self.blocked.signal(key);
+            if !self.contains(key) {
+                self.insert(key.to_string(), Value::$variant(Default::default()), None);
+            }
+            self.$get_mut(key).map(|v| v.unwrap())
+        }
+    };
+}
+
+#[derive(Clone)]
+struct Entry {
+    value: Value,
+    // `None` means the key never expires
+    expires_at: Option<Instant>,
 }
 
-enum Error {
-    Other,
+impl Entry {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|when| when <= now)
+    }
 }
 
 impl Database {
     pub fn new() -> Self {
+        Self::with_config(Config::default())
+    }
+
+    pub fn with_config(config: Config) -> Self {
         Database {
-            store: HashMap::new(),
+            store: Dict::new(),
+            blocked: Waiters::default(),
+            versions: Versions::default(),
+            broker: Broker::default(),
+            functions: Libraries::default(),
+            config_sender: watch::Sender::new(config.clone()),
+            config,
+            expire_cursor: 0,
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: Config) {
+        self.config_sender.send_replace(config.clone());
+        self.config = config;
+    }
+
+    /// The config as it changes, readable while a script holds the database
+    pub fn watch_config(&self) -> watch::Receiver<Config> {
+        self.config_sender.subscribe()
+    }
+
+    /// The pub/sub channels, see `pubsub`
+    pub fn broker(&self) -> &Broker {
+        &self.broker
+    }
+
+    pub fn broker_mut(&mut self) -> &mut Broker {
+        &mut self.broker
+    }
+
+    pub fn functions(&self) -> &Libraries {
+        &self.functions
+    }
+
+    pub fn functions_mut(&mut self) -> &mut Libraries {
+        &mut self.functions
+    }
+
+    /// Publish the keyspace event `event` of `class` about `key`, if
+    /// `notify-keyspace-events` asks for it. Commands call it after they
+    /// modify a key, the database itself for `new`, `expired` and the `del`
+    /// of a collection left empty. It is also how a write through a `_mut`
+    /// accessor reaches the clients watching `key`.
+    pub fn notify(&mut self, class: Events, event: &str, key: &str) {
+        self.versions.touch(key);
+        let events = self.config.notify_keyspace_events;
+        if !events.publishes(class) {
+            return;
+        }
+        if events.contains(Events::KEYSPACE) {
+            let channel = Bytes::from(format!("__keyspace@0__:{key}"));
+            self.broker.publish(&channel, &Bytes::copy_from_slice(event.as_bytes()));
+        }
+        if events.contains(Events::KEYEVENT) {
+            let channel = Bytes::from(format!("__keyevent@0__:{event}"));
+            self.broker.publish(&channel, &Bytes::copy_from_slice(key.as_bytes()));
+        }
+    }
+
+    /// The string at `key`, an expired key reads as missing
+    pub fn get(&self, key: &str) -> Result<Option<Bytes>, WrongType> {
+        match self.get_value(key) {
+            Some(Value::String(bs)) => Ok(Some(bs.clone())),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Store the string `val` at `key`, replacing a value of any type, and
+    /// drop any time to live, like `SET` does
+    pub fn set(&mut self, key: String, val: Bytes) -> Option<Value> {
+        self.insert(key, Value::String(val), None)
+    }
+
+    /// Replace the string at `key` but keep its time to live, the way
+    /// commands modifying a value in place, e.g. `INCR` or `APPEND`, do
+    pub fn update(&mut self, key: String, val: Bytes) -> Option<Value> {
+        let expires_at = self.expires_at(&key);
+        self.insert(key, Value::String(val), expires_at)
+    }
+
+    typed_accessors!(List, VecDeque<Bytes>, get_list, get_list_mut, list_entry);
+    typed_accessors!(Hash, Hash, get_hash, get_hash_mut, hash_entry);
+    typed_accessors!(Set, Set, get_set, get_set_mut, set_entry);
+    typed_accessors!(ZSet, ZSet, get_zset, get_zset_mut, zset_entry);
+    typed_accessors!(Stream, Stream, get_stream, get_stream_mut, stream_entry);
+
+    /// Remove `key` if it holds a collection with no elements left, redis
+    /// never keeps empty lists, hashes, sets or sorted sets around
+    pub fn remove_if_empty(&mut self, key: &str) {
+        if self.get_value(key).is_some_and(|value| value.is_empty_collection()) {
+            self.remove(key);
+            self.notify(Events::GENERIC, "del", key);
+        }
+    }
+
+    pub fn get_value(&self, key: &str) -> Option<&Value> {
+        self.entry(key).map(|entry| &entry.value)
+    }
+
+    pub fn get_value_mut(&mut self, key: &str) -> Option<&mut Value> {
+        // a write through it is counted by the `notify` that follows
+        self.purge_expired(key);
+        self.store.get_mut(key).map(|entry| &mut entry.value)
+    }
+
+    /// Store `value` at `key` with the given deadline, returns the live value
+    /// it replaced
+    pub fn insert(&mut self, key: String, value: Value, expires_at: Option<Instant>) -> Option<Value> {
+        self.blocked.signal(&key);
+        self.versions.touch(&key);
+        let new = self.config.notify_keyspace_events.publishes(Events::NEW) && !self.contains(&key);
+        if new {
+            self.notify(Events::NEW, "new", &key);
+        }
+        let replaced = self.store.insert(key.clone(), Entry { value, expires_at });
+        self.live(&key, replaced).map(|entry| entry.value)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        let removed = self.store.remove(key);
+        if removed.is_some() {
+            self.versions.touch(key);
+        }
+        self.live(key, removed).map(|entry| entry.value)
+    }
+
+    /// Remove `key` and its deadline, e.g. to move it to another key
+    pub fn take(&mut self, key: &str) -> Option<(Value, Option<Instant>)> {
+        let removed = self.store.remove(key);
+        if removed.is_some() {
+            self.versions.touch(key);
+        }
+        self.live(key, removed).map(|entry| (entry.value, entry.expires_at))
+    }
+
+    /// Like `remove`, but a large value is freed by a background task
+    /// instead of while the database is locked. Returns whether a key was
+    /// removed.
+    pub fn unlink(&mut self, key: &str) -> bool {
+        let value = match self.remove(key) {
+            Some(value) => value,
+            None => return false,
+        };
+        if value.free_effort() > LAZYFREE_THRESHOLD {
+            if let Ok(handle) = tokio::runtime::Handle::try_current() {
+                handle.spawn_blocking(move || drop(value));
+            }
+        }
+        true
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.entry(key).is_some()
+    }
+
+    /// A random live key, `None` if the database is empty
+    pub fn random_key(&mut self) -> Option<String> {
+        let now = Instant::now();
+        // expired keys found on the way are purged, so this does not spin
+        // on a database holding nothing but expired keys
+        while let Some((key, entry)) = self.store.random() {
+            if !entry.is_expired(now) {
+                return Some(key.clone());
+            }
+            let key = key.clone();
+            self.store.remove(&key);
+            self.versions.touch(&key);
+            self.notify(Events::EXPIRED, "expired", &key);
+        }
+        None
+    }
+
+    /// Call `f` on about `count` live keys from `cursor` on, returns the
+    /// cursor to continue from, `0` once done. See `Dict::scan` for what is
+    /// guaranteed across calls.
+    pub fn scan(&self, cursor: u64, count: usize, mut f: impl FnMut(&str, &Value)) -> u64 {
+        let now = Instant::now();
+        self.store.scan(cursor, count, |key, entry| {
+            if !entry.is_expired(now) {
+                f(key, &entry.value)
+            }
+        })
+    }
+
+    /// Every live key, in no particular order
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        let now = Instant::now();
+        self.store
+            .iter()
+            .filter(move |(_, entry)| !entry.is_expired(now))
+            .map(|(key, _)| key)
+    }
+
+    /// Park a client until `serve` succeeds after a write to one of `keys`.
+    /// The reply is sent on the returned channel, `id` is for `unblock`.
+    pub fn block(&mut self, keys: Vec<String>, serve: Serve) -> (u64, oneshot::Receiver<Frame>) {
+        self.blocked.add(keys, serve)
+    }
+
+    /// Stop waiting, e.g. on timeout. Returns `false` if the client was
+    /// served in the meantime and its reply is in the channel.
+    pub fn unblock(&mut self, id: u64) -> bool {
+        self.blocked.remove(id)
+    }
+
+    /// Serve the clients blocked on keys written since the last call, the
+    /// oldest waiter of a key first. To be called after every command,
+    /// before the database lock is released.
+    pub fn serve_blocked(&mut self) {
+        while let Some(key) = self.blocked.next_ready() {
+            for id in self.blocked.queue(&key) {
+                // served through another of its keys already
+                let mut waiter = match self.blocked.take(id) {
+                    Some(waiter) => waiter,
+                    None => continue,
+                };
+                if waiter.reply.is_closed() {
+                    // gone, e.g. on shutdown, and must not consume anything
+                    self.blocked.forget(id, &waiter.keys);
+                    continue;
+                }
+                match (waiter.serve)(self) {
+                    Some(frame) => {
+                        self.blocked.forget(id, &waiter.keys);
+                        let _ = waiter.reply.send(frame);
+                    }
+                    None => {
+                        // the key is drained, the others keep waiting
+                        self.blocked.put_back(id, waiter);
+                        break;
+                    }
+                }
+            }
         }
     }
-    pub fn get<'a>(&'a self, key: &String) -> Option<Bytes> {
-        self.store.get(key).cloned()
+
+    /// Start tracking the writes to `key` for a client's `WATCH`, returns
+    /// its version. Each call needs an `unwatch`.
+    pub fn watch(&mut self, key: &str) -> u64 {
+        self.versions.watch(key)
+    }
+
+    pub fn unwatch(&mut self, key: &str) {
+        self.versions.unwatch(key)
+    }
+
+    /// Bumped by every write to a watched `key`, see `transaction`
+    pub fn version(&self, key: &str) -> u64 {
+        self.versions.version(key)
+    }
+
+    /// When `key` expires, `None` if it is missing or has no time to live
+    pub fn expires_at(&self, key: &str) -> Option<Instant> {
+        self.entry(key).and_then(|entry| entry.expires_at)
     }
-    pub fn set(&mut self, key: String, val: Bytes) -> Option<Bytes> {
-        self.store.insert(key, val)
+
+    /// Set or, with `None`, clear the time to live of `key`.
+    /// Returns `false` if there is no such key.
+    pub fn set_expires_at(&mut self, key: &str, expires_at: Option<Instant>) -> bool {
+        self.purge_expired(key);
+        match self.store.get_mut(key) {
+            Some(entry) => {
+                entry.expires_at = expires_at;
+                self.versions.touch(key);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn entry(&self, key: &str) -> Option<&Entry> {
+        self.store
+            .get(key)
+            .filter(|entry| !entry.is_expired(Instant::now()))
+    }
+
+    fn purge_expired(&mut self, key: &str) {
+        if self.store.get(key).is_some_and(|entry| entry.is_expired(Instant::now())) {
+            self.store.remove(key);
+            self.versions.touch(key);
+            self.notify(Events::EXPIRED, "expired", key);
+        }
+    }
+
+    /// Remove keys past their deadline even if nobody reads them, like the
+    /// active expire cycle of redis. Goes on from where the last call
+    /// stopped, `EXPIRE_SAMPLE` keys at a time, for as long as more than a
+    /// quarter of them had expired and fewer than `max_keys` were looked
+    /// at. Returns how many keys were removed.
+    pub fn expire_keys(&mut self, max_keys: usize) -> usize {
+        let now = Instant::now();
+        let (mut visited, mut removed) = (0, 0);
+        loop {
+            let mut sample = 0;
+            let mut expired = vec![];
+            self.expire_cursor = self.store.scan(self.expire_cursor, EXPIRE_SAMPLE, |key, entry| {
+                sample += 1;
+                if entry.is_expired(now) {
+                    expired.push(key.clone());
+                }
+            });
+            for key in expired.iter() {
+                self.store.remove(key);
+                self.versions.touch(key);
+                self.notify(Events::EXPIRED, "expired", key);
+            }
+            visited += sample;
+            removed += expired.len();
+            if expired.len() * 4 <= sample || visited >= max_keys {
+                return removed;
+            }
+        }
+    }
+
+    /// The entry removed from or replaced at `key`, unless it had already
+    /// expired, which is notified then
+    fn live(&mut self, key: &str, entry: Option<Entry>) -> Option<Entry> {
+        match entry {
+            Some(entry) if entry.is_expired(Instant::now()) => {
+                self.notify(Events::EXPIRED, "expired", key);
+                None
+            }
+            entry => entry,
+        }
     }
 }
+
+impl Default for Database {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
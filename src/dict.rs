@@ -0,0 +1,288 @@
+//! A chained hash table that can be scanned with a cursor
+//!
+//! `std::collections::HashMap` gives no way to resume an iteration once the
+//! map has been modified, which is what `SCAN` needs: the lock is released
+//! between two calls and the table may grow or shrink in between.
+//!
+//! `Dict` keeps a power of two number of buckets and scans them the same way
+//! redis does, incrementing the cursor from its highest bit down. A bucket
+//! of a smaller table maps to buckets of a larger table that share its low
+//! bits, and these are exactly the buckets the reversed increment visits
+//! next. So an element that is in the table for the whole scan is returned
+//! at least once, whatever resizes happen in between. An element may be
+//! returned more than once after the table shrinks.
+
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+};
+
+use rand::Rng;
+
+const MIN_BUCKETS: usize = 4;
+
+pub struct Dict<K, V> {
+    buckets: Vec<Vec<(K, V)>>,
+    len: usize,
+    hasher: RandomState,
+}
+
+impl<K: Hash + Eq, V> Dict<K, V> {
+    pub fn new() -> Self {
+        Dict {
+            buckets: Vec::new(),
+            len: 0,
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        self.buckets[self.bucket(key)]
+            .iter()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let i = self.bucket(key);
+        self.buckets[i]
+            .iter_mut()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Insert `value` at `key`, returns the value it replaced
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if !self.buckets.is_empty() {
+            let i = self.bucket(&key);
+            if let Some(pos) = self.buckets[i].iter().position(|(k, _)| *k == key) {
+                return Some(std::mem::replace(&mut self.buckets[i][pos].1, value));
+            }
+        }
+        if self.len >= self.buckets.len() {
+            self.resize((self.len + 1).next_power_of_two().max(MIN_BUCKETS));
+        }
+        let i = self.bucket(&key);
+        self.buckets[i].push((key, value));
+        self.len += 1;
+        None
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let i = self.bucket(key);
+        let pos = self.buckets[i].iter().position(|(k, _)| k.borrow() == key)?;
+        let (_, value) = self.buckets[i].swap_remove(pos);
+        self.len -= 1;
+        // shrink once less than an eighth of the buckets would be used
+        if self.buckets.len() > MIN_BUCKETS && self.len * 8 < self.buckets.len() {
+            self.resize(self.len.next_power_of_two().max(MIN_BUCKETS));
+        }
+        Some(value)
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets = Vec::new();
+        self.len = 0;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.buckets.iter().flatten().map(|(k, v)| (k, v))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> {
+        self.buckets.iter_mut().flatten().map(|(k, v)| (&*k, v))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
+    }
+
+    /// A random element, `None` if the table is empty.
+    ///
+    /// Random buckets are tried until a non empty one shows up, since at
+    /// least an eighth of them are used this takes a few tries at most.
+    pub fn random(&self) -> Option<(&K, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut rng = rand::rng();
+        loop {
+            let bucket = &self.buckets[rng.random_range(0..self.buckets.len())];
+            if !bucket.is_empty() {
+                let (k, v) = &bucket[rng.random_range(0..bucket.len())];
+                return Some((k, v));
+            }
+        }
+    }
+
+    /// Call `f` on the elements of the buckets from `cursor` on, until about
+    /// `count` elements have been visited. Returns the cursor to continue
+    /// from, `0` once the whole table has been scanned.
+    ///
+    /// Runs of empty buckets are bounded too, so a sparse table does not
+    /// make a single call walk all of it.
+    pub fn scan(&self, mut cursor: u64, count: usize, mut f: impl FnMut(&K, &V)) -> u64 {
+        if self.buckets.is_empty() {
+            return 0;
+        }
+        let mask = (self.buckets.len() - 1) as u64;
+        let mut visited = 0;
+        let mut empty_visits = count.saturating_mul(10);
+        loop {
+            let bucket = &self.buckets[(cursor & mask) as usize];
+            for (k, v) in bucket {
+                f(k, v);
+            }
+            visited += bucket.len();
+            if bucket.is_empty() {
+                empty_visits = empty_visits.saturating_sub(1);
+            }
+
+            // increment the bits under the mask starting from the highest one
+            cursor |= !mask;
+            cursor = cursor.reverse_bits().wrapping_add(1).reverse_bits();
+
+            if cursor == 0 || visited >= count || empty_visits == 0 {
+                return cursor;
+            }
+        }
+    }
+
+    fn bucket<Q: Hash + ?Sized>(&self, key: &Q) -> usize {
+        (self.hasher.hash_one(key) as usize) & (self.buckets.len() - 1)
+    }
+
+    /// Rehash everything into `n` buckets, `n` is a power of two
+    fn resize(&mut self, n: usize) {
+        let old = std::mem::replace(&mut self.buckets, (0..n).map(|_| Vec::new()).collect());
+        for (k, v) in old.into_iter().flatten() {
+            let i = self.bucket(&k);
+            self.buckets[i].push((k, v));
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> Default for Dict<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Clone for Dict<K, V> {
+    fn clone(&self) -> Self {
+        Dict {
+            buckets: self.buckets.clone(),
+            len: self.len,
+            hasher: self.hasher.clone(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + std::fmt::Debug, V: std::fmt::Debug> std::fmt::Debug for Dict<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Hash + Eq, V: PartialEq> PartialEq for Dict<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[test]
+fn test_insert_remove() {
+    let mut dict = Dict::new();
+    for i in 0..1000 {
+        assert_eq!(dict.insert(i, i * 2), None);
+    }
+    assert_eq!(dict.insert(7, 0), Some(14));
+    assert_eq!(dict.len(), 1000);
+    for i in 0..990 {
+        assert_eq!(dict.remove(&i), Some(if i == 7 { 0 } else { i * 2 }));
+    }
+    assert_eq!(dict.remove(&0), None);
+    assert_eq!(dict.len(), 10);
+    assert!(dict.buckets.len() <= 16);
+    let mut keys: Vec<_> = dict.keys().copied().collect();
+    keys.sort();
+    assert_eq!(keys, (990..1000).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_scan_survives_resizes() {
+    use std::collections::HashSet;
+
+    let mut dict = Dict::new();
+    for i in 0..100 {
+        dict.insert(i, ());
+    }
+    let mut seen = HashSet::new();
+    let mut cursor = 0;
+    let mut round = 0;
+    loop {
+        cursor = dict.scan(cursor, 5, |k, _| {
+            seen.insert(*k);
+        });
+        if cursor == 0 {
+            break;
+        }
+        // grow to eight times the size, then shrink back, while scanning
+        round += 1;
+        if round % 4 == 1 {
+            for i in 1000..1800 {
+                dict.insert(i, ());
+            }
+        } else if round % 4 == 3 {
+            for i in 1000..1800 {
+                dict.remove(&i);
+            }
+        }
+    }
+    assert!((0..100).all(|i| seen.contains(&i)));
+}
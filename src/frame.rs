@@ -11,24 +11,35 @@ use std::{
 use bytes::{Buf, BufMut, Bytes};
 
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Frame {
     Simple(String),
     Error(String),
-    Integer(u64),
+    Integer(i64),
     Bulk(Bytes),
     Null,
+    /// the nil reply of a command otherwise replying an array, `*-1`
+    NullArray,
     Array(Vec<Frame>),
+    /// RESP3 out of band data, e.g. a pub/sub message, shaped like an array
+    Push(Vec<Frame>),
+    /// RESP3 map, its keys and values in turn, e.g. the reply of `HELLO 3`
+    Map(Vec<Frame>),
 }
 
 const FLAG_SIMPLE: u8 = b'+';
 const FLAG_ERROR: u8 = b'-';
 const FLAG_INTEGER: u8 = b':';
-const FLAG_BULK: u8 = b'$';
-const FLAG_ARRAY: u8 = b'*';
+pub(crate) const FLAG_BULK: u8 = b'$';
+pub(crate) const FLAG_ARRAY: u8 = b'*';
+const FLAG_PUSH: u8 = b'>';
+const FLAG_MAP: u8 = b'%';
 const FLAG_NULL: u8 = b'-';
-const CRLF: &[u8] = b"\r\n";
+pub(crate) const CRLF: &[u8] = b"\r\n";
 const NULL_BULK: &[u8] = b"-1\r\n";
+/// arrays nested deeper than this are rejected instead of recursing until
+/// the stack overflows
+const MAX_NESTING: usize = 64;
 
 #[derive(Debug)]
 pub enum Error {
@@ -41,7 +52,7 @@ impl fmt::Display for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::Incomplete => "stream ended early".fmt(fmt),
-            Error::Other(_) => todo!(),
+            Error::Other(msg) => msg.fmt(fmt),
         }
     }
 }
@@ -61,7 +72,8 @@ fn peek_u8(buf: &mut Cursor<&[u8]>) -> Result<u8, Error> {
 }
 fn get_line<'a>(buf: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
     let start = buf.position() as usize;
-    let end = buf.get_ref().len() - 1;
+    // `saturating_sub` so an empty buffer does not underflow
+    let end = buf.get_ref().len().saturating_sub(1);
     for i in start..end {
         if buf.get_ref()[i] == b'\r' && buf.get_ref()[i + 1] == b'\n' {
             buf.set_position((i + 2) as u64);
@@ -92,6 +104,10 @@ fn skip(buf: &mut Cursor<&[u8]>, n: usize) -> Result<(), Error> {
 impl Frame {
     // check if `buf` has complete frame
     pub fn check(buf: &mut Cursor<&[u8]>) -> Result<(), Error> {
+        Frame::check_nested(buf, 0)
+    }
+
+    fn check_nested(buf: &mut Cursor<&[u8]>, depth: usize) -> Result<(), Error> {
         let flag = get_u8(buf)?;
         match flag {
             FLAG_SIMPLE => {
@@ -111,7 +127,10 @@ impl Frame {
                     skip(buf, 4)
                 } else {
                     let len: usize = get_decimal(buf)?.try_into()?;
-                    skip(buf, len + 2)
+                    let len = len
+                        .checked_add(2)
+                        .ok_or("protocol error; bulk length out of range")?;
+                    skip(buf, len)
                 }
             }
             FLAG_ARRAY => {
@@ -119,19 +138,39 @@ impl Frame {
                     skip(buf, 4)
                 } else {
                     let len = get_decimal(buf)?;
+                    if depth >= MAX_NESTING {
+                        return Err("protocol error; array nested too deep".into());
+                    }
 
                     for _ in 0..len {
-                        Frame::check(buf)?;
+                        Frame::check_nested(buf, depth + 1)?;
                     }
                     Ok(())
                 }
             }
+            FLAG_PUSH | FLAG_MAP => {
+                let len = get_decimal(buf)?;
+                // a map has a key and a value per entry
+                let per_entry = if flag == FLAG_MAP { 2 } else { 1 };
+                let len = len.checked_mul(per_entry).ok_or("protocol error; map length out of range")?;
+                if depth >= MAX_NESTING {
+                    return Err("protocol error; array nested too deep".into());
+                }
+                for _ in 0..len {
+                    Frame::check_nested(buf, depth + 1)?;
+                }
+                Ok(())
+            }
             unknown => Err(format!("protocol error; invalid frame type byte `{}`", unknown).into()),
         }
     }
 
     // parse the frame from `buf`
     pub fn decode(buf: &mut Cursor<&[u8]>) -> Result<Frame, Error> {
+        Frame::decode_nested(buf, 0)
+    }
+
+    fn decode_nested(buf: &mut Cursor<&[u8]>, depth: usize) -> Result<Frame, Error> {
         match get_u8(buf)? {
             FLAG_SIMPLE => {
                 let s = get_line(buf)?;
@@ -142,7 +181,9 @@ impl Frame {
                 Ok(Frame::Error(std::str::from_utf8(s)?.to_string()))
             }
             FLAG_INTEGER => {
-                let n = get_decimal(buf)?;
+                let line = get_line(buf)?;
+                let n = atoi::atoi::<i64>(line)
+                    .ok_or("protocol error; invalid frame format")?;
 
                 Ok(Frame::Integer(n))
             }
@@ -157,8 +198,10 @@ impl Frame {
                         .into()),
                     }
                 } else {
-                    let len = get_decimal(buf)? as usize;
-                    let end = len + 2;
+                    let len: usize = get_decimal(buf)?.try_into()?;
+                    let end = len
+                        .checked_add(2)
+                        .ok_or("protocol error; bulk length out of range")?;
                     if buf.remaining() < end {
                         return Err(Error::Incomplete);
                     }
@@ -171,7 +214,7 @@ impl Frame {
             FLAG_ARRAY => {
                 if FLAG_NULL == peek_u8(buf)? {
                     match get_line(buf)? {
-                        b"-1" => Ok(Frame::Null),
+                        b"-1" => Ok(Frame::NullArray),
                         unknown => Err(format!(
                             "protocol error: invalid array frame format {:?}",
                             unknown
@@ -179,18 +222,39 @@ impl Frame {
                         .into()),
                     }
                 } else {
-                    let len = get_decimal(buf)?.try_into()?;
-                    let mut data = Vec::with_capacity(len);
+                    let len: usize = get_decimal(buf)?.try_into()?;
+                    if depth >= MAX_NESTING {
+                        return Err("protocol error; array nested too deep".into());
+                    }
+                    // the length is untrusted, every element takes at least 3 bytes
+                    let mut data = Vec::with_capacity(len.min(buf.remaining() / 3));
                     for _ in 0..len {
-                        data.push(Frame::decode(buf)?);
+                        data.push(Frame::decode_nested(buf, depth + 1)?);
                     }
                     Ok(Frame::Array(data))
                 }
             }
+            FLAG_PUSH => Ok(Frame::Push(Frame::decode_elements(buf, depth, 1)?)),
+            FLAG_MAP => Ok(Frame::Map(Frame::decode_elements(buf, depth, 2)?)),
             unknown => Err(format!("protocol error; invalid frame type byte `{}`", unknown).into()),
         }
     }
 
+    /// The elements of a push or a map, `per_entry` frames for every one
+    /// its length counts
+    fn decode_elements(buf: &mut Cursor<&[u8]>, depth: usize, per_entry: usize) -> Result<Vec<Frame>, Error> {
+        let len: usize = get_decimal(buf)?.try_into()?;
+        let len = len.checked_mul(per_entry).ok_or("protocol error; map length out of range")?;
+        if depth >= MAX_NESTING {
+            return Err("protocol error; array nested too deep".into());
+        }
+        let mut data = Vec::with_capacity(len.min(buf.remaining() / 3));
+        for _ in 0..len {
+            data.push(Frame::decode_nested(buf, depth + 1)?);
+        }
+        Ok(data)
+    }
+
     pub fn encode<T: BufMut>(&self, buf: &mut T) -> usize {
         match self {
             Frame::Simple(s) => {
@@ -226,13 +290,13 @@ impl Frame {
                 buf.put(NULL_BULK);
                 1 + NULL_BULK.len()
             }
-            Frame::Array(arr) => {
-                let mut written = 0;
+            Frame::NullArray => {
                 buf.put_u8(FLAG_ARRAY);
-                let n = arr.len().to_string();
-                buf.put(n.as_bytes());
-                buf.put(CRLF);
-                written += 1 + n.len() + CRLF.len();
+                buf.put(NULL_BULK);
+                1 + NULL_BULK.len()
+            }
+            Frame::Array(arr) | Frame::Push(arr) | Frame::Map(arr) => {
+                let mut written = self.encode_header(buf);
                 for frame in arr {
                     written += frame.encode(buf);
                 }
@@ -240,6 +304,33 @@ impl Frame {
             }
         }
     }
+    /// Encode only the header of a bulk, array, push or map frame, i.e. the
+    /// flag and the length line. The caller is responsible for the payload
+    /// and the trailing CRLF of a bulk, or for the elements of an array.
+    ///
+    /// # Panics
+    ///
+    /// panics if `self` is neither a bulk, an array, a push nor a map
+    pub(crate) fn encode_header<T: BufMut>(&self, buf: &mut T) -> usize {
+        let (flag, len) = match self {
+            Frame::Bulk(bs) => (FLAG_BULK, bs.len()),
+            Frame::Array(arr) => (FLAG_ARRAY, arr.len()),
+            Frame::Push(arr) => (FLAG_PUSH, arr.len()),
+            Frame::Map(arr) => (FLAG_MAP, arr.len() / 2),
+            _ => panic!("frame has no header"),
+        };
+        buf.put_u8(flag);
+        let n = len.to_string();
+        buf.put(n.as_bytes());
+        buf.put(CRLF);
+        1 + n.len() + CRLF.len()
+    }
+
+    /// A nil reply, of either kind
+    pub fn is_nil(&self) -> bool {
+        matches!(self, Frame::Null | Frame::NullArray)
+    }
+
     /// Returns an empty array
     pub(crate) fn new_array_frame() -> Frame {
         Frame::Array(vec![])
@@ -264,7 +355,8 @@ impl Frame {
     /// # Panics
     ///
     /// panics if `self` is not an array
-    pub(crate) fn push_int(&mut self, value: u64) {
+    #[allow(dead_code)]
+    pub(crate) fn push_int(&mut self, value: i64) {
         match self {
             Frame::Array(vec) => {
                 vec.push(Frame::Integer(value));
@@ -293,13 +385,21 @@ impl Frame {
 pub(crate) trait Parse {
     fn next_string(&mut self) -> Result<String, Error>;
     fn next_bytes(&mut self) -> Result<Bytes, Error>;
-    fn next_int(&mut self) -> Result<u64, Error>;
+    /// an integer frame, or a string holding a signed 64 bit integer
+    fn next_int(&mut self) -> Result<i64, Error>;
+    /// a string holding a finite floating point number
+    fn next_float(&mut self) -> Result<f64, Error>;
+    /// `true` if there are arguments left, for commands with optional ones
+    fn has_next(&self) -> bool;
+    /// make sure every argument has been consumed
+    fn finish(&mut self) -> Result<(), Error>;
 }
 
+const WRONG_ARGS: &str = "wrong number of arguments";
 
 impl Parse for vec::IntoIter<Frame> {
     fn next_string(self: &mut std::vec::IntoIter<Frame>) -> Result<String, Error>  {
-        let frame = self.next().ok_or(Error::Other("not a string".to_string()))?;
+        let frame = self.next().ok_or(WRONG_ARGS)?;
         match frame {
             Frame::Bulk(bs) => {
                 Ok(String::from_utf8(bs.as_ref().to_vec())?)
@@ -312,24 +412,64 @@ impl Parse for vec::IntoIter<Frame> {
     }
 
     fn next_bytes(&mut self) -> Result<Bytes, Error> {
-        let frame = self.next().ok_or(Error::Other("not a bytes".to_string()))?;
+        let frame = self.next().ok_or(WRONG_ARGS)?;
         match frame {
             Frame::Bulk(bs) => {
                 Ok(bs)
             },
+            Frame::Simple(s) => {
+                Ok(Bytes::from(s))
+            },
             _ => Err(Error::Other("not a frame with bytes".to_string()))
         }
     }
 
-    fn next_int(&mut self) -> Result<u64, Error> {
-        let frame = self.next().ok_or(Error::Other("not a bytes".to_string()))?;
+    fn next_int(&mut self) -> Result<i64, Error> {
+        const NOT_INT: &str = "value is not an integer or out of range";
+
+        let frame = self.next().ok_or(WRONG_ARGS)?;
         match frame {
             Frame::Integer(n) => {
                 Ok(n)
             }
-            _ => Err(Error::Other("not a integer frame".to_string()))
+            Frame::Bulk(bs) => {
+                std::str::from_utf8(&bs).ok().and_then(|s| s.parse().ok()).ok_or_else(|| NOT_INT.into())
+            }
+            Frame::Simple(s) => {
+                s.parse().map_err(|_| NOT_INT.into())
+            }
+            _ => Err(NOT_INT.into())
         }
     }
+
+    fn next_float(&mut self) -> Result<f64, Error> {
+        const NOT_FLOAT: &str = "value is not a valid float";
+
+        let frame = self.next().ok_or(WRONG_ARGS)?;
+        let n: f64 = match frame {
+            Frame::Integer(n) => n as f64,
+            Frame::Bulk(bs) => {
+                std::str::from_utf8(&bs).ok().and_then(|s| s.parse().ok()).ok_or(NOT_FLOAT)?
+            }
+            Frame::Simple(s) => s.parse().map_err(|_| NOT_FLOAT)?,
+            _ => return Err(NOT_FLOAT.into()),
+        };
+        if n.is_nan() {
+            return Err(NOT_FLOAT.into());
+        }
+        Ok(n)
+    }
+
+    fn has_next(&self) -> bool {
+        self.len() > 0
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        if self.has_next() {
+            return Err(WRONG_ARGS.into());
+        }
+        Ok(())
+    }
 }
 
 
@@ -374,6 +514,9 @@ impl PartialEq<&str> for Frame {
 //////////////////////////////
 /// Unit Test
 ////////////////////////////// 
+#[cfg(test)]
+use bytes::BytesMut;
+
 #[test]
 #[should_panic(expected = "protocol error")]
 fn test_check() {
@@ -413,3 +556,94 @@ fn test_encode() {
         buf
     );
 }
+
+#[test]
+fn test_get_line_empty() {
+    let mut buf = Cursor::new(&b""[..]);
+    assert!(matches!(get_line(&mut buf), Err(Error::Incomplete)));
+}
+
+#[test]
+fn test_malformed_lengths() {
+    // bulk length that overflows `len + 2`
+    let input = format!("${}\r\n", usize::MAX);
+    let mut buf = Cursor::new(input.as_bytes());
+    assert!(Frame::check(&mut buf).is_err());
+    buf.set_position(0);
+    assert!(Frame::decode(&mut buf).is_err());
+
+    // array length far beyond what the buffer holds
+    let mut buf = Cursor::new(&b"*9223372036854775807\r\n:1\r\n"[..]);
+    assert!(matches!(Frame::decode(&mut buf), Err(Error::Incomplete)));
+
+    // nesting deep enough to overflow the stack
+    let input = b"*1\r\n".repeat(100_000);
+    let mut buf = Cursor::new(&input[..]);
+    assert!(Frame::check(&mut buf).is_err());
+    buf.set_position(0);
+    assert!(Frame::decode(&mut buf).is_err());
+}
+
+#[cfg(test)]
+impl proptest::arbitrary::Arbitrary for Frame {
+    type Parameters = ();
+    type Strategy = proptest::strategy::BoxedStrategy<Frame>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        use proptest::prelude::*;
+
+        // simple strings and errors are line based, so they never contain CR or LF
+        let leaf = prop_oneof![
+            "[^\r\n]*".prop_map(Frame::Simple),
+            "[^\r\n]*".prop_map(Frame::Error),
+            any::<i64>().prop_map(Frame::Integer),
+            any::<Vec<u8>>().prop_map(|v| Frame::Bulk(v.into())),
+            Just(Frame::Null),
+            Just(Frame::NullArray),
+        ];
+        leaf.prop_recursive(4, 64, 8, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..8).prop_map(Frame::Array),
+                prop::collection::vec(inner.clone(), 0..8).prop_map(Frame::Push),
+                prop::collection::vec((inner.clone(), inner), 0..4)
+                    .prop_map(|entries| Frame::Map(entries.into_iter().flat_map(|(k, v)| [k, v]).collect())),
+            ]
+        })
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_round_trip(frame: Frame) {
+        let mut buf = BytesMut::new();
+        let written = frame.encode(&mut buf);
+        proptest::prop_assert_eq!(written, buf.len());
+
+        let mut cursor = Cursor::new(buf.as_ref());
+        Frame::check(&mut cursor).unwrap();
+        proptest::prop_assert_eq!(cursor.position() as usize, buf.len());
+
+        cursor.set_position(0);
+        proptest::prop_assert_eq!(Frame::decode(&mut cursor).unwrap(), frame);
+    }
+
+    #[test]
+    fn test_partial_is_incomplete(frame: Frame, cut: proptest::sample::Index) {
+        let mut buf = BytesMut::new();
+        frame.encode(&mut buf);
+        let cut = cut.index(buf.len());
+
+        let mut cursor = Cursor::new(&buf[..cut]);
+        proptest::prop_assert!(matches!(Frame::check(&mut cursor), Err(Error::Incomplete)));
+    }
+
+    #[test]
+    fn test_random_bytes_never_panic(input: Vec<u8>) {
+        let mut cursor = Cursor::new(&input[..]);
+        let _ = Frame::check(&mut cursor);
+        cursor.set_position(0);
+        let _ = Frame::decode(&mut cursor);
+    }
+}
@@ -0,0 +1,206 @@
+//! Function libraries, `FUNCTION` and `FCALL`
+//!
+//! A library is Lua code starting with a `#!lua name=<library>` line, that
+//! calls `redis.register_function` for each function it provides. Unlike
+//! scripts, libraries belong to the dataset: the database keeps their code,
+//! which `FUNCTION DUMP` serializes and `FUNCTION RESTORE` loads back, and
+//! the interpreter of `script` compiles them again whenever they changed.
+//! There are no snapshots or replicas, so `DUMP` and `RESTORE` are also the
+//! only way to keep libraries across restarts or copy them to another
+//! server.
+//!
+//! A function registered with the `no-writes` flag may only read, it is
+//! the only kind `FCALL_RO` runs.
+
+use std::collections::BTreeMap;
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::script::sha1_hex;
+
+/// the flags `redis.register_function` accepts
+pub const FLAGS: &[&str] = &["no-writes", "allow-oom", "allow-stale", "no-cluster", "allow-cross-slot-keys"];
+
+/// first bytes of a `FUNCTION DUMP` payload, then a format version
+const DUMP_MAGIC: &[u8] = b"MRFN";
+const DUMP_VERSION: u8 = 1;
+/// bytes of the SHA1 of the rest of a payload, at its end
+const DUMP_CHECKSUM_LEN: usize = 8;
+
+/// A function of a library
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibraryFunction {
+    pub name: String,
+    pub flags: Vec<String>,
+}
+
+impl LibraryFunction {
+    /// Whether it was registered as read only, with `no-writes`
+    pub fn no_writes(&self) -> bool {
+        self.flags.iter().any(|flag| flag == "no-writes")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Library {
+    pub name: String,
+    pub code: Bytes,
+    pub functions: Vec<LibraryFunction>,
+}
+
+/// The libraries of the database
+#[derive(Debug, Clone, Default)]
+pub struct Libraries {
+    libraries: BTreeMap<String, Library>,
+    // bumped by every change, for the interpreter to know when to compile
+    // them again
+    version: u64,
+}
+
+impl Libraries {
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// The libraries, by name
+    pub fn iter(&self) -> impl Iterator<Item = &Library> {
+        self.libraries.values()
+    }
+
+    /// The function `name` of any library
+    pub fn function(&self, name: &str) -> Option<&LibraryFunction> {
+        self.iter().flat_map(|library| library.functions.iter()).find(|function| function.name == name)
+    }
+
+    /// Add `library`, or with `replace` put it in place of the one of the
+    /// same name. Its functions may not be those of another library.
+    pub fn add(&mut self, library: Library, replace: bool) -> Result<(), String> {
+        if !replace && self.libraries.contains_key(&library.name) {
+            return Err(format!("Library '{}' already exists", library.name));
+        }
+        let others = self.iter().filter(|other| other.name != library.name);
+        let taken = others.flat_map(|other| other.functions.iter()).find(|function| {
+            library.functions.iter().any(|new| new.name == function.name)
+        });
+        if let Some(function) = taken {
+            return Err(format!("Function {} already exists", function.name));
+        }
+        self.libraries.insert(library.name.clone(), library);
+        self.version += 1;
+        Ok(())
+    }
+
+    /// Returns whether there was such a library
+    pub fn delete(&mut self, name: &str) -> bool {
+        let deleted = self.libraries.remove(name).is_some();
+        self.version += deleted as u64;
+        deleted
+    }
+
+    pub fn flush(&mut self) {
+        self.libraries.clear();
+        self.version += 1;
+    }
+
+    /// The code of every library, in the format of `restore`
+    pub fn dump(&self) -> Bytes {
+        let mut payload = BytesMut::from(DUMP_MAGIC);
+        payload.put_u8(DUMP_VERSION);
+        for library in self.iter() {
+            payload.put_u32(library.code.len() as u32);
+            payload.put_slice(&library.code);
+        }
+        let checksum = sha1_hex(&payload);
+        payload.put_slice(&checksum.as_bytes()[..DUMP_CHECKSUM_LEN]);
+        payload.freeze()
+    }
+}
+
+/// The code of the libraries in a `dump` payload, `None` if it is corrupt
+pub fn restore(payload: &[u8]) -> Option<Vec<Bytes>> {
+    let (content, checksum) = payload.split_at_checked(payload.len().checked_sub(DUMP_CHECKSUM_LEN)?)?;
+    if checksum != &sha1_hex(content).as_bytes()[..DUMP_CHECKSUM_LEN] {
+        return None;
+    }
+    let mut rest = content.strip_prefix(DUMP_MAGIC)?.strip_prefix(&[DUMP_VERSION])?;
+    let mut codes = vec![];
+    while !rest.is_empty() {
+        let (len, tail) = rest.split_first_chunk::<4>()?;
+        let (code, tail) = tail.split_at_checked(u32::from_be_bytes(*len) as usize)?;
+        codes.push(Bytes::copy_from_slice(code));
+        rest = tail;
+    }
+    Some(codes)
+}
+
+/// The library name of the `#!lua name=<library>` first line of `code`
+pub fn library_name(code: &[u8]) -> Result<String, String> {
+    let first_line = code.split(|b| *b == b'\n').next().unwrap_or_default();
+    let shebang = String::from_utf8_lossy(first_line);
+    let Some(shebang) = shebang.strip_prefix("#!") else {
+        return Err("Missing library metadata".to_string());
+    };
+    let mut parts = shebang.split_whitespace();
+    let engine = parts.next().unwrap_or_default();
+    if !engine.eq_ignore_ascii_case("lua") {
+        return Err(format!("Engine '{engine}' not found"));
+    }
+    let mut name = None;
+    for part in parts {
+        match part.strip_prefix("name=") {
+            Some(value) => name = Some(value.to_string()),
+            None => return Err(format!("Invalid metadata value given: {part}")),
+        }
+    }
+    let name = name.ok_or("Library name was not given")?;
+    if !valid_name(&name) {
+        return Err(
+            "Library names can only contain letters, numbers, or underscores(_) and must be at least one character \
+             long"
+                .to_string(),
+        );
+    }
+    Ok(name)
+}
+
+/// Whether `name` may name a library or a function
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[test]
+fn test_libraries() {
+    fn library(name: &str, functions: &[&str]) -> Library {
+        let functions = functions.iter().map(|name| LibraryFunction { name: name.to_string(), flags: vec![] });
+        Library {
+            name: name.to_string(),
+            code: Bytes::from(format!("#!lua name={name}\n")),
+            functions: functions.collect(),
+        }
+    }
+
+    let mut libraries = Libraries::default();
+    libraries.add(library("a", &["f", "g"]), false).unwrap();
+    assert_eq!(libraries.add(library("a", &["h"]), false), Err("Library 'a' already exists".to_string()));
+    assert_eq!(libraries.add(library("b", &["g"]), false), Err("Function g already exists".to_string()));
+    // a library may keep its own functions when replaced
+    libraries.add(library("a", &["g"]), true).unwrap();
+    libraries.add(library("b", &["f"]), false).unwrap();
+    assert_eq!(libraries.version(), 3);
+
+    let codes = restore(&libraries.dump()).unwrap();
+    assert_eq!(codes, vec![Bytes::from("#!lua name=a\n"), Bytes::from("#!lua name=b\n")]);
+    let mut corrupt = libraries.dump().to_vec();
+    corrupt[6] ^= 1;
+    assert_eq!(restore(&corrupt), None);
+    assert_eq!(restore(b"MRFN"), None);
+
+    assert_eq!(library_name(b"#!lua name=mylib\nreturn"), Ok("mylib".to_string()));
+    assert_eq!(library_name(b"return 1"), Err("Missing library metadata".to_string()));
+    assert_eq!(library_name(b"#!js name=x"), Err("Engine 'js' not found".to_string()));
+    assert_eq!(library_name(b"#!lua"), Err("Library name was not given".to_string()));
+    assert!(library_name(b"#!lua name=my-lib").is_err());
+}
@@ -1,8 +1,20 @@
+pub mod blocking;
+pub mod client;
 pub mod cmd;
+pub mod codec;
 pub mod connection;
+pub mod convert;
 pub mod database;
+pub mod dict;
 pub mod frame;
-pub mod  server;
+pub mod function;
+pub mod notify;
+pub mod pubsub;
+pub mod script;
+pub mod server;
+pub mod slot;
+pub mod transaction;
+pub mod types;
 
 
 #[inline]
@@ -58,7 +70,7 @@ impl Solution {
                 num += 1;
                 use std::borrow::Borrow; //blanket implementation
 
-                let lmax = Self::next_node((&**rc_node ).borrow().left.as_ref(), num);
+                let lmax = Self::next_node((**rc_node).borrow().left.as_ref(), num);
 
                 let rmax = Self::next_node(<Rc<RefCell<TreeNode>> as Borrow<RefCell<TreeNode>>>::borrow(rc_node).borrow().right.as_ref(), num);
                 lmax.max(rmax)
@@ -104,14 +116,12 @@ pub fn generate_binary_tree(max: i32) -> Option<Rc<RefCell<TreeNode>>> {
     root
 }
 
+#[allow(dead_code)]
 fn traverse(root: Option<Rc<RefCell<TreeNode>>>) {
-    match root {
-        Some(node) => {
-            // println!("{node:?}");
-            traverse(node.borrow().left.clone());
-            traverse(node.borrow().right.clone());
-        }
-        None => (),
+    if let Some(node) = root {
+        // println!("{node:?}");
+        traverse(node.borrow().left.clone());
+        traverse(node.borrow().right.clone());
     }
 }
 
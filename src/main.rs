@@ -18,7 +18,9 @@ impl TreeNode {
 }
 use std::rc::Rc;
 use std::cell::RefCell;
+#[allow(dead_code)]
 struct Solution;
+#[allow(dead_code)]
 impl Solution {
     pub fn max_depth(root: Option<Rc<RefCell<TreeNode>>>) -> i32 {
         Self::next_node(root.as_ref(), 0)
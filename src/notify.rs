@@ -0,0 +1,143 @@
+//! Keyspace notifications
+//!
+//! Commands modifying a key, and the database when a key expires, publish
+//! an event. With `K` in `notify-keyspace-events` it is published on
+//! `__keyspace@0__:<key>` with the event as the message, with `E` on
+//! `__keyevent@0__:<event>` with the key as the message. The other flags
+//! select the classes of events published, e.g. `KEA` for all of them on
+//! both channels or `Ex` for the expired keys only.
+
+use std::{fmt, ops::BitOr};
+
+/// A set of the flags of `notify-keyspace-events`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Events(u16);
+
+impl Events {
+    pub const NONE: Events = Events(0);
+    /// `K`, publish on `__keyspace@0__:<key>`
+    pub const KEYSPACE: Events = Events(1 << 0);
+    /// `E`, publish on `__keyevent@0__:<event>`
+    pub const KEYEVENT: Events = Events(1 << 1);
+    /// `g`, commands not specific to a type, e.g. `DEL` or `RENAME`
+    pub const GENERIC: Events = Events(1 << 2);
+    /// `$`
+    pub const STRING: Events = Events(1 << 3);
+    /// `l`
+    pub const LIST: Events = Events(1 << 4);
+    /// `s`
+    pub const SET: Events = Events(1 << 5);
+    /// `h`
+    pub const HASH: Events = Events(1 << 6);
+    /// `z`
+    pub const ZSET: Events = Events(1 << 7);
+    /// `x`, a key expired
+    pub const EXPIRED: Events = Events(1 << 8);
+    /// `e`, a key was evicted, never published here as there is no `maxmemory`
+    pub const EVICTED: Events = Events(1 << 9);
+    /// `t`
+    pub const STREAM: Events = Events(1 << 10);
+    /// `m`, a key was read but missing, never published here
+    pub const KEY_MISS: Events = Events(1 << 11);
+    /// `d`, module events, never published here
+    pub const MODULE: Events = Events(1 << 12);
+    /// `n`, a key was created
+    pub const NEW: Events = Events(1 << 13);
+    /// `A`, every class but `m` and `n`
+    pub const ALL: Events = Events(
+        Self::GENERIC.0
+            | Self::STRING.0
+            | Self::LIST.0
+            | Self::SET.0
+            | Self::HASH.0
+            | Self::ZSET.0
+            | Self::EXPIRED.0
+            | Self::EVICTED.0
+            | Self::STREAM.0
+            | Self::MODULE.0,
+    );
+
+    /// The flag of each class, in the order redis lists them
+    const CLASSES: [(char, Events); 10] = [
+        ('g', Self::GENERIC),
+        ('$', Self::STRING),
+        ('l', Self::LIST),
+        ('s', Self::SET),
+        ('h', Self::HASH),
+        ('z', Self::ZSET),
+        ('x', Self::EXPIRED),
+        ('e', Self::EVICTED),
+        ('t', Self::STREAM),
+        ('d', Self::MODULE),
+    ];
+
+    /// Parse flags like `KEA`, `None` on an unknown character
+    pub fn parse(flags: &str) -> Option<Events> {
+        flags.chars().try_fold(Events::NONE, |events, c| {
+            let flag = match c {
+                'A' => Self::ALL,
+                'K' => Self::KEYSPACE,
+                'E' => Self::KEYEVENT,
+                'm' => Self::KEY_MISS,
+                'n' => Self::NEW,
+                _ => Self::CLASSES.iter().find(|(flag, _)| *flag == c)?.1,
+            };
+            Some(events | flag)
+        })
+    }
+
+    pub fn contains(self, other: Events) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether events of `class` are published on at least one channel
+    pub fn publishes(self, class: Events) -> bool {
+        self.0 & class.0 != 0 && self.0 & (Self::KEYSPACE.0 | Self::KEYEVENT.0) != 0
+    }
+}
+
+impl BitOr for Events {
+    type Output = Events;
+
+    fn bitor(self, rhs: Events) -> Events {
+        Events(self.0 | rhs.0)
+    }
+}
+
+/// The flags the way `CONFIG GET` shows them, `A` for all the classes
+impl fmt::Display for Events {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.contains(Self::ALL) {
+            write!(f, "A")?;
+        } else {
+            for (flag, class) in Self::CLASSES {
+                if self.contains(class) {
+                    write!(f, "{flag}")?;
+                }
+            }
+        }
+        for (flag, events) in [('K', Self::KEYSPACE), ('E', Self::KEYEVENT), ('m', Self::KEY_MISS), ('n', Self::NEW)] {
+            if self.contains(events) {
+                write!(f, "{flag}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[test]
+fn test_events() {
+    let events = Events::parse("KEA").unwrap();
+    assert!(events.publishes(Events::EXPIRED) && events.publishes(Events::GENERIC));
+    assert!(!events.publishes(Events::NEW));
+    assert_eq!(events.to_string(), "AKE");
+    assert_eq!(Events::parse("Eg$x").unwrap().to_string(), "g$xE");
+    assert_eq!(Events::parse("").unwrap(), Events::NONE);
+    assert_eq!(Events::parse("").unwrap().to_string(), "");
+    // a class alone goes nowhere
+    assert!(!Events::parse("x").unwrap().publishes(Events::EXPIRED));
+    assert_eq!(Events::parse("KEw"), None);
+}
@@ -0,0 +1,447 @@
+//! Publish/subscribe
+//!
+//! The broker maps channels and patterns to the clients subscribed to
+//! them. Every client has a channel its messages are sent on, and its
+//! handler writes them out while it waits for the next request. A client
+//! that does not keep up is disconnected once the messages it has not been
+//! written yet pass its limit, like the pubsub `client-output-buffer-limit`
+//! of redis, so a slow subscriber can not grow the server without bound.
+//! The broker lives in the database, so a `PUBLISH` is ordered with the
+//! writes around it.
+//!
+//! Shard channels, for `SSUBSCRIBE` and `SPUBLISH`, are kept apart by the
+//! slot they hash to like keys do, see `slot`, so that a partitioned server
+//! would only hold the channels of its own slots.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use bytes::Bytes;
+use tokio::sync::{mpsc, Notify};
+
+use crate::{cmd::glob_match, frame::Frame, slot::key_slot};
+
+/// ids of the subscribers, shared by every database
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A message delivered to a subscriber
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    /// the pattern the channel matched, for a `PSUBSCRIBE`
+    pub pattern: Option<Bytes>,
+    /// published to a shard channel, with `SPUBLISH`
+    pub shard: bool,
+    pub channel: Bytes,
+    pub payload: Bytes,
+}
+
+impl Message {
+    /// How much of a subscriber's limit the message takes
+    fn size(&self) -> usize {
+        self.pattern.as_ref().map_or(0, Bytes::len) + self.channel.len() + self.payload.len()
+    }
+
+    /// `message channel payload`, `smessage channel payload` or
+    /// `pmessage pattern channel payload`
+    pub fn into_frame(self, resp3: bool) -> Frame {
+        let mut frames = match self.pattern {
+            Some(pattern) => vec![Frame::Bulk(Bytes::from("pmessage")), Frame::Bulk(pattern)],
+            None if self.shard => vec![Frame::Bulk(Bytes::from("smessage"))],
+            None => vec![Frame::Bulk(Bytes::from("message"))],
+        };
+        frames.push(Frame::Bulk(self.channel));
+        frames.push(Frame::Bulk(self.payload));
+        push_frame(frames, resp3)
+    }
+
+    /// The message in a frame sent to a subscriber, `None` for anything
+    /// else, e.g. the confirmation of a subscription
+    pub fn from_frame(frame: Frame) -> Option<Message> {
+        let (Frame::Array(frames) | Frame::Push(frames)) = frame else { return None };
+        let mut bulks = frames.into_iter().map(|frame| match frame {
+            Frame::Bulk(bs) => Some(bs),
+            _ => None,
+        });
+        let kind = bulks.next()??;
+        let pattern = if &kind[..] == b"pmessage" { Some(bulks.next()??) } else { None };
+        let (channel, payload) = (bulks.next()??, bulks.next()??);
+        match &kind[..] {
+            b"message" | b"smessage" | b"pmessage" if bulks.next().is_none() => {
+                Some(Message { pattern, shard: &kind[..] == b"smessage", channel, payload })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// What a subscriber is sent outside of replies, an array under RESP2 and a
+/// push under RESP3
+pub fn push_frame(frames: Vec<Frame>, resp3: bool) -> Frame {
+    if resp3 {
+        Frame::Push(frames)
+    } else {
+        Frame::Array(frames)
+    }
+}
+
+/// The messages sent to a subscriber and not taken by its handler yet
+struct Backlog {
+    bytes: AtomicUsize,
+    // `usize::MAX` for no limit
+    limit: AtomicUsize,
+    overflowed: AtomicBool,
+    notify: Notify,
+}
+
+impl Backlog {
+    /// Waits until the backlog went over the limit
+    async fn overflowed(&self) {
+        // `notify_one` keeps a permit when nobody waits yet
+        if !self.overflowed.load(Ordering::Acquire) {
+            self.notify.notified().await;
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Sender {
+    messages: mpsc::UnboundedSender<Message>,
+    backlog: Arc<Backlog>,
+}
+
+impl Sender {
+    /// Queue `message`, returns `false` if the subscriber is gone or over
+    /// its limit, when it gets nothing more
+    fn send(&self, message: Message) -> bool {
+        let backlog = &self.backlog;
+        if backlog.overflowed.load(Ordering::Acquire) {
+            return false;
+        }
+        let size = message.size();
+        let bytes = backlog.bytes.fetch_add(size, Ordering::Relaxed) + size;
+        if bytes > backlog.limit.load(Ordering::Relaxed) {
+            backlog.overflowed.store(true, Ordering::Release);
+            backlog.notify.notify_one();
+            return false;
+        }
+        self.messages.send(message).is_ok()
+    }
+}
+
+type Subscribers = HashMap<Bytes, HashMap<u64, Sender>>;
+
+/// The subscribers of every channel and pattern
+#[derive(Default)]
+pub struct Broker {
+    channels: Subscribers,
+    patterns: Subscribers,
+    // by slot
+    shard_channels: HashMap<u16, Subscribers>,
+}
+
+impl Broker {
+    /// Send `payload` to the subscribers of `channel` and of the patterns
+    /// it matches, returns how many messages were sent
+    pub fn publish(&self, channel: &Bytes, payload: &Bytes) -> usize {
+        let mut sent = 0;
+        for sender in self.channels.get(channel).into_iter().flat_map(HashMap::values) {
+            let message = Message { pattern: None, shard: false, channel: channel.clone(), payload: payload.clone() };
+            sent += sender.send(message) as usize;
+        }
+        for (pattern, subscribers) in self.patterns.iter() {
+            if !glob_match(pattern, channel) {
+                continue;
+            }
+            for sender in subscribers.values() {
+                let message = Message {
+                    pattern: Some(pattern.clone()),
+                    shard: false,
+                    channel: channel.clone(),
+                    payload: payload.clone(),
+                };
+                sent += sender.send(message) as usize;
+            }
+        }
+        sent
+    }
+
+    /// Send `payload` to the subscribers of the shard channel `channel`,
+    /// patterns do not apply. Returns how many messages were sent.
+    pub fn spublish(&self, channel: &Bytes, payload: &Bytes) -> usize {
+        let subscribers = self.shard_channels.get(&key_slot(channel)).and_then(|slot| slot.get(channel));
+        subscribers.into_iter().flat_map(HashMap::values).fold(0, |sent, sender| {
+            let message = Message { pattern: None, shard: true, channel: channel.clone(), payload: payload.clone() };
+            sent + sender.send(message) as usize
+        })
+    }
+
+    /// The channels with at least one subscriber, those matching `pattern`
+    /// if any
+    pub fn channels(&self, pattern: Option<&[u8]>) -> Vec<Bytes> {
+        self.channels
+            .keys()
+            .filter(|channel| pattern.is_none_or(|pattern| glob_match(pattern, channel)))
+            .cloned()
+            .collect()
+    }
+
+    /// How many clients subscribed to `channel`, patterns aside
+    pub fn num_subscribers(&self, channel: &[u8]) -> usize {
+        self.channels.get(channel).map_or(0, HashMap::len)
+    }
+
+    /// How many patterns have at least one subscriber
+    pub fn num_patterns(&self) -> usize {
+        self.patterns.len()
+    }
+
+    /// The shard channels with at least one subscriber, those matching
+    /// `pattern` if any
+    pub fn shard_channels(&self, pattern: Option<&[u8]>) -> Vec<Bytes> {
+        self.shard_channels
+            .values()
+            .flat_map(HashMap::keys)
+            .filter(|channel| pattern.is_none_or(|pattern| glob_match(pattern, channel)))
+            .cloned()
+            .collect()
+    }
+
+    /// How many clients subscribed to the shard channel `channel`
+    pub fn num_shard_subscribers(&self, channel: &[u8]) -> usize {
+        let subscribers = self.shard_channels.get(&key_slot(channel)).and_then(|slot| slot.get(channel));
+        subscribers.map_or(0, HashMap::len)
+    }
+
+    /// Add or remove a subscriber of a shard channel, see `toggle`
+    fn toggle_shard(&mut self, channel: &Bytes, id: u64, sender: Option<&Sender>) -> bool {
+        let slot = key_slot(channel);
+        let subscribers = self.shard_channels.entry(slot).or_default();
+        let toggled = toggle(subscribers, channel, id, sender);
+        if subscribers.is_empty() {
+            self.shard_channels.remove(&slot);
+        }
+        toggled
+    }
+}
+
+/// Add or remove `id` from the subscribers of `name`, returns `false` if
+/// it already was, or was not, there
+fn toggle(map: &mut Subscribers, name: &Bytes, id: u64, sender: Option<&Sender>) -> bool {
+    match sender {
+        Some(sender) => map.entry(name.clone()).or_default().insert(id, sender.clone()).is_none(),
+        None => {
+            let Some(subscribers) = map.get_mut(name) else { return false };
+            let removed = subscribers.remove(&id).is_some();
+            if subscribers.is_empty() {
+                map.remove(name);
+            }
+            removed
+        }
+    }
+}
+
+/// The subscriptions of a client, and where its messages arrive
+///
+/// The client stays registered with the broker until `clear`, which its
+/// handler calls when the connection ends.
+pub struct Subscriptions {
+    id: u64,
+    sender: Sender,
+    receiver: mpsc::UnboundedReceiver<Message>,
+    backlog: Arc<Backlog>,
+    channels: HashSet<Bytes>,
+    patterns: HashSet<Bytes>,
+    shard_channels: HashSet<Bytes>,
+}
+
+impl Subscriptions {
+    pub fn new() -> Self {
+        let (messages, receiver) = mpsc::unbounded_channel();
+        let backlog = Arc::new(Backlog {
+            bytes: AtomicUsize::new(0),
+            limit: AtomicUsize::new(usize::MAX),
+            overflowed: AtomicBool::new(false),
+            notify: Notify::new(),
+        });
+        Subscriptions {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            sender: Sender { messages, backlog: backlog.clone() },
+            receiver,
+            backlog,
+            channels: HashSet::new(),
+            patterns: HashSet::new(),
+            shard_channels: HashSet::new(),
+        }
+    }
+
+    /// Most bytes of messages the client may have waiting before it is cut
+    /// off, `None` for no limit
+    pub fn set_limit(&self, limit: Option<usize>) {
+        self.backlog.limit.store(limit.unwrap_or(usize::MAX), Ordering::Relaxed);
+    }
+
+    /// How many channels and patterns the client is subscribed to, shard
+    /// channels aside
+    pub fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+
+    /// How many shard channels the client is subscribed to
+    pub fn shard_count(&self) -> usize {
+        self.shard_channels.len()
+    }
+
+    /// No subscriptions of any kind
+    pub fn is_empty(&self) -> bool {
+        self.count() == 0 && self.shard_count() == 0
+    }
+
+    pub fn channels(&self) -> Vec<Bytes> {
+        self.channels.iter().cloned().collect()
+    }
+
+    pub fn patterns(&self) -> Vec<Bytes> {
+        self.patterns.iter().cloned().collect()
+    }
+
+    pub fn shard_channels(&self) -> Vec<Bytes> {
+        self.shard_channels.iter().cloned().collect()
+    }
+
+    /// Subscribe to `channel`, returns the count after
+    pub fn subscribe(&mut self, broker: &mut Broker, channel: Bytes) -> usize {
+        toggle(&mut broker.channels, &channel, self.id, Some(&self.sender));
+        self.channels.insert(channel);
+        self.count()
+    }
+
+    /// Unsubscribe from `channel`, returns the count after
+    pub fn unsubscribe(&mut self, broker: &mut Broker, channel: &Bytes) -> usize {
+        toggle(&mut broker.channels, channel, self.id, None);
+        self.channels.remove(channel);
+        self.count()
+    }
+
+    pub fn psubscribe(&mut self, broker: &mut Broker, pattern: Bytes) -> usize {
+        toggle(&mut broker.patterns, &pattern, self.id, Some(&self.sender));
+        self.patterns.insert(pattern);
+        self.count()
+    }
+
+    pub fn punsubscribe(&mut self, broker: &mut Broker, pattern: &Bytes) -> usize {
+        toggle(&mut broker.patterns, pattern, self.id, None);
+        self.patterns.remove(pattern);
+        self.count()
+    }
+
+    /// Subscribe to the shard channel `channel`, returns the number of
+    /// shard channels after
+    pub fn ssubscribe(&mut self, broker: &mut Broker, channel: Bytes) -> usize {
+        broker.toggle_shard(&channel, self.id, Some(&self.sender));
+        self.shard_channels.insert(channel);
+        self.shard_count()
+    }
+
+    pub fn sunsubscribe(&mut self, broker: &mut Broker, channel: &Bytes) -> usize {
+        broker.toggle_shard(channel, self.id, None);
+        self.shard_channels.remove(channel);
+        self.shard_count()
+    }
+
+    /// Unsubscribe from everything
+    pub fn clear(&mut self, broker: &mut Broker) {
+        for channel in self.channels.drain() {
+            toggle(&mut broker.channels, &channel, self.id, None);
+        }
+        for pattern in self.patterns.drain() {
+            toggle(&mut broker.patterns, &pattern, self.id, None);
+        }
+        for channel in self.shard_channels.drain() {
+            broker.toggle_shard(&channel, self.id, None);
+        }
+    }
+
+    /// The next message, `None` once the client is over its limit and has
+    /// to be disconnected. It waits forever if there are no subscriptions.
+    pub async fn recv(&mut self) -> Option<Message> {
+        tokio::select! {
+            biased;
+            _ = self.backlog.overflowed() => None,
+            message = self.receiver.recv() => {
+                // `self.sender` is never dropped first, so this is never `None`
+                let message = message.unwrap();
+                self.backlog.bytes.fetch_sub(message.size(), Ordering::Relaxed);
+                Some(message)
+            }
+        }
+    }
+
+    /// Waits until the client is over its limit, to cut off one that stopped
+    /// reading while its handler waits to write
+    pub async fn overflowed(&self) {
+        self.backlog.overflowed().await
+    }
+}
+
+impl Default for Subscriptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[test]
+fn test_broker() {
+    let mut broker = Broker::default();
+    let (mut alice, mut bob) = (Subscriptions::new(), Subscriptions::new());
+    assert_eq!(alice.subscribe(&mut broker, Bytes::from("news")), 1);
+    assert_eq!(alice.psubscribe(&mut broker, Bytes::from("n*")), 2);
+    assert_eq!(bob.subscribe(&mut broker, Bytes::from("news")), 1);
+    // subscribing twice changes nothing
+    assert_eq!(bob.subscribe(&mut broker, Bytes::from("news")), 1);
+
+    assert_eq!(broker.publish(&Bytes::from("news"), &Bytes::from("hi")), 3);
+    assert_eq!(broker.publish(&Bytes::from("nope"), &Bytes::from("hi")), 1);
+    assert_eq!(broker.num_subscribers(b"news"), 2);
+    assert_eq!(broker.num_patterns(), 1);
+    assert_eq!(broker.channels(Some(b"x*")), Vec::<Bytes>::new());
+
+    let received: Vec<_> = std::iter::from_fn(|| alice.receiver.try_recv().ok()).collect();
+    assert_eq!(received.len(), 3);
+    assert!(received.contains(&Message {
+        pattern: Some(Bytes::from("n*")),
+        shard: false,
+        channel: Bytes::from("nope"),
+        payload: Bytes::from("hi"),
+    }));
+
+    // shard channels are apart, patterns do not match them
+    assert_eq!(bob.ssubscribe(&mut broker, Bytes::from("news")), 1);
+    assert_eq!(broker.spublish(&Bytes::from("news"), &Bytes::from("hi")), 1);
+    assert_eq!(broker.num_shard_subscribers(b"news"), 1);
+    assert_eq!(broker.num_subscribers(b"news"), 2);
+    assert_eq!(bob.sunsubscribe(&mut broker, &Bytes::from("news")), 0);
+    assert!(broker.shard_channels.is_empty());
+
+    // over the limit, a subscriber gets nothing more
+    let mut carol = Subscriptions::new();
+    carol.set_limit(Some(10));
+    assert_eq!(carol.subscribe(&mut broker, Bytes::from("big")), 1);
+    assert_eq!(broker.publish(&Bytes::from("big"), &Bytes::from("12345")), 1);
+    assert_eq!(broker.publish(&Bytes::from("big"), &Bytes::from("12345")), 0);
+    assert_eq!(broker.publish(&Bytes::from("big"), &Bytes::from("1")), 0);
+    assert!(carol.backlog.overflowed.load(Ordering::Acquire));
+    carol.clear(&mut broker);
+
+    alice.clear(&mut broker);
+    assert_eq!(bob.unsubscribe(&mut broker, &Bytes::from("news")), 0);
+    assert_eq!(broker.channels(None), Vec::<Bytes>::new());
+    assert_eq!(broker.num_patterns(), 0);
+}
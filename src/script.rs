@@ -0,0 +1,542 @@
+//! Lua scripting, `EVAL` and `SCRIPT`
+//!
+//! Scripts run in a single Lua 5.1 interpreter shared by every client,
+//! under the database lock, so they are atomic like a transaction. Each
+//! script is compiled once into the global function `f_<sha1>` of its body,
+//! which is what `EVALSHA` calls. Inside a script `redis.call` and
+//! `redis.pcall` run a command through `Request`, with the replies
+//! converted the way redis does:
+//!
+//! | RESP           | Lua                    |
+//! |----------------|------------------------|
+//! | integer        | number                 |
+//! | bulk string    | string                 |
+//! | array          | table, as a sequence   |
+//! | nil            | `false`                |
+//! | status         | table with an `ok` field  |
+//! | error          | table with an `err` field |
+//!
+//! and back, where a number is truncated to an integer, `true` becomes
+//! `1` and a sequence stops at the first `nil`.
+//!
+//! The functions of the libraries in the database, see `function`, run in
+//! the same interpreter, `FCALL` compiles them again after any change.
+//!
+//! A script running for longer than `lua-time-limit` is not stopped, but
+//! other clients are then told the server is busy, and may stop it with
+//! `SCRIPT KILL` unless it already wrote to the dataset. The top level code
+//! of a library fails after `LOAD_TIMEOUT` on top of that.
+//!
+//! Only the base, `table`, `string` and `math` libraries are loaded, without
+//! `loadfile` and `dofile`, so a script can not touch files or processes.
+
+use std::{
+    cell::RefCell,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use mlua::{Function, HookTriggers, IntoLuaMulti, Lua, LuaOptions, StdLib, Table, Value, Variadic};
+use tokio::time::Instant;
+
+use crate::{
+    cmd::Request,
+    database::Database,
+    frame::Frame,
+    function::{self, Libraries, Library, LibraryFunction},
+};
+
+/// how often, in Lua instructions, a running script checks for `SCRIPT KILL`
+const KILL_CHECK_INSTRUCTIONS: u32 = 1000;
+
+pub const BUSY: &str = "BUSY Redis is busy running a script. You can only call SCRIPT KILL or SHUTDOWN NOSAVE.";
+
+const KILLED: &str = "Script killed by user with SCRIPT KILL...";
+
+/// how long the top level code of a library may run, `LOAD_TIMEOUT_MS` of
+/// redis. It only registers functions, so one that takes longer never ends.
+const LOAD_TIMEOUT: Duration = Duration::from_millis(500);
+
+const LOAD_TIMED_OUT: &str = "FUNCTION LOAD timeout";
+
+/// The `redis` library functions written in Lua, `redis.pcall` is bound to
+/// the database by every run
+const PRELUDE: &str = r#"
+redis = redis or {}
+redis.LOG_DEBUG, redis.LOG_VERBOSE, redis.LOG_NOTICE, redis.LOG_WARNING = 0, 1, 2, 3
+function redis.call(...)
+    local reply = redis.pcall(...)
+    if type(reply) == 'table' and reply.err then
+        error(reply)
+    end
+    return reply
+end
+function redis.error_reply(err)
+    return { err = err }
+end
+function redis.status_reply(ok)
+    return { ok = ok }
+end
+function redis.log(level, message)
+end
+function redis.register_function(name, callback, flags)
+    if type(name) == 'table' then
+        name, callback, flags = name.function_name, name.callback, name.flags
+    end
+    if redis.__library == nil then
+        error('redis.register_function can only be called on FUNCTION LOAD command')
+    end
+    if type(name) ~= 'string' or not name:match('^[%w_]+$') then
+        error('Function names can only contain letters, numbers, or underscores(_) and must be at least one character long')
+    end
+    if type(callback) ~= 'function' then
+        error('callback must be a function')
+    end
+    table.insert(redis.__library, { name = name, callback = callback, flags = flags or {} })
+end
+"#;
+
+/// The script in progress, if any
+#[derive(Default)]
+struct Running {
+    since: Option<Instant>,
+    // `lua-time-limit` when it started
+    limit: Duration,
+    // it wrote to the dataset, `SCRIPT KILL` would leave it half done
+    wrote: bool,
+    killed: bool,
+    // the top level code of a library, stopped after `LOAD_TIMEOUT`
+    loading: bool,
+    timed_out: bool,
+}
+
+/// The interpreter and its compiled scripts, shared by the clients
+pub struct Scripts {
+    vm: Mutex<Vm>,
+    running: Arc<Mutex<Running>>,
+}
+
+struct Vm {
+    lua: Lua,
+    // the `Libraries::version` its functions were compiled from
+    libraries: Option<u64>,
+}
+
+impl Scripts {
+    pub fn new() -> Self {
+        let running = Arc::new(Mutex::new(Running::default()));
+        let vm = Vm { lua: new_lua(running.clone()), libraries: None };
+        Scripts { vm: Mutex::new(vm), running }
+    }
+
+    /// Compile `body` unless it is cached, returns its SHA1
+    pub fn load(&self, body: &[u8]) -> Result<String, Frame> {
+        let vm = self.vm.lock().unwrap();
+        load(&vm.lua, body)
+    }
+
+    pub fn exists(&self, sha: &str) -> bool {
+        let vm = self.vm.lock().unwrap();
+        let exists = vm.lua.globals().contains_key(format!("f_{}", sha.to_lowercase())).unwrap_or(false);
+        exists
+    }
+
+    /// Forget every script, with a new interpreter. The functions are
+    /// compiled again on their next call.
+    pub fn flush(&self) {
+        *self.vm.lock().unwrap() = Vm { lua: new_lua(self.running.clone()), libraries: None };
+    }
+
+    /// Run `body`, caching it
+    pub fn eval(&self, db: &mut Database, body: &[u8], keys: &[String], args: &[Bytes]) -> Frame {
+        let vm = self.vm.lock().unwrap();
+        match load(&vm.lua, body) {
+            Ok(sha) => self.run_script(&vm.lua, db, &sha, keys, args),
+            Err(error) => error,
+        }
+    }
+
+    /// Run the cached script of SHA1 `sha`
+    pub fn eval_sha(&self, db: &mut Database, sha: &str, keys: &[String], args: &[Bytes]) -> Frame {
+        let vm = self.vm.lock().unwrap();
+        let sha = sha.to_lowercase();
+        if !vm.lua.globals().contains_key(format!("f_{sha}")).unwrap_or(false) {
+            return Frame::Error("NOSCRIPT No matching script. Please use EVAL.".to_string());
+        }
+        self.run_script(&vm.lua, db, &sha, keys, args)
+    }
+
+    /// Run the code of a library, to learn its name and functions. It is
+    /// not added to `db`.
+    pub fn compile_library(&self, db: &Database, code: &[u8]) -> Result<Library, String> {
+        let vm = self.vm.lock().unwrap();
+        self.loading(db, || register(&vm.lua, code).map(|(library, _)| library))
+    }
+
+    /// Call the library function `name`, with `read_only` for `FCALL_RO`
+    pub fn fcall(&self, db: &mut Database, name: &str, keys: &[String], args: &[Bytes], read_only: bool) -> Frame {
+        let mut vm = self.vm.lock().unwrap();
+        let Some(function) = db.functions().function(name) else {
+            return Frame::Error("ERR Function not found".to_string());
+        };
+        let no_writes = function.no_writes();
+        if read_only && !no_writes {
+            return Frame::Error("ERR Can not execute a script with write flag using *_ro command.".to_string());
+        }
+        let version = db.functions().version();
+        if vm.libraries != Some(version) {
+            if let Err(e) = self.loading(db, || compile_libraries(&vm.lua, db.functions())) {
+                return Frame::Error(format!("ERR {e}"));
+            }
+            vm.libraries = Some(version);
+        }
+        let callback = || -> mlua::Result<(Function, Table, Table)> {
+            let functions: Table = vm.lua.named_registry_value("functions")?;
+            let keys = vm.lua.create_sequence_from(keys.iter().map(|key| key.as_str()))?;
+            let args = args.iter().map(|arg| vm.lua.create_string(arg)).collect::<mlua::Result<Vec<_>>>()?;
+            Ok((functions.get(name)?, keys, vm.lua.create_sequence_from(args)?))
+        };
+        let reply = match callback() {
+            Ok((callback, keys, args)) => self.run(&vm.lua, db, name, callback, (keys, args), no_writes),
+            Err(e) => Frame::Error(format!("ERR {e}")),
+        };
+        reply
+    }
+
+    /// Run the top level code of libraries in `load` like a script, under
+    /// the time limit and open to `SCRIPT KILL`. It fails once it has run
+    /// for `LOAD_TIMEOUT`.
+    fn loading<T>(&self, db: &Database, load: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+        self.start(db);
+        self.running.lock().unwrap().loading = true;
+        let loaded = load();
+        let running = std::mem::take(&mut *self.running.lock().unwrap());
+        match loaded {
+            Err(_) if running.killed => Err(KILLED.to_string()),
+            Err(_) if running.timed_out => Err(LOAD_TIMED_OUT.to_string()),
+            loaded => loaded,
+        }
+    }
+
+    /// A script runs from now on, see `busy`
+    fn start(&self, db: &Database) {
+        *self.running.lock().unwrap() =
+            Running { since: Some(Instant::now()), limit: db.config().lua_time_limit, ..Default::default() };
+    }
+
+    /// Whether a script holds the database
+    pub fn running(&self) -> bool {
+        self.running.lock().unwrap().since.is_some()
+    }
+
+    /// Whether a script has been running for longer than its time limit,
+    /// other clients are refused with `BUSY` then
+    pub fn busy(&self) -> bool {
+        let running = self.running.lock().unwrap();
+        running.since.is_some_and(|since| since.elapsed() >= running.limit)
+    }
+
+    /// Stop the running script, it fails with an error
+    pub fn kill(&self) -> Frame {
+        let mut running = self.running.lock().unwrap();
+        if running.since.is_none() {
+            return Frame::Error("NOTBUSY No scripts in execution right now.".to_string());
+        }
+        if running.wrote {
+            return Frame::Error(
+                "UNKILLABLE Sorry the script already executed write commands against the dataset. You can either \
+                 wait the script termination or kill the server in a hard way using the SHUTDOWN NOSAVE command."
+                    .to_string(),
+            );
+        }
+        running.killed = true;
+        Frame::Simple("OK".to_string())
+    }
+
+    /// Run the cached script `sha`, with its `KEYS` and `ARGV` globals
+    fn run_script(&self, lua: &Lua, db: &mut Database, sha: &str, keys: &[String], args: &[Bytes]) -> Frame {
+        let script = || -> mlua::Result<Function> {
+            let globals = lua.globals();
+            globals.set("KEYS", lua.create_sequence_from(keys.iter().map(|key| key.as_str()))?)?;
+            let args = args.iter().map(|arg| lua.create_string(arg)).collect::<mlua::Result<Vec<_>>>()?;
+            globals.set("ARGV", lua.create_sequence_from(args)?)?;
+            globals.get(format!("f_{sha}"))
+        };
+        match script() {
+            Ok(script) => self.run(lua, db, &format!("f_{sha}"), script, (), false),
+            Err(e) => Frame::Error(format!("ERR {e}")),
+        }
+    }
+
+    /// Call `function`, named `name` in errors, with `redis.pcall` bound to
+    /// `db`. A `read_only` one may not write.
+    fn run<'lua>(
+        &self,
+        lua: &'lua Lua,
+        db: &mut Database,
+        name: &str,
+        function: Function<'lua>,
+        args: impl IntoLuaMulti<'lua>,
+        read_only: bool,
+    ) -> Frame {
+        self.start(db);
+        let db = RefCell::new(db);
+        let reply = lua.scope(|scope| {
+            let redis: Table = lua.globals().get("redis")?;
+            let pcall = scope.create_function(|lua, args: Variadic<Value>| {
+                let reply = call(lua, &mut db.borrow_mut(), &self.running, read_only, args)?;
+                to_lua(lua, reply)
+            })?;
+            redis.set("pcall", pcall)?;
+
+            // the base `pcall`, in case the script replaced the global one
+            let protected: Function = lua.named_registry_value("pcall")?;
+            let (ok, value): (bool, Value) = protected.call((function, args))?;
+            if ok {
+                return Ok(to_frame(value));
+            }
+            if self.running.lock().unwrap().killed {
+                return Ok(Frame::Error(format!("ERR {KILLED}")));
+            }
+            if let Some(error @ Frame::Error(_)) = error_table(&value) {
+                return Ok(error);
+            }
+            let message: String = lua.globals().get::<_, Function>("tostring")?.call(value)?;
+            Ok(Frame::Error(format!("ERR Error running script (call to {name}): {message}")))
+        });
+        *self.running.lock().unwrap() = Running::default();
+        reply.unwrap_or_else(|e| Frame::Error(format!("ERR {e}")))
+    }
+}
+
+impl Default for Scripts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// SHA1 of `body`, in lowercase hexadecimal
+pub fn sha1_hex(body: &[u8]) -> String {
+    sha1_smol::Sha1::from(body).digest().to_string()
+}
+
+fn new_lua(running: Arc<Mutex<Running>>) -> Lua {
+    // no `io` or `os`, scripts must not reach past the dataset
+    let libs = StdLib::TABLE | StdLib::STRING | StdLib::MATH;
+    let lua = Lua::new_with(libs, LuaOptions::default()).expect("the standard libraries load");
+    let setup = || -> mlua::Result<()> {
+        for name in ["os", "io", "loadfile", "dofile"] {
+            lua.globals().raw_remove(name)?;
+        }
+        let pcall: Function = lua.globals().get("pcall")?;
+        lua.set_named_registry_value("pcall", pcall)?;
+        lua.load(PRELUDE).set_name("@prelude").exec()?;
+        let redis: Table = lua.globals().get("redis")?;
+        let sha1hex = lua.create_function(|_, body: mlua::String| Ok(sha1_hex(body.as_bytes())))?;
+        redis.set("sha1hex", sha1hex)
+    };
+    setup().expect("the redis library is valid");
+    lua.set_hook(HookTriggers::new().every_nth_instruction(KILL_CHECK_INSTRUCTIONS), move |_, _| {
+        let mut running = running.lock().unwrap();
+        if running.loading && running.since.is_some_and(|since| since.elapsed() >= LOAD_TIMEOUT) {
+            running.timed_out = true;
+        }
+        // raised again and again, so a `pcall` in the script can not
+        // swallow it for long
+        if running.killed {
+            return Err(mlua::Error::RuntimeError(KILLED.to_string()));
+        }
+        if running.timed_out {
+            return Err(mlua::Error::RuntimeError(LOAD_TIMED_OUT.to_string()));
+        }
+        Ok(())
+    });
+    lua
+}
+
+fn load(lua: &Lua, body: &[u8]) -> Result<String, Frame> {
+    let sha = sha1_hex(body);
+    let name = format!("f_{sha}");
+    if lua.globals().contains_key(name.as_str()).unwrap_or(false) {
+        return Ok(sha);
+    }
+    let function = lua
+        .load(body)
+        .set_name("@user_script")
+        .into_function()
+        .map_err(|e| Frame::Error(format!("ERR Error compiling script (new function): {e}")))?;
+    lua.globals().set(name, function).map_err(|e| Frame::Error(format!("ERR {e}")))?;
+    Ok(sha)
+}
+
+/// Run the code of a library, returns the library and the callbacks of its
+/// functions
+fn register<'lua>(lua: &'lua Lua, code: &[u8]) -> Result<(Library, Vec<(String, Function<'lua>)>), String> {
+    let name = function::library_name(code)?;
+    // Lua does not skip the `#!lua` line, the newline keeps line numbers
+    let body = &code[code.iter().position(|b| *b == b'\n').unwrap_or(code.len())..];
+    let registered = || -> mlua::Result<Result<Table, String>> {
+        let redis: Table = lua.globals().get("redis")?;
+        let library = lua.create_table()?;
+        redis.set("__library", library.clone())?;
+        let chunk = match lua.load(body).set_name("@user_function").into_function() {
+            Ok(chunk) => chunk,
+            Err(e) => return Ok(Err(format!("Error compiling function: {e}"))),
+        };
+        let protected: Function = lua.named_registry_value("pcall")?;
+        let (ok, error): (bool, Value) = protected.call(chunk)?;
+        redis.set("__library", Value::Nil)?;
+        if !ok {
+            let message: String = lua.globals().get::<_, Function>("tostring")?.call(error)?;
+            return Ok(Err(format!("Error registering functions: {message}")));
+        }
+        Ok(Ok(library))
+    };
+    let registered = registered().map_err(|e| e.to_string())??;
+
+    let mut functions = vec![];
+    let mut callbacks = vec![];
+    for entry in registered.sequence_values::<Table>() {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let name: String = entry.get("name").map_err(|e| e.to_string())?;
+        if functions.iter().any(|function: &LibraryFunction| function.name == name) {
+            return Err(format!("Function {name} already exists"));
+        }
+        let flags: Vec<String> = entry.get("flags").map_err(|e| e.to_string())?;
+        if let Some(flag) = flags.iter().find(|flag| !function::FLAGS.contains(&flag.as_str())) {
+            return Err(format!("Unknown flag given: {flag}"));
+        }
+        callbacks.push((name.clone(), entry.get("callback").map_err(|e| e.to_string())?));
+        functions.push(LibraryFunction { name, flags });
+    }
+    if functions.is_empty() {
+        return Err("No functions registered".to_string());
+    }
+    Ok((Library { name, code: Bytes::copy_from_slice(code), functions }, callbacks))
+}
+
+/// Compile every library into the `functions` table of the registry
+fn compile_libraries(lua: &Lua, libraries: &Libraries) -> Result<(), String> {
+    let functions = lua.create_table().map_err(|e| e.to_string())?;
+    for library in libraries.iter() {
+        for (name, callback) in register(lua, &library.code)?.1 {
+            functions.set(name, callback).map_err(|e| e.to_string())?;
+        }
+    }
+    lua.set_named_registry_value("functions", functions).map_err(|e| e.to_string())
+}
+
+/// `redis.pcall`, run a command against the database
+fn call(
+    lua: &Lua,
+    db: &mut Database,
+    running: &Mutex<Running>,
+    read_only: bool,
+    args: Variadic<Value>,
+) -> mlua::Result<Frame> {
+    if args.is_empty() {
+        return Ok(Frame::Error("ERR Please specify at least one argument for this redis lib call".to_string()));
+    }
+    let mut frame = Frame::new_array_frame();
+    for arg in args.into_iter() {
+        match arg {
+            Value::String(_) | Value::Integer(_) | Value::Number(_) => {
+                let arg = lua.coerce_string(arg)?.expect("strings and numbers coerce");
+                frame.push_bulk(Bytes::copy_from_slice(arg.as_bytes()));
+            }
+            _ => {
+                return Ok(Frame::Error(
+                    "ERR Lua redis lib command arguments must be strings or integers".to_string(),
+                ))
+            }
+        }
+    }
+    // connection commands, `EVAL` included, refuse to run here
+    Ok(match Request::from_frame(frame) {
+        Ok(req) if read_only && req.is_write() => {
+            Frame::Error("ERR Write commands are not allowed from read-only scripts.".to_string())
+        }
+        Ok(req) => {
+            if req.is_write() {
+                running.lock().unwrap().wrote = true;
+            }
+            req.execute(db)
+        }
+        Err(e) => Frame::Error(format!("ERR {e}")),
+    })
+}
+
+fn to_lua(lua: &Lua, frame: Frame) -> mlua::Result<Value<'_>> {
+    Ok(match frame {
+        Frame::Simple(ok) => Value::Table(lua.create_table_from([("ok", ok)])?),
+        Frame::Error(err) => Value::Table(lua.create_table_from([("err", err)])?),
+        Frame::Integer(n) => Value::Number(n as f64),
+        Frame::Bulk(bs) => Value::String(lua.create_string(&bs)?),
+        Frame::Null | Frame::NullArray => Value::Boolean(false),
+        Frame::Array(frames) | Frame::Push(frames) | Frame::Map(frames) => {
+            let items = frames.into_iter().map(|frame| to_lua(lua, frame)).collect::<mlua::Result<Vec<_>>>()?;
+            Value::Table(lua.create_sequence_from(items)?)
+        }
+    })
+}
+
+/// The reply of a script
+fn to_frame(value: Value) -> Frame {
+    match value {
+        Value::Boolean(true) => Frame::Integer(1),
+        Value::Integer(n) => Frame::Integer(n),
+        Value::Number(n) => Frame::Integer(n as i64),
+        Value::String(s) => Frame::Bulk(Bytes::copy_from_slice(s.as_bytes())),
+        Value::Table(table) => match error_table(&Value::Table(table.clone())) {
+            Some(frame) => frame,
+            None => {
+                let items = table.sequence_values::<Value>().map_while(Result::ok).map(to_frame);
+                Frame::Array(items.collect())
+            }
+        },
+        _ => Frame::Null,
+    }
+}
+
+/// The error or status of a table with an `err` or an `ok` field
+fn error_table(value: &Value) -> Option<Frame> {
+    let Value::Table(table) = value else { return None };
+    if let Ok(Value::String(err)) = table.raw_get::<_, Value>("err") {
+        return Some(Frame::Error(err.to_string_lossy().into_owned()));
+    }
+    if let Ok(Value::String(ok)) = table.raw_get::<_, Value>("ok") {
+        return Some(Frame::Simple(ok.to_string_lossy().into_owned()));
+    }
+    None
+}
+
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[test]
+fn test_conversions() {
+    let lua = Lua::new();
+    let frame = Frame::Array(vec![
+        Frame::Integer(3),
+        Frame::Bulk(Bytes::from("bulk")),
+        Frame::Null,
+        Frame::Simple("OK".to_string()),
+        Frame::Error("ERR oops".to_string()),
+    ]);
+    let value = to_lua(&lua, frame).unwrap();
+    // nil stops the sequence, false does not
+    assert_eq!(
+        to_frame(value),
+        Frame::Array(vec![
+            Frame::Integer(3),
+            Frame::Bulk(Bytes::from("bulk")),
+            Frame::Null,
+            Frame::Simple("OK".to_string()),
+            Frame::Error("ERR oops".to_string()),
+        ])
+    );
+    let value: Value = lua.load("return {1.9, true, nil, 'lost'}").eval().unwrap();
+    assert_eq!(to_frame(value), Frame::Array(vec![Frame::Integer(1), Frame::Integer(1)]));
+    assert_eq!(sha1_hex(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+}
@@ -1,10 +1,40 @@
-use std::io;
+use std::{
+    future::Future,
+    io,
+    sync::{Arc, Mutex, MutexGuard, TryLockError},
+    time::Duration,
+};
+
+use crate::{
+    cmd::{self, Request},
+    connection::{self, Connection},
+    database::{Config, Database},
+    frame::Frame,
+    pubsub::Subscriptions,
+    script::{self, Scripts},
+    transaction::{Transaction, Watches},
+};
+use tokio::{
+    net::TcpListener,
+    sync::{broadcast, watch},
+};
+
+/// how often expired keys are looked for, redis does it `hz` times a second
+const EXPIRE_PERIOD: Duration = Duration::from_millis(100);
+
+/// most keys looked at by one round of `Server::expire_keys`
+const EXPIRE_MAX_KEYS: usize = 1000;
+
+/// how often a client waiting for a script looks at the database again
+const SCRIPT_WAIT: Duration = Duration::from_millis(1);
 
-use crate::{cmd::Request, connection::Connection, database::Database, frame::Frame};
-use tokio::{net::TcpListener, sync::broadcast};
 pub struct Server {
     // shared database
-    db: Database,
+    db: Arc<Mutex<Database>>,
+    // the Lua interpreter, shared too
+    scripts: Arc<Scripts>,
+    // what new connections take their settings from
+    config: watch::Receiver<Config>,
 
     // shutdown notice
     shutdown_broacaster: broadcast::Sender<()>,
@@ -12,86 +42,407 @@ pub struct Server {
 
 pub struct Handler {
     connection: Connection,
+    db: Arc<Mutex<Database>>,
+    scripts: Arc<Scripts>,
+    config: watch::Receiver<Config>,
     shutdown_receiver: broadcast::Receiver<()>,
+    // the channels and patterns the client subscribed to
+    subscriptions: Subscriptions,
+    // switched to RESP3 by `HELLO 3`
+    resp3: bool,
+    // the commands queued since `MULTI`
+    transaction: Option<Transaction>,
+    // the keys watched for the next `EXEC`
+    watches: Watches,
 }
 
+/// What a RESP2 client may send while it is subscribed, its replies could
+/// not be told apart from the messages otherwise
+const SUBSCRIBED_COMMANDS: &[&str] =
+    &["subscribe", "unsubscribe", "psubscribe", "punsubscribe", "ssubscribe", "sunsubscribe", "ping", "quit"];
+
 impl Handler {
     pub async fn start(&mut self) {
-        println!("start hanlder");
+        // not from the database, a script may hold it and this client has
+        // to be told it is busy, or send SCRIPT KILL
+        let config = self.config.borrow().clone();
+        self.connection.set_stream_threshold(config.stream_bulk_threshold);
+        self.subscriptions.set_limit(config.pubsub_buffer_limit);
+        self.serve().await;
+        // nothing is sent to a closed connection
+        let mut db = lock(&self.db, &self.scripts).await;
+        self.subscriptions.clear(db.broker_mut());
+        self.watches.clear(&mut db);
+    }
+
+    async fn serve(&mut self) {
         let mut peer_shutdown = false;
-        loop {
+        while !peer_shutdown {
             let frame = tokio::select! {
-                res = self.connection.read_frame(), if !peer_shutdown => {
+                res = self.connection.read_frame() => {
                     match res {
                         Ok(frame) => frame,
                         Err(msg) => {println!("{msg:?}"); peer_shutdown = true; continue;}
                     }
                 }
+                message = self.subscriptions.recv() => {
+                    let Some(message) = message else {
+                        println!("subscriber over its buffer limit");
+                        return;
+                    };
+                    let frame = message.into_frame(self.resp3);
+                    if let Err(e) = self.write_replies(vec![frame]).await {
+                        println!("{e:?}");
+                        return;
+                    }
+                    continue;
+                }
                 _ = self.shutdown_receiver.recv() => {
                     println!("shutdown received for connection");
                     return;
                 }
             };
-            println!("receive a frame");
-
-            // let frame = self.connection.read_frame().await.unwrap();
-            let req = Request::from_frame(frame).unwrap();
-            match req {
-                Request::Get(_) => {
-                    self.connection
-                        .write_frame(Frame::Simple("simon".to_string()))
-                        .await
-                        .unwrap();
-                }
-                Request::Set(_) => {
-                    self.connection
-                        .write_frame(Frame::Simple("OK".to_string()))
-                        .await
-                        .unwrap();
-                }
+
+            let quit = is_command(&frame, "quit");
+            let replies = match self.refuse_when_subscribed(&frame) {
+                Some(error) => vec![error],
+                None => match Request::from_frame(frame) {
+                    Ok(req) => match self.dispatch(req).await {
+                        Some(replies) => replies,
+                        None => {
+                            println!("blocked connection stopped");
+                            return;
+                        }
+                    },
+                    Err(e) => {
+                        // e.g. an unknown command, `EXEC` will fail
+                        if let Some(transaction) = self.transaction.as_mut() {
+                            transaction.abort();
+                        }
+                        vec![Frame::Error(format!("ERR {}", e))]
+                    }
+                },
+            };
+            if let Err(e) = self.write_replies(replies).await {
+                println!("{e:?}");
+                return;
+            }
+            if quit {
+                let _ = self.connection.flush().await;
+                return;
+            }
+        }
+    }
+
+    async fn write_replies(&mut self, replies: Vec<Frame>) -> Result<(), connection::Error> {
+        let (connection, subscriptions) = (&mut self.connection, &self.subscriptions);
+        let write = async {
+            for reply in replies {
+                connection.write_frame(reply).await?;
+            }
+            // a subscriber may not send anything before the next message
+            if !subscriptions.is_empty() {
+                connection.flush().await?;
+            }
+            Ok(())
+        };
+        // a subscriber that stopped reading is cut off without waiting for it
+        tokio::select! {
+            written = write => written,
+            _ = subscriptions.overflowed() => Err(connection::Error::Other("subscriber over its buffer limit".into())),
+        }
+    }
+
+    /// A RESP2 client with subscriptions is in subscriber mode, where it
+    /// may only change them, `PING` or `QUIT`
+    fn subscriber_mode(&self) -> bool {
+        !self.resp3 && !self.subscriptions.is_empty()
+    }
+
+    fn refuse_when_subscribed(&self, frame: &Frame) -> Option<Frame> {
+        if !self.subscriber_mode() || SUBSCRIBED_COMMANDS.iter().any(|name| is_command(frame, name)) {
+            return None;
+        }
+        let name = match frame {
+            Frame::Array(args) => match args.first() {
+                Some(Frame::Bulk(name)) => String::from_utf8_lossy(name).to_lowercase(),
+                _ => String::new(),
+            },
+            _ => String::new(),
+        };
+        Some(Frame::Error(format!(
+            "ERR Can't execute '{name}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT are allowed in this context"
+        )))
+    }
+
+    /// The replies to `req`, connection commands included, see `execute`
+    async fn dispatch(&mut self, req: Request) -> Option<Vec<Frame>> {
+        // the script holds the database lock, this does not need it
+        if let Request::Script(cmd @ cmd::Script::Kill) = &req {
+            return Some(vec![cmd.execute(&self.scripts)]);
+        }
+        if self.scripts.busy() {
+            return Some(vec![Frame::Error(script::BUSY.to_string())]);
+        }
+        if self.transaction.is_some() && !matches!(req, Request::Quit(_)) {
+            return Some(vec![self.queue(req).await]);
+        }
+        match &req {
+            Request::Hello(cmd) => Some(vec![cmd.execute(&mut self.resp3)]),
+            Request::Ping(cmd) if self.subscriber_mode() => Some(vec![cmd.subscribed_reply()]),
+            Request::Multi(cmd) => Some(vec![cmd.execute(&mut self.transaction)]),
+            Request::Exec(_) | Request::Discard(_) | Request::Watch(_) | Request::Unwatch(_) => {
+                let mut db = match lock_unless_busy(&self.db, &self.scripts).await {
+                    Ok(db) => db,
+                    Err(busy) => return Some(vec![busy]),
+                };
+                Some(vec![match &req {
+                    Request::Exec(cmd) => cmd.execute(&mut db, None, &mut self.watches, &self.scripts),
+                    Request::Discard(cmd) => cmd.execute(&mut db, &mut self.transaction, &mut self.watches),
+                    Request::Watch(cmd) => cmd.execute(&mut db, &mut self.watches),
+                    Request::Unwatch(cmd) => cmd.execute(&mut db, &mut self.watches),
+                    _ => unreachable!(),
+                }])
+            }
+            Request::Eval(_) | Request::EvalSha(_) | Request::Script(_) | Request::Function(_) | Request::FCall(_) => {
+                Some(vec![self.script(req).await])
+            }
+            _ => self.execute(req).await,
+        }
+    }
+
+    /// Run a scripting command on a thread of its own, so that a long
+    /// script does not stall the other clients served by this worker
+    async fn script(&self, req: Request) -> Frame {
+        let (db, scripts) = (self.db.clone(), self.scripts.clone());
+        let script = tokio::task::spawn_blocking(move || {
+            let mut db = db.lock().unwrap();
+            let frame = req.script(&mut db, &scripts).expect("a scripting command");
+            db.serve_blocked();
+            frame
+        });
+        script.await.unwrap_or_else(|e| Frame::Error(format!("ERR {e}")))
+    }
+
+    /// Inside `MULTI`, queue `req` and reply `QUEUED`, unless it ends the
+    /// transaction. A command that can not be queued aborts it.
+    async fn queue(&mut self, req: Request) -> Frame {
+        match req {
+            Request::Exec(cmd) => {
+                let mut db = match lock_unless_busy(&self.db, &self.scripts).await {
+                    Ok(db) => db,
+                    Err(busy) => return busy,
+                };
+                let reply = cmd.execute(&mut db, self.transaction.take(), &mut self.watches, &self.scripts);
+                db.serve_blocked();
+                reply
+            }
+            Request::Discard(cmd) => match lock_unless_busy(&self.db, &self.scripts).await {
+                Ok(mut db) => cmd.execute(&mut db, &mut self.transaction, &mut self.watches),
+                Err(busy) => busy,
+            },
+            Request::Multi(cmd) => cmd.execute(&mut self.transaction),
+            Request::Watch(_) => Frame::Error("ERR WATCH inside MULTI is not allowed".to_string()),
+            Request::Subscribe(_)
+            | Request::Unsubscribe(_)
+            | Request::PSubscribe(_)
+            | Request::PUnsubscribe(_)
+            | Request::SSubscribe(_)
+            | Request::SUnsubscribe(_)
+            | Request::Hello(_) => {
+                self.transaction.as_mut().unwrap().abort();
+                Frame::Error("ERR Command not allowed inside a transaction".to_string())
+            }
+            req => {
+                self.transaction.as_mut().unwrap().queue(req);
+                Frame::Simple("QUEUED".to_string())
+            }
+        }
+    }
+}
+
+/// Lock the database, waiting for the script that holds it without blocking
+/// the worker, which serves other clients meanwhile. `SCRIPT KILL` among
+/// them.
+async fn lock<'a>(db: &'a Mutex<Database>, scripts: &Scripts) -> MutexGuard<'a, Database> {
+    loop {
+        match db.try_lock() {
+            Ok(db) => return db,
+            Err(TryLockError::Poisoned(e)) => panic!("{e}"),
+            Err(TryLockError::WouldBlock) => {}
+        }
+        wait_for_script(scripts).await;
+    }
+}
+
+/// `lock`, or `BUSY` once the script holding the database has run for
+/// longer than its time limit
+async fn lock_unless_busy<'a>(db: &'a Mutex<Database>, scripts: &Scripts) -> Result<MutexGuard<'a, Database>, Frame> {
+    loop {
+        if scripts.busy() {
+            return Err(Frame::Error(script::BUSY.to_string()));
+        }
+        match db.try_lock() {
+            Ok(db) => return Ok(db),
+            Err(TryLockError::Poisoned(e)) => panic!("{e}"),
+            Err(TryLockError::WouldBlock) => {}
+        }
+        wait_for_script(scripts).await;
+    }
+}
+
+async fn wait_for_script(scripts: &Scripts) {
+    match scripts.running() {
+        true => tokio::time::sleep(SCRIPT_WAIT).await,
+        // another client, for a moment
+        false => tokio::task::yield_now().await,
+    }
+}
+
+/// Whether `frame` is a request for the command `name`
+fn is_command(frame: &Frame, name: &str) -> bool {
+    match frame {
+        Frame::Array(args) => matches!(args.first(), Some(Frame::Bulk(bs)) if bs.eq_ignore_ascii_case(name.as_bytes())),
+        _ => false,
+    }
+}
+
+impl Handler {
+    /// Run `req` and return its replies, several for a change of
+    /// subscriptions. A blocking command that can not be served right away
+    /// parks the client until it is served, it times out, or the server
+    /// shuts down, which returns `None` as does a lost connection.
+    async fn execute(&mut self, mut req: Request) -> Option<Vec<Frame>> {
+        let (id, mut reply, timeout, nil) = {
+            // the lock is released before the reply is written
+            let mut db = match lock_unless_busy(&self.db, &self.scripts).await {
+                Ok(db) => db,
+                Err(busy) => return Some(vec![busy]),
+            };
+            if let Some(replies) = req.subscription(&mut db, &mut self.subscriptions, self.resp3) {
+                return Some(replies);
             }
+            let frame = req.execute(&mut db);
+            db.serve_blocked();
+            let (keys, timeout) = match req.blocking_keys() {
+                Some((keys, timeout)) if frame.is_nil() => (keys.to_vec(), timeout),
+                _ => return Some(vec![frame]),
+            };
+            req.pin(&db);
+            let serve = Box::new(move |db: &mut Database| match req.execute(db) {
+                frame if frame.is_nil() => None,
+                frame => Some(frame),
+            });
+            let (id, reply) = db.block(keys, serve);
+            // the reply when the timeout is over
+            (id, reply, timeout, frame)
+        };
+        // the replies to the requests pipelined before this one are not
+        // held back until it is served
+        if self.connection.flush().await.is_err() {
+            lock(&self.db, &self.scripts).await.unblock(id);
+            return None;
         }
+
+        let timeout = async {
+            match timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        let frame = tokio::select! {
+            frame = &mut reply => frame.unwrap_or(nil),
+            _ = timeout => {
+                if lock(&self.db, &self.scripts).await.unblock(id) {
+                    nil
+                } else {
+                    // served right before the timeout
+                    reply.try_recv().unwrap_or(nil)
+                }
+            }
+            _ = self.shutdown_receiver.recv() => {
+                lock(&self.db, &self.scripts).await.unblock(id);
+                return None;
+            }
+        };
+        Some(vec![frame])
     }
 }
 
 impl Server {
     pub fn new() -> Self {
+        Self::with_config(Config::default())
+    }
+
+    pub fn with_config(config: Config) -> Self {
         let (tx, _rx) = broadcast::channel(1);
+        let db = Database::with_config(config);
         Server {
-            db: Database::new(),
+            config: db.watch_config(),
+            db: Arc::new(Mutex::new(db)),
+            scripts: Arc::new(Scripts::new()),
             shutdown_broacaster: tx,
         }
     }
 
+    /// Accept connections on `listener` until `shutdown` completes, then
+    /// tell every handler to stop. Expired keys are removed meanwhile.
+    pub async fn run(&self, listener: TcpListener, shutdown: impl Future) -> Result<(), io::Error> {
+        let res = tokio::select! {
+            res = self.accept(&listener) => res,
+            _ = self.expire_keys() => Ok(()),
+            _ = shutdown => Ok(()),
+        };
+        let _ = self.shutdown_broacaster.send(());
+        res
+    }
+
+    /// Remove the expired keys nobody reads, so they are notified too,
+    /// see `Database::expire_keys`
+    async fn expire_keys(&self) {
+        let mut interval = tokio::time::interval(EXPIRE_PERIOD);
+        loop {
+            interval.tick().await;
+            // skipped while a script holds the database, rather than stall
+            // the clients of this worker until it ends
+            if let Ok(mut db) = self.db.try_lock() {
+                db.expire_keys(EXPIRE_MAX_KEYS);
+            }
+        }
+    }
+
+    async fn accept(&self, listener: &TcpListener) -> Result<(), io::Error> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let mut handler = Handler {
+                connection: Connection::new(stream).map_err(|e| io::Error::other(e.to_string()))?,
+                db: self.db.clone(),
+                scripts: self.scripts.clone(),
+                config: self.config.clone(),
+                shutdown_receiver: self.shutdown_broacaster.subscribe(),
+                subscriptions: Subscriptions::new(),
+                resp3: false,
+                transaction: None,
+                watches: Watches::new(),
+            };
 
+            tokio::spawn(async move {
+                handler.start().await;
+            });
+        }
+    }
 }
-pub async fn start( addr: &str) -> Result<(), io::Error> {
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub async fn start(addr: &str) -> Result<(), io::Error> {
     let listener = TcpListener::bind(addr).await?;
-    let (stream, _) = listener.accept().await?;
-    let connection = Connection::new(stream).unwrap();
     let server = Server::new();
-    let rx = server.shutdown_broacaster.subscribe();
-    let mut handler = Handler {
-        connection,
-        shutdown_receiver: rx,
-    };
-
-    let join = tokio::spawn(async move {
-        handler.start().await;
-    });
-    tokio::select! {
-        Err(_) = join => {
-            println!("handler return with error")
-        }
-        _ = tokio::signal::ctrl_c() => {
-            drop(server);
-            // server.shutdown_broacaster.send(());
-            use std::thread;
-            use std::time::Duration;
-            thread::sleep(Duration::from_millis(10));
-            println!("shutdown server");
-        }
-    };
-    
+    server.run(listener, tokio::signal::ctrl_c()).await?;
+    println!("shutdown server");
     Ok(())
-}
\ No newline at end of file
+}
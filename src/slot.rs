@@ -0,0 +1,45 @@
+//! Hash slots
+//!
+//! A key hashes to one of 16384 slots with CRC16, the same as in redis
+//! cluster, so a partitioned server could split the keyspace by slot.
+//! Shard channels hash the same way. Only the part between the first `{`
+//! and the next `}`, if not empty, is hashed, so related keys can be put
+//! in the same slot on purpose.
+
+pub const SLOTS: u16 = 16384;
+
+/// CRC16-CCITT, the XMODEM variant
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in bytes {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// The slot of `key`, or of a shard channel
+pub fn key_slot(key: &[u8]) -> u16 {
+    let tag = key.iter().position(|&c| c == b'{').and_then(|open| {
+        let close = key[open + 1..].iter().position(|&c| c == b'}')?;
+        Some(&key[open + 1..open + 1 + close]).filter(|tag| !tag.is_empty())
+    });
+    crc16(tag.unwrap_or(key)) % SLOTS
+}
+
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[test]
+fn test_key_slot() {
+    assert_eq!(crc16(b"123456789"), 0x31c3);
+    assert_eq!(key_slot(b"foo"), 12182);
+    assert_eq!(key_slot(b"bar"), 5061);
+    assert_eq!(key_slot(b"{user1000}.following"), key_slot(b"{user1000}.followers"));
+    assert_eq!(key_slot(b"{user1000}.following"), key_slot(b"user1000"));
+    // an empty tag does not count, the whole key is hashed
+    assert_eq!(key_slot(b"foo{}{bar}"), crc16(b"foo{}{bar}") % SLOTS);
+    assert_eq!(key_slot(b"foo{{bar}}zap"), key_slot(b"{bar"));
+}
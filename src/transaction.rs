@@ -0,0 +1,157 @@
+//! Transactions, `MULTI` / `EXEC` and `WATCH`
+//!
+//! After `MULTI` a client's commands are parsed and queued instead of run,
+//! and `EXEC` runs them all under a single database lock, so no other
+//! client sees the writes in between. A command refused while queueing,
+//! e.g. an unknown one, discards the whole transaction at `EXEC`.
+//!
+//! `WATCH` makes the next `EXEC` fail, with a nil reply, if any of the keys
+//! changed since. The database counts the writes to every watched key, a
+//! key's version, and `EXEC` compares the versions to those seen by
+//! `WATCH`. A key that expired in between counts as changed too.
+
+use std::collections::HashMap;
+
+use crate::{cmd::Request, database::Database, frame::Frame, script::Scripts};
+
+/// The versions of the watched keys, in the database
+#[derive(Default)]
+pub(crate) struct Versions {
+    // the version and the number of clients watching, a key is tracked
+    // from its first watcher to its last one
+    keys: HashMap<String, (u64, usize)>,
+}
+
+impl Versions {
+    /// Start tracking `key` for one more client, returns its version
+    pub(crate) fn watch(&mut self, key: &str) -> u64 {
+        let (version, watchers) = self.keys.entry(key.to_string()).or_default();
+        *watchers += 1;
+        *version
+    }
+
+    pub(crate) fn unwatch(&mut self, key: &str) {
+        if let Some((_, watchers)) = self.keys.get_mut(key) {
+            *watchers -= 1;
+            if *watchers == 0 {
+                self.keys.remove(key);
+            }
+        }
+    }
+
+    pub(crate) fn version(&self, key: &str) -> u64 {
+        self.keys.get(key).map_or(0, |(version, _)| *version)
+    }
+
+    /// Count a write to `key`, called by every database write
+    pub(crate) fn touch(&mut self, key: &str) {
+        if self.keys.is_empty() {
+            return;
+        }
+        if let Some((version, _)) = self.keys.get_mut(key) {
+            *version += 1;
+        }
+    }
+}
+
+/// A key watched by a client
+struct Watched {
+    key: String,
+    version: u64,
+    // whether it existed when watched, so an expiration can be told
+    live: bool,
+}
+
+/// The keys a client watches, until its next `EXEC`, `DISCARD` or
+/// `UNWATCH`, or until it disconnects
+#[derive(Default)]
+pub struct Watches {
+    keys: Vec<Watched>,
+}
+
+impl Watches {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn watch(&mut self, db: &mut Database, key: &str) {
+        if self.keys.iter().any(|watched| watched.key == key) {
+            return;
+        }
+        let version = db.watch(key);
+        self.keys.push(Watched { key: key.to_string(), version, live: db.contains(key) });
+    }
+
+    /// Whether any of the keys was written, or expired, since it was watched
+    pub fn changed(&self, db: &Database) -> bool {
+        self.keys
+            .iter()
+            .any(|watched| db.version(&watched.key) != watched.version || (watched.live && !db.contains(&watched.key)))
+    }
+
+    pub fn clear(&mut self, db: &mut Database) {
+        for watched in self.keys.drain(..) {
+            db.unwatch(&watched.key);
+        }
+    }
+}
+
+/// The commands queued since `MULTI`
+#[derive(Default)]
+pub struct Transaction {
+    queued: Vec<Request>,
+    // a command was refused, `EXEC` fails
+    aborted: bool,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn queue(&mut self, req: Request) {
+        self.queued.push(req);
+    }
+
+    /// Make `EXEC` fail, after a command could not be queued
+    pub fn abort(&mut self) {
+        self.aborted = true;
+    }
+
+    /// Run the queued commands, with one reply each, unless the
+    /// transaction was aborted or a watched key changed, which replies a
+    /// null array. Every key is unwatched either way.
+    pub fn exec(self, db: &mut Database, watches: &mut Watches, scripts: &Scripts) -> Frame {
+        let changed = watches.changed(db);
+        watches.clear(db);
+        if self.aborted {
+            return Frame::Error("EXECABORT Transaction discarded because of previous errors.".to_string());
+        }
+        if changed {
+            return Frame::NullArray;
+        }
+        // a blocking command does not block, it replies nil right away
+        let replies = self.queued.iter().map(|req| req.script(db, scripts).unwrap_or_else(|| req.execute(db)));
+        Frame::Array(replies.collect())
+    }
+}
+
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[test]
+fn test_versions() {
+    let mut versions = Versions::default();
+    // only watched keys are tracked
+    versions.touch("key");
+    assert_eq!(versions.watch("key"), 0);
+    assert_eq!(versions.watch("key"), 0);
+    versions.touch("key");
+    versions.touch("other");
+    assert_eq!((versions.version("key"), versions.version("other")), (1, 0));
+    versions.unwatch("key");
+    assert_eq!(versions.version("key"), 1);
+    versions.unwatch("key");
+    assert_eq!(versions.version("key"), 0);
+    assert!(versions.keys.is_empty());
+}
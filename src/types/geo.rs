@@ -0,0 +1,259 @@
+//! Geohashes, the scores of a sorted set of places
+//!
+//! A place is stored as a member of a sorted set scored by the 52 bits
+//! geohash of its coordinates: 26 bits of longitude and 26 bits of
+//! latitude, interleaved from the highest bit with the longitude first, the
+//! same as redis. Latitudes are limited to the range of the Web Mercator
+//! projection. Nearby places share a prefix of their geohash, so a search
+//! reads the score ranges of the 9 cells around the center, at the
+//! smallest precision that covers the area, then checks each place.
+
+/// The limits of the coordinates of a place
+pub const LON_MIN: f64 = -180.0;
+pub const LON_MAX: f64 = 180.0;
+pub const LAT_MIN: f64 = -85.051_128_78;
+pub const LAT_MAX: f64 = 85.051_128_78;
+
+/// bits per coordinate
+const STEP: u32 = 26;
+const EARTH_RADIUS: f64 = 6_372_797.560_856;
+const MERCATOR_MAX: f64 = 20_037_726.37;
+const BASE32: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+pub fn is_valid(lon: f64, lat: f64) -> bool {
+    (LON_MIN..=LON_MAX).contains(&lon) && (LAT_MIN..=LAT_MAX).contains(&lat)
+}
+
+/// Spread the bits of `x` to the even bits
+fn spread(x: u32) -> u64 {
+    let mut x = x as u64;
+    x = (x | (x << 16)) & 0x0000_ffff_0000_ffff;
+    x = (x | (x << 8)) & 0x00ff_00ff_00ff_00ff;
+    x = (x | (x << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+    (x | (x << 1)) & 0x5555_5555_5555_5555
+}
+
+/// Gather the even bits of `x`
+fn squash(x: u64) -> u32 {
+    let mut x = x & 0x5555_5555_5555_5555;
+    x = (x | (x >> 1)) & 0x3333_3333_3333_3333;
+    x = (x | (x >> 2)) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | (x >> 4)) & 0x00ff_00ff_00ff_00ff;
+    x = (x | (x >> 8)) & 0x0000_ffff_0000_ffff;
+    ((x | (x >> 16)) & 0x0000_0000_ffff_ffff) as u32
+}
+
+/// The cell of a coordinate among `2^step` cells across `min..max`
+fn cell_index(x: f64, min: f64, max: f64, step: u32) -> u32 {
+    let cells = 1u64 << step;
+    (((x - min) / (max - min) * cells as f64) as u64).min(cells - 1) as u32
+}
+
+/// A cell, as the indexes of its latitude and longitude
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    lat: u32,
+    lon: u32,
+    step: u32,
+}
+
+impl Cell {
+    fn of(lon: f64, lat: f64, lat_range: (f64, f64), step: u32) -> Self {
+        Cell {
+            lat: cell_index(lat, lat_range.0, lat_range.1, step),
+            lon: cell_index(lon, LON_MIN, LON_MAX, step),
+            step,
+        }
+    }
+
+    fn from_hash(hash: u64, step: u32) -> Self {
+        Cell { lat: squash(hash), lon: squash(hash >> 1), step }
+    }
+
+    fn hash(self) -> u64 {
+        spread(self.lat) | (spread(self.lon) << 1)
+    }
+
+    /// The cell `dlon` cells east and `dlat` cells north, wrapping around
+    fn moved(self, dlon: i64, dlat: i64) -> Self {
+        let cells = 1i64 << self.step;
+        Cell {
+            lat: (self.lat as i64 + dlat).rem_euclid(cells) as u32,
+            lon: (self.lon as i64 + dlon).rem_euclid(cells) as u32,
+            step: self.step,
+        }
+    }
+
+    /// `(lon_min, lon_max, lat_min, lat_max)`
+    fn area(self) -> (f64, f64, f64, f64) {
+        let cells = (1u64 << self.step) as f64;
+        let lon_width = (LON_MAX - LON_MIN) / cells;
+        let lat_width = (LAT_MAX - LAT_MIN) / cells;
+        let lon_min = LON_MIN + self.lon as f64 * lon_width;
+        let lat_min = LAT_MIN + self.lat as f64 * lat_width;
+        (lon_min, lon_min + lon_width, lat_min, lat_min + lat_width)
+    }
+
+    /// The scores of the places within, `min..max`
+    fn scores(self) -> (u64, u64) {
+        let shift = 2 * (STEP - self.step);
+        (self.hash() << shift, (self.hash() + 1) << shift)
+    }
+}
+
+/// The geohash of a place, its score in the sorted set
+pub fn encode(lon: f64, lat: f64) -> u64 {
+    Cell::of(lon, lat, (LAT_MIN, LAT_MAX), STEP).hash()
+}
+
+/// The coordinates of the center of the cell of a geohash, `(lon, lat)`
+pub fn decode(hash: u64) -> (f64, f64) {
+    let (lon_min, lon_max, lat_min, lat_max) = Cell::from_hash(hash, STEP).area();
+    let lon = ((lon_min + lon_max) / 2.0).clamp(LON_MIN, LON_MAX);
+    let lat = ((lat_min + lat_max) / 2.0).clamp(LAT_MIN, LAT_MAX);
+    (lon, lat)
+}
+
+/// The standard 11 characters geohash of a place, which unlike the score
+/// covers latitudes from -90 to 90
+pub fn to_base32(hash: u64) -> String {
+    let (lon, lat) = decode(hash);
+    let hash = Cell::of(lon, lat, (-90.0, 90.0), STEP).hash();
+    (0..11)
+        .map(|i| {
+            // 55 bits for 52, the last character is padded with zeros
+            let index = if i == 10 { 0 } else { (hash >> (52 - (i + 1) * 5)) & 0x1f };
+            BASE32[index as usize] as char
+        })
+        .collect()
+}
+
+/// The great circle distance in meters, with the haversine formula
+pub fn distance(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let u = ((lat2 - lat1) / 2.0).sin();
+    let v = ((lon2.to_radians() - lon1.to_radians()) / 2.0).sin();
+    2.0 * EARTH_RADIUS * (u * u + lat1.cos() * lat2.cos() * v * v).sqrt().asin()
+}
+
+/// The area of a search around its center, in meters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+impl Shape {
+    /// The radius of a circle around the shape
+    fn radius(self) -> f64 {
+        match self {
+            Shape::Radius(radius) => radius,
+            Shape::Box { width, height } => (width / 2.0).hypot(height / 2.0),
+        }
+    }
+
+    /// The distance of a place from the center, if the place is within
+    pub fn distance_within(self, center: (f64, f64), lon: f64, lat: f64) -> Option<f64> {
+        match self {
+            Shape::Radius(radius) => Some(distance(center.0, center.1, lon, lat)).filter(|d| *d <= radius),
+            Shape::Box { width, height } => {
+                let lat_distance = EARTH_RADIUS * (lat.to_radians() - center.1.to_radians()).abs();
+                if lat_distance > height / 2.0 || distance(center.0, lat, lon, lat) > width / 2.0 {
+                    return None;
+                }
+                Some(distance(center.0, center.1, lon, lat))
+            }
+        }
+    }
+
+    /// `(lon_min, lon_max, lat_min, lat_max)` of a box around the shape
+    fn bounds(self, lon: f64, lat: f64) -> (f64, f64, f64, f64) {
+        let (width, height) = match self {
+            Shape::Radius(radius) => (radius * 2.0, radius * 2.0),
+            Shape::Box { width, height } => (width, height),
+        };
+        let lat_delta = (height / 2.0 / EARTH_RADIUS).to_degrees();
+        // longitudes get closer away from the equator
+        let widest = (lat.abs() + lat_delta).min(90.0).to_radians().cos();
+        let lon_delta = (width / 2.0 / EARTH_RADIUS / widest).to_degrees();
+        (lon - lon_delta, lon + lon_delta, lat - lat_delta, lat + lat_delta)
+    }
+}
+
+/// The most bits per coordinate for cells as large as the search
+fn steps_for(radius: f64, lat: f64) -> u32 {
+    if radius == 0.0 {
+        return STEP;
+    }
+    let mut radius = radius;
+    let mut step: i32 = 1;
+    while radius < MERCATOR_MAX {
+        radius *= 2.0;
+        step += 1;
+    }
+    step -= 2;
+    // cells are narrower near the poles
+    if lat.abs() > 66.0 {
+        step -= 1;
+        if lat.abs() > 80.0 {
+            step -= 1;
+        }
+    }
+    step.clamp(1, STEP as i32) as u32
+}
+
+/// The score ranges, `min..max`, holding every place in `shape` around
+/// `(lon, lat)`, sorted and disjoint
+pub fn search_ranges(lon: f64, lat: f64, shape: Shape) -> Vec<(u64, u64)> {
+    let (lon_min, lon_max, lat_min, lat_max) = shape.bounds(lon, lat);
+    let mut step = steps_for(shape.radius(), lat);
+    let mut center = Cell::of(lon, lat, (LAT_MIN, LAT_MAX), step);
+    // the cells around may still be too small to reach the bounds
+    if step > 1 {
+        let too_small = center.moved(0, 1).area().3 < lat_max
+            || center.moved(0, -1).area().2 > lat_min
+            || center.moved(1, 0).area().1 < lon_max
+            || center.moved(-1, 0).area().0 > lon_min;
+        if too_small {
+            step -= 1;
+            center = Cell::of(lon, lat, (LAT_MIN, LAT_MAX), step);
+        }
+    }
+
+    let mut ranges: Vec<(u64, u64)> = (-1..=1)
+        .flat_map(|dlon| (-1..=1).map(move |dlat| center.moved(dlon, dlat).scores()))
+        .collect();
+    ranges.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = vec![];
+    for (min, max) in ranges {
+        match merged.last_mut() {
+            Some(last) if min <= last.1 => last.1 = last.1.max(max),
+            _ => merged.push((min, max)),
+        }
+    }
+    merged
+}
+
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[test]
+fn test_geohash() {
+    // Palermo, from the documentation of redis
+    let hash = encode(13.361389, 38.115556);
+    assert_eq!(hash, 3479099956230698);
+    assert_eq!(to_base32(hash), "sqc8b49rny0");
+    let (lon, lat) = decode(hash);
+    assert!((lon - 13.361389).abs() < 1e-5 && (lat - 38.115556).abs() < 1e-5);
+
+    let catania = encode(15.087269, 37.502669);
+    assert_eq!(to_base32(catania), "sqdtr74hyu0");
+    let (lon2, lat2) = decode(catania);
+    assert!((distance(lon, lat, lon2, lat2) - 166274.1516).abs() < 0.01);
+
+    let ranges = search_ranges(15.0, 37.0, Shape::Radius(200_000.0));
+    assert!(ranges.iter().any(|(min, max)| (*min..*max).contains(&hash)));
+    assert!(ranges.iter().any(|(min, max)| (*min..*max).contains(&catania)));
+    assert!(ranges.windows(2).all(|w| w[0].1 < w[1].0));
+}
@@ -0,0 +1,159 @@
+//! The hash value type
+//!
+//! A small hash is a vector of field and value pairs, searched linearly,
+//! which for a handful of fields is faster than hashing and takes much less
+//! memory. Past its `CompactLimits` it is converted to a `Dict` for good,
+//! the same way redis turns a listpack into a hash table.
+
+use bytes::Bytes;
+use rand::Rng;
+
+use super::CompactLimits;
+use crate::dict::Dict;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Hash {
+    Compact(Vec<(Bytes, Bytes)>),
+    Table(Dict<Bytes, Bytes>),
+}
+
+impl Default for Hash {
+    fn default() -> Self {
+        Hash::Compact(Vec::new())
+    }
+}
+
+impl Hash {
+    pub fn len(&self) -> usize {
+        match self {
+            Hash::Compact(pairs) => pairs.len(),
+            Hash::Table(table) => table.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_compact(&self) -> bool {
+        matches!(self, Hash::Compact(_))
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&Bytes> {
+        match self {
+            Hash::Compact(pairs) => pairs.iter().find(|(f, _)| f == field).map(|(_, v)| v),
+            Hash::Table(table) => table.get(field),
+        }
+    }
+
+    pub fn contains(&self, field: &[u8]) -> bool {
+        self.get(field).is_some()
+    }
+
+    /// Set `field`, returns `true` if it is a new field
+    pub fn insert(&mut self, field: Bytes, value: Bytes, limits: &CompactLimits) -> bool {
+        if let Hash::Compact(pairs) = self {
+            let len = pairs.len();
+            if let Some(pair) = pairs.iter_mut().find(|(f, _)| *f == field) {
+                // a longer value may not fit any more
+                if limits.allows(len, &value) {
+                    pair.1 = value;
+                    return false;
+                }
+            } else {
+                let fits = limits.allows(len + 1, &field) && limits.allows(len + 1, &value);
+                if fits {
+                    pairs.push((field, value));
+                    return true;
+                }
+            }
+            self.convert();
+        }
+        match self {
+            Hash::Table(table) => table.insert(field, value).is_none(),
+            Hash::Compact(_) => unreachable!(),
+        }
+    }
+
+    pub fn remove(&mut self, field: &[u8]) -> Option<Bytes> {
+        match self {
+            Hash::Compact(pairs) => {
+                let i = pairs.iter().position(|(f, _)| f == field)?;
+                // keep the insertion order, like a listpack
+                Some(pairs.remove(i).1)
+            }
+            Hash::Table(table) => table.remove(field),
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&Bytes, &Bytes)> + '_> {
+        match self {
+            Hash::Compact(pairs) => Box::new(pairs.iter().map(|(f, v)| (f, v))),
+            Hash::Table(table) => Box::new(table.iter()),
+        }
+    }
+
+    /// A random field and its value, `None` if the hash is empty
+    pub fn random(&self) -> Option<(&Bytes, &Bytes)> {
+        match self {
+            Hash::Compact(pairs) if pairs.is_empty() => None,
+            Hash::Compact(pairs) => {
+                let (f, v) = &pairs[rand::rng().random_range(0..pairs.len())];
+                Some((f, v))
+            }
+            Hash::Table(table) => table.random(),
+        }
+    }
+
+    /// See `Dict::scan`, a compact hash is returned whole in one call
+    pub fn scan(&self, cursor: u64, count: usize, mut f: impl FnMut(&Bytes, &Bytes)) -> u64 {
+        match self {
+            Hash::Compact(pairs) => {
+                pairs.iter().for_each(|(field, value)| f(field, value));
+                0
+            }
+            Hash::Table(table) => table.scan(cursor, count, f),
+        }
+    }
+
+    fn convert(&mut self) {
+        if let Hash::Compact(pairs) = self {
+            let mut table = Dict::new();
+            for (field, value) in pairs.drain(..) {
+                table.insert(field, value);
+            }
+            *self = Hash::Table(table);
+        }
+    }
+}
+
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[test]
+fn test_converts_past_limits() {
+    let limits = CompactLimits { max_entries: 2, max_value: 4 };
+    let mut hash = Hash::default();
+    assert!(hash.insert(Bytes::from("a"), Bytes::from("1"), &limits));
+    assert!(hash.insert(Bytes::from("b"), Bytes::from("2"), &limits));
+    assert!(!hash.insert(Bytes::from("a"), Bytes::from("3"), &limits));
+    assert!(hash.is_compact());
+    assert!(hash.insert(Bytes::from("c"), Bytes::from("4"), &limits));
+    assert!(!hash.is_compact());
+    assert_eq!(hash.len(), 3);
+    assert_eq!(hash.get(b"a"), Some(&Bytes::from("3")));
+
+    let mut hash = Hash::default();
+    hash.insert(Bytes::from("a"), Bytes::from("too long"), &limits);
+    assert!(!hash.is_compact());
+    assert_eq!(hash.remove(b"a"), Some(Bytes::from("too long")));
+    assert!(hash.is_empty());
+
+    // so does a field updated to a value past the limit
+    let mut hash = Hash::default();
+    hash.insert(Bytes::from("a"), Bytes::from("1"), &limits);
+    assert!(!hash.insert(Bytes::from("a"), Bytes::from("too long"), &limits));
+    assert!(!hash.is_compact());
+    assert_eq!(hash.len(), 1);
+    assert_eq!(hash.get(b"a"), Some(&Bytes::from("too long")));
+}
@@ -0,0 +1,391 @@
+//! HyperLogLog, stored as a string
+//!
+//! The string is laid out exactly like redis lays it out, so a value
+//! dumped from redis can be counted here and the other way around: a 16
+//! bytes header, `HYLL`, the encoding, 3 unused bytes and the cached
+//! cardinality in little endian, its highest bit set when stale. Then
+//! 16384 registers of 6 bits, either packed one after the other from the
+//! lowest bits (dense), or run length encoded (sparse):
+//!
+//! - `00xxxxxx`: `xxxxxx + 1` registers set to 0
+//! - `01xxxxxx yyyyyyyy`: `xxxxxxyyyyyyyy + 1` registers set to 0
+//! - `1vvvvvxx`: `xx + 1` registers set to `vvvvv + 1`
+//!
+//! A sparse HyperLogLog turns dense when a register goes over 32, or when
+//! it grows past `hll-sparse-max-bytes`. The sparse encoding is edited by
+//! decoding it whole, which is cheap at that size.
+
+use bytes::Bytes;
+
+/// bits of the hash picking a register
+const P: u32 = 14;
+/// bits of the hash counted for a register
+const Q: u32 = 50;
+pub const REGISTERS: usize = 1 << P;
+
+const HEADER_LEN: usize = 16;
+const DENSE_LEN: usize = HEADER_LEN + REGISTERS * 6 / 8;
+const MAGIC: &[u8] = b"HYLL";
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+const SPARSE_VAL_MAX: u8 = 32;
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+/// Why a string is not a HyperLogLog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Invalid {
+    /// not a HyperLogLog at all
+    NotHll,
+    /// the header is right but the registers are not
+    Corrupted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HyperLogLog {
+    bytes: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    /// Empty and sparse, with a cached cardinality of `0`
+    fn default() -> Self {
+        let mut bytes = header(SPARSE);
+        bytes.extend_from_slice(&xzero(REGISTERS));
+        HyperLogLog { bytes }
+    }
+}
+
+fn header(encoding: u8) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(DENSE_LEN);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&[encoding, 0, 0, 0]);
+    bytes.extend_from_slice(&[0; 8]);
+    bytes
+}
+
+fn xzero(len: usize) -> [u8; 2] {
+    let len = len - 1;
+    [0b0100_0000 | (len >> 8) as u8, len as u8]
+}
+
+impl HyperLogLog {
+    pub fn from_bytes(bs: &[u8]) -> Result<Self, Invalid> {
+        if bs.len() < HEADER_LEN || &bs[..4] != MAGIC {
+            return Err(Invalid::NotHll);
+        }
+        match bs[4] {
+            DENSE if bs.len() == DENSE_LEN => {}
+            DENSE => return Err(Invalid::NotHll),
+            SPARSE if sparse_decode(&bs[HEADER_LEN..]).is_some() => {}
+            SPARSE => return Err(Invalid::Corrupted),
+            _ => return Err(Invalid::NotHll),
+        }
+        Ok(HyperLogLog { bytes: bs.to_vec() })
+    }
+
+    /// A HyperLogLog with these registers, sparse if it fits in `sparse_max`
+    /// bytes
+    pub fn from_registers(registers: &[u8], sparse_max: usize) -> Self {
+        let mut hll = match sparse_encode(registers) {
+            Some(sparse) if HEADER_LEN + sparse.len() <= sparse_max => {
+                let mut bytes = header(SPARSE);
+                bytes.extend_from_slice(&sparse);
+                HyperLogLog { bytes }
+            }
+            _ => dense_from(registers),
+        };
+        hll.invalidate();
+        hll
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        Bytes::from(self.bytes)
+    }
+
+    pub fn is_sparse(&self) -> bool {
+        self.bytes[4] == SPARSE
+    }
+
+    pub fn registers(&self) -> Vec<u8> {
+        if self.is_sparse() {
+            // checked in `from_bytes`
+            sparse_decode(&self.bytes[HEADER_LEN..]).unwrap()
+        } else {
+            (0..REGISTERS).map(|i| dense_get(&self.bytes[HEADER_LEN..], i)).collect()
+        }
+    }
+
+    /// Raise `registers` to the registers of this HyperLogLog
+    pub fn merge_into(&self, registers: &mut [u8]) {
+        for (max, register) in registers.iter_mut().zip(self.registers()) {
+            *max = (*max).max(register);
+        }
+    }
+
+    /// Count `elements`, `true` if any register changed
+    pub fn add<'a>(&mut self, elements: impl IntoIterator<Item = &'a [u8]>, sparse_max: usize) -> bool {
+        let changed = if self.is_sparse() {
+            let mut registers = self.registers();
+            let mut changed = false;
+            for element in elements {
+                let (index, count) = hash(element);
+                if count > registers[index] {
+                    registers[index] = count;
+                    changed = true;
+                }
+            }
+            if changed {
+                // redis leaves the stale cardinality in the header
+                let card: [u8; 8] = self.bytes[8..HEADER_LEN].try_into().unwrap();
+                *self = HyperLogLog::from_registers(&registers, sparse_max);
+                self.bytes[8..HEADER_LEN].copy_from_slice(&card);
+            }
+            changed
+        } else {
+            let mut changed = false;
+            for element in elements {
+                let (index, count) = hash(element);
+                if count > dense_get(&self.bytes[HEADER_LEN..], index) {
+                    dense_set(&mut self.bytes[HEADER_LEN..], index, count);
+                    changed = true;
+                }
+            }
+            changed
+        };
+        if changed {
+            self.invalidate();
+        }
+        changed
+    }
+
+    /// The cardinality cached in the header, unless it is stale
+    pub fn cached_count(&self) -> Option<u64> {
+        (self.bytes[15] & 0x80 == 0).then(|| u64::from_le_bytes(self.bytes[8..HEADER_LEN].try_into().unwrap()))
+    }
+
+    /// The estimated cardinality, cached in the header
+    pub fn count(&mut self) -> u64 {
+        if let Some(count) = self.cached_count() {
+            return count;
+        }
+        let count = estimate(&self.registers());
+        self.bytes[8..HEADER_LEN].copy_from_slice(&count.to_le_bytes());
+        count
+    }
+
+    fn invalidate(&mut self) {
+        self.bytes[15] |= 0x80;
+    }
+}
+
+fn dense_from(registers: &[u8]) -> HyperLogLog {
+    let mut bytes = header(DENSE);
+    bytes.resize(DENSE_LEN, 0);
+    for (i, register) in registers.iter().enumerate() {
+        dense_set(&mut bytes[HEADER_LEN..], i, *register);
+    }
+    HyperLogLog { bytes }
+}
+
+fn dense_get(registers: &[u8], i: usize) -> u8 {
+    let bit = i * 6;
+    let (byte, shift) = (bit / 8, bit % 8);
+    let low = registers[byte] as u16 >> shift;
+    let high = registers.get(byte + 1).map_or(0, |b| (*b as u16) << (8 - shift));
+    ((low | high) & 0x3f) as u8
+}
+
+fn dense_set(registers: &mut [u8], i: usize, value: u8) {
+    let bit = i * 6;
+    let (byte, shift) = (bit / 8, bit % 8);
+    let value = value as u16;
+    registers[byte] &= !((0x3f_u16 << shift) as u8);
+    registers[byte] |= (value << shift) as u8;
+    if shift > 2 {
+        registers[byte + 1] &= !((0x3f_u16 >> (8 - shift)) as u8);
+        registers[byte + 1] |= (value >> (8 - shift)) as u8;
+    }
+}
+
+/// `None` if the opcodes do not add up to every register
+fn sparse_decode(bs: &[u8]) -> Option<Vec<u8>> {
+    let mut registers = Vec::with_capacity(REGISTERS);
+    let mut i = 0;
+    while i < bs.len() {
+        let op = bs[i];
+        let (value, len) = if op & 0x80 != 0 {
+            (((op >> 2) & 0x1f) + 1, (op & 0x03) as usize + 1)
+        } else if op & 0x40 != 0 {
+            i += 1;
+            (0, ((((op & 0x3f) as usize) << 8) | *bs.get(i)? as usize) + 1)
+        } else {
+            (0, (op & 0x3f) as usize + 1)
+        };
+        if registers.len() + len > REGISTERS {
+            return None;
+        }
+        registers.resize(registers.len() + len, value);
+        i += 1;
+    }
+    (registers.len() == REGISTERS).then_some(registers)
+}
+
+/// `None` if a register is too large for the sparse encoding
+fn sparse_encode(registers: &[u8]) -> Option<Vec<u8>> {
+    let mut bs = vec![];
+    let mut i = 0;
+    while i < registers.len() {
+        let value = registers[i];
+        if value > SPARSE_VAL_MAX {
+            return None;
+        }
+        let run = registers[i..].iter().take_while(|r| **r == value).count();
+        i += run;
+        let mut run = run;
+        while run > 0 {
+            let len = if value > 0 {
+                let len = run.min(4);
+                bs.push(0x80 | ((value - 1) << 2) | (len - 1) as u8);
+                len
+            } else if run > 64 {
+                bs.extend_from_slice(&xzero(run));
+                run
+            } else {
+                bs.push((run - 1) as u8);
+                run
+            };
+            run -= len;
+        }
+    }
+    Some(bs)
+}
+
+/// MurmurHash64A, the hash of redis' HyperLogLog
+fn murmur64a(data: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4_a793_5bd1_e995;
+    const R: u32 = 47;
+    let mut h = seed ^ (data.len() as u64).wrapping_mul(M);
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+/// The register of `element`, and the value it would raise it to: the
+/// position of the first set bit in the rest of the hash
+fn hash(element: &[u8]) -> (usize, u8) {
+    let hash = murmur64a(element, 0xadc8_3b19);
+    let index = (hash & (REGISTERS as u64 - 1)) as usize;
+    // a sentinel bit so that the count stops at `Q + 1`
+    let rest = (hash >> P) | (1 << Q);
+    (index, rest.trailing_zeros() as u8 + 1)
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let prev = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if prev == z {
+            return z / 3.0;
+        }
+    }
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let prev = z;
+        z += x * y;
+        y += y;
+        if prev == z {
+            return z;
+        }
+    }
+}
+
+/// The cardinality estimated from `registers`, with the estimator of
+/// Otmar Ertl that redis uses
+pub fn estimate(registers: &[u8]) -> u64 {
+    let m = REGISTERS as f64;
+    let mut histogram = [0u32; 64];
+    for register in registers {
+        histogram[*register as usize] += 1;
+    }
+    let mut z = m * tau((m - histogram[Q as usize + 1] as f64) / m);
+    for count in histogram[1..=Q as usize].iter().rev() {
+        z += *count as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+    (ALPHA_INF * m * m / z).round() as u64
+}
+
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[test]
+fn test_encodings() {
+    // the empty HyperLogLog of redis
+    let empty = HyperLogLog::default();
+    assert_eq!(&empty.bytes[..], b"HYLL\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x7f\xff");
+    assert_eq!(HyperLogLog::from_bytes(&empty.bytes), Ok(empty.clone()));
+    let truncated = b"HYLL\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+    assert_eq!(HyperLogLog::from_bytes(truncated), Err(Invalid::Corrupted));
+    assert_eq!(HyperLogLog::from_bytes(b"hello"), Err(Invalid::NotHll));
+
+    let mut registers = vec![0; REGISTERS];
+    registers[0] = 3;
+    registers[1] = 3;
+    registers[100] = 40;
+    registers[REGISTERS - 1] = 1;
+    assert_eq!(sparse_encode(&registers), None);
+    registers[100] = 32;
+    let sparse = sparse_encode(&registers).unwrap();
+    assert_eq!(sparse_decode(&sparse), Some(registers.clone()));
+    // two 3s, then 98 zeros
+    assert_eq!(sparse[..3], [0b1000_1001, 0b0100_0000, 97]);
+
+    let dense = dense_from(&registers);
+    assert_eq!(dense.registers(), registers);
+    assert_eq!(dense.bytes.len(), DENSE_LEN);
+}
+
+#[test]
+fn test_estimate() {
+    let mut hll = HyperLogLog::default();
+    assert_eq!(hll.count(), 0);
+    let elements: Vec<String> = (0..20000).map(|i| format!("element:{}", i)).collect();
+    assert!(hll.add(elements.iter().map(|e| e.as_bytes()), 3000));
+    assert!(!hll.is_sparse());
+    assert_eq!(hll.cached_count(), None);
+    let count = hll.count() as f64;
+    assert!((count - 20000.0).abs() / 20000.0 < 0.02, "{}", count);
+    assert!(!hll.add([&b"element:1"[..]], 3000));
+}
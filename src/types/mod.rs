@@ -0,0 +1,44 @@
+//! The data structures behind the collection values of `Database`
+
+pub mod geo;
+pub mod hash;
+pub mod hyperloglog;
+pub mod set;
+mod skiplist;
+pub mod stream;
+pub mod zset;
+
+pub use hash::Hash;
+pub use hyperloglog::HyperLogLog;
+pub use set::Set;
+pub use stream::Stream;
+pub use zset::ZSet;
+
+/// `bytes` as an integer, if it is the canonical representation of one,
+/// like `string2ll` of redis: "01", "+1" or " 1" are not integers
+pub(crate) fn as_int(bytes: &[u8]) -> Option<i64> {
+    let n: i64 = std::str::from_utf8(bytes).ok()?.parse().ok()?;
+    (n.to_string().as_bytes() == bytes).then_some(n)
+}
+
+/// When a small collection stops using its compact encoding, the same
+/// settings as `hash-max-listpack-entries` and `hash-max-listpack-value`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactLimits {
+    /// most elements a compact collection holds
+    pub max_entries: usize,
+    /// longest element, in bytes, a compact collection holds
+    pub max_value: usize,
+}
+
+impl CompactLimits {
+    fn allows(&self, len: usize, value: &[u8]) -> bool {
+        len <= self.max_entries && value.len() <= self.max_value
+    }
+}
+
+impl Default for CompactLimits {
+    fn default() -> Self {
+        CompactLimits { max_entries: 128, max_value: 64 }
+    }
+}
@@ -0,0 +1,163 @@
+//! The set value type
+//!
+//! A set of integers only is a sorted vector of `i64`, like the intset of
+//! redis: 8 bytes a member and a binary search to find one. Adding a member
+//! that is not an integer, or more than `max_intset` members, converts it
+//! to a `Dict` for good.
+
+use bytes::Bytes;
+use rand::{seq::index, Rng};
+
+use super::as_int;
+use crate::dict::Dict;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Set {
+    IntSet(Vec<i64>),
+    Table(Dict<Bytes, ()>),
+}
+
+impl Default for Set {
+    fn default() -> Self {
+        Set::IntSet(Vec::new())
+    }
+}
+
+fn int_bytes(n: i64) -> Bytes {
+    Bytes::from(n.to_string())
+}
+
+impl Set {
+    pub fn len(&self) -> usize {
+        match self {
+            Set::IntSet(ints) => ints.len(),
+            Set::Table(table) => table.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_intset(&self) -> bool {
+        matches!(self, Set::IntSet(_))
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match self {
+            Set::IntSet(ints) => as_int(member).is_some_and(|n| ints.binary_search(&n).is_ok()),
+            Set::Table(table) => table.contains_key(member),
+        }
+    }
+
+    /// Add `member`, returns `true` if it is new
+    pub fn insert(&mut self, member: Bytes, max_intset: usize) -> bool {
+        if let Set::IntSet(ints) = self {
+            match as_int(&member) {
+                Some(n) => match ints.binary_search(&n) {
+                    Ok(_) => return false,
+                    Err(i) if ints.len() < max_intset => {
+                        ints.insert(i, n);
+                        return true;
+                    }
+                    Err(_) => self.convert(),
+                },
+                None => self.convert(),
+            }
+        }
+        match self {
+            Set::Table(table) => table.insert(member, ()).is_none(),
+            Set::IntSet(_) => unreachable!(),
+        }
+    }
+
+    /// Remove `member`, returns `true` if it was there
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self {
+            Set::IntSet(ints) => match as_int(member).map(|n| ints.binary_search(&n)) {
+                Some(Ok(i)) => {
+                    ints.remove(i);
+                    true
+                }
+                _ => false,
+            },
+            Set::Table(table) => table.remove(member).is_some(),
+        }
+    }
+
+    /// The members, integers come out in order
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Bytes> + '_> {
+        match self {
+            Set::IntSet(ints) => Box::new(ints.iter().map(|&n| int_bytes(n))),
+            Set::Table(table) => Box::new(table.keys().cloned()),
+        }
+    }
+
+    /// A random member, `None` if the set is empty
+    pub fn random(&self) -> Option<Bytes> {
+        match self {
+            Set::IntSet(ints) if ints.is_empty() => None,
+            Set::IntSet(ints) => Some(int_bytes(ints[rand::rng().random_range(0..ints.len())])),
+            Set::Table(table) => table.random().map(|(member, _)| member.clone()),
+        }
+    }
+
+    /// Up to `count` distinct random members
+    pub fn random_distinct(&self, count: usize) -> Vec<Bytes> {
+        let members: Vec<Bytes> = self.iter().collect();
+        let n = count.min(members.len());
+        index::sample(&mut rand::rng(), members.len(), n)
+            .into_iter()
+            .map(|i| members[i].clone())
+            .collect()
+    }
+
+    /// See `Dict::scan`, an intset is returned whole in one call
+    pub fn scan(&self, cursor: u64, count: usize, mut f: impl FnMut(Bytes)) -> u64 {
+        match self {
+            Set::IntSet(ints) => {
+                ints.iter().for_each(|&n| f(int_bytes(n)));
+                0
+            }
+            Set::Table(table) => table.scan(cursor, count, |member, _| f(member.clone())),
+        }
+    }
+
+    fn convert(&mut self) {
+        if let Set::IntSet(ints) = self {
+            let mut table = Dict::new();
+            for &n in ints.iter() {
+                table.insert(int_bytes(n), ());
+            }
+            *self = Set::Table(table);
+        }
+    }
+}
+
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[test]
+fn test_intset_conversion() {
+    let mut set = Set::default();
+    assert!(set.insert(Bytes::from("3"), 3));
+    assert!(set.insert(Bytes::from("-1"), 3));
+    assert!(!set.insert(Bytes::from("3"), 3));
+    assert!(set.insert(Bytes::from("2"), 3));
+    assert!(set.is_intset());
+    assert_eq!(set.iter().collect::<Vec<_>>(), vec!["-1", "2", "3"]);
+    assert!(!set.contains(b"03"));
+
+    // past `max_intset`
+    assert!(set.insert(Bytes::from("4"), 3));
+    assert!(!set.is_intset());
+    assert!(set.contains(b"4") && set.contains(b"-1"));
+
+    let mut set = Set::default();
+    set.insert(Bytes::from("1"), 512);
+    assert!(set.insert(Bytes::from("01"), 512));
+    assert!(!set.is_intset());
+    assert_eq!(set.len(), 2);
+    assert!(set.remove(b"01"));
+    assert!(!set.remove(b"01"));
+}
@@ -0,0 +1,269 @@
+//! The ordered half of a sorted set
+//!
+//! The skiplist of redis: members are ordered by score then by bytes, and
+//! each link counts the nodes it skips, so the rank of a member and the
+//! member at a rank are both found in O(log n). Nodes live in a vector and
+//! link to each other by index, the first slot being the head.
+
+use bytes::Bytes;
+use rand::Rng;
+
+const MAX_LEVEL: usize = 32;
+const HEAD: usize = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Link {
+    next: Option<usize>,
+    // the number of nodes this link moves forward by
+    span: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Node {
+    member: Bytes,
+    score: f64,
+    backward: Option<usize>,
+    levels: Vec<Link>,
+}
+
+impl Node {
+    /// Whether this node comes before `(score, member)`
+    fn is_before(&self, score: f64, member: &[u8]) -> bool {
+        self.score < score || (self.score == score && &self.member[..] < member)
+    }
+
+    fn is(&self, score: f64, member: &[u8]) -> bool {
+        self.score == score && &self.member[..] == member
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SkipList {
+    nodes: Vec<Node>,
+    // slots of deleted nodes, to reuse
+    free: Vec<usize>,
+    level: usize,
+    len: usize,
+}
+
+impl Default for SkipList {
+    fn default() -> Self {
+        let head = Node {
+            member: Bytes::new(),
+            score: 0.0,
+            backward: None,
+            levels: vec![Link { next: None, span: 0 }; MAX_LEVEL],
+        };
+        SkipList { nodes: vec![head], free: vec![], level: 1, len: 0 }
+    }
+}
+
+/// A level for a new node, each level up is 4 times less likely
+fn random_level() -> usize {
+    let mut rng = rand::rng();
+    let mut level = 1;
+    while level < MAX_LEVEL && rng.random_ratio(1, 4) {
+        level += 1;
+    }
+    level
+}
+
+impl SkipList {
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Add `member`, which must not be in the list already
+    pub(crate) fn insert(&mut self, score: f64, member: Bytes) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i == self.level - 1 { 0 } else { rank[i + 1] };
+            while let Some(next) = self.nodes[x].levels[i].next {
+                if !self.nodes[next].is_before(score, &member) {
+                    break;
+                }
+                rank[i] += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+
+        let level = random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let node = Node { member, score, backward: None, levels: vec![Link { next: None, span: 0 }; level] };
+        let new = match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = node;
+                slot
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        for i in 0..level {
+            let prev = self.nodes[update[i]].levels[i];
+            self.nodes[new].levels[i] = Link { next: prev.next, span: prev.span - (rank[0] - rank[i]) };
+            self.nodes[update[i]].levels[i] = Link { next: Some(new), span: rank[0] - rank[i] + 1 };
+        }
+        for (i, &prev) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[prev].levels[i].span += 1;
+        }
+
+        self.nodes[new].backward = (update[0] != HEAD).then_some(update[0]);
+        if let Some(next) = self.nodes[new].levels[0].next {
+            self.nodes[next].backward = Some(new);
+        }
+        self.len += 1;
+    }
+
+    /// Remove `member` with `score`, `false` if it is not there
+    pub(crate) fn remove(&mut self, score: f64, member: &[u8]) -> bool {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].next {
+                if !self.nodes[next].is_before(score, member) {
+                    break;
+                }
+                x = next;
+            }
+            update[i] = x;
+        }
+        let x = match self.nodes[x].levels[0].next {
+            Some(x) if self.nodes[x].is(score, member) => x,
+            _ => return false,
+        };
+
+        for (i, &prev) in update.iter().enumerate().take(self.level) {
+            let link = self.nodes[prev].levels[i];
+            self.nodes[prev].levels[i] = if link.next == Some(x) {
+                let removed = self.nodes[x].levels[i];
+                Link { next: removed.next, span: link.span + removed.span - 1 }
+            } else {
+                Link { span: link.span - 1, ..link }
+            };
+        }
+        if let Some(next) = self.nodes[x].levels[0].next {
+            self.nodes[next].backward = self.nodes[x].backward;
+        }
+        while self.level > 1 && self.nodes[HEAD].levels[self.level - 1].next.is_none() {
+            self.level -= 1;
+        }
+        self.nodes[x].member = Bytes::new();
+        self.nodes[x].levels = vec![];
+        self.free.push(x);
+        self.len -= 1;
+        true
+    }
+
+    /// The last node for which `advance` holds, as the head and a rank of
+    /// `0` if there is none, the rank counting from `1`. `advance` must
+    /// hold for a prefix of the list.
+    fn seek(&self, advance: impl Fn(f64, &[u8]) -> bool) -> (usize, usize) {
+        let mut rank = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].next {
+                if !advance(self.nodes[next].score, &self.nodes[next].member) {
+                    break;
+                }
+                rank += self.nodes[x].levels[i].span;
+                x = next;
+            }
+        }
+        (x, rank)
+    }
+
+    /// The rank of `member` with `score`, from `0`
+    pub(crate) fn rank(&self, score: f64, member: &[u8]) -> Option<usize> {
+        let (x, rank) = self.seek(|s, m| s < score || (s == score && m <= member));
+        (x != HEAD && self.nodes[x].is(score, member)).then(|| rank - 1)
+    }
+
+    /// The rank of the first member for which `below` does not hold, `len`
+    /// if there is none
+    pub(crate) fn first_rank(&self, below: impl Fn(f64, &[u8]) -> bool) -> usize {
+        self.seek(below).1
+    }
+
+    /// The node at `rank`, from `0`
+    fn node_at(&self, rank: usize) -> Option<usize> {
+        if rank >= self.len {
+            return None;
+        }
+        let target = rank + 1;
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].next {
+                if traversed + self.nodes[x].levels[i].span > target {
+                    break;
+                }
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if traversed == target {
+                return Some(x);
+            }
+        }
+        None
+    }
+
+    /// The members from `rank` on, towards the tail or the head if `rev`
+    pub(crate) fn iter_from(&self, rank: usize, rev: bool) -> impl Iterator<Item = (&Bytes, f64)> + '_ {
+        let mut x = self.node_at(rank);
+        std::iter::from_fn(move || {
+            let node = &self.nodes[x?];
+            x = if rev { node.backward } else { node.levels[0].next };
+            Some((&node.member, node.score))
+        })
+    }
+}
+
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[test]
+fn test_ranks_survive_inserts_and_removes() {
+    let mut list = SkipList::default();
+    let mut expected: Vec<(f64, Bytes)> = vec![];
+    for i in 0..500u64 {
+        // scores repeat, so the order of members matters too
+        let score = (i * 7919 % 101) as f64;
+        let member = Bytes::from(format!("m{}", i));
+        list.insert(score, member.clone());
+        expected.push((score, member));
+    }
+    for i in (0..500u64).step_by(3) {
+        let score = (i * 7919 % 101) as f64;
+        assert!(list.remove(score, format!("m{}", i).as_bytes()));
+    }
+    assert!(!list.remove(1000.0, b"m0"));
+    expected.retain(|(_, member)| {
+        let i: u64 = std::str::from_utf8(&member[1..]).unwrap().parse().unwrap();
+        !i.is_multiple_of(3)
+    });
+    expected.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+
+    assert_eq!(list.len(), expected.len());
+    for (rank, (score, member)) in expected.iter().enumerate() {
+        assert_eq!(list.rank(*score, member), Some(rank));
+        assert_eq!(list.iter_from(rank, false).next(), Some((member, *score)));
+    }
+    let backwards: Vec<_> = list.iter_from(expected.len() - 1, true).map(|(m, _)| m.clone()).collect();
+    let mut forwards: Vec<_> = expected.iter().map(|(_, m)| m.clone()).collect();
+    forwards.reverse();
+    assert_eq!(backwards, forwards);
+    assert_eq!(list.first_rank(|score, _| score < 50.0), expected.iter().filter(|(s, _)| *s < 50.0).count());
+}
@@ -0,0 +1,464 @@
+//! The stream value type
+//!
+//! Entries are kept ordered by ID in a B-tree, which stands in for the
+//! radix tree of redis: IDs share long prefixes and are always appended at
+//! the end, and both structures give ordered range scans. A stream is not
+//! removed when its last entry is, it keeps its last ID and its groups.
+//!
+//! Each consumer group keeps the entries delivered but not acknowledged
+//! yet, its pending entries list (PEL). The PEL of the group holds the
+//! details of every such entry, and each consumer has the IDs of its own
+//! ones, kept in step by the methods of `Group`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use bytes::Bytes;
+
+/// An entry ID, milliseconds and a sequence number for entries added in
+/// the same millisecond
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId { ms: u64::MAX, seq: u64::MAX };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        StreamId { ms, seq }
+    }
+
+    /// `ms-seq`, or `ms` alone with `default_seq` as the sequence number
+    pub fn parse(bs: &[u8], default_seq: u64) -> Option<StreamId> {
+        let s = std::str::from_utf8(bs).ok()?;
+        match s.split_once('-') {
+            Some((ms, seq)) => Some(StreamId { ms: ms.parse().ok()?, seq: seq.parse().ok()? }),
+            None => Some(StreamId { ms: s.parse().ok()?, seq: default_seq }),
+        }
+    }
+
+    /// The smallest ID after this one
+    pub fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId { seq, ..self }),
+            None => Some(StreamId { ms: self.ms.checked_add(1)?, seq: 0 }),
+        }
+    }
+
+    /// The largest ID before this one
+    pub fn prev(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId { seq, ..self }),
+            None => Some(StreamId { ms: self.ms.checked_sub(1)?, seq: u64::MAX }),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// The field/value pairs of an entry
+pub type Fields = Vec<(Bytes, Bytes)>;
+
+/// What `XTRIM` and `XADD` evict
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trim {
+    /// the oldest entries past this many
+    MaxLen(u64),
+    /// the entries with a smaller ID
+    MinId(StreamId),
+}
+
+/// A delivered entry waiting for an `XACK`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pending {
+    pub consumer: Bytes,
+    /// unix time in milliseconds of the last delivery
+    pub delivered_at: u64,
+    pub deliveries: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Consumer {
+    pending: BTreeSet<StreamId>,
+    /// unix time in milliseconds the consumer was last seen
+    pub seen_at: u64,
+}
+
+impl Consumer {
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Group {
+    /// the last entry handed to a consumer, `>` reads after it
+    pub last_delivered: StreamId,
+    pending: BTreeMap<StreamId, Pending>,
+    consumers: BTreeMap<Bytes, Consumer>,
+}
+
+impl Group {
+    pub fn new(last_delivered: StreamId) -> Self {
+        Group { last_delivered, ..Group::default() }
+    }
+
+    /// Returns `false` if there is such a consumer already
+    pub fn create_consumer(&mut self, name: &Bytes, now: u64) -> bool {
+        if self.consumers.contains_key(name) {
+            return false;
+        }
+        self.consumer(name, now);
+        true
+    }
+
+    /// The consumer `name`, created if it is new, seen at `now`
+    fn consumer(&mut self, name: &Bytes, now: u64) -> &mut Consumer {
+        let consumer = self.consumers.entry(name.clone()).or_default();
+        consumer.seen_at = now;
+        consumer
+    }
+
+    /// Remove a consumer and its pending entries, the number of which is
+    /// returned, `None` if there is no such consumer
+    pub fn delete_consumer(&mut self, name: &[u8]) -> Option<usize> {
+        let consumer = self.consumers.remove(name)?;
+        for id in consumer.pending.iter() {
+            self.pending.remove(id);
+        }
+        Some(consumer.pending.len())
+    }
+
+    pub fn consumers(&self) -> impl Iterator<Item = (&Bytes, &Consumer)> {
+        self.consumers.iter()
+    }
+
+    /// The pending entries from `start` to `end`
+    pub fn pending(&self, start: StreamId, end: StreamId) -> impl Iterator<Item = (&StreamId, &Pending)> {
+        let range = if start <= end { Some(self.pending.range(start..=end)) } else { None };
+        range.into_iter().flatten()
+    }
+
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Hand `id` to `consumer`, from whichever consumer had it before
+    fn deliver(&mut self, id: StreamId, consumer: &Bytes, delivered_at: u64, deliveries: u64) {
+        let previous = self.pending.insert(id, Pending { consumer: consumer.clone(), delivered_at, deliveries });
+        if let Some(previous) = previous {
+            if let Some(owner) = self.consumers.get_mut(&previous.consumer) {
+                owner.pending.remove(&id);
+            }
+        }
+        self.consumers.entry(consumer.clone()).or_default().pending.insert(id);
+    }
+
+    /// Acknowledge `id`, `false` if it was not pending
+    pub fn ack(&mut self, id: StreamId) -> bool {
+        match self.pending.remove(&id) {
+            Some(pending) => {
+                if let Some(consumer) = self.consumers.get_mut(&pending.consumer) {
+                    consumer.pending.remove(&id);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// How `XCLAIM` and `XAUTOCLAIM` take over pending entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Claim {
+    /// skip entries delivered less than this many milliseconds ago
+    pub min_idle: u64,
+    /// the new delivery time of a claimed entry
+    pub delivered_at: u64,
+    /// set the delivery count instead of adding one
+    pub deliveries: Option<u64>,
+    /// count the claim as a delivery, not with `JUSTID`
+    pub bump: bool,
+    /// claim an entry of the stream even if it is not pending
+    pub force: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Fields>,
+    last_id: StreamId,
+    groups: BTreeMap<Bytes, Group>,
+}
+
+impl Stream {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The largest ID ever added, even if that entry is gone
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// The ID for an entry added at `now`, `None` once every ID is taken
+    pub fn next_id(&self, now: u64) -> Option<StreamId> {
+        if now > self.last_id.ms {
+            Some(StreamId::new(now, 0))
+        } else {
+            self.last_id.next()
+        }
+    }
+
+    /// Add an entry, `id` must be larger than `last_id`
+    pub fn insert(&mut self, id: StreamId, fields: Fields) {
+        debug_assert!(id > self.last_id || (self.last_id == StreamId::MIN && self.entries.is_empty()));
+        self.last_id = id;
+        self.entries.insert(id, fields);
+    }
+
+    pub fn get(&self, id: StreamId) -> Option<&Fields> {
+        self.entries.get(&id)
+    }
+
+    pub fn remove(&mut self, id: StreamId) -> bool {
+        self.entries.remove(&id).is_some()
+    }
+
+    /// The entries from `start` to `end`, both included
+    pub fn range(&self, start: StreamId, end: StreamId) -> impl DoubleEndedIterator<Item = (&StreamId, &Fields)> {
+        let range = if start <= end { Some(self.entries.range(start..=end)) } else { None };
+        range.into_iter().flatten()
+    }
+
+    /// Evict the oldest entries, at most `limit` of them, returns how many
+    pub fn trim(&mut self, trim: Trim, limit: Option<usize>) -> usize {
+        let mut evicted = 0;
+        while limit.is_none_or(|limit| evicted < limit) {
+            let evict = match (trim, self.entries.first_key_value()) {
+                (Trim::MaxLen(max), Some(_)) => self.entries.len() as u64 > max,
+                (Trim::MinId(min), Some((id, _))) => *id < min,
+                (_, None) => false,
+            };
+            if !evict {
+                break;
+            }
+            self.entries.pop_first();
+            evicted += 1;
+        }
+        evicted
+    }
+
+    pub fn group(&self, name: &[u8]) -> Option<&Group> {
+        self.groups.get(name)
+    }
+
+    pub fn group_mut(&mut self, name: &[u8]) -> Option<&mut Group> {
+        self.groups.get_mut(name)
+    }
+
+    /// Returns `false` if there is such a group already
+    pub fn create_group(&mut self, name: Bytes, last_delivered: StreamId) -> bool {
+        if self.groups.contains_key(&name) {
+            return false;
+        }
+        self.groups.insert(name, Group::new(last_delivered));
+        true
+    }
+
+    pub fn destroy_group(&mut self, name: &[u8]) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = (&Bytes, &Group)> {
+        self.groups.iter()
+    }
+
+    /// Deliver up to `count` entries never delivered to `group` to
+    /// `consumer`, adding them to the PELs unless `noack`. `None` if there
+    /// is no such group.
+    pub fn read_group(
+        &mut self,
+        group: &[u8],
+        consumer: &Bytes,
+        count: usize,
+        noack: bool,
+        now: u64,
+    ) -> Option<Vec<(StreamId, Fields)>> {
+        let group = self.groups.get_mut(group)?;
+        group.consumer(consumer, now);
+        let start = match group.last_delivered.next() {
+            Some(start) => start,
+            None => return Some(vec![]),
+        };
+        let entries: Vec<(StreamId, Fields)> =
+            self.entries.range(start..).take(count).map(|(id, fields)| (*id, fields.clone())).collect();
+        for (id, _) in entries.iter() {
+            group.last_delivered = *id;
+            if !noack {
+                group.deliver(*id, consumer, now, 1);
+            }
+        }
+        Some(entries)
+    }
+
+    /// The entries pending for `consumer` after `after`, `None` for the
+    /// fields of an entry deleted since. `None` if there is no such group.
+    pub fn read_history(
+        &mut self,
+        group: &[u8],
+        consumer: &Bytes,
+        after: StreamId,
+        count: usize,
+        now: u64,
+    ) -> Option<Vec<(StreamId, Option<Fields>)>> {
+        let group = self.groups.get_mut(group)?;
+        let consumer = group.consumer(consumer, now);
+        let start = match after.next() {
+            Some(start) => start,
+            None => return Some(vec![]),
+        };
+        let ids: Vec<StreamId> = consumer.pending.range(start..).take(count).copied().collect();
+        let entries = ids.into_iter().map(|id| (id, self.entries.get(&id).cloned()));
+        Some(entries.collect())
+    }
+
+    /// Take over the pending `ids` for `consumer` if `claim` allows it,
+    /// returns the claimed ones. IDs whose entry was deleted are dropped
+    /// from the PEL. `None` if there is no such group.
+    pub fn claim(
+        &mut self,
+        group: &[u8],
+        consumer: &Bytes,
+        ids: &[StreamId],
+        claim: &Claim,
+        now: u64,
+    ) -> Option<Vec<StreamId>> {
+        let group = self.groups.get_mut(group)?;
+        group.consumer(consumer, now);
+        let claimed = ids.iter().filter(|&&id| claim_one(group, &self.entries, id, consumer, claim, now) == Claimed::Yes);
+        Some(claimed.copied().collect())
+    }
+
+    /// Scan the PEL of `group` from `start` and claim up to `count` entries
+    /// idle for long enough. Returns the ID to continue from, `0-0` once
+    /// the whole PEL is scanned, the claimed IDs and the ones dropped from
+    /// the PEL because their entry was deleted. `None` if there is no such
+    /// group.
+    pub fn auto_claim(
+        &mut self,
+        group: &[u8],
+        consumer: &Bytes,
+        start: StreamId,
+        count: usize,
+        claim: &Claim,
+        now: u64,
+    ) -> Option<(StreamId, Vec<StreamId>, Vec<StreamId>)> {
+        let group = self.groups.get_mut(group)?;
+        group.consumer(consumer, now);
+        // a bounded amount of work, like a SCAN
+        let scanned: Vec<StreamId> = group.pending.range(start..).map(|(id, _)| *id).take(count * 10 + 1).collect();
+        let mut claimed = vec![];
+        let mut deleted = vec![];
+        let mut next = StreamId::MIN;
+        for (i, id) in scanned.iter().enumerate() {
+            if claimed.len() == count || i == count * 10 {
+                next = *id;
+                break;
+            }
+            match claim_one(group, &self.entries, *id, consumer, claim, now) {
+                Claimed::Yes => claimed.push(*id),
+                Claimed::Deleted => deleted.push(*id),
+                Claimed::No => {}
+            }
+        }
+        Some((next, claimed, deleted))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Claimed {
+    Yes,
+    No,
+    Deleted,
+}
+
+fn claim_one(
+    group: &mut Group,
+    entries: &BTreeMap<StreamId, Fields>,
+    id: StreamId,
+    consumer: &Bytes,
+    claim: &Claim,
+    now: u64,
+) -> Claimed {
+    let exists = entries.contains_key(&id);
+    let (delivered_at, deliveries) = match group.pending.get(&id) {
+        Some(pending) => (pending.delivered_at, pending.deliveries),
+        // as if it had just been delivered
+        None if claim.force && exists => (now, 0),
+        None => return Claimed::No,
+    };
+    if !exists {
+        group.ack(id);
+        return Claimed::Deleted;
+    }
+    if now.saturating_sub(delivered_at) < claim.min_idle {
+        return Claimed::No;
+    }
+    let deliveries = match claim.deliveries {
+        Some(deliveries) => deliveries,
+        None if claim.bump => deliveries + 1,
+        None => deliveries,
+    };
+    group.deliver(id, consumer, claim.delivered_at, deliveries);
+    Claimed::Yes
+}
+
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[test]
+fn test_pending_entries_follow_their_consumer() {
+    let mut stream = Stream::default();
+    for ms in 1..=3 {
+        stream.insert(StreamId::new(ms, 0), vec![]);
+    }
+    stream.create_group(Bytes::from("g"), StreamId::MIN);
+    let (alice, bob) = (Bytes::from("alice"), Bytes::from("bob"));
+
+    let read = stream.read_group(b"g", &alice, 2, false, 100).unwrap();
+    assert_eq!(read.len(), 2);
+    assert_eq!(stream.read_group(b"g", &bob, 10, false, 100).unwrap().len(), 1);
+
+    let claim = Claim { min_idle: 50, delivered_at: 200, deliveries: None, bump: true, force: false };
+    let ids = [StreamId::new(1, 0), StreamId::new(2, 0)];
+    assert!(stream.claim(b"g", &bob, &ids, &claim, 120).unwrap().is_empty());
+    assert_eq!(stream.claim(b"g", &bob, &ids[..1], &claim, 200).unwrap(), vec![ids[0]]);
+
+    let group = stream.group(b"g").unwrap();
+    let owners: Vec<_> = group.pending(StreamId::MIN, StreamId::MAX).map(|(_, p)| (p.consumer.clone(), p.deliveries)).collect();
+    assert_eq!(owners, vec![(bob.clone(), 2), (alice.clone(), 1), (bob.clone(), 1)]);
+    let counts: Vec<_> = group.consumers().map(|(name, c)| (name.clone(), c.pending_len())).collect();
+    assert_eq!(counts, vec![(alice.clone(), 1), (bob.clone(), 2)]);
+
+    // a deleted entry leaves the PEL when it is claimed
+    stream.remove(StreamId::new(2, 0));
+    let (next, claimed, deleted) = stream.auto_claim(b"g", &alice, StreamId::MIN, 10, &claim, 300).unwrap();
+    assert_eq!(next, StreamId::MIN);
+    assert_eq!(claimed, vec![StreamId::new(1, 0), StreamId::new(3, 0)]);
+    assert_eq!(deleted, vec![StreamId::new(2, 0)]);
+    assert_eq!(stream.group_mut(b"g").unwrap().delete_consumer(b"alice"), Some(2));
+    assert_eq!(stream.group(b"g").unwrap().pending_len(), 0);
+}
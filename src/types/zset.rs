@@ -0,0 +1,196 @@
+//! The sorted set value type
+//!
+//! A hash table from member to score for O(1) lookups, and a skiplist
+//! ordered by score for ranks and ranges, see `types::skiplist`. Both hold
+//! every member.
+
+use std::ops::Range;
+
+use bytes::Bytes;
+
+use super::skiplist::SkipList;
+use crate::dict::Dict;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ZSet {
+    scores: Dict<Bytes, f64>,
+    list: SkipList,
+}
+
+/// An interval of scores, either end may be excluded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreRange {
+    pub min: f64,
+    pub min_exclusive: bool,
+    pub max: f64,
+    pub max_exclusive: bool,
+}
+
+impl ScoreRange {
+    pub fn new(min: f64, max: f64) -> Self {
+        ScoreRange { min, min_exclusive: false, max, max_exclusive: false }
+    }
+
+    fn below_min(&self, score: f64) -> bool {
+        if self.min_exclusive {
+            score <= self.min
+        } else {
+            score < self.min
+        }
+    }
+
+    fn within_max(&self, score: f64) -> bool {
+        if self.max_exclusive {
+            score < self.max
+        } else {
+            score <= self.max
+        }
+    }
+}
+
+/// One end of an interval of members, compared byte by byte
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexBound {
+    /// `-`, before any member
+    Min,
+    /// `+`, after any member
+    Max,
+    Inclusive(Bytes),
+    Exclusive(Bytes),
+}
+
+/// An interval of members, meant for a sorted set where every score is the
+/// same, so members are in byte order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexRange {
+    pub min: LexBound,
+    pub max: LexBound,
+}
+
+impl LexRange {
+    fn below_min(&self, member: &[u8]) -> bool {
+        match &self.min {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(min) => member < &min[..],
+            LexBound::Exclusive(min) => member <= &min[..],
+        }
+    }
+
+    fn within_max(&self, member: &[u8]) -> bool {
+        match &self.max {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(max) => member <= &max[..],
+            LexBound::Exclusive(max) => member < &max[..],
+        }
+    }
+}
+
+impl ZSet {
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Add `member` or move it to `score`, returns `true` if it is new
+    pub fn insert(&mut self, member: Bytes, score: f64) -> bool {
+        match self.scores.insert(member.clone(), score) {
+            Some(old) if old == score => false,
+            Some(old) => {
+                self.list.remove(old, &member);
+                self.list.insert(score, member);
+                false
+            }
+            None => {
+                self.list.insert(score, member);
+                true
+            }
+        }
+    }
+
+    /// Remove `member`, returns `true` if it was there
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => self.list.remove(score, member),
+            None => false,
+        }
+    }
+
+    /// The rank of `member` from `0`, from the highest score if `rev`
+    pub fn rank(&self, member: &[u8], rev: bool) -> Option<usize> {
+        let rank = self.list.rank(self.score(member)?, member)?;
+        Some(if rev { self.len() - 1 - rank } else { rank })
+    }
+
+    /// The members from `rank` on, by ascending score, or from the highest
+    /// score down if `rev`, in which case `rank` counts from the end too
+    pub fn iter_from(&self, rank: usize, rev: bool) -> impl Iterator<Item = (&Bytes, f64)> + '_ {
+        let start = if rev { self.len().checked_sub(rank + 1) } else { Some(rank) };
+        // past the end the iterator is empty either way
+        self.list.iter_from(start.unwrap_or(self.len()), rev)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, f64)> + '_ {
+        self.iter_from(0, false)
+    }
+
+    /// The ranks of the members with a score in `range`
+    pub fn score_ranks(&self, range: &ScoreRange) -> Range<usize> {
+        let start = self.list.first_rank(|score, _| range.below_min(score));
+        let end = self.list.first_rank(|score, _| range.within_max(score));
+        start..end.max(start)
+    }
+
+    /// The ranks of the members in `range`
+    pub fn lex_ranks(&self, range: &LexRange) -> Range<usize> {
+        let start = self.list.first_rank(|_, member| range.below_min(member));
+        let end = self.list.first_rank(|_, member| range.within_max(member));
+        start..end.max(start)
+    }
+
+    /// See `Dict::scan`
+    pub fn scan(&self, cursor: u64, count: usize, mut f: impl FnMut(&Bytes, f64)) -> u64 {
+        self.scores.scan(cursor, count, |member, &score| f(member, score))
+    }
+}
+
+//////////////////////////////
+/// Unit Test
+//////////////////////////////
+#[test]
+fn test_ranges() {
+    let mut zset = ZSet::default();
+    for (member, score) in [("a", 1.0), ("b", 2.0), ("c", 2.0), ("d", 3.0)] {
+        assert!(zset.insert(Bytes::from(member), score));
+    }
+    assert!(!zset.insert(Bytes::from("a"), 2.5));
+    assert_eq!(zset.rank(b"a", false), Some(2));
+    assert_eq!(zset.rank(b"a", true), Some(1));
+
+    let mut range = ScoreRange::new(2.0, 3.0);
+    assert_eq!(zset.score_ranks(&range), 0..4);
+    range.min_exclusive = true;
+    range.max_exclusive = true;
+    assert_eq!(zset.score_ranks(&range), 2..3);
+    assert_eq!(zset.score_ranks(&ScoreRange::new(5.0, 1.0)), 4..4);
+
+    let first: Vec<_> = zset.iter_from(0, true).map(|(member, _)| member.clone()).collect();
+    assert_eq!(first, vec!["d", "a", "c", "b"]);
+
+    let mut lex = ZSet::default();
+    for member in ["a", "b", "c", "d"] {
+        lex.insert(Bytes::from(member), 0.0);
+    }
+    let range = LexRange { min: LexBound::Exclusive(Bytes::from("a")), max: LexBound::Inclusive(Bytes::from("c")) };
+    assert_eq!(lex.lex_ranks(&range), 1..3);
+    let range = LexRange { min: LexBound::Min, max: LexBound::Max };
+    assert_eq!(lex.lex_ranks(&range), 0..4);
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use bytes::Bytes;
+use common::{command, connect, new_runtime, request, start_server};
+use miniredis::{
+    cmd::{self, BitType, Bitwise, Overflow},
+    frame::Frame,
+};
+
+fn b(s: &[u8]) -> Bytes {
+    Bytes::copy_from_slice(s)
+}
+
+#[test]
+fn test_setbit_getbit() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        assert_eq!(request(&mut conn, cmd::SetBit::new("k", 7, true).into_frame()).await, Frame::Integer(0));
+        assert_eq!(request(&mut conn, cmd::SetBit::new("k", 7, false).into_frame()).await, Frame::Integer(1));
+        // the string grows with zero bytes
+        request(&mut conn, cmd::SetBit::new("k", 17, true).into_frame()).await;
+        assert_eq!(request(&mut conn, cmd::Get::new("k").into_frame()).await, Frame::Bulk(b(&[0, 0, 0x40])));
+        assert_eq!(request(&mut conn, cmd::GetBit::new("k", 17).into_frame()).await, Frame::Integer(1));
+        assert_eq!(request(&mut conn, cmd::GetBit::new("k", 1000).into_frame()).await, Frame::Integer(0));
+
+        let reply = request(&mut conn, command(&["setbit", "k", "1", "2"])).await;
+        assert_eq!(reply, Frame::Error("ERR bit is not an integer or out of range".to_string()));
+        let reply = request(&mut conn, command(&["setbit", "k", "4294967296", "1"])).await;
+        assert_eq!(reply, Frame::Error("ERR bit offset is not an integer or out of range".to_string()));
+    });
+}
+
+#[test]
+fn test_bitcount_bitpos() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        request(&mut conn, cmd::Set::new("k", b(b"foobar")).into_frame()).await;
+        assert_eq!(request(&mut conn, cmd::BitCount::new("k").into_frame()).await, Frame::Integer(26));
+        assert_eq!(request(&mut conn, cmd::BitCount::new("k").range(1, 1).into_frame()).await, Frame::Integer(6));
+        let bitcount = cmd::BitCount::new("k").bit_range(5, 30);
+        assert_eq!(request(&mut conn, bitcount.into_frame()).await, Frame::Integer(17));
+        let reply = request(&mut conn, command(&["bitcount", "k", "0"])).await;
+        assert_eq!(reply, Frame::Error("ERR syntax error".to_string()));
+
+        request(&mut conn, cmd::Set::new("p", b(&[0xff, 0xf0, 0x00])).into_frame()).await;
+        assert_eq!(request(&mut conn, cmd::BitPos::new("p", false).into_frame()).await, Frame::Integer(12));
+        assert_eq!(request(&mut conn, cmd::BitPos::new("p", true).start(2).into_frame()).await, Frame::Integer(-1));
+        let bitpos = cmd::BitPos::new("p", true).start(7).end(15).bits();
+        assert_eq!(request(&mut conn, bitpos.into_frame()).await, Frame::Integer(7));
+
+        // past the end of a string of ones, unless an end is given
+        request(&mut conn, cmd::Set::new("ones", b(&[0xff])).into_frame()).await;
+        assert_eq!(request(&mut conn, cmd::BitPos::new("ones", false).into_frame()).await, Frame::Integer(8));
+        let bitpos = cmd::BitPos::new("ones", false).start(0).end(-1);
+        assert_eq!(request(&mut conn, bitpos.into_frame()).await, Frame::Integer(-1));
+        assert_eq!(request(&mut conn, cmd::BitPos::new("none", false).into_frame()).await, Frame::Integer(0));
+    });
+}
+
+#[test]
+fn test_bitop() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        request(&mut conn, cmd::Set::new("a", b(&[0b1100, 0xff])).into_frame()).await;
+        request(&mut conn, cmd::Set::new("b", b(&[0b1010])).into_frame()).await;
+        let cases = [
+            // a missing key is all zeros
+            (Bitwise::And, vec![0, 0]),
+            (Bitwise::Or, vec![0b1110, 0xff]),
+            (Bitwise::Xor, vec![0b0110, 0xff]),
+        ];
+        for (op, expected) in cases {
+            let bitop = cmd::BitOp::new(op, "dest", &["a", "b", "missing"]);
+            assert_eq!(request(&mut conn, bitop.into_frame()).await, Frame::Integer(2));
+            assert_eq!(request(&mut conn, cmd::Get::new("dest").into_frame()).await, Frame::Bulk(b(&expected)));
+        }
+        let bitop = cmd::BitOp::new(Bitwise::Not, "dest", &["b"]);
+        assert_eq!(request(&mut conn, bitop.into_frame()).await, Frame::Integer(1));
+        assert_eq!(request(&mut conn, cmd::Get::new("dest").into_frame()).await, Frame::Bulk(b(&[0b1111_0101])));
+
+        let bitop = cmd::BitOp::new(Bitwise::Or, "dest", &["missing"]);
+        assert_eq!(request(&mut conn, bitop.into_frame()).await, Frame::Integer(0));
+        assert_eq!(request(&mut conn, cmd::Exists::new(&["dest"]).into_frame()).await, Frame::Integer(0));
+        let reply = request(&mut conn, command(&["bitop", "not", "dest", "a", "b"])).await;
+        assert_eq!(reply, Frame::Error("ERR BITOP NOT must be called with a single source key.".to_string()));
+    });
+}
+
+#[test]
+fn test_bitfield() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+        let u8 = BitType::unsigned(8).unwrap();
+        let i5 = BitType::signed(5).unwrap();
+
+        let bitfield = cmd::BitField::new("k").set(u8, 0, 255).get(u8, 0).incr_by(u8, 0, 10).get(i5, 3);
+        let reply = request(&mut conn, bitfield.into_frame()).await;
+        let expected = [0, 255, 9, 9].map(Frame::Integer);
+        assert_eq!(reply, Frame::Array(expected.to_vec()));
+
+        let bitfield = cmd::BitField::new("k")
+            .overflow(Overflow::Sat)
+            .incr_by(u8, 0, 1000)
+            .overflow(Overflow::Fail)
+            .incr_by(u8, 0, 1)
+            .incr_by(i5, 8, -17);
+        let reply = request(&mut conn, bitfield.into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![Frame::Integer(255), Frame::Null, Frame::Null]));
+        // the string still grew to fit the failed field
+        assert_eq!(request(&mut conn, cmd::Get::new("k").into_frame()).await, Frame::Bulk(b(&[0xff, 0])));
+
+        // `#` offsets count fields
+        let reply = request(&mut conn, command(&["bitfield", "k", "set", "u8", "#1", "7", "get", "u16", "0"])).await;
+        assert_eq!(reply, Frame::Array(vec![Frame::Integer(0), Frame::Integer(0xff07)]));
+
+        let reply = request(&mut conn, command(&["bitfield_ro", "k", "get", "u4", "12"])).await;
+        assert_eq!(reply, Frame::Array(vec![Frame::Integer(7)]));
+        let reply = request(&mut conn, command(&["bitfield_ro", "k", "set", "u4", "0", "1"])).await;
+        assert_eq!(reply, Frame::Error("ERR BITFIELD_RO only supports the GET subcommand".to_string()));
+        let reply = request(&mut conn, command(&["bitfield", "k", "get", "u64", "0"])).await;
+        let err = "ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.";
+        assert_eq!(reply, Frame::Error(err.to_string()));
+        let bitfield = cmd::BitField::read_only("missing").get(i5, 100);
+        assert_eq!(request(&mut conn, bitfield.into_frame()).await, Frame::Array(vec![Frame::Integer(0)]));
+        assert_eq!(request(&mut conn, cmd::Exists::new(&["missing"]).into_frame()).await, Frame::Integer(0));
+    });
+}
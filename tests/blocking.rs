@@ -0,0 +1,142 @@
+mod common;
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use common::{command, connect, new_runtime, request, start_server};
+use miniredis::{
+    cmd::{self, End},
+    connection::Connection,
+    frame::Frame,
+    server::Server,
+};
+use tokio::{net::TcpListener, sync::oneshot, time::timeout};
+
+fn elements(items: &[&str]) -> Vec<Bytes> {
+    items.iter().map(|item| Bytes::from(item.to_string())).collect()
+}
+
+fn popped(key: &str, element: &str) -> Frame {
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from(key.to_string())),
+        Frame::Bulk(Bytes::from(element.to_string())),
+    ])
+}
+
+/// Send a blocking command without waiting for the reply, and give the
+/// server time to park the client
+async fn send(conn: &mut Connection, frame: Frame) {
+    conn.write_frame(frame).await.unwrap();
+    conn.flush().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+}
+
+async fn next_reply(conn: &mut Connection) -> Frame {
+    timeout(Duration::from_secs(5), conn.read_frame()).await.unwrap().unwrap()
+}
+
+#[test]
+fn test_blpop_ready_and_timeout() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        request(&mut conn, cmd::RPush::new("b", elements(&["1", "2"])).into_frame()).await;
+        let frame = cmd::BLPop::new(&["a", "b"], Some(Duration::from_secs(1))).into_frame();
+        assert_eq!(request(&mut conn, frame).await, popped("b", "1"));
+        let frame = cmd::BRPop::new(&["a", "b"], None).into_frame();
+        assert_eq!(request(&mut conn, frame).await, popped("b", "2"));
+
+        // a null array once the timeout is over
+        let frame = cmd::BLPop::new(&["a", "b"], Some(Duration::from_millis(100))).into_frame();
+        assert_eq!(request(&mut conn, frame).await, Frame::NullArray);
+        let frame = cmd::BLMove::new("a", "c", End::Left, End::Left, Some(Duration::from_millis(100))).into_frame();
+        assert_eq!(request(&mut conn, frame).await, Frame::Null);
+
+        let reply = request(&mut conn, command(&["blpop", "a", "-1"])).await;
+        assert_eq!(reply, Frame::Error("ERR timeout is negative".to_string()));
+        let reply = request(&mut conn, command(&["blpop", "a", "soon"])).await;
+        assert_eq!(reply, Frame::Error("ERR timeout is not a float or out of range".to_string()));
+        let reply = request(&mut conn, command(&["blpop", "a", "1e300"])).await;
+        assert_eq!(reply, Frame::Error("ERR timeout is out of range".to_string()));
+    });
+}
+
+#[test]
+fn test_pipelined_replies_before_blocking() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+        conn.write_frame(cmd::Set::new("x", Bytes::from("1")).into_frame()).await.unwrap();
+        conn.write_frame(cmd::BLPop::new(&["bq"], Some(Duration::from_secs(2))).into_frame()).await.unwrap();
+        conn.flush().await.unwrap();
+        let reply = timeout(Duration::from_millis(500), conn.read_frame()).await;
+        assert_eq!(reply.unwrap().unwrap(), "OK");
+    });
+}
+
+#[test]
+fn test_wakeups_are_fifo() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut waiters = vec![];
+        for _ in 0..3 {
+            let mut conn = connect(addr).await;
+            send(&mut conn, cmd::BLPop::new(&["queue"], None).into_frame()).await;
+            waiters.push(conn);
+        }
+
+        let mut producer = connect(addr).await;
+        let reply = request(&mut producer, cmd::RPush::new("queue", elements(&["1", "2"])).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(2));
+        assert_eq!(next_reply(&mut waiters[0]).await, popped("queue", "1"));
+        assert_eq!(next_reply(&mut waiters[1]).await, popped("queue", "2"));
+        // the elements went to the waiters, not to the list
+        let reply = request(&mut producer, cmd::LLen::new("queue").into_frame()).await;
+        assert_eq!(reply, Frame::Integer(0));
+
+        request(&mut producer, cmd::LPush::new("queue", elements(&["3"])).into_frame()).await;
+        assert_eq!(next_reply(&mut waiters[2]).await, popped("queue", "3"));
+    });
+}
+
+#[test]
+fn test_blmove_and_blmpop_chain() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+
+        let mut consumer = connect(addr).await;
+        send(&mut consumer, cmd::BLMPop::new(&["x", "done"], End::Right, 5, None).into_frame()).await;
+        let mut mover = connect(addr).await;
+        send(&mut mover, cmd::BLMove::new("todo", "done", End::Left, End::Left, None).into_frame()).await;
+
+        let mut producer = connect(addr).await;
+        request(&mut producer, cmd::RPush::new("todo", elements(&["job"])).into_frame()).await;
+
+        assert_eq!(next_reply(&mut mover).await, Frame::Bulk(Bytes::from("job")));
+        // the element moved to `done` woke up the second client
+        let expected = Frame::Array(vec![
+            Frame::Bulk(Bytes::from("done")),
+            Frame::Array(vec![Frame::Bulk(Bytes::from("job"))]),
+        ]);
+        assert_eq!(next_reply(&mut consumer).await, expected);
+        let reply = request(&mut producer, cmd::Exists::new(&["todo", "done"]).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(0));
+    });
+}
+
+#[test]
+fn test_shutdown_interrupts_blocked_clients() {
+    new_runtime().block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = oneshot::channel::<()>();
+        tokio::spawn(async move { Server::new().run(listener, rx).await.unwrap() });
+
+        let mut conn = connect(addr).await;
+        send(&mut conn, cmd::BLPop::new(&["queue"], None).into_frame()).await;
+        tx.send(()).unwrap();
+
+        // the connection is closed instead of waiting forever
+        let res = timeout(Duration::from_secs(5), conn.read_frame()).await.unwrap();
+        assert!(res.is_err());
+    });
+}
@@ -1,5 +1,6 @@
 use bytes::Bytes;
 use miniredis::{
+    client::Client,
     cmd,
     connection::{Connection},
     frame::{Frame}, database::Database,
@@ -12,11 +13,11 @@ use tokio::{
 #[test]
 fn test_set_cmd() {
     new_runtime().block_on(async {
-        const server_addr: &str = "127.0.0.1:6381";
-        start_server(server_addr).await;
+        const SERVER_ADDR: &str = "127.0.0.1:6381";
+        start_server(SERVER_ADDR).await;
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
 
-        let stream = TcpStream::connect(server_addr).await.unwrap();
+        let stream = TcpStream::connect(SERVER_ADDR).await.unwrap();
         let mut conn = Connection::new(stream).unwrap();
 
         let cmd = cmd::Set::new("name", Bytes::from("simon"));
@@ -44,11 +45,11 @@ fn test_set_cmd() {
 #[test]
 fn test_get_cmd() {
     new_runtime().block_on(async {
-        const server_addr: &str = "127.0.0.1:6380";
-        start_server(server_addr).await;
+        const SERVER_ADDR: &str = "127.0.0.1:6380";
+        start_server(SERVER_ADDR).await;
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
 
-        let stream = TcpStream::connect(server_addr).await.unwrap();
+        let stream = TcpStream::connect(SERVER_ADDR).await.unwrap();
         let mut conn = Connection::new(stream).unwrap();
         const LOOPS: usize = 2;
 
@@ -64,6 +65,34 @@ fn test_get_cmd() {
         }
     });
 }
+#[test]
+fn test_client_get_as() {
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    enum Plan {
+        Free,
+        Pro,
+    }
+
+    new_runtime().block_on(async {
+        const SERVER_ADDR: &str = "127.0.0.1:6382";
+        start_server(SERVER_ADDR).await;
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        let mut client = Client::connect(SERVER_ADDR).await.unwrap();
+        assert_eq!(client.get_as::<u32>("visits").await.unwrap(), None);
+
+        client.set_as("visits", &42u32).await.unwrap();
+        assert_eq!(client.get_as::<u32>("visits").await.unwrap(), Some(42));
+        assert_eq!(client.get("visits").await.unwrap(), Some(Bytes::from("42")));
+
+        for plan in [Plan::Free, Plan::Pro] {
+            client.set_as("plan", &plan).await.unwrap();
+            assert_eq!(client.get_as::<Plan>("plan").await.unwrap(), Some(plan));
+        }
+        assert!(client.get_as::<bool>("plan").await.is_err());
+    });
+}
+
 #[test]
 fn test_get_cmd_external_server() {
     new_runtime().block_on(async {
@@ -96,8 +125,9 @@ async fn start_server(addr: &'static str) {
             println!("receive {:?}", req);
 
             match req {
-                cmd::Request::Get(cmd) => cmd.apply(&db, &mut conn).await.unwrap(),
-                cmd::Request::Set(cmd) => cmd.apply(&mut db, &mut conn).await.unwrap()
+                cmd::Request::Get(cmd) => cmd.apply(&mut db, &mut conn).await.unwrap(),
+                cmd::Request::Set(cmd) => cmd.apply(&mut db, &mut conn).await.unwrap(),
+                req => req.apply(&mut db, &mut conn).await.unwrap(),
             };
         }
     });
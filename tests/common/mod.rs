@@ -0,0 +1,53 @@
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+
+use bytes::Bytes;
+use miniredis::{connection::Connection, database::Config, frame::Frame, server::Server};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    runtime,
+};
+
+/// Start a server on a free port, it runs until the runtime is dropped
+pub async fn start_server() -> SocketAddr {
+    start_server_with_config(Config::default()).await
+}
+
+pub async fn start_server_with_config(config: Config) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        Server::with_config(config)
+            .run(listener, std::future::pending::<()>())
+            .await
+            .unwrap();
+    });
+    addr
+}
+
+pub async fn connect(addr: SocketAddr) -> Connection {
+    Connection::new(TcpStream::connect(addr).await.unwrap()).unwrap()
+}
+
+/// Send `frame` and wait for the reply
+pub async fn request(conn: &mut Connection, frame: Frame) -> Frame {
+    conn.write_frame(frame).await.unwrap();
+    conn.read_frame().await.unwrap()
+}
+
+/// A raw command, for requests without a builder or with invalid arguments
+pub fn command(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+            .collect(),
+    )
+}
+
+pub fn new_runtime() -> runtime::Runtime {
+    runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
@@ -0,0 +1,217 @@
+mod common;
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use common::{command, connect, new_runtime, request, start_server};
+use miniredis::{
+    cmd::{self, RestorePolicy},
+    frame::Frame,
+};
+use tokio::time::timeout;
+
+fn b(s: &str) -> Bytes {
+    Bytes::from(s.to_string())
+}
+
+fn err(message: &str) -> Frame {
+    Frame::Error(message.to_string())
+}
+
+const ACCOUNTS: &str = "#!lua name=accounts
+local function withdraw(keys, args)
+    local balance = tonumber(redis.call('GET', keys[1]) or '0')
+    if balance < tonumber(args[1]) then
+        return redis.error_reply('ERR insufficient funds')
+    end
+    return redis.call('DECRBY', keys[1], args[1])
+end
+redis.register_function('withdraw', withdraw)
+redis.register_function{
+    function_name = 'balance',
+    callback = function(keys) return redis.call('GET', keys[1]) end,
+    flags = { 'no-writes' },
+}
+";
+
+#[test]
+fn test_function_load_and_fcall() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+        let reply = request(&mut conn, cmd::Function::load(ACCOUNTS).into_frame()).await;
+        assert_eq!(reply, Frame::Bulk(b("accounts")));
+        let reply = request(&mut conn, cmd::Function::load(ACCOUNTS).into_frame()).await;
+        assert_eq!(reply, err("ERR Library 'accounts' already exists"));
+        let reply = request(&mut conn, cmd::Function::load(ACCOUNTS).replace().into_frame()).await;
+        assert_eq!(reply, Frame::Bulk(b("accounts")));
+
+        request(&mut conn, cmd::Set::new("alice", b("100")).into_frame()).await;
+        let reply = request(&mut conn, cmd::FCall::new("withdraw", &["alice"], vec![b("30")]).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(70));
+        let reply = request(&mut conn, cmd::FCall::new("withdraw", &["alice"], vec![b("80")]).into_frame()).await;
+        assert_eq!(reply, err("ERR insufficient funds"));
+        let reply = request(&mut conn, cmd::FCall::read_only("balance", &["alice"], vec![]).into_frame()).await;
+        assert_eq!(reply, Frame::Bulk(b("70")));
+
+        // FCALL_RO only runs functions that do not write
+        let reply = request(&mut conn, cmd::FCall::read_only("withdraw", &["alice"], vec![b("1")]).into_frame()).await;
+        assert_eq!(reply, err("ERR Can not execute a script with write flag using *_ro command."));
+        let reply = request(&mut conn, cmd::FCall::new("missing", &[], vec![]).into_frame()).await;
+        assert_eq!(reply, err("ERR Function not found"));
+
+        // scripts flushed, functions compiled again
+        request(&mut conn, cmd::Script::Flush.into_frame()).await;
+        let reply = request(&mut conn, cmd::FCall::new("withdraw", &["alice"], vec![b("10")]).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(60));
+    });
+}
+
+#[test]
+fn test_function_load_errors() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+        let cases = [
+            ("return 1", "ERR Missing library metadata"),
+            ("#!js name=lib\n", "ERR Engine 'js' not found"),
+            ("#!lua\n", "ERR Library name was not given"),
+            ("#!lua name=lib\nlocal x = 1", "ERR No functions registered"),
+            (
+                "#!lua name=lib\nredis.register_function{function_name='f', callback=function() end, flags={'fast'}}",
+                "ERR Unknown flag given: fast",
+            ),
+        ];
+        for (code, error) in cases {
+            assert_eq!(request(&mut conn, cmd::Function::load(code).into_frame()).await, err(error), "{code}");
+        }
+        let reply = request(&mut conn, cmd::Function::load("#!lua name=lib\nreturn 1 +").into_frame()).await;
+        let Frame::Error(message) = reply else { panic!("{reply:?}") };
+        assert!(message.starts_with("ERR Error compiling function"), "{message}");
+
+        request(&mut conn, cmd::Function::load(ACCOUNTS).into_frame()).await;
+        let code = "#!lua name=other\nredis.register_function('balance', function() return 1 end)";
+        let reply = request(&mut conn, cmd::Function::load(code).into_frame()).await;
+        assert_eq!(reply, err("ERR Function balance already exists"));
+
+        // a read-only function may not write
+        let code = "#!lua name=sneaky\nredis.register_function{function_name='set', \
+                    callback=function(keys) return redis.call('SET', keys[1], 'x') end, flags={'no-writes'}}";
+        request(&mut conn, cmd::Function::load(code).into_frame()).await;
+        let reply = request(&mut conn, cmd::FCall::read_only("set", &["k"], vec![]).into_frame()).await;
+        assert_eq!(reply, err("ERR Write commands are not allowed from read-only scripts."));
+        let reply = request(&mut conn, command(&["eval", "redis.register_function('f', function() end)", "0"])).await;
+        let Frame::Error(message) = reply else { panic!("{reply:?}") };
+        assert!(message.contains("can only be called on FUNCTION LOAD command"), "{message}");
+    });
+}
+
+#[test]
+fn test_function_list_and_delete() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+        request(&mut conn, cmd::Function::load(ACCOUNTS).into_frame()).await;
+        let code = "#!lua name=misc\nredis.register_function('ping', function() return 'pong' end)";
+        request(&mut conn, cmd::Function::load(code).into_frame()).await;
+
+        let reply = request(&mut conn, cmd::Function::list().library_name("mis*").with_code().into_frame()).await;
+        let function = Frame::Array(vec![
+            Frame::Bulk(b("name")),
+            Frame::Bulk(b("ping")),
+            Frame::Bulk(b("description")),
+            Frame::Null,
+            Frame::Bulk(b("flags")),
+            Frame::Array(vec![]),
+        ]);
+        let library = Frame::Array(vec![
+            Frame::Bulk(b("library_name")),
+            Frame::Bulk(b("misc")),
+            Frame::Bulk(b("engine")),
+            Frame::Bulk(b("LUA")),
+            Frame::Bulk(b("functions")),
+            Frame::Array(vec![function]),
+            Frame::Bulk(b("library_code")),
+            Frame::Bulk(b(code)),
+        ]);
+        assert_eq!(reply, Frame::Array(vec![library]));
+        let Frame::Array(libraries) = request(&mut conn, cmd::Function::list().into_frame()).await else { panic!() };
+        assert_eq!(libraries.len(), 2);
+
+        assert_eq!(request(&mut conn, cmd::Function::delete("misc").into_frame()).await, "OK");
+        let reply = request(&mut conn, cmd::Function::delete("misc").into_frame()).await;
+        assert_eq!(reply, err("ERR Library not found"));
+        let reply = request(&mut conn, cmd::FCall::new("ping", &[], vec![]).into_frame()).await;
+        assert_eq!(reply, err("ERR Function not found"));
+        assert_eq!(request(&mut conn, cmd::Function::Flush.into_frame()).await, "OK");
+        assert_eq!(request(&mut conn, cmd::Function::list().into_frame()).await, Frame::Array(vec![]));
+    });
+}
+
+#[test]
+fn test_function_dump_restore() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+        let mut copy = connect(start_server().await).await;
+        request(&mut conn, cmd::Function::load(ACCOUNTS).into_frame()).await;
+        let Frame::Bulk(payload) = request(&mut conn, cmd::Function::Dump.into_frame()).await else { panic!() };
+
+        let restore = cmd::Function::restore(payload.clone(), RestorePolicy::Append);
+        assert_eq!(request(&mut copy, restore.into_frame()).await, "OK");
+        request(&mut copy, cmd::Set::new("bob", b("5")).into_frame()).await;
+        let reply = request(&mut copy, cmd::FCall::read_only("balance", &["bob"], vec![]).into_frame()).await;
+        assert_eq!(reply, Frame::Bulk(b("5")));
+
+        let restore = cmd::Function::restore(payload.clone(), RestorePolicy::Append);
+        assert_eq!(request(&mut copy, restore.into_frame()).await, err("ERR Library 'accounts' already exists"));
+        let restore = cmd::Function::restore(payload.clone(), RestorePolicy::Replace);
+        assert_eq!(request(&mut copy, restore.into_frame()).await, "OK");
+
+        // FLUSH drops the libraries missing from the payload
+        let code = "#!lua name=misc\nredis.register_function('ping', function() return 'pong' end)";
+        request(&mut copy, cmd::Function::load(code).into_frame()).await;
+        let restore = cmd::Function::restore(payload.clone(), RestorePolicy::Flush);
+        assert_eq!(request(&mut copy, restore.into_frame()).await, "OK");
+        let Frame::Array(libraries) = request(&mut copy, cmd::Function::list().into_frame()).await else { panic!() };
+        assert_eq!(libraries.len(), 1);
+
+        let mut corrupt = payload.to_vec();
+        corrupt[5] ^= 1;
+        let restore = cmd::Function::restore(Bytes::from(corrupt), RestorePolicy::Flush);
+        assert_eq!(request(&mut copy, restore.into_frame()).await, err("ERR payload version or checksum are wrong"));
+        let reply = request(&mut copy, command(&["function", "restore", "x", "merge"])).await;
+        let policy_err = "ERR Wrong restore policy given, value should be either FLUSH, APPEND or REPLACE.";
+        assert_eq!(reply, err(policy_err));
+    });
+}
+
+#[test]
+fn test_library_code_is_killable() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut conn = connect(addr).await;
+        let mut other = connect(addr).await;
+        request(&mut conn, cmd::Config::set("lua-time-limit", 50).into_frame()).await;
+
+        // runs forever when compiled again, at the next FCALL
+        let code = "#!lua name=slow\nif loaded then while true do end end loaded = true\n\
+                    redis.register_function('f', function() return 1 end)";
+        assert_eq!(request(&mut conn, cmd::Function::load(code).into_frame()).await, Frame::Bulk(b("slow")));
+        conn.write_frame(cmd::FCall::new("f", &[], vec![]).into_frame()).await.unwrap();
+        conn.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let reply = request(&mut other, cmd::Get::new("foo").into_frame()).await;
+        assert_eq!(reply, err(miniredis::script::BUSY));
+        assert_eq!(request(&mut other, cmd::Script::Kill.into_frame()).await, "OK");
+        assert_eq!(conn.read_frame().await.unwrap(), err("ERR Script killed by user with SCRIPT KILL..."));
+        assert_eq!(request(&mut other, cmd::Get::new("foo").into_frame()).await, Frame::Null);
+    });
+}
+
+#[test]
+fn test_library_load_times_out() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+        let code = "#!lua name=endless\nwhile true do pcall(function() end) end";
+        let reply = timeout(Duration::from_secs(2), request(&mut conn, cmd::Function::load(code).into_frame())).await;
+        assert_eq!(reply.unwrap(), err("ERR FUNCTION LOAD timeout"));
+        assert_eq!(request(&mut conn, cmd::Function::list().into_frame()).await, Frame::Array(vec![]));
+    });
+}
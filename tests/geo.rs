@@ -0,0 +1,172 @@
+mod common;
+
+use bytes::Bytes;
+use common::{command, connect, new_runtime, request, start_server};
+use miniredis::{
+    cmd::{self, GeoBy, GeoFrom, Order, Unit},
+    connection::Connection,
+    frame::Frame,
+};
+
+fn b(s: &str) -> Bytes {
+    Bytes::from(s.to_string())
+}
+
+fn bulks(items: &[&str]) -> Frame {
+    Frame::Array(items.iter().map(|item| Frame::Bulk(b(item))).collect())
+}
+
+/// The example of the redis documentation
+async fn sicily(conn: &mut Connection) {
+    let geoadd = cmd::GeoAdd::new("Sicily")
+        .place(13.361389, 38.115556, b("Palermo"))
+        .place(15.087269, 37.502669, b("Catania"));
+    assert_eq!(request(conn, geoadd.into_frame()).await, Frame::Integer(2));
+}
+
+#[test]
+fn test_geoadd_dist_pos_hash() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+        sicily(&mut conn).await;
+
+        // places are sorted set members scored by their geohash
+        let reply = request(&mut conn, cmd::ZScore::new("Sicily", b("Palermo")).into_frame()).await;
+        assert_eq!(reply, "3479099956230698");
+
+        let geodist = cmd::GeoDist::new("Sicily", b("Palermo"), b("Catania"), Unit::M);
+        assert_eq!(request(&mut conn, geodist.into_frame()).await, "166274.1516");
+        let geodist = cmd::GeoDist::new("Sicily", b("Palermo"), b("Catania"), Unit::Km);
+        assert_eq!(request(&mut conn, geodist.into_frame()).await, "166.2742");
+        let geodist = cmd::GeoDist::new("Sicily", b("Palermo"), b("Rome"), Unit::Km);
+        assert_eq!(request(&mut conn, geodist.into_frame()).await, Frame::Null);
+
+        let reply = request(&mut conn, cmd::GeoHash::new("Sicily", vec![b("Palermo"), b("Rome")]).into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![Frame::Bulk(b("sqc8b49rny0")), Frame::Null]));
+
+        let reply = request(&mut conn, cmd::GeoPos::new("Sicily", vec![b("Catania")]).into_frame()).await;
+        let Frame::Array(positions) = reply else { panic!() };
+        let Frame::Array(coords) = &positions[0] else { panic!() };
+        let coord = |frame: &Frame| match frame {
+            Frame::Bulk(bs) => std::str::from_utf8(bs).unwrap().parse::<f64>().unwrap(),
+            _ => panic!(),
+        };
+        assert!((coord(&coords[0]) - 15.087269).abs() < 1e-5);
+        assert!((coord(&coords[1]) - 37.502669).abs() < 1e-5);
+
+        let reply = request(&mut conn, command(&["geoadd", "Sicily", "200", "10", "Nowhere"])).await;
+        assert_eq!(reply, Frame::Error("ERR invalid longitude,latitude pair 200.000000,10.000000".to_string()));
+        let reply = request(&mut conn, command(&["geoadd", "Sicily", "10", "10"])).await;
+        assert_eq!(reply, Frame::Error("ERR syntax error".to_string()));
+        let geoadd = cmd::GeoAdd::new("Sicily").xx().ch().place(13.0, 38.0, b("Palermo")).place(1.0, 1.0, b("Rome"));
+        assert_eq!(request(&mut conn, geoadd.into_frame()).await, Frame::Integer(1));
+    });
+}
+
+#[test]
+fn test_geosearch() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+        sicily(&mut conn).await;
+        let geoadd = cmd::GeoAdd::new("Sicily")
+            .place(12.758489, 38.788135, b("edge1"))
+            .place(17.241510, 38.788135, b("edge2"));
+        request(&mut conn, geoadd.into_frame()).await;
+
+        let from = GeoFrom::LonLat(15.0, 37.0);
+        let geosearch = cmd::GeoSearch::new("Sicily", from.clone(), GeoBy::Radius(200.0, Unit::Km)).order(Order::Asc);
+        assert_eq!(request(&mut conn, geosearch.into_frame()).await, bulks(&["Catania", "Palermo"]));
+
+        let geosearch = cmd::GeoSearch::new("Sicily", from.clone(), GeoBy::Box(400.0, 400.0, Unit::Km))
+            .order(Order::Desc)
+            .with_dist()
+            .with_coord()
+            .with_hash();
+        let Frame::Array(places) = request(&mut conn, geosearch.into_frame()).await else { panic!() };
+        let names: Vec<_> = places
+            .iter()
+            .map(|place| match place {
+                Frame::Array(fields) => fields[0].clone(),
+                _ => panic!(),
+            })
+            .collect();
+        assert_eq!(Frame::Array(names), bulks(&["edge1", "edge2", "Palermo", "Catania"]));
+        let Frame::Array(catania) = &places[3] else { panic!() };
+        assert_eq!(catania[1], "56.4413");
+        assert_eq!(catania[2], Frame::Integer(3479447370796909));
+        assert!(matches!(&catania[3], Frame::Array(coords) if coords.len() == 2));
+
+        // COUNT alone gives the closest ones
+        let geosearch = cmd::GeoSearch::new("Sicily", from.clone(), GeoBy::Box(400.0, 400.0, Unit::Km)).count(1, false);
+        assert_eq!(request(&mut conn, geosearch.into_frame()).await, bulks(&["Catania"]));
+        let geosearch = cmd::GeoSearch::new("Sicily", from, GeoBy::Box(400.0, 400.0, Unit::Km)).count(2, true);
+        let Frame::Array(places) = request(&mut conn, geosearch.into_frame()).await else { panic!() };
+        assert_eq!(places.len(), 2);
+
+        let reply = request(
+            &mut conn,
+            command(&["geosearch", "Sicily", "frommember", "Palermo", "byradius", "100", "km", "withdist"]),
+        )
+        .await;
+        let palermo = Frame::Array(vec![Frame::Bulk(b("Palermo")), Frame::Bulk(b("0.0000"))]);
+        let edge1 = Frame::Array(vec![Frame::Bulk(b("edge1")), Frame::Bulk(b("91.4007"))]);
+        let Frame::Array(places) = reply else { panic!() };
+        assert_eq!(places.len(), 2);
+        assert!(places.contains(&palermo) && places.contains(&edge1));
+
+        let errors = [
+            (
+                vec!["geosearch", "Sicily", "frommember", "Rome", "byradius", "1", "km"],
+                "ERR could not decode requested zset member",
+            ),
+            (
+                vec!["geosearch", "Sicily", "byradius", "1", "km"],
+                "ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for geosearch",
+            ),
+            (
+                vec!["geosearch", "Sicily", "fromlonlat", "15", "37", "byradius", "1", "km", "bybox", "1", "1", "km"],
+                "ERR exactly one of BYRADIUS and BYBOX can be specified for geosearch",
+            ),
+            (
+                vec!["geosearch", "Sicily", "fromlonlat", "15", "37", "byradius", "1", "km", "any"],
+                "ERR the ANY argument requires COUNT argument",
+            ),
+            (
+                vec!["geosearch", "Sicily", "fromlonlat", "15", "37", "byradius", "1", "yd"],
+                "ERR unsupported unit provided. please use M, KM, FT, MI",
+            ),
+        ];
+        for (args, err) in errors {
+            assert_eq!(request(&mut conn, command(&args)).await, Frame::Error(err.to_string()));
+        }
+    });
+}
+
+#[test]
+fn test_geosearchstore() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+        sicily(&mut conn).await;
+
+        let search = cmd::GeoSearch::new("Sicily", GeoFrom::LonLat(15.0, 37.0), GeoBy::Radius(200.0, Unit::Km));
+        let store = cmd::GeoSearchStore::new("near", search);
+        assert_eq!(request(&mut conn, store.into_frame()).await, Frame::Integer(2));
+        let reply = request(&mut conn, cmd::GeoHash::new("near", vec![b("Palermo")]).into_frame()).await;
+        assert_eq!(reply, bulks(&["sqc8b49rny0"]));
+
+        let search = cmd::GeoSearch::new("Sicily", GeoFrom::Member(b("Catania")), GeoBy::Radius(1.0, Unit::Km));
+        let store = cmd::GeoSearchStore::new("near", search).store_dist();
+        assert_eq!(request(&mut conn, store.into_frame()).await, Frame::Integer(1));
+        assert_eq!(request(&mut conn, cmd::ZScore::new("near", b("Catania")).into_frame()).await, "0");
+
+        let reply = request(
+            &mut conn,
+            command(&["geosearchstore", "near", "Sicily", "frommember", "Catania", "byradius", "1", "km", "withdist"]),
+        )
+        .await;
+        assert_eq!(reply, Frame::Error("ERR syntax error".to_string()));
+        let search = cmd::GeoSearch::new("missing", GeoFrom::LonLat(0.0, 0.0), GeoBy::Radius(1.0, Unit::Km));
+        assert_eq!(request(&mut conn, cmd::GeoSearchStore::new("near", search).into_frame()).await, Frame::Integer(0));
+        assert_eq!(request(&mut conn, cmd::Exists::new(&["near"]).into_frame()).await, Frame::Integer(0));
+    });
+}
@@ -0,0 +1,183 @@
+mod common;
+
+use bytes::Bytes;
+use common::{command, connect, new_runtime, request, start_server, start_server_with_config};
+use miniredis::{
+    client::Client,
+    cmd,
+    database::Config,
+    frame::Frame,
+    types::CompactLimits,
+};
+use serde::{Deserialize, Serialize};
+
+fn b(s: &str) -> Bytes {
+    Bytes::from(s.to_string())
+}
+
+fn bulks(items: &[&str]) -> Frame {
+    Frame::Array(items.iter().map(|item| Frame::Bulk(b(item))).collect())
+}
+
+/// sort a flat field/value reply by field, hashes have no order
+fn sorted_pairs(frame: Frame) -> Vec<(Frame, Frame)> {
+    let frames = match frame {
+        Frame::Array(frames) => frames,
+        frame => panic!("unexpected reply {:?}", frame),
+    };
+    let mut pairs: Vec<_> = frames.chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect();
+    pairs.sort_by_key(|(field, _)| format!("{:?}", field));
+    pairs
+}
+
+#[test]
+fn test_set_get_del() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        let hset = cmd::HSet::new("h").field(b("name"), b("simon")).field(b("age"), b("30"));
+        assert_eq!(request(&mut conn, hset.into_frame()).await, Frame::Integer(2));
+        let hset = cmd::HSet::new("h").field(b("age"), b("31")).field(b("city"), b("paris"));
+        assert_eq!(request(&mut conn, hset.into_frame()).await, Frame::Integer(1));
+
+        assert_eq!(request(&mut conn, cmd::HGet::new("h", b("age")).into_frame()).await, "31");
+        assert_eq!(request(&mut conn, cmd::HGet::new("h", b("nope")).into_frame()).await, Frame::Null);
+        let reply = request(&mut conn, cmd::HMGet::new("h", vec![b("name"), b("nope")]).into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![Frame::Bulk(b("simon")), Frame::Null]));
+        assert_eq!(request(&mut conn, cmd::HLen::new("h").into_frame()).await, Frame::Integer(3));
+        assert_eq!(request(&mut conn, cmd::HExists::new("h", b("city")).into_frame()).await, Frame::Integer(1));
+
+        let reply = request(&mut conn, cmd::HGetAll::new("h").into_frame()).await;
+        assert_eq!(
+            sorted_pairs(reply),
+            vec![
+                (Frame::Bulk(b("age")), Frame::Bulk(b("31"))),
+                (Frame::Bulk(b("city")), Frame::Bulk(b("paris"))),
+                (Frame::Bulk(b("name")), Frame::Bulk(b("simon"))),
+            ]
+        );
+        assert_eq!(request(&mut conn, cmd::HKeys::new("h").into_frame()).await, bulks(&["name", "age", "city"]));
+        assert_eq!(request(&mut conn, cmd::HVals::new("h").into_frame()).await, bulks(&["simon", "31", "paris"]));
+
+        let reply = request(&mut conn, cmd::HSetNx::new("h", b("name"), b("x")).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(0));
+        let reply = request(&mut conn, cmd::HSetNx::new("h", b("zip"), b("75")).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(1));
+
+        let reply = request(&mut conn, cmd::HDel::new("h", vec![b("name"), b("age"), b("nope")]).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(2));
+        request(&mut conn, cmd::HDel::new("h", vec![b("city"), b("zip")]).into_frame()).await;
+        assert_eq!(request(&mut conn, cmd::Exists::new(&["h"]).into_frame()).await, Frame::Integer(0));
+
+        let reply = request(&mut conn, command(&["hset", "h", "field"])).await;
+        assert_eq!(reply, Frame::Error("ERR wrong number of arguments".to_string()));
+        request(&mut conn, cmd::Set::new("s", b("v")).into_frame()).await;
+        let reply = request(&mut conn, cmd::HGet::new("s", b("f")).into_frame()).await;
+        assert!(matches!(reply, Frame::Error(msg) if msg.starts_with("WRONGTYPE")));
+    });
+}
+
+#[test]
+fn test_incr() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        let reply = request(&mut conn, cmd::HIncrBy::new("h", b("n"), 5).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(5));
+        let reply = request(&mut conn, cmd::HIncrBy::new("h", b("n"), -7).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(-2));
+        let reply = request(&mut conn, cmd::HIncrByFloat::new("h", b("f"), 1.5).into_frame()).await;
+        assert_eq!(reply, "1.5");
+        let reply = request(&mut conn, cmd::HIncrByFloat::new("h", b("n"), 0.5).into_frame()).await;
+        assert_eq!(reply, "-1.5");
+
+        let reply = request(&mut conn, cmd::HIncrBy::new("h", b("f"), 1).into_frame()).await;
+        assert_eq!(reply, Frame::Error("ERR hash value is not an integer".to_string()));
+        request(&mut conn, cmd::HSet::new("h").field(b("s"), b("abc")).into_frame()).await;
+        let reply = request(&mut conn, cmd::HIncrByFloat::new("h", b("s"), 1.0).into_frame()).await;
+        assert_eq!(reply, Frame::Error("ERR hash value is not a float".to_string()));
+        let reply = request(&mut conn, command(&["hincrbyfloat", "other", "f", "inf"])).await;
+        assert!(matches!(reply, Frame::Error(_)));
+        assert_eq!(request(&mut conn, cmd::Exists::new(&["other"]).into_frame()).await, Frame::Integer(0));
+    });
+}
+
+#[test]
+fn test_randfield_and_scan_past_limits() {
+    // a tiny limit so the hash gets converted to a table
+    let config = Config {
+        hash_limits: CompactLimits { max_entries: 4, max_value: 64 },
+        ..Config::default()
+    };
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server_with_config(config).await).await;
+
+        let mut hset = cmd::HSet::new("h");
+        for i in 0..50 {
+            hset = hset.field(b(&format!("f{}", i)), b(&i.to_string()));
+        }
+        assert_eq!(request(&mut conn, hset.into_frame()).await, Frame::Integer(50));
+
+        let reply = request(&mut conn, cmd::HRandField::new("h", None, false).into_frame()).await;
+        assert!(matches!(reply, Frame::Bulk(_)));
+        let reply = request(&mut conn, cmd::HRandField::new("h", Some(10), true).into_frame()).await;
+        let pairs = sorted_pairs(reply);
+        assert_eq!(pairs.len(), 10);
+        pairs.windows(2).for_each(|w| assert_ne!(w[0].0, w[1].0));
+        let reply = request(&mut conn, cmd::HRandField::new("h", Some(100), false).into_frame()).await;
+        assert!(matches!(reply, Frame::Array(fields) if fields.len() == 50));
+        let reply = request(&mut conn, cmd::HRandField::new("h", Some(-80), false).into_frame()).await;
+        assert!(matches!(reply, Frame::Array(fields) if fields.len() == 80));
+        // fields repeat with a negative count, so it is capped at a million,
+        // unlike redis, see `MAX_RANDOM_REPEATS`
+        let reply = request(&mut conn, command(&["hrandfield", "h", "-2000000"])).await;
+        assert_eq!(reply, Frame::Error("ERR value is out of range".to_string()));
+        let reply = request(&mut conn, command(&["hrandfield", "h", &i64::MIN.to_string()])).await;
+        assert_eq!(reply, Frame::Error("ERR value is out of range".to_string()));
+        let reply = request(&mut conn, cmd::HRandField::new("nope", None, false).into_frame()).await;
+        assert_eq!(reply, Frame::Null);
+
+        let mut seen = 0;
+        let mut cursor = 0;
+        loop {
+            let reply = request(&mut conn, cmd::HScan::new("h", cursor).pattern("f1*").count(5).into_frame()).await;
+            let mut parts = match reply {
+                Frame::Array(parts) => parts.into_iter(),
+                reply => panic!("unexpected reply {:?}", reply),
+            };
+            cursor = match parts.next() {
+                Some(Frame::Bulk(bs)) => std::str::from_utf8(&bs).unwrap().parse().unwrap(),
+                frame => panic!("unexpected cursor {:?}", frame),
+            };
+            if let Some(Frame::Array(items)) = parts.next() {
+                seen += items.len() / 2;
+            }
+            if cursor == 0 {
+                break;
+            }
+        }
+        // f1 and f10 to f19
+        assert_eq!(seen, 11);
+    });
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Session {
+    user: String,
+    visits: u32,
+    referrer: Option<String>,
+}
+
+#[test]
+fn test_client_structs() {
+    new_runtime().block_on(async {
+        let mut client = Client::connect(start_server().await).await.unwrap();
+
+        let session = Session { user: "simon".to_string(), visits: 3, referrer: None };
+        assert_eq!(client.hset_struct("session:1", &session).await.unwrap(), 2);
+        let read: Option<Session> = client.hgetall_as("session:1").await.unwrap();
+        assert_eq!(read, Some(session));
+        let read: Option<Session> = client.hgetall_as("session:2").await.unwrap();
+        assert_eq!(read, None);
+    });
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use bytes::Bytes;
+use common::{command, connect, new_runtime, request, start_server, start_server_with_config};
+use miniredis::{cmd, database::Config, frame::Frame};
+
+fn elements(range: std::ops::Range<u32>) -> Vec<Bytes> {
+    range.map(|i| Bytes::from(format!("element:{}", i))).collect()
+}
+
+fn count(reply: Frame) -> f64 {
+    match reply {
+        Frame::Integer(n) => n as f64,
+        reply => panic!("{:?}", reply),
+    }
+}
+
+#[test]
+fn test_pfadd_pfcount() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        assert_eq!(request(&mut conn, cmd::PfAdd::new("h", vec![]).into_frame()).await, Frame::Integer(1));
+        assert_eq!(request(&mut conn, cmd::PfAdd::new("h", vec![]).into_frame()).await, Frame::Integer(0));
+        let pfadd = cmd::PfAdd::new("h", elements(0..7));
+        assert_eq!(request(&mut conn, pfadd.into_frame()).await, Frame::Integer(1));
+        assert_eq!(request(&mut conn, cmd::PfAdd::new("h", elements(0..7)).into_frame()).await, Frame::Integer(0));
+        assert_eq!(request(&mut conn, cmd::PfCount::new(&["h"]).into_frame()).await, Frame::Integer(7));
+
+        // the value is a string in the layout of redis, sparse when small
+        let Frame::Bulk(value) = request(&mut conn, cmd::Get::new("h").into_frame()).await else { panic!() };
+        assert_eq!(&value[..5], b"HYLL\x01");
+        // with the count cached in the header by PFCOUNT
+        assert_eq!(value[8..16], [7, 0, 0, 0, 0, 0, 0, 0]);
+
+        request(&mut conn, cmd::PfAdd::new("h", elements(0..10000)).into_frame()).await;
+        let Frame::Bulk(value) = request(&mut conn, cmd::Get::new("h").into_frame()).await else { panic!() };
+        assert_eq!(&value[..5], b"HYLL\x00");
+        assert_eq!(value.len(), 16 + 12288);
+        let estimate = count(request(&mut conn, cmd::PfCount::new(&["h"]).into_frame()).await);
+        assert!((estimate - 10000.0).abs() < 200.0, "{}", estimate);
+
+        request(&mut conn, cmd::Set::new("s", Bytes::from("hello")).into_frame()).await;
+        let reply = request(&mut conn, cmd::PfAdd::new("s", elements(0..1)).into_frame()).await;
+        assert_eq!(reply, Frame::Error("WRONGTYPE Key is not a valid HyperLogLog string value.".to_string()));
+        let reply = request(&mut conn, command(&["pfcount", "h", "s"])).await;
+        assert_eq!(reply, Frame::Error("WRONGTYPE Key is not a valid HyperLogLog string value.".to_string()));
+    });
+}
+
+#[test]
+fn test_union() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        request(&mut conn, cmd::PfAdd::new("a", elements(0..3000)).into_frame()).await;
+        request(&mut conn, cmd::PfAdd::new("b", elements(2000..5000)).into_frame()).await;
+        let union = count(request(&mut conn, cmd::PfCount::new(&["a", "b", "missing"]).into_frame()).await);
+        assert!((union - 5000.0).abs() < 150.0, "{}", union);
+
+        let reply = request(&mut conn, cmd::PfMerge::new("dest", &["a", "b"]).into_frame()).await;
+        assert_eq!(reply, "OK");
+        let merged = count(request(&mut conn, cmd::PfCount::new(&["dest"]).into_frame()).await);
+        assert_eq!(merged, union);
+
+        // the destination counts as a source
+        request(&mut conn, cmd::PfAdd::new("c", elements(5000..6000)).into_frame()).await;
+        request(&mut conn, cmd::PfMerge::new("dest", &["c"]).into_frame()).await;
+        let merged = count(request(&mut conn, cmd::PfCount::new(&["dest"]).into_frame()).await);
+        assert!((merged - 6000.0).abs() < 180.0, "{}", merged);
+    });
+}
+
+#[test]
+fn test_sparse_limit() {
+    new_runtime().block_on(async {
+        let config = Config { hll_sparse_max_bytes: 40, ..Config::default() };
+        let mut conn = connect(start_server_with_config(config).await).await;
+
+        request(&mut conn, cmd::PfAdd::new("h", elements(0..3)).into_frame()).await;
+        let Frame::Bulk(value) = request(&mut conn, cmd::Get::new("h").into_frame()).await else { panic!() };
+        assert_eq!(value[4], 1);
+        request(&mut conn, cmd::PfAdd::new("h", elements(3..20)).into_frame()).await;
+        let Frame::Bulk(value) = request(&mut conn, cmd::Get::new("h").into_frame()).await else { panic!() };
+        assert_eq!(value[4], 0);
+        assert_eq!(request(&mut conn, cmd::PfCount::new(&["h"]).into_frame()).await, Frame::Integer(20));
+    });
+}
@@ -0,0 +1,220 @@
+mod common;
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use common::{command, connect, new_runtime, request, start_server};
+use miniredis::{cmd, frame::Frame};
+
+#[test]
+fn test_del_exists_touch() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        request(&mut conn, cmd::Set::new("a", Bytes::from("1")).into_frame()).await;
+        request(&mut conn, cmd::Set::new("b", Bytes::from("2")).into_frame()).await;
+
+        let reply = request(&mut conn, cmd::Exists::new(&["a", "a", "b", "c"]).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(3));
+        let reply = request(&mut conn, cmd::Touch::new(&["a", "a", "c"]).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(1));
+
+        let reply = request(&mut conn, cmd::Del::new(&["a", "c"]).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(1));
+        let reply = request(&mut conn, cmd::Unlink::new(&["a", "b"]).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(1));
+        let reply = request(&mut conn, cmd::Exists::new(&["a", "b"]).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(0));
+
+        let reply = request(&mut conn, command(&["del"])).await;
+        assert_eq!(reply, Frame::Error("ERR wrong number of arguments".to_string()));
+    });
+}
+
+#[test]
+fn test_type_and_randomkey() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        let reply = request(&mut conn, cmd::RandomKey::new().into_frame()).await;
+        assert_eq!(reply, Frame::Null);
+        let reply = request(&mut conn, cmd::Type::new("k").into_frame()).await;
+        assert_eq!(reply, Frame::Simple("none".to_string()));
+
+        request(&mut conn, cmd::Set::new("k", Bytes::from("v")).into_frame()).await;
+        let reply = request(&mut conn, cmd::Type::new("k").into_frame()).await;
+        assert_eq!(reply, Frame::Simple("string".to_string()));
+        let reply = request(&mut conn, cmd::RandomKey::new().into_frame()).await;
+        assert_eq!(reply, "k");
+    });
+}
+
+#[test]
+fn test_rename_keeps_ttl() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        let reply = request(&mut conn, cmd::Rename::new("missing", "other").into_frame()).await;
+        assert_eq!(reply, Frame::Error("ERR no such key".to_string()));
+
+        request(&mut conn, cmd::Set::new("old", Bytes::from("v")).into_frame()).await;
+        request(&mut conn, cmd::GetEx::new("old", Some(cmd::Expiry::Px(100))).into_frame()).await;
+        request(&mut conn, cmd::Set::new("new", Bytes::from("replaced")).into_frame()).await;
+
+        let reply = request(&mut conn, cmd::Rename::new("old", "new").into_frame()).await;
+        assert_eq!(reply, Frame::Simple("OK".to_string()));
+        let reply = request(&mut conn, cmd::Get::new("new").into_frame()).await;
+        assert_eq!(reply, "v");
+        let reply = request(&mut conn, cmd::Exists::new(&["old"]).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(0));
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let reply = request(&mut conn, cmd::Get::new("new").into_frame()).await;
+        assert_eq!(reply, Frame::Null);
+    });
+}
+
+#[test]
+fn test_renamenx() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        request(&mut conn, cmd::Set::new("a", Bytes::from("1")).into_frame()).await;
+        request(&mut conn, cmd::Set::new("b", Bytes::from("2")).into_frame()).await;
+
+        let reply = request(&mut conn, cmd::RenameNx::new("a", "b").into_frame()).await;
+        assert_eq!(reply, Frame::Integer(0));
+        let reply = request(&mut conn, cmd::RenameNx::new("a", "a").into_frame()).await;
+        assert_eq!(reply, Frame::Integer(0));
+        let reply = request(&mut conn, cmd::RenameNx::new("a", "c").into_frame()).await;
+        assert_eq!(reply, Frame::Integer(1));
+        let reply = request(&mut conn, cmd::Get::new("c").into_frame()).await;
+        assert_eq!(reply, "1");
+    });
+}
+
+#[test]
+fn test_copy() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        let reply = request(&mut conn, cmd::Copy::new("src", "dst", false).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(0));
+
+        request(&mut conn, cmd::Set::new("src", Bytes::from("1")).into_frame()).await;
+        request(&mut conn, cmd::Set::new("dst", Bytes::from("2")).into_frame()).await;
+        let reply = request(&mut conn, cmd::Copy::new("src", "dst", false).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(0));
+        let reply = request(&mut conn, cmd::Copy::new("src", "dst", true).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(1));
+        let reply = request(&mut conn, cmd::Get::new("dst").into_frame()).await;
+        assert_eq!(reply, "1");
+
+        let reply = request(&mut conn, command(&["copy", "src", "dst", "db", "1"])).await;
+        assert_eq!(reply, Frame::Error("ERR DB index is out of range".to_string()));
+        let reply = request(&mut conn, command(&["copy", "src", "src"])).await;
+        assert!(matches!(reply, Frame::Error(_)));
+    });
+}
+
+fn scan_reply(reply: Frame) -> (u64, Vec<String>) {
+    let mut parts = match reply {
+        Frame::Array(parts) if parts.len() == 2 => parts.into_iter(),
+        reply => panic!("unexpected reply {:?}", reply),
+    };
+    let cursor = match parts.next().unwrap() {
+        Frame::Bulk(bs) => std::str::from_utf8(&bs).unwrap().parse().unwrap(),
+        frame => panic!("unexpected cursor {:?}", frame),
+    };
+    let keys = match parts.next().unwrap() {
+        Frame::Array(keys) => keys
+            .into_iter()
+            .map(|key| match key {
+                Frame::Bulk(bs) => String::from_utf8(bs.to_vec()).unwrap(),
+                frame => panic!("unexpected key {:?}", frame),
+            })
+            .collect(),
+        frame => panic!("unexpected keys {:?}", frame),
+    };
+    (cursor, keys)
+}
+
+#[test]
+fn test_scan_while_resizing() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        for i in 0..200 {
+            request(&mut conn, cmd::Set::new(&format!("key:{}", i), Bytes::from("v")).into_frame()).await;
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        let mut cursor = 0;
+        let mut round = 0;
+        loop {
+            let (next, keys) = scan_reply(request(&mut conn, cmd::Scan::new(cursor).count(7).into_frame()).await);
+            seen.extend(keys);
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+            // make the table grow and shrink in the middle of the iteration
+            round += 1;
+            if round == 3 {
+                for i in 0..2000 {
+                    request(&mut conn, cmd::Set::new(&format!("tmp:{}", i), Bytes::from("v")).into_frame()).await;
+                }
+            } else if round == 10 {
+                for i in 0..2000 {
+                    request(&mut conn, cmd::Del::new(&[&format!("tmp:{}", i)]).into_frame()).await;
+                }
+            }
+        }
+        assert!((0..200).all(|i| seen.contains(&format!("key:{}", i))));
+    });
+}
+
+#[test]
+fn test_scan_filters_and_keys() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        for key in ["user:1", "user:2", "item:1"] {
+            request(&mut conn, cmd::Set::new(key, Bytes::from("v")).into_frame()).await;
+        }
+
+        let mut found = vec![];
+        let mut cursor = 0;
+        loop {
+            let frame = cmd::Scan::new(cursor).pattern("user:*").type_name("string").into_frame();
+            let (next, keys) = scan_reply(request(&mut conn, frame).await);
+            found.extend(keys);
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+        found.sort();
+        assert_eq!(found, vec!["user:1", "user:2"]);
+
+        let (cursor, keys) = scan_reply(request(&mut conn, command(&["scan", "0", "type", "list", "count", "100"])).await);
+        assert_eq!((cursor, keys), (0, vec![]));
+        let reply = request(&mut conn, command(&["scan", "x"])).await;
+        assert_eq!(reply, Frame::Error("ERR invalid cursor".to_string()));
+        let reply = request(&mut conn, command(&["scan", "0", "count", "0"])).await;
+        assert_eq!(reply, Frame::Error("ERR syntax error".to_string()));
+
+        let reply = request(&mut conn, cmd::HScan::new("user:1", 0).into_frame()).await;
+        assert!(matches!(reply, Frame::Error(msg) if msg.starts_with("WRONGTYPE")));
+        let (cursor, fields) = scan_reply(request(&mut conn, cmd::HScan::new("missing", 0).into_frame()).await);
+        assert_eq!((cursor, fields), (0, vec![]));
+
+        let reply = request(&mut conn, cmd::Keys::new("*:1").into_frame()).await;
+        let mut keys = match reply {
+            Frame::Array(keys) => keys,
+            reply => panic!("unexpected reply {:?}", reply),
+        };
+        keys.sort_by_key(|key| format!("{:?}", key));
+        assert_eq!(keys, vec![Frame::Bulk(Bytes::from("item:1")), Frame::Bulk(Bytes::from("user:1"))]);
+    });
+}
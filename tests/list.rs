@@ -0,0 +1,161 @@
+mod common;
+
+use bytes::Bytes;
+use common::{command, connect, new_runtime, request, start_server};
+use miniredis::{
+    client::Client,
+    cmd::{self, End},
+    frame::Frame,
+};
+
+fn bulks(items: &[&str]) -> Frame {
+    Frame::Array(items.iter().map(|item| Frame::Bulk(Bytes::from(item.to_string()))).collect())
+}
+
+fn elements(items: &[&str]) -> Vec<Bytes> {
+    items.iter().map(|item| Bytes::from(item.to_string())).collect()
+}
+
+#[test]
+fn test_push_pop_range() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        let reply = request(&mut conn, cmd::LPush::new("l", elements(&["b", "a"])).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(2));
+        let reply = request(&mut conn, cmd::RPush::new("l", elements(&["c", "d", "e"])).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(5));
+
+        let reply = request(&mut conn, cmd::LRange::new("l", 0, -1).into_frame()).await;
+        assert_eq!(reply, bulks(&["a", "b", "c", "d", "e"]));
+        let reply = request(&mut conn, cmd::LRange::new("l", -3, -2).into_frame()).await;
+        assert_eq!(reply, bulks(&["c", "d"]));
+        let reply = request(&mut conn, cmd::LRange::new("l", 4, 100).into_frame()).await;
+        assert_eq!(reply, bulks(&["e"]));
+        let reply = request(&mut conn, cmd::LIndex::new("l", -1).into_frame()).await;
+        assert_eq!(reply, "e");
+        let reply = request(&mut conn, cmd::LIndex::new("l", 5).into_frame()).await;
+        assert_eq!(reply, Frame::Null);
+
+        let reply = request(&mut conn, cmd::LPop::new("l", None).into_frame()).await;
+        assert_eq!(reply, "a");
+        let reply = request(&mut conn, cmd::RPop::new("l", Some(2)).into_frame()).await;
+        assert_eq!(reply, bulks(&["e", "d"]));
+        let reply = request(&mut conn, cmd::LPop::new("l", Some(10)).into_frame()).await;
+        assert_eq!(reply, bulks(&["b", "c"]));
+
+        // the emptied list is gone
+        let reply = request(&mut conn, cmd::Type::new("l").into_frame()).await;
+        assert_eq!(reply, Frame::Simple("none".to_string()));
+        let reply = request(&mut conn, cmd::LPop::new("l", None).into_frame()).await;
+        assert_eq!(reply, Frame::Null);
+        // nil of the array kind with a count
+        let reply = request(&mut conn, cmd::RPop::new("l", Some(2)).into_frame()).await;
+        assert_eq!(reply, Frame::NullArray);
+        let reply = request(&mut conn, command(&["lpop", "l", "-1"])).await;
+        assert_eq!(reply, Frame::Error("ERR value is out of range, must be positive".to_string()));
+    });
+}
+
+#[test]
+fn test_modify() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        request(&mut conn, cmd::RPush::new("l", elements(&["x", "a", "x", "b", "x"])).into_frame()).await;
+
+        let reply = request(&mut conn, cmd::LSet::new("l", -2, Bytes::from("B")).into_frame()).await;
+        assert_eq!(reply, Frame::Simple("OK".to_string()));
+        let reply = request(&mut conn, cmd::LSet::new("l", 10, Bytes::from("B")).into_frame()).await;
+        assert_eq!(reply, Frame::Error("ERR index out of range".to_string()));
+        let reply = request(&mut conn, cmd::LSet::new("missing", 0, Bytes::from("B")).into_frame()).await;
+        assert_eq!(reply, Frame::Error("ERR no such key".to_string()));
+
+        let reply = request(&mut conn, cmd::LRem::new("l", -1, Bytes::from("x")).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(1));
+        let reply = request(&mut conn, cmd::LRange::new("l", 0, -1).into_frame()).await;
+        assert_eq!(reply, bulks(&["x", "a", "x", "B"]));
+        let reply = request(&mut conn, cmd::LRem::new("l", 0, Bytes::from("x")).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(2));
+
+        let reply = request(&mut conn, cmd::LInsert::new("l", true, Bytes::from("B"), Bytes::from("b")).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(3));
+        let reply = request(&mut conn, cmd::LInsert::new("l", false, Bytes::from("B"), Bytes::from("c")).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(4));
+        let reply = request(&mut conn, cmd::LInsert::new("l", false, Bytes::from("?"), Bytes::from("c")).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(-1));
+        let reply = request(&mut conn, cmd::LRange::new("l", 0, -1).into_frame()).await;
+        assert_eq!(reply, bulks(&["a", "b", "B", "c"]));
+
+        let reply = request(&mut conn, cmd::LTrim::new("l", 1, -2).into_frame()).await;
+        assert_eq!(reply, Frame::Simple("OK".to_string()));
+        let reply = request(&mut conn, cmd::LRange::new("l", 0, -1).into_frame()).await;
+        assert_eq!(reply, bulks(&["b", "B"]));
+        request(&mut conn, cmd::LTrim::new("l", 5, 10).into_frame()).await;
+        let reply = request(&mut conn, cmd::LLen::new("l").into_frame()).await;
+        assert_eq!(reply, Frame::Integer(0));
+        let reply = request(&mut conn, cmd::Exists::new(&["l"]).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(0));
+    });
+}
+
+#[test]
+fn test_lpos() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        request(&mut conn, cmd::RPush::new("l", elements(&["a", "b", "c", "1", "2", "3", "c", "c"])).into_frame()).await;
+
+        let lpos = |lpos: cmd::LPos| lpos.into_frame();
+        let c = || cmd::LPos::new("l", Bytes::from("c"));
+        let reply = request(&mut conn, lpos(c())).await;
+        assert_eq!(reply, Frame::Integer(2));
+        let reply = request(&mut conn, lpos(c().rank(2))).await;
+        assert_eq!(reply, Frame::Integer(6));
+        let reply = request(&mut conn, lpos(c().rank(-1))).await;
+        assert_eq!(reply, Frame::Integer(7));
+        let reply = request(&mut conn, lpos(c().count(2))).await;
+        assert_eq!(reply, Frame::Array(vec![Frame::Integer(2), Frame::Integer(6)]));
+        let reply = request(&mut conn, lpos(c().rank(-1).count(0))).await;
+        assert_eq!(reply, Frame::Array(vec![Frame::Integer(7), Frame::Integer(6), Frame::Integer(2)]));
+        let reply = request(&mut conn, lpos(c().count(0).maxlen(4))).await;
+        assert_eq!(reply, Frame::Array(vec![Frame::Integer(2)]));
+        let reply = request(&mut conn, lpos(cmd::LPos::new("l", Bytes::from("z")))).await;
+        assert_eq!(reply, Frame::Null);
+
+        let reply = request(&mut conn, command(&["lpos", "l", "c", "rank", "0"])).await;
+        assert!(matches!(reply, Frame::Error(msg) if msg.starts_with("ERR RANK can't be zero")));
+    });
+}
+
+#[test]
+fn test_lmove_and_wrongtype() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut client = Client::connect(addr).await.unwrap();
+
+        client.rpush("jobs", elements(&["1", "2", "3"])).await.unwrap();
+        let job = client.lmove("jobs", "working", End::Left, End::Right).await.unwrap();
+        assert_eq!(job, Some(Bytes::from("1")));
+        // rotate a list onto itself
+        let job = client.lmove("jobs", "jobs", End::Left, End::Right).await.unwrap();
+        assert_eq!(job, Some(Bytes::from("2")));
+        assert_eq!(client.lrange("jobs", 0, -1).await.unwrap(), elements(&["3", "2"]));
+        assert_eq!(client.lrange("working", 0, -1).await.unwrap(), elements(&["1"]));
+        assert_eq!(client.lmove("empty", "jobs", End::Left, End::Left).await.unwrap(), None);
+
+        client.set("s", Bytes::from("v")).await.unwrap();
+        let err = client.lpush("s", elements(&["x"])).await.unwrap_err();
+        assert!(err.to_string().starts_with("WRONGTYPE"));
+        let err = client.lmove("jobs", "s", End::Left, End::Left).await.unwrap_err();
+        assert!(err.to_string().starts_with("WRONGTYPE"));
+        // nothing was popped
+        assert_eq!(client.lrange("jobs", 0, -1).await.unwrap(), elements(&["3", "2"]));
+        let err = client.get("jobs").await.unwrap_err();
+        assert!(err.to_string().starts_with("WRONGTYPE"));
+
+        assert_eq!(client.lpop("jobs").await.unwrap(), Some(Bytes::from("3")));
+        assert_eq!(client.rpop("jobs").await.unwrap(), Some(Bytes::from("2")));
+        assert_eq!(client.rpop("jobs").await.unwrap(), None);
+    });
+}
@@ -0,0 +1,172 @@
+mod common;
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use common::{command, connect, new_runtime, request, start_server};
+use miniredis::{
+    cmd::{self, Expiry},
+    connection::Connection,
+    frame::Frame,
+};
+use tokio::time::timeout;
+
+fn b(s: &str) -> Bytes {
+    Bytes::from(s.to_string())
+}
+
+fn bulks(items: &[&str]) -> Frame {
+    Frame::Array(items.iter().map(|item| Frame::Bulk(b(item))).collect())
+}
+
+async fn read(conn: &mut Connection) -> Frame {
+    timeout(Duration::from_secs(1), conn.read_frame()).await.unwrap().unwrap()
+}
+
+/// The next `(channel, message)` received by a subscriber of a pattern
+async fn next_event(conn: &mut Connection) -> (String, String) {
+    match read(conn).await {
+        Frame::Array(frames) => match &frames[..] {
+            [_, _, Frame::Bulk(channel), Frame::Bulk(message)] => (
+                String::from_utf8(channel.to_vec()).unwrap(),
+                String::from_utf8(message.to_vec()).unwrap(),
+            ),
+            _ => panic!("{frames:?}"),
+        },
+        frame => panic!("{frame:?}"),
+    }
+}
+
+async fn subscribe_events(conn: &mut Connection, pattern: &str) {
+    request(conn, cmd::PSubscribe::new(&[pattern]).into_frame()).await;
+}
+
+#[test]
+fn test_config_get_set() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+        let reply = request(&mut conn, cmd::Config::get(&["notify-keyspace-events"]).into_frame()).await;
+        assert_eq!(reply, bulks(&["notify-keyspace-events", ""]));
+
+        let set = cmd::Config::set("notify-keyspace-events", "KEA").and("SET-MAX-INTSET-ENTRIES", 16);
+        assert_eq!(request(&mut conn, set.into_frame()).await, "OK");
+        let reply = request(&mut conn, cmd::Config::get(&["notify-*", "set-max-intset-entries"]).into_frame()).await;
+        assert_eq!(reply, bulks(&["notify-keyspace-events", "AKE", "set-max-intset-entries", "16"]));
+        let reply = request(&mut conn, cmd::Config::get(&["*max*"]).into_frame()).await;
+        let Frame::Array(pairs) = reply else { panic!() };
+        assert_eq!(pairs.len(), 8);
+
+        // nothing is set if any value is invalid
+        let set = cmd::Config::set("notify-keyspace-events", "Ex").and("notify-keyspace-events", "Ew");
+        let err = "ERR CONFIG SET failed (possibly related to argument 'notify-keyspace-events') - Invalid event \
+                   class character. Use 'Ag$lshzxeKEtmdn'.";
+        assert_eq!(request(&mut conn, set.into_frame()).await, Frame::Error(err.to_string()));
+        let set = cmd::Config::set("set-max-intset-entries", "many");
+        let err = "ERR CONFIG SET failed (possibly related to argument 'set-max-intset-entries') - argument \
+                   couldn't be parsed into an integer";
+        assert_eq!(request(&mut conn, set.into_frame()).await, Frame::Error(err.to_string()));
+        let reply = request(&mut conn, cmd::Config::get(&["notify-keyspace-events"]).into_frame()).await;
+        assert_eq!(reply, bulks(&["notify-keyspace-events", "AKE"]));
+
+        let reply = request(&mut conn, cmd::Config::set("maxclients", 10).into_frame()).await;
+        let err = "ERR Unknown option or number of arguments for CONFIG SET - 'maxclients'";
+        assert_eq!(reply, Frame::Error(err.to_string()));
+        let reply = request(&mut conn, command(&["config", "set", "notify-keyspace-events"])).await;
+        assert_eq!(reply, Frame::Error("ERR wrong number of arguments".to_string()));
+        let reply = request(&mut conn, command(&["config", "rewrite"])).await;
+        assert_eq!(reply, Frame::Error("ERR unknown subcommand 'rewrite'. Try CONFIG HELP.".to_string()));
+    });
+}
+
+#[test]
+fn test_keyspace_events() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut conn = connect(addr).await;
+        let mut subscriber = connect(addr).await;
+        subscribe_events(&mut subscriber, "__key*@0__:*").await;
+
+        // off by default
+        request(&mut conn, cmd::Set::new("silent", b("1")).into_frame()).await;
+        request(&mut conn, cmd::Config::set("notify-keyspace-events", "KEA").into_frame()).await;
+
+        request(&mut conn, cmd::Set::new("foo", b("bar")).into_frame()).await;
+        assert_eq!(next_event(&mut subscriber).await, ("__keyspace@0__:foo".into(), "set".into()));
+        assert_eq!(next_event(&mut subscriber).await, ("__keyevent@0__:set".into(), "foo".into()));
+
+        request(&mut conn, cmd::Rename::new("foo", "baz").into_frame()).await;
+        request(&mut conn, cmd::Del::new(&["baz", "missing"]).into_frame()).await;
+        request(&mut conn, cmd::RPush::new("list", vec![b("a")]).into_frame()).await;
+        request(&mut conn, cmd::LPop::new("list", None).into_frame()).await;
+        // nothing changes, nothing is published
+        request(&mut conn, cmd::SRem::new("set", vec![b("a")]).into_frame()).await;
+        request(&mut conn, cmd::HSet::new("hash").field(b("f"), b("v")).into_frame()).await;
+
+        let mut events = vec![];
+        for _ in 0..7 {
+            let (channel, message) = next_event(&mut subscriber).await;
+            if let Some(key) = channel.strip_prefix("__keyspace@0__:") {
+                events.push(format!("{message} {key}"));
+            }
+            next_event(&mut subscriber).await;
+        }
+        assert_eq!(
+            events,
+            vec!["rename_from foo", "rename_to baz", "del baz", "rpush list", "lpop list", "del list", "hset hash"]
+        );
+    });
+}
+
+#[test]
+fn test_event_classes() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut conn = connect(addr).await;
+        let mut subscriber = connect(addr).await;
+        subscribe_events(&mut subscriber, "__key*@0__:*").await;
+
+        // string events on the keyspace channel only, and new keys
+        request(&mut conn, cmd::Config::set("notify-keyspace-events", "K$n").into_frame()).await;
+        request(&mut conn, cmd::LPush::new("list", vec![b("a")]).into_frame()).await;
+        assert_eq!(next_event(&mut subscriber).await, ("__keyspace@0__:list".into(), "new".into()));
+        request(&mut conn, cmd::Incr::new("counter").into_frame()).await;
+        assert_eq!(next_event(&mut subscriber).await, ("__keyspace@0__:counter".into(), "new".into()));
+        assert_eq!(next_event(&mut subscriber).await, ("__keyspace@0__:counter".into(), "incrby".into()));
+        request(&mut conn, cmd::Append::new("counter", b("0")).into_frame()).await;
+        assert_eq!(next_event(&mut subscriber).await, ("__keyspace@0__:counter".into(), "append".into()));
+
+        // the event channel only, sorted set events
+        request(&mut conn, cmd::Config::set("notify-keyspace-events", "Ez").into_frame()).await;
+        request(&mut conn, cmd::Set::new("string", b("v")).into_frame()).await;
+        request(&mut conn, cmd::ZAdd::new("zset").member(1.0, b("a")).into_frame()).await;
+        assert_eq!(next_event(&mut subscriber).await, ("__keyevent@0__:zadd".into(), "zset".into()));
+        request(&mut conn, cmd::ZIncrBy::new("zset", 1.0, b("a")).into_frame()).await;
+        assert_eq!(next_event(&mut subscriber).await, ("__keyevent@0__:zincr".into(), "zset".into()));
+
+        // classes without K or E go nowhere
+        request(&mut conn, cmd::Config::set("notify-keyspace-events", "A").into_frame()).await;
+        request(&mut conn, cmd::Set::new("string", b("v")).into_frame()).await;
+        assert!(timeout(Duration::from_millis(100), subscriber.read_frame()).await.is_err());
+    });
+}
+
+#[test]
+fn test_expired_events() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut conn = connect(addr).await;
+        let mut subscriber = connect(addr).await;
+        request(&mut subscriber, cmd::Subscribe::new(&["__keyevent@0__:expired"]).into_frame()).await;
+        request(&mut conn, cmd::Config::set("notify-keyspace-events", "Ex").into_frame()).await;
+
+        request(&mut conn, cmd::Set::new("session", b("v")).into_frame()).await;
+        request(&mut conn, cmd::GetEx::new("session", Some(Expiry::Px(50))).into_frame()).await;
+        request(&mut conn, cmd::Set::new("kept", b("v")).into_frame()).await;
+
+        // expired keys are removed even if nobody reads them
+        let frame = read(&mut subscriber).await;
+        assert_eq!(frame, bulks(&["message", "__keyevent@0__:expired", "session"]));
+        assert_eq!(request(&mut conn, cmd::Exists::new(&["session", "kept"]).into_frame()).await, Frame::Integer(1));
+        assert!(timeout(Duration::from_millis(200), subscriber.read_frame()).await.is_err());
+    });
+}
@@ -0,0 +1,362 @@
+mod common;
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use common::{command, connect, new_runtime, request, start_server};
+use miniredis::{cmd, connection::Connection, frame::Frame};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+fn b(s: &str) -> Bytes {
+    Bytes::from(s.to_string())
+}
+
+fn bulk(s: &str) -> Frame {
+    Frame::Bulk(b(s))
+}
+
+fn confirmation(kind: &str, name: &str, count: i64) -> Frame {
+    Frame::Array(vec![bulk(kind), bulk(name), Frame::Integer(count)])
+}
+
+async fn read(conn: &mut Connection) -> Frame {
+    timeout(Duration::from_secs(1), conn.read_frame()).await.unwrap().unwrap()
+}
+
+async fn publish(conn: &mut Connection, channel: &str, message: &str) -> Frame {
+    request(conn, cmd::Publish::new(channel, b(message)).into_frame()).await
+}
+
+#[test]
+fn test_subscribe_and_publish() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut subscriber = connect(addr).await;
+        let mut publisher = connect(addr).await;
+
+        subscriber.write_frame(cmd::Subscribe::new(&["news", "sport"]).into_frame()).await.unwrap();
+        assert_eq!(read(&mut subscriber).await, confirmation("subscribe", "news", 1));
+        assert_eq!(read(&mut subscriber).await, confirmation("subscribe", "sport", 2));
+        let reply = request(&mut subscriber, cmd::PSubscribe::new(&["n*"]).into_frame()).await;
+        assert_eq!(reply, confirmation("psubscribe", "n*", 3));
+
+        assert_eq!(publish(&mut publisher, "news", "hello").await, Frame::Integer(2));
+        assert_eq!(publish(&mut publisher, "weather", "rain").await, Frame::Integer(0));
+        let mut messages = vec![read(&mut subscriber).await, read(&mut subscriber).await];
+        messages.sort_by_key(|frame| format!("{frame:?}"));
+        assert_eq!(
+            messages,
+            vec![
+                Frame::Array(vec![bulk("message"), bulk("news"), bulk("hello")]),
+                Frame::Array(vec![bulk("pmessage"), bulk("n*"), bulk("news"), bulk("hello")]),
+            ]
+        );
+
+        // only subscription commands, PING and QUIT are allowed
+        let reply = request(&mut subscriber, cmd::Get::new("key").into_frame()).await;
+        let err = "ERR Can't execute 'get': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT are allowed in this context";
+        assert_eq!(reply, Frame::Error(err.to_string()));
+        let reply = request(&mut subscriber, cmd::Ping::new(None).into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![bulk("pong"), bulk("")]));
+
+        let reply = request(&mut subscriber, cmd::Unsubscribe::new(&["news"]).into_frame()).await;
+        assert_eq!(reply, confirmation("unsubscribe", "news", 2));
+        assert_eq!(publish(&mut publisher, "news", "again").await, Frame::Integer(1));
+        let reply = read(&mut subscriber).await;
+        assert_eq!(reply, Frame::Array(vec![bulk("pmessage"), bulk("n*"), bulk("news"), bulk("again")]));
+
+        let reply = request(&mut subscriber, cmd::PUnsubscribe::new(&[]).into_frame()).await;
+        assert_eq!(reply, confirmation("punsubscribe", "n*", 1));
+        let reply = request(&mut subscriber, cmd::Unsubscribe::new(&[]).into_frame()).await;
+        assert_eq!(reply, confirmation("unsubscribe", "sport", 0));
+        let reply = request(&mut subscriber, cmd::Unsubscribe::new(&[]).into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![bulk("unsubscribe"), Frame::Null, Frame::Integer(0)]));
+
+        // back to normal
+        assert_eq!(request(&mut subscriber, cmd::Get::new("key").into_frame()).await, Frame::Null);
+        assert_eq!(request(&mut subscriber, cmd::Ping::new(None).into_frame()).await, "PONG");
+        assert_eq!(request(&mut subscriber, cmd::Ping::new(Some(b("hi"))).into_frame()).await, "hi");
+    });
+}
+
+#[test]
+fn test_resp3_push() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut subscriber = connect(addr).await;
+        let mut publisher = connect(addr).await;
+
+        // a map under RESP3, an array under RESP2
+        let Frame::Map(hello) = request(&mut subscriber, cmd::Hello::new(Some(3)).into_frame()).await else {
+            panic!()
+        };
+        assert_eq!(hello[4..6], [bulk("proto"), Frame::Integer(3)]);
+        let Frame::Array(hello) = request(&mut publisher, cmd::Hello::new(Some(2)).into_frame()).await else {
+            panic!()
+        };
+        assert_eq!(hello[4..6], [bulk("proto"), Frame::Integer(2)]);
+        let reply = request(&mut subscriber, cmd::Hello::new(Some(4)).into_frame()).await;
+        assert_eq!(reply, Frame::Error("NOPROTO unsupported protocol version".to_string()));
+
+        let reply = request(&mut subscriber, cmd::Subscribe::new(&["news"]).into_frame()).await;
+        assert_eq!(reply, Frame::Push(vec![bulk("subscribe"), bulk("news"), Frame::Integer(1)]));
+        assert_eq!(publish(&mut publisher, "news", "hello").await, Frame::Integer(1));
+        let reply = read(&mut subscriber).await;
+        assert_eq!(reply, Frame::Push(vec![bulk("message"), bulk("news"), bulk("hello")]));
+
+        // pushes can be told apart from replies, so any command is allowed
+        let reply = request(&mut subscriber, cmd::Set::new("key", b("value")).into_frame()).await;
+        assert_eq!(reply, "OK");
+        assert_eq!(request(&mut subscriber, cmd::Ping::new(None).into_frame()).await, "PONG");
+    });
+}
+
+#[test]
+fn test_pubsub_introspection() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut conn = connect(addr).await;
+        let mut first = connect(addr).await;
+        let mut second = connect(addr).await;
+
+        request(&mut first, cmd::Subscribe::new(&["news"]).into_frame()).await;
+        request(&mut first, cmd::PSubscribe::new(&["s*"]).into_frame()).await;
+        first.write_frame(cmd::Subscribe::new(&["sport", "news"]).into_frame()).await.unwrap();
+        read(&mut first).await;
+        read(&mut first).await;
+        request(&mut second, cmd::PSubscribe::new(&["s*", "n*"]).into_frame()).await;
+        read(&mut second).await;
+
+        let reply = request(&mut conn, cmd::PubSub::Channels(None).into_frame()).await;
+        let Frame::Array(mut channels) = reply else { panic!() };
+        channels.sort_by_key(|frame| format!("{frame:?}"));
+        assert_eq!(channels, vec![bulk("news"), bulk("sport")]);
+        let reply = request(&mut conn, cmd::PubSub::Channels(Some(b("s*"))).into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![bulk("sport")]));
+
+        let reply = request(&mut conn, cmd::PubSub::NumSub(vec![b("news"), b("none")]).into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![bulk("news"), Frame::Integer(1), bulk("none"), Frame::Integer(0)]));
+        assert_eq!(request(&mut conn, cmd::PubSub::NumPat.into_frame()).await, Frame::Integer(2));
+
+        let reply = request(&mut conn, command(&["pubsub", "nope"])).await;
+        assert_eq!(reply, Frame::Error("ERR unknown subcommand 'nope'. Try PUBSUB HELP.".to_string()));
+
+        // a closed connection leaves the broker
+        assert_eq!(request(&mut first, cmd::Quit::new().into_frame()).await, "OK");
+        assert!(first.read_frame().await.is_err());
+        drop(second);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(request(&mut conn, cmd::PubSub::NumPat.into_frame()).await, Frame::Integer(0));
+        assert_eq!(request(&mut conn, cmd::PubSub::Channels(None).into_frame()).await, Frame::Array(vec![]));
+    });
+}
+
+#[test]
+fn test_shard_channels() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut subscriber = connect(addr).await;
+        let mut conn = connect(addr).await;
+
+        // hash tags put both channels in the same slot
+        let ssubscribe = cmd::SSubscribe::new(&["{user1}.news", "{user1}.sport"]);
+        subscriber.write_frame(ssubscribe.into_frame()).await.unwrap();
+        assert_eq!(read(&mut subscriber).await, confirmation("ssubscribe", "{user1}.news", 1));
+        assert_eq!(read(&mut subscriber).await, confirmation("ssubscribe", "{user1}.sport", 2));
+        let reply = request(&mut subscriber, cmd::SSubscribe::new(&["news", "sport"]).into_frame()).await;
+        assert_eq!(reply, Frame::Error("CROSSSLOT Keys in request don't hash to the same slot".to_string()));
+        // shard channels have their own count
+        let reply = request(&mut subscriber, cmd::Subscribe::new(&["news"]).into_frame()).await;
+        assert_eq!(reply, confirmation("subscribe", "news", 1));
+
+        // PUBLISH does not reach shard channels, nor SPUBLISH plain ones
+        assert_eq!(publish(&mut conn, "{user1}.news", "plain").await, Frame::Integer(0));
+        let spublish = cmd::SPublish::new("{user1}.news", b("hello"));
+        assert_eq!(request(&mut conn, spublish.into_frame()).await, Frame::Integer(1));
+        let reply = read(&mut subscriber).await;
+        assert_eq!(reply, Frame::Array(vec![bulk("smessage"), bulk("{user1}.news"), bulk("hello")]));
+        assert_eq!(request(&mut conn, cmd::SPublish::new("news", b("x")).into_frame()).await, Frame::Integer(0));
+
+        let reply = request(&mut conn, cmd::PubSub::ShardChannels(Some(b("*sport"))).into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![bulk("{user1}.sport")]));
+        let reply = request(&mut conn, cmd::PubSub::ShardNumSub(vec![b("{user1}.news"), b("news")]).into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![bulk("{user1}.news"), Frame::Integer(1), bulk("news"), Frame::Integer(0)]));
+        let reply = request(&mut conn, cmd::PubSub::Channels(None).into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![bulk("news")]));
+
+        subscriber.write_frame(cmd::SUnsubscribe::new(&[]).into_frame()).await.unwrap();
+        let replies = [read(&mut subscriber).await, read(&mut subscriber).await];
+        let (first, second) = match &replies {
+            [Frame::Array(first), Frame::Array(second)] => (first, second),
+            _ => panic!(),
+        };
+        assert_eq!((&first[2], &second[2]), (&Frame::Integer(1), &Frame::Integer(0)));
+        let mut channels = vec![first[1].clone(), second[1].clone()];
+        channels.sort_by_key(|frame| format!("{frame:?}"));
+        assert_eq!(channels, vec![bulk("{user1}.news"), bulk("{user1}.sport")]);
+        // still subscribed to a plain channel
+        let reply = request(&mut subscriber, cmd::Get::new("key").into_frame()).await;
+        assert!(matches!(reply, Frame::Error(_)));
+    });
+}
+
+#[test]
+fn test_client_subscriber_stream() {
+    use futures::StreamExt;
+    use miniredis::{client::Client, pubsub::Message};
+
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut publisher = Client::connect(addr).await.unwrap();
+        let subscriber = Client::connect(addr).await.unwrap().subscribe(&["news"]).await.unwrap();
+        let shard_subscriber = Client::connect(addr).await.unwrap().ssubscribe(&["orders"]).await.unwrap();
+        let err = Client::connect(addr).await.unwrap().ssubscribe(&["a", "b"]).await.err().unwrap();
+        assert_eq!(err.to_string(), "CROSSSLOT Keys in request don't hash to the same slot");
+
+        assert_eq!(publisher.publish("news", b("one")).await.unwrap(), 1);
+        assert_eq!(publisher.publish("news", b("two")).await.unwrap(), 1);
+        assert_eq!(publisher.spublish("orders", b("three")).await.unwrap(), 1);
+
+        let messages: Vec<_> = subscriber.take(2).collect().await;
+        let payloads: Vec<_> = messages.iter().map(|message| message.payload.clone()).collect();
+        assert_eq!(payloads, vec![b("one"), b("two")]);
+
+        let mut shard_subscriber = shard_subscriber;
+        let message = shard_subscriber.next().await.unwrap();
+        assert_eq!(message, Message { pattern: None, shard: true, channel: b("orders"), payload: b("three") });
+    });
+}
+
+#[test]
+fn test_client_subscriber_changes() {
+    use futures::StreamExt;
+    use miniredis::client::Client;
+
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut publisher = Client::connect(addr).await.unwrap();
+        let mut subscriber = Client::connect(addr).await.unwrap().subscribe(&["news"]).await.unwrap();
+
+        subscriber.subscribe(&["sport"]).await.unwrap();
+        subscriber.psubscribe(&["w*"]).await.unwrap();
+        subscriber.ssubscribe(&["orders"]).await.unwrap();
+        assert_eq!(publisher.publish("sport", b("one")).await.unwrap(), 1);
+        assert_eq!(publisher.publish("weather", b("two")).await.unwrap(), 1);
+        assert_eq!(publisher.spublish("orders", b("three")).await.unwrap(), 1);
+
+        subscriber.unsubscribe(&["news", "sport"]).await.unwrap();
+        subscriber.punsubscribe(&["w*"]).await.unwrap();
+        subscriber.sunsubscribe(&["orders"]).await.unwrap();
+        // nothing to change
+        subscriber.unsubscribe(&[]).await.unwrap();
+        assert_eq!(publisher.publish("sport", b("lost")).await.unwrap(), 0);
+        subscriber.subscribe(&["news"]).await.unwrap();
+        assert_eq!(publisher.publish("news", b("four")).await.unwrap(), 1);
+
+        let messages: Vec<_> = timeout(Duration::from_secs(1), subscriber.take(4).collect::<Vec<_>>()).await.unwrap();
+        let payloads: Vec<_> = messages.iter().map(|message| message.payload.clone()).collect();
+        assert_eq!(payloads, vec![b("one"), b("two"), b("three"), b("four")]);
+        assert_eq!(messages[1].pattern, Some(b("w*")));
+    });
+}
+
+#[test]
+fn test_client_subscriber_reconnects() {
+    use futures::StreamExt;
+    use miniredis::{client::Client, server::Server};
+    use tokio::{net::TcpListener, sync::oneshot};
+
+    /// A server which stops, closing its connections, when `stop` is sent
+    async fn start(listener: TcpListener) -> oneshot::Sender<()> {
+        let (stop, stopped) = oneshot::channel::<()>();
+        tokio::spawn(async move {
+            Server::new().run(listener, async { stopped.await.ok(); }).await.unwrap();
+        });
+        stop
+    }
+
+    new_runtime().block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let stop = start(listener).await;
+        let mut subscriber = Client::connect(addr).await.unwrap().subscribe(&["news"]).await.unwrap();
+        subscriber.psubscribe(&["w*"]).await.unwrap();
+        subscriber.ssubscribe(&["{user}.a", "{user}.b"]).await.unwrap();
+
+        stop.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let _stop = start(TcpListener::bind(addr).await.unwrap()).await;
+
+        // wait for the subscriber to be back
+        let mut publisher = Client::connect(addr).await.unwrap();
+        let mut receivers = 0;
+        for _ in 0..50 {
+            receivers = publisher.spublish("{user}.b", b("ping")).await.unwrap();
+            if receivers == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(receivers, 1);
+        assert_eq!(publisher.publish("news", b("one")).await.unwrap(), 1);
+        assert_eq!(publisher.publish("weather", b("two")).await.unwrap(), 1);
+
+        let messages: Vec<_> = timeout(Duration::from_secs(1), subscriber.take(3).collect::<Vec<_>>()).await.unwrap();
+        let payloads: Vec<_> = messages.iter().map(|message| message.payload.clone()).collect();
+        assert_eq!(payloads, vec![b("ping"), b("one"), b("two")]);
+    });
+}
+
+#[test]
+fn test_large_request_while_subscribed() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut subscriber = TcpStream::connect(addr).await.unwrap();
+        let mut publisher = connect(addr).await;
+        subscriber.write_all(b"*2\r\n$5\r\nhello\r\n$1\r\n3\r\n*2\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // streamed, as larger than the threshold, and interrupted by messages
+        let value = vec![b'x'; 256 * 1024];
+        let mut set = format!("*3\r\n$3\r\nset\r\n$5\r\nlarge\r\n${}\r\n", value.len()).into_bytes();
+        set.extend_from_slice(&value);
+        set.extend_from_slice(b"\r\n");
+        for piece in set.chunks(64 * 1024) {
+            subscriber.write_all(piece).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert_eq!(publish(&mut publisher, "news", "hello").await, Frame::Integer(1));
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let reply = request(&mut publisher, cmd::Strlen::new("large").into_frame()).await;
+        assert_eq!(reply, Frame::Integer(value.len() as i64));
+    });
+}
+
+#[test]
+fn test_slow_subscriber_is_disconnected() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut publisher = connect(addr).await;
+        let reply = request(&mut publisher, cmd::Config::set("pubsub-buffer-limit", 1024 * 1024).into_frame()).await;
+        assert_eq!(reply, "OK");
+        // subscribes, then never reads
+        let mut subscriber = TcpStream::connect(addr).await.unwrap();
+        subscriber.write_all(b"*2\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let payload = "x".repeat(64 * 1024);
+        let mut published = 0;
+        while publish(&mut publisher, "news", &payload).await == Frame::Integer(1) {
+            published += 1;
+            assert!(published < 1000, "the subscriber is never cut off");
+        }
+        let mut received = vec![];
+        let closed = timeout(Duration::from_secs(1), subscriber.read_to_end(&mut received)).await;
+        assert!(closed.is_ok(), "the subscriber is still connected");
+        assert_eq!(publish(&mut publisher, "news", "hi").await, Frame::Integer(0));
+    });
+}
@@ -0,0 +1,219 @@
+mod common;
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use common::{command, connect, new_runtime, request, start_server};
+use miniredis::{cmd, frame::Frame, script::sha1_hex};
+use tokio::time::timeout;
+
+fn b(s: &str) -> Bytes {
+    Bytes::from(s.to_string())
+}
+
+fn err(message: &str) -> Frame {
+    Frame::Error(message.to_string())
+}
+
+#[test]
+fn test_eval_conversions() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+        let eval = cmd::Eval::new("return {KEYS[1], ARGV[1], 3.7, true, false, {ok='fine'}}", &["k"], vec![b("a")]);
+        let reply = request(&mut conn, eval.into_frame()).await;
+        assert_eq!(
+            reply,
+            Frame::Array(vec![
+                Frame::Bulk(b("k")),
+                Frame::Bulk(b("a")),
+                Frame::Integer(3),
+                Frame::Integer(1),
+                Frame::Null,
+                Frame::Simple("fine".to_string()),
+            ])
+        );
+        let eval = cmd::Eval::new("return redis.error_reply('MY error')", &[], vec![]);
+        assert_eq!(request(&mut conn, eval.into_frame()).await, err("MY error"));
+        let reply = request(&mut conn, cmd::Eval::new("return nil", &[], vec![]).into_frame()).await;
+        assert_eq!(reply, Frame::Null);
+        let reply = request(&mut conn, command(&["eval", "return 1", "2", "only"])).await;
+        assert_eq!(reply, err("ERR Number of keys can't be greater than number of args"));
+
+        // nothing outside of the dataset
+        let eval = cmd::Eval::new("return {type(io), type(os), type(loadfile), type(dofile)}", &[], vec![]);
+        let reply = request(&mut conn, eval.into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![Frame::Bulk(b("nil")); 4]));
+    });
+}
+
+#[test]
+fn test_redis_call() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+        // check-and-set on a balance
+        let script = "
+            local balance = tonumber(redis.call('GET', KEYS[1]) or '0')
+            if balance < tonumber(ARGV[1]) then
+                return redis.error_reply('ERR insufficient funds')
+            end
+            return redis.call('DECRBY', KEYS[1], ARGV[1])
+        ";
+        request(&mut conn, cmd::Set::new("balance", b("100")).into_frame()).await;
+        let reply = request(&mut conn, cmd::Eval::new(script, &["balance"], vec![b("30")]).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(70));
+        let reply = request(&mut conn, cmd::Eval::new(script, &["balance"], vec![b("80")]).into_frame()).await;
+        assert_eq!(reply, err("ERR insufficient funds"));
+
+        let script = "
+            redis.call('RPUSH', 'list', 'a', 'b', 1)
+            return {redis.call('LRANGE', 'list', 0, -1), redis.call('SET', 'k', 'v'), redis.call('GET', 'none')}
+        ";
+        let reply = request(&mut conn, cmd::Eval::new(script, &[], vec![]).into_frame()).await;
+        assert_eq!(
+            reply,
+            Frame::Array(vec![
+                Frame::Array(vec![Frame::Bulk(b("a")), Frame::Bulk(b("b")), Frame::Bulk(b("1"))]),
+                Frame::Simple("OK".to_string()),
+                Frame::Null,
+            ])
+        );
+
+        // redis.call raises errors, redis.pcall returns them
+        let wrongtype = "WRONGTYPE Operation against a key holding the wrong kind of value";
+        let eval = cmd::Eval::new("return redis.call('INCR', 'list')", &[], vec![]);
+        assert_eq!(request(&mut conn, eval.into_frame()).await, err(wrongtype));
+        let script = "local reply = redis.pcall('INCR', 'list') return reply.err";
+        let reply = request(&mut conn, cmd::Eval::new(script, &[], vec![]).into_frame()).await;
+        assert_eq!(reply, Frame::Bulk(b(wrongtype)));
+        let reply = request(&mut conn, cmd::Eval::new("return redis.call('NOPE')", &[], vec![]).into_frame()).await;
+        assert_eq!(reply, err("ERR unknown command 'nope'"));
+        let eval = cmd::Eval::new("return redis.call('EVAL', 'return 1', 0)", &[], vec![]);
+        assert_eq!(request(&mut conn, eval.into_frame()).await, err("ERR command not allowed here"));
+
+        let reply = request(&mut conn, cmd::Eval::new("return 1 +", &[], vec![]).into_frame()).await;
+        let Frame::Error(message) = reply else { panic!("{reply:?}") };
+        assert!(message.starts_with("ERR Error compiling script"), "{message}");
+        let reply = request(&mut conn, cmd::Eval::new("return nil + 1", &[], vec![]).into_frame()).await;
+        let Frame::Error(message) = reply else { panic!("{reply:?}") };
+        assert!(message.starts_with("ERR Error running script"), "{message}");
+    });
+}
+
+#[test]
+fn test_script_cache() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+        let body = "return ARGV[1]";
+        let sha = sha1_hex(body.as_bytes());
+        let reply = request(&mut conn, cmd::EvalSha::new(&sha, &[], vec![b("x")]).into_frame()).await;
+        assert_eq!(reply, err("NOSCRIPT No matching script. Please use EVAL."));
+
+        let reply = request(&mut conn, cmd::Script::load(body).into_frame()).await;
+        assert_eq!(reply, Frame::Bulk(b(&sha)));
+        let reply = request(&mut conn, cmd::EvalSha::new(&sha.to_uppercase(), &[], vec![b("x")]).into_frame()).await;
+        assert_eq!(reply, Frame::Bulk(b("x")));
+        let reply = request(&mut conn, cmd::Script::exists(&[&sha, "missing"]).into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![Frame::Integer(1), Frame::Integer(0)]));
+
+        // EVAL caches too
+        request(&mut conn, cmd::Eval::new("return 2", &[], vec![]).into_frame()).await;
+        let sha2 = sha1_hex(b"return 2");
+        let reply = request(&mut conn, cmd::EvalSha::new(&sha2, &[], vec![]).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(2));
+
+        assert_eq!(request(&mut conn, cmd::Script::Flush.into_frame()).await, "OK");
+        let reply = request(&mut conn, cmd::Script::exists(&[&sha, &sha2]).into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![Frame::Integer(0), Frame::Integer(0)]));
+    });
+}
+
+#[test]
+fn test_eval_in_transaction() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+        request(&mut conn, cmd::Multi::new().into_frame()).await;
+        request(&mut conn, cmd::Eval::new("return redis.call('INCR', KEYS[1])", &["n"], vec![]).into_frame()).await;
+        request(&mut conn, cmd::Incr::new("n").into_frame()).await;
+        let reply = request(&mut conn, cmd::Exec::new().into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![Frame::Integer(1), Frame::Integer(2)]));
+    });
+}
+
+#[test]
+fn test_script_kill() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut conn = connect(addr).await;
+        let mut other = connect(addr).await;
+        let reply = request(&mut other, cmd::Script::Kill.into_frame()).await;
+        assert_eq!(reply, err("NOTBUSY No scripts in execution right now."));
+        request(&mut conn, cmd::Config::set("lua-time-limit", 50).into_frame()).await;
+
+        conn.write_frame(cmd::Eval::new("while true do end", &[], vec![]).into_frame()).await.unwrap();
+        conn.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let reply = request(&mut other, cmd::Get::new("foo").into_frame()).await;
+        assert_eq!(reply, err(miniredis::script::BUSY));
+        assert_eq!(request(&mut other, cmd::Script::Kill.into_frame()).await, "OK");
+        let reply = conn.read_frame().await.unwrap();
+        assert_eq!(reply, err("ERR Script killed by user with SCRIPT KILL..."));
+        assert_eq!(request(&mut other, cmd::Get::new("foo").into_frame()).await, Frame::Null);
+
+        // a script that wrote can not be stopped half way
+        let script = "redis.call('SET', 'foo', 'bar') for i = 1, 1e8 do end";
+        conn.write_frame(cmd::Eval::new(script, &[], vec![]).into_frame()).await.unwrap();
+        conn.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let reply = request(&mut other, cmd::Script::Kill.into_frame()).await;
+        let Frame::Error(message) = reply else { panic!("{reply:?}") };
+        assert!(message.starts_with("UNKILLABLE"), "{message}");
+        assert_eq!(conn.read_frame().await.unwrap(), Frame::Null);
+        assert_eq!(request(&mut other, cmd::Get::new("foo").into_frame()).await, Frame::Bulk(b("bar")));
+    });
+}
+
+#[test]
+fn test_busy_while_waiting() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut conn = connect(addr).await;
+        let mut waiting = connect(addr).await;
+        let mut other = connect(addr).await;
+        request(&mut conn, cmd::Config::set("lua-time-limit", 100).into_frame()).await;
+
+        conn.write_frame(cmd::Eval::new("while true do end", &[], vec![]).into_frame()).await.unwrap();
+        conn.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        // sent before the time limit, it waits for the script without
+        // holding up the worker
+        waiting.write_frame(cmd::Get::new("foo").into_frame()).await.unwrap();
+        waiting.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(request(&mut other, cmd::Ping::new(None).into_frame()).await, err(miniredis::script::BUSY));
+        assert_eq!(request(&mut other, cmd::Script::Kill.into_frame()).await, "OK");
+        assert_eq!(waiting.read_frame().await.unwrap(), err(miniredis::script::BUSY));
+        let reply = conn.read_frame().await.unwrap();
+        assert_eq!(reply, err("ERR Script killed by user with SCRIPT KILL..."));
+    });
+}
+
+#[test]
+fn test_connect_while_busy() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut conn = connect(addr).await;
+        request(&mut conn, cmd::Config::set("lua-time-limit", 50).into_frame()).await;
+        conn.write_frame(cmd::Eval::new("while true do end", &[], vec![]).into_frame()).await.unwrap();
+        conn.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // a client connecting now is told the server is busy, and may kill the script
+        let mut late = connect(addr).await;
+        let reply = timeout(Duration::from_secs(1), request(&mut late, cmd::Ping::new(None).into_frame())).await;
+        assert_eq!(reply.unwrap(), err(miniredis::script::BUSY));
+        let reply = timeout(Duration::from_secs(1), request(&mut late, cmd::Script::Kill.into_frame())).await;
+        assert_eq!(reply.unwrap(), "OK");
+        let reply = conn.read_frame().await.unwrap();
+        assert_eq!(reply, err("ERR Script killed by user with SCRIPT KILL..."));
+    });
+}
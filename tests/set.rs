@@ -0,0 +1,157 @@
+mod common;
+
+use bytes::Bytes;
+use common::{command, connect, new_runtime, request, start_server, start_server_with_config};
+use miniredis::{cmd, database::Config, frame::Frame};
+
+fn b(s: &str) -> Bytes {
+    Bytes::from(s.to_string())
+}
+
+fn members(items: &[&str]) -> Vec<Bytes> {
+    items.iter().map(|item| b(item)).collect()
+}
+
+/// the members of an array reply, sorted, sets have no order
+fn sorted(frame: Frame) -> Vec<String> {
+    let frames = match frame {
+        Frame::Array(frames) => frames,
+        frame => panic!("unexpected reply {:?}", frame),
+    };
+    let mut members: Vec<String> = frames
+        .into_iter()
+        .map(|frame| match frame {
+            Frame::Bulk(member) => String::from_utf8(member.to_vec()).unwrap(),
+            frame => panic!("unexpected member {:?}", frame),
+        })
+        .collect();
+    members.sort();
+    members
+}
+
+#[test]
+fn test_add_remove_members() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        let reply = request(&mut conn, cmd::SAdd::new("s", members(&["a", "b", "a", "3"])).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(3));
+        assert_eq!(request(&mut conn, cmd::SCard::new("s").into_frame()).await, Frame::Integer(3));
+        assert_eq!(sorted(request(&mut conn, cmd::SMembers::new("s").into_frame()).await), ["3", "a", "b"]);
+        assert_eq!(request(&mut conn, cmd::SIsMember::new("s", b("a")).into_frame()).await, Frame::Integer(1));
+        let reply = request(&mut conn, cmd::SMIsMember::new("s", members(&["b", "c"])).into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![Frame::Integer(1), Frame::Integer(0)]));
+
+        let reply = request(&mut conn, cmd::SRem::new("s", members(&["a", "c"])).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(1));
+
+        // popping everything removes the key
+        let reply = request(&mut conn, cmd::SPop::new("s", Some(5)).into_frame()).await;
+        assert_eq!(sorted(reply), ["3", "b"]);
+        assert_eq!(request(&mut conn, cmd::Exists::new(&["s"]).into_frame()).await, Frame::Integer(0));
+        assert_eq!(request(&mut conn, cmd::SPop::new("s", None).into_frame()).await, Frame::Null);
+
+        request(&mut conn, cmd::Set::new("str", b("x")).into_frame()).await;
+        let reply = request(&mut conn, cmd::SAdd::new("str", members(&["a"])).into_frame()).await;
+        assert!(matches!(reply, Frame::Error(e) if e.starts_with("WRONGTYPE")));
+    });
+}
+
+#[test]
+fn test_randmember() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        request(&mut conn, cmd::SAdd::new("s", members(&["a", "b", "c"])).into_frame()).await;
+        let reply = request(&mut conn, cmd::SRandMember::new("s", Some(10)).into_frame()).await;
+        assert_eq!(sorted(reply), ["a", "b", "c"]);
+        let reply = request(&mut conn, cmd::SRandMember::new("s", Some(-10)).into_frame()).await;
+        assert_eq!(sorted(reply).len(), 10);
+        let reply = request(&mut conn, command(&["srandmember", "s", "-2000000"])).await;
+        assert_eq!(reply, Frame::Error("ERR value is out of range".to_string()));
+        let reply = request(&mut conn, cmd::SRandMember::new("s", None).into_frame()).await;
+        assert!(["a", "b", "c"].iter().any(|member| reply == *member));
+        assert_eq!(request(&mut conn, cmd::SCard::new("s").into_frame()).await, Frame::Integer(3));
+    });
+}
+
+#[test]
+fn test_algebra() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        request(&mut conn, cmd::SAdd::new("a", members(&["1", "2", "3", "x"])).into_frame()).await;
+        request(&mut conn, cmd::SAdd::new("b", members(&["2", "3", "4"])).into_frame()).await;
+        request(&mut conn, cmd::SAdd::new("c", members(&["3", "x", "2"])).into_frame()).await;
+
+        let reply = request(&mut conn, cmd::SInter::new(&["a", "b", "c"]).into_frame()).await;
+        assert_eq!(sorted(reply), ["2", "3"]);
+        let reply = request(&mut conn, cmd::SUnion::new(&["a", "b"]).into_frame()).await;
+        assert_eq!(sorted(reply), ["1", "2", "3", "4", "x"]);
+        let reply = request(&mut conn, cmd::SDiff::new(&["a", "b", "missing"]).into_frame()).await;
+        assert_eq!(sorted(reply), ["1", "x"]);
+
+        // a missing key is an empty set
+        let reply = request(&mut conn, cmd::SInter::new(&["a", "missing"]).into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![]));
+
+        let reply = request(&mut conn, cmd::SInterCard::new(&["a", "b", "c"], 0).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(2));
+        let reply = request(&mut conn, cmd::SInterCard::new(&["a", "b", "c"], 1).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(1));
+
+        // the destination may be one of the sources
+        let reply = request(&mut conn, cmd::SUnionStore::new("a", &["a", "b"]).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(5));
+        let reply = request(&mut conn, cmd::SInterStore::new("d", &["a", "c"]).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(3));
+        assert_eq!(sorted(request(&mut conn, cmd::SMembers::new("d").into_frame()).await), ["2", "3", "x"]);
+
+        // an empty result deletes the destination
+        let reply = request(&mut conn, cmd::SDiffStore::new("d", &["b", "a"]).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(0));
+        assert_eq!(request(&mut conn, cmd::Exists::new(&["d"]).into_frame()).await, Frame::Integer(0));
+
+        request(&mut conn, cmd::Set::new("str", b("x")).into_frame()).await;
+        let reply = request(&mut conn, cmd::SUnion::new(&["missing", "str"]).into_frame()).await;
+        assert!(matches!(reply, Frame::Error(e) if e.starts_with("WRONGTYPE")));
+    });
+}
+
+#[test]
+fn test_scan_past_intset_limit() {
+    let config = Config { set_max_intset_entries: 8, ..Config::default() };
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server_with_config(config).await).await;
+
+        let all: Vec<String> = (0..100).map(|i| i.to_string()).collect();
+        let reply = request(&mut conn, cmd::SAdd::new("s", all.iter().map(|m| b(m)).collect()).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(100));
+
+        let mut seen = vec![];
+        let mut cursor = 0;
+        loop {
+            let reply = request(&mut conn, cmd::SScan::new("s", cursor).count(10).into_frame()).await;
+            let (next, page) = match reply {
+                Frame::Array(mut parts) => {
+                    let page = parts.pop().unwrap();
+                    (parts.pop().unwrap(), page)
+                }
+                frame => panic!("unexpected reply {:?}", frame),
+            };
+            seen.extend(sorted(page));
+            cursor = match next {
+                Frame::Bulk(next) => std::str::from_utf8(&next).unwrap().parse().unwrap(),
+                frame => panic!("unexpected cursor {:?}", frame),
+            };
+            if cursor == 0 {
+                break;
+            }
+        }
+        seen.sort();
+        seen.dedup();
+        let mut expected = all.clone();
+        expected.sort();
+        assert_eq!(seen, expected);
+    });
+}
@@ -0,0 +1,194 @@
+mod common;
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use common::{command, connect, new_runtime, request, start_server};
+use miniredis::{
+    cmd::{self, ReadFrom},
+    connection::Connection,
+    frame::Frame,
+    types::stream::{StreamId, Trim},
+};
+use tokio::time::timeout;
+
+fn b(s: &str) -> Bytes {
+    Bytes::from(s.to_string())
+}
+
+fn bulk(s: &str) -> Frame {
+    Frame::Bulk(b(s))
+}
+
+fn entry(id: &str, fields: &[&str]) -> Frame {
+    Frame::Array(vec![bulk(id), Frame::Array(fields.iter().map(|f| bulk(f)).collect())])
+}
+
+fn xadd(key: &str, ms: u64, seq: u64, value: &str) -> Frame {
+    cmd::XAdd::new(key).id(StreamId::new(ms, seq)).field(b("f"), b(value)).into_frame()
+}
+
+#[test]
+fn test_xadd_ids_and_ranges() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        assert_eq!(request(&mut conn, xadd("s", 1, 1, "a")).await, "1-1");
+        assert_eq!(request(&mut conn, command(&["xadd", "s", "1-*", "f", "b"])).await, "1-2");
+        assert_eq!(request(&mut conn, command(&["xadd", "s", "3", "f", "c"])).await, "3-0");
+        let reply = request(&mut conn, xadd("s", 2, 0, "x")).await;
+        let err = "ERR The ID specified in XADD is equal or smaller than the target stream top item";
+        assert_eq!(reply, Frame::Error(err.to_string()));
+        let reply = request(&mut conn, xadd("empty", 0, 0, "x")).await;
+        assert_eq!(reply, Frame::Error("ERR The ID specified in XADD must be greater than 0-0".to_string()));
+        assert_eq!(request(&mut conn, cmd::Exists::new(&["empty"]).into_frame()).await, Frame::Integer(0));
+        let reply = request(&mut conn, cmd::XAdd::new("nope").nomkstream().field(b("f"), b("v")).into_frame()).await;
+        assert_eq!(reply, Frame::Null);
+
+        // auto IDs are millisecond times
+        let Frame::Bulk(id) = request(&mut conn, command(&["xadd", "s", "*", "f", "d"])).await else { panic!() };
+        assert!(StreamId::parse(&id, 0).unwrap() > StreamId::new(3, 0));
+        assert_eq!(request(&mut conn, cmd::XLen::new("s").into_frame()).await, Frame::Integer(4));
+
+        let reply = request(&mut conn, command(&["xrange", "s", "-", "3"])).await;
+        let expected = vec![entry("1-1", &["f", "a"]), entry("1-2", &["f", "b"]), entry("3-0", &["f", "c"])];
+        assert_eq!(reply, Frame::Array(expected));
+        let reply = request(&mut conn, command(&["xrange", "s", "(1-1", "+", "count", "1"])).await;
+        assert_eq!(reply, Frame::Array(vec![entry("1-2", &["f", "b"])]));
+        let reply = request(&mut conn, command(&["xrevrange", "s", "3", "1", "count", "2"])).await;
+        assert_eq!(reply, Frame::Array(vec![entry("3-0", &["f", "c"]), entry("1-2", &["f", "b"])]));
+
+        let xdel = cmd::XDel::new("s", vec![StreamId::new(1, 2), StreamId::new(9, 9)]);
+        assert_eq!(request(&mut conn, xdel.into_frame()).await, Frame::Integer(1));
+        // the last ID stays the top even once deleted
+        request(&mut conn, command(&["xdel", "s", &String::from_utf8_lossy(&id)])).await;
+        let reply = request(&mut conn, xadd("s", 3, 1, "x")).await;
+        assert_eq!(reply, Frame::Error(err.to_string()));
+    });
+}
+
+#[test]
+fn test_trim() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        for i in 1..=10 {
+            request(&mut conn, xadd("s", i, 0, "v")).await;
+        }
+        let xtrim = cmd::XTrim::new("s", Trim::MaxLen(7));
+        assert_eq!(request(&mut conn, xtrim.into_frame()).await, Frame::Integer(3));
+        let xtrim = cmd::XTrim::new("s", Trim::MinId(StreamId::new(6, 0)));
+        assert_eq!(request(&mut conn, xtrim.into_frame()).await, Frame::Integer(2));
+        let reply = request(&mut conn, command(&["xtrim", "s", "maxlen", "~", "0", "limit", "2"])).await;
+        assert_eq!(reply, Frame::Integer(2));
+        let reply = request(&mut conn, command(&["xtrim", "s", "maxlen", "0", "limit", "2"])).await;
+        let err = "ERR syntax error, LIMIT cannot be used without the special ~ option";
+        assert_eq!(reply, Frame::Error(err.to_string()));
+
+        let xadd = cmd::XAdd::new("s").trim(Trim::MaxLen(1)).id(StreamId::new(11, 0)).field(b("f"), b("v"));
+        request(&mut conn, xadd.into_frame()).await;
+        assert_eq!(request(&mut conn, cmd::XLen::new("s").into_frame()).await, Frame::Integer(1));
+    });
+}
+
+async fn next_reply(conn: &mut Connection) -> Frame {
+    timeout(Duration::from_secs(5), conn.read_frame()).await.unwrap().unwrap()
+}
+
+#[test]
+fn test_xread_blocks_for_new_entries() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut conn = connect(addr).await;
+
+        request(&mut conn, xadd("s", 1, 0, "old")).await;
+        let xread = cmd::XRead::new(&[("s", ReadFrom::After(StreamId::MIN)), ("t", ReadFrom::Last)]);
+        let reply = request(&mut conn, xread.into_frame()).await;
+        let expected = Frame::Array(vec![bulk("s"), Frame::Array(vec![entry("1-0", &["f", "old"])])]);
+        assert_eq!(reply, Frame::Array(vec![expected]));
+
+        let xread = cmd::XRead::new(&[("s", ReadFrom::Last)]).block(Some(Duration::from_millis(100)));
+        assert_eq!(request(&mut conn, xread.into_frame()).await, Frame::NullArray);
+
+        // `$` is the last ID when the client blocks, not when it wakes up
+        let xread = cmd::XRead::new(&[("s", ReadFrom::Last)]).block(None);
+        conn.write_frame(xread.into_frame()).await.unwrap();
+        conn.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let mut producer = connect(addr).await;
+        request(&mut producer, xadd("s", 2, 0, "new")).await;
+        let expected = Frame::Array(vec![bulk("s"), Frame::Array(vec![entry("2-0", &["f", "new"])])]);
+        assert_eq!(next_reply(&mut conn).await, Frame::Array(vec![expected]));
+
+        let reply = request(&mut conn, command(&["xread", "streams", "s", "t", "0"])).await;
+        let err = "ERR Unbalanced 'xread' list of streams: for each stream key an ID or '$' must be specified.";
+        assert_eq!(reply, Frame::Error(err.to_string()));
+    });
+}
+
+#[test]
+fn test_consumer_groups() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        let reply = request(&mut conn, command(&["xgroup", "create", "s", "g", "$"])).await;
+        assert!(matches!(reply, Frame::Error(e) if e.starts_with("ERR The XGROUP subcommand requires the key")));
+        let reply = request(&mut conn, command(&["xgroup", "create", "s", "g", "$", "mkstream"])).await;
+        assert_eq!(reply, "OK");
+        let reply = request(&mut conn, command(&["xgroup", "create", "s", "g", "0"])).await;
+        assert_eq!(reply, Frame::Error("BUSYGROUP Consumer Group name already exists".to_string()));
+        for i in 1..=3 {
+            request(&mut conn, xadd("s", i, 0, "v")).await;
+        }
+
+        // new entries go to the consumer that reads them
+        let xreadgroup = cmd::XReadGroup::new(b("g"), b("alice"), &[("s", None)]).count(2);
+        let entries = Frame::Array(vec![entry("1-0", &["f", "v"]), entry("2-0", &["f", "v"])]);
+        let reply = request(&mut conn, xreadgroup.into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![Frame::Array(vec![bulk("s"), entries])]));
+        let reply = request(&mut conn, command(&["xreadgroup", "group", "g", "bob", "streams", "s", ">"])).await;
+        let entries = Frame::Array(vec![entry("3-0", &["f", "v"])]);
+        assert_eq!(reply, Frame::Array(vec![Frame::Array(vec![bulk("s"), entries])]));
+        let reply = request(&mut conn, command(&["xreadgroup", "group", "g", "bob", "streams", "s", ">"])).await;
+        assert_eq!(reply, Frame::NullArray);
+
+        let reply = request(&mut conn, cmd::XPending::new("s", b("g")).into_frame()).await;
+        let consumers = Frame::Array(vec![
+            Frame::Array(vec![bulk("alice"), bulk("2")]),
+            Frame::Array(vec![bulk("bob"), bulk("1")]),
+        ]);
+        assert_eq!(reply, Frame::Array(vec![Frame::Integer(3), bulk("1-0"), bulk("3-0"), consumers]));
+
+        let xack = cmd::XAck::new("s", b("g"), vec![StreamId::new(1, 0), StreamId::new(1, 0)]);
+        assert_eq!(request(&mut conn, xack.into_frame()).await, Frame::Integer(1));
+        // the history of a consumer, a deleted entry has no fields
+        request(&mut conn, command(&["xdel", "s", "2-0"])).await;
+        let reply = request(&mut conn, command(&["xreadgroup", "group", "g", "alice", "streams", "s", "0"])).await;
+        let entries = Frame::Array(vec![Frame::Array(vec![bulk("2-0"), Frame::Null])]);
+        assert_eq!(reply, Frame::Array(vec![Frame::Array(vec![bulk("s"), entries])]));
+
+        // bob's entry moves to alice, the deleted one is dropped
+        let xclaim = cmd::XClaim::new("s", b("g"), b("alice"), 0, vec![StreamId::new(3, 0)]).justid();
+        assert_eq!(request(&mut conn, xclaim.into_frame()).await, Frame::Array(vec![bulk("3-0")]));
+        let reply = request(&mut conn, command(&["xpending", "s", "g", "-", "+", "10", "bob"])).await;
+        assert_eq!(reply, Frame::Array(vec![]));
+        let xautoclaim = cmd::XAutoClaim::new("s", b("g"), b("bob"), 0, StreamId::MIN);
+        let reply = request(&mut conn, xautoclaim.into_frame()).await;
+        let claimed = Frame::Array(vec![entry("3-0", &["f", "v"])]);
+        assert_eq!(reply, Frame::Array(vec![bulk("0-0"), claimed, Frame::Array(vec![bulk("2-0")])]));
+
+        let reply = request(&mut conn, command(&["xpending", "s", "g", "-", "+", "10"])).await;
+        let Frame::Array(pending) = reply else { panic!() };
+        assert_eq!(pending.len(), 1);
+        let Frame::Array(fields) = &pending[0] else { panic!() };
+        assert_eq!((&fields[0], &fields[1], &fields[3]), (&bulk("3-0"), &bulk("bob"), &Frame::Integer(2)));
+
+        let reply = request(&mut conn, command(&["xgroup", "delconsumer", "s", "g", "bob"])).await;
+        assert_eq!(reply, Frame::Integer(1));
+        let reply = request(&mut conn, command(&["xreadgroup", "group", "x", "bob", "streams", "s", ">"])).await;
+        let err = "NOGROUP No such key 's' or consumer group 'x' in XREADGROUP with GROUP option";
+        assert_eq!(reply, Frame::Error(err.to_string()));
+        let reply = request(&mut conn, command(&["xgroup", "destroy", "s", "g"])).await;
+        assert_eq!(reply, Frame::Integer(1));
+    });
+}
@@ -0,0 +1,229 @@
+mod common;
+
+use bytes::Bytes;
+use common::{command, connect, new_runtime, request, start_server};
+use miniredis::{cmd, frame::Frame};
+
+#[test]
+fn test_counters() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        let reply = request(&mut conn, cmd::Incr::new("hits").into_frame()).await;
+        assert_eq!(reply, Frame::Integer(1));
+        let reply = request(&mut conn, cmd::IncrBy::new("hits", 41).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(42));
+        let reply = request(&mut conn, cmd::DecrBy::new("hits", 50).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(-8));
+        let reply = request(&mut conn, cmd::Decr::new("hits").into_frame()).await;
+        assert_eq!(reply, Frame::Integer(-9));
+        let reply = request(&mut conn, cmd::Get::new("hits").into_frame()).await;
+        assert_eq!(reply, "-9");
+
+        request(&mut conn, cmd::Set::new("max", Bytes::from(i64::MAX.to_string())).into_frame()).await;
+        let reply = request(&mut conn, cmd::Incr::new("max").into_frame()).await;
+        assert_eq!(reply, Frame::Error("ERR increment or decrement would overflow".to_string()));
+
+        request(&mut conn, cmd::Set::new("name", Bytes::from("simon")).into_frame()).await;
+        let reply = request(&mut conn, cmd::Incr::new("name").into_frame()).await;
+        assert_eq!(reply, Frame::Error("ERR value is not an integer or out of range".to_string()));
+        let reply = request(&mut conn, command(&["incrby", "hits", "1.5"])).await;
+        assert!(matches!(reply, Frame::Error(_)));
+
+        // only the canonical form of an integer is one
+        for value in ["012", "+12", " 1"] {
+            request(&mut conn, cmd::Set::new("loose", Bytes::from(value)).into_frame()).await;
+            let reply = request(&mut conn, cmd::Incr::new("loose").into_frame()).await;
+            assert_eq!(reply, Frame::Error("ERR value is not an integer or out of range".to_string()), "{value:?}");
+        }
+    });
+}
+
+#[test]
+fn test_incrbyfloat() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        let reply = request(&mut conn, cmd::IncrByFloat::new("f", 10.5).into_frame()).await;
+        assert_eq!(reply, "10.5");
+        let reply = request(&mut conn, cmd::IncrByFloat::new("f", 0.1).into_frame()).await;
+        assert_eq!(reply, "10.6");
+        request(&mut conn, cmd::Set::new("f", Bytes::from("5.0e3")).into_frame()).await;
+        let reply = request(&mut conn, cmd::IncrByFloat::new("f", 200.0).into_frame()).await;
+        assert_eq!(reply, "5200");
+
+        let reply = request(&mut conn, command(&["incrbyfloat", "f", "inf"])).await;
+        assert_eq!(reply, Frame::Error("ERR increment would produce NaN or Infinity".to_string()));
+        let reply = request(&mut conn, command(&["incrbyfloat", "f", "nan"])).await;
+        assert!(matches!(reply, Frame::Error(_)));
+    });
+}
+
+#[test]
+fn test_append_and_ranges() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        let reply = request(&mut conn, cmd::Append::new("log", Bytes::from("Hello")).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(5));
+        let reply = request(&mut conn, cmd::Append::new("log", Bytes::from(" World")).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(11));
+        let reply = request(&mut conn, cmd::Strlen::new("log").into_frame()).await;
+        assert_eq!(reply, Frame::Integer(11));
+        let reply = request(&mut conn, cmd::Strlen::new("missing").into_frame()).await;
+        assert_eq!(reply, Frame::Integer(0));
+
+        let reply = request(&mut conn, cmd::GetRange::new("log", 0, 4).into_frame()).await;
+        assert_eq!(reply, "Hello");
+        let reply = request(&mut conn, cmd::GetRange::new("log", -5, -1).into_frame()).await;
+        assert_eq!(reply, "World");
+        let reply = request(&mut conn, cmd::GetRange::new("log", 5, 2).into_frame()).await;
+        assert_eq!(reply, "");
+
+        let reply = request(&mut conn, cmd::SetRange::new("log", 6, Bytes::from("Redis")).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(11));
+        let reply = request(&mut conn, cmd::Get::new("log").into_frame()).await;
+        assert_eq!(reply, "Hello Redis");
+
+        let reply = request(&mut conn, cmd::SetRange::new("pad", 3, Bytes::from("x")).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(4));
+        let reply = request(&mut conn, cmd::Get::new("pad").into_frame()).await;
+        assert_eq!(reply, Frame::Bulk(Bytes::from(&b"\0\0\0x"[..])));
+
+        let reply = request(&mut conn, cmd::SetRange::new("none", 3, Bytes::new()).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(0));
+        let reply = request(&mut conn, cmd::Get::new("none").into_frame()).await;
+        assert_eq!(reply, Frame::Null);
+        let reply = request(&mut conn, cmd::SetRange::new("log", -1, Bytes::from("x")).into_frame()).await;
+        assert_eq!(reply, Frame::Error("ERR offset is out of range".to_string()));
+    });
+}
+
+#[test]
+fn test_bad_requests() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        let reply = request(&mut conn, command(&["nosuchcmd", "x"])).await;
+        assert_eq!(reply, Frame::Error("ERR unknown command 'nosuchcmd'".to_string()));
+        let reply = request(&mut conn, command(&["strlen"])).await;
+        assert_eq!(reply, Frame::Error("ERR wrong number of arguments".to_string()));
+        let reply = request(&mut conn, command(&["strlen", "a", "b"])).await;
+        assert_eq!(reply, Frame::Error("ERR wrong number of arguments".to_string()));
+
+        // the connection is still usable
+        let reply = request(&mut conn, cmd::Incr::new("n").into_frame()).await;
+        assert_eq!(reply, Frame::Integer(1));
+    });
+}
+
+#[test]
+fn test_mget_mset() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        let sets = vec![
+            cmd::Set::new("a", Bytes::from("1")),
+            cmd::Set::new("b", Bytes::from("2")),
+        ];
+        let reply = request(&mut conn, cmd::MSet::new(sets).into_frame()).await;
+        assert_eq!(reply, "OK");
+        let reply = request(&mut conn, cmd::MGet::new(&["a", "missing", "b"]).into_frame()).await;
+        assert_eq!(
+            reply,
+            Frame::Array(vec![Frame::Bulk("1".into()), Frame::Null, Frame::Bulk("2".into())])
+        );
+
+        let sets = vec![
+            cmd::Set::new("c", Bytes::from("3")),
+            cmd::Set::new("a", Bytes::from("x")),
+        ];
+        let reply = request(&mut conn, cmd::MSetNx::new(sets).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(0));
+        let reply = request(&mut conn, cmd::MGet::new(&["a", "c"]).into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![Frame::Bulk("1".into()), Frame::Null]));
+
+        let sets = vec![cmd::Set::new("c", Bytes::from("3")), cmd::Set::new("d", Bytes::from("4"))];
+        let reply = request(&mut conn, cmd::MSetNx::new(sets).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(1));
+
+        let reply = request(&mut conn, command(&["mset", "a", "1", "b"])).await;
+        assert_eq!(reply, Frame::Error("ERR wrong number of arguments".to_string()));
+    });
+}
+
+#[test]
+fn test_mset_is_atomic() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut writer = connect(addr).await;
+        let mut reader = connect(addr).await;
+
+        let writes = tokio::spawn(async move {
+            for i in 0..200 {
+                let value = Bytes::from(i.to_string());
+                let sets = vec![cmd::Set::new("x", value.clone()), cmd::Set::new("y", value)];
+                request(&mut writer, cmd::MSet::new(sets).into_frame()).await;
+            }
+        });
+        for _ in 0..200 {
+            let reply = request(&mut reader, cmd::MGet::new(&["x", "y"]).into_frame()).await;
+            match reply {
+                Frame::Array(values) => assert_eq!(values[0], values[1]),
+                reply => panic!("unexpected reply {:?}", reply),
+            }
+        }
+        writes.await.unwrap();
+    });
+}
+
+#[test]
+fn test_getset_getdel_getex() {
+    use std::time::Duration;
+
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        let reply = request(&mut conn, cmd::GetSet::new("k", Bytes::from("1")).into_frame()).await;
+        assert_eq!(reply, Frame::Null);
+        let reply = request(&mut conn, cmd::GetSet::new("k", Bytes::from("2")).into_frame()).await;
+        assert_eq!(reply, "1");
+        let reply = request(&mut conn, cmd::GetDel::new("k").into_frame()).await;
+        assert_eq!(reply, "2");
+        let reply = request(&mut conn, cmd::GetDel::new("k").into_frame()).await;
+        assert_eq!(reply, Frame::Null);
+
+        request(&mut conn, cmd::Set::new("k", Bytes::from("v")).into_frame()).await;
+        let reply = request(&mut conn, cmd::GetEx::new("k", None).into_frame()).await;
+        assert_eq!(reply, "v");
+        let reply = request(&mut conn, cmd::GetEx::new("k", Some(cmd::Expiry::Px(50))).into_frame()).await;
+        assert_eq!(reply, "v");
+        let reply = request(&mut conn, cmd::GetEx::new("k", Some(cmd::Expiry::Persist)).into_frame()).await;
+        assert_eq!(reply, "v");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let reply = request(&mut conn, cmd::Get::new("k").into_frame()).await;
+        assert_eq!(reply, "v");
+
+        request(&mut conn, cmd::GetEx::new("k", Some(cmd::Expiry::Px(50))).into_frame()).await;
+        // modifying the value keeps the time to live
+        request(&mut conn, cmd::Append::new("k", Bytes::from("w")).into_frame()).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let reply = request(&mut conn, cmd::Get::new("k").into_frame()).await;
+        assert_eq!(reply, Frame::Null);
+
+        request(&mut conn, cmd::Set::new("k", Bytes::from("v")).into_frame()).await;
+        let reply = request(&mut conn, cmd::GetEx::new("k", Some(cmd::Expiry::PxAt(1))).into_frame()).await;
+        assert_eq!(reply, "v");
+        let reply = request(&mut conn, cmd::Get::new("k").into_frame()).await;
+        assert_eq!(reply, Frame::Null);
+
+        let reply = request(&mut conn, command(&["getex", "k", "ex", "0"])).await;
+        assert_eq!(reply, Frame::Error("ERR invalid expire time".to_string()));
+        let reply = request(&mut conn, command(&["getex", "k", "later", "1"])).await;
+        assert_eq!(reply, Frame::Error("ERR syntax error".to_string()));
+        let reply = request(&mut conn, command(&["getex", "k", "ex", "10", "px", "10"])).await;
+        assert_eq!(reply, Frame::Error("ERR syntax error".to_string()));
+        let reply = request(&mut conn, command(&["getex", "k", "persist", "ex", "10"])).await;
+        assert_eq!(reply, Frame::Error("ERR syntax error".to_string()));
+    });
+}
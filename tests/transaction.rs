@@ -0,0 +1,163 @@
+mod common;
+
+use bytes::Bytes;
+use common::{command, connect, new_runtime, request, start_server};
+use miniredis::{
+    cmd::{self, Expiry},
+    frame::Frame,
+};
+
+fn b(s: &str) -> Bytes {
+    Bytes::from(s.to_string())
+}
+
+fn queued() -> Frame {
+    Frame::Simple("QUEUED".to_string())
+}
+
+#[test]
+fn test_multi_exec() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut conn = connect(addr).await;
+        let mut other = connect(addr).await;
+        assert_eq!(request(&mut conn, cmd::Multi::new().into_frame()).await, "OK");
+        assert_eq!(request(&mut conn, cmd::Set::new("balance", b("100")).into_frame()).await, queued());
+        assert_eq!(request(&mut conn, cmd::IncrBy::new("balance", -30).into_frame()).await, queued());
+        assert_eq!(request(&mut conn, cmd::LPush::new("balance", vec![b("a")]).into_frame()).await, queued());
+        // nothing runs before EXEC
+        assert_eq!(request(&mut other, cmd::Get::new("balance").into_frame()).await, Frame::Null);
+
+        let reply = request(&mut conn, cmd::Exec::new().into_frame()).await;
+        let wrongtype = "WRONGTYPE Operation against a key holding the wrong kind of value";
+        // a command failing at run time does not stop the others
+        assert_eq!(
+            reply,
+            Frame::Array(vec![
+                Frame::Simple("OK".to_string()),
+                Frame::Integer(70),
+                Frame::Error(wrongtype.to_string())
+            ])
+        );
+        assert_eq!(request(&mut other, cmd::Get::new("balance").into_frame()).await, Frame::Bulk(b("70")));
+
+        // blocking commands do not block
+        request(&mut conn, cmd::Multi::new().into_frame()).await;
+        request(&mut conn, cmd::BLPop::new(&["empty"], None).into_frame()).await;
+        assert_eq!(request(&mut conn, cmd::Exec::new().into_frame()).await, Frame::Array(vec![Frame::NullArray]));
+    });
+}
+
+#[test]
+fn test_exec_abort() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+        request(&mut conn, cmd::Multi::new().into_frame()).await;
+        request(&mut conn, cmd::Set::new("foo", b("bar")).into_frame()).await;
+        let reply = request(&mut conn, command(&["nosuchcommand"])).await;
+        assert_eq!(reply, Frame::Error("ERR unknown command 'nosuchcommand'".to_string()));
+        let reply = request(&mut conn, cmd::Subscribe::new(&["news"]).into_frame()).await;
+        assert_eq!(reply, Frame::Error("ERR Command not allowed inside a transaction".to_string()));
+        let reply = request(&mut conn, cmd::Exec::new().into_frame()).await;
+        let err = "EXECABORT Transaction discarded because of previous errors.";
+        assert_eq!(reply, Frame::Error(err.to_string()));
+        assert_eq!(request(&mut conn, cmd::Get::new("foo").into_frame()).await, Frame::Null);
+    });
+}
+
+#[test]
+fn test_discard_and_errors() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+        let reply = request(&mut conn, cmd::Exec::new().into_frame()).await;
+        assert_eq!(reply, Frame::Error("ERR EXEC without MULTI".to_string()));
+        let reply = request(&mut conn, cmd::Discard::new().into_frame()).await;
+        assert_eq!(reply, Frame::Error("ERR DISCARD without MULTI".to_string()));
+
+        request(&mut conn, cmd::Multi::new().into_frame()).await;
+        let reply = request(&mut conn, cmd::Multi::new().into_frame()).await;
+        assert_eq!(reply, Frame::Error("ERR MULTI calls can not be nested".to_string()));
+        let reply = request(&mut conn, cmd::Watch::new(&["foo"]).into_frame()).await;
+        assert_eq!(reply, Frame::Error("ERR WATCH inside MULTI is not allowed".to_string()));
+        request(&mut conn, cmd::Set::new("foo", b("bar")).into_frame()).await;
+        assert_eq!(request(&mut conn, cmd::Discard::new().into_frame()).await, "OK");
+        assert_eq!(request(&mut conn, cmd::Get::new("foo").into_frame()).await, Frame::Null);
+    });
+}
+
+#[test]
+fn test_watch() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut conn = connect(addr).await;
+        let mut other = connect(addr).await;
+        request(&mut conn, cmd::Set::new("balance", b("100")).into_frame()).await;
+
+        // check-and-set, unchanged
+        assert_eq!(request(&mut conn, cmd::Watch::new(&["balance", "missing"]).into_frame()).await, "OK");
+        request(&mut conn, cmd::Multi::new().into_frame()).await;
+        request(&mut conn, cmd::Set::new("balance", b("90")).into_frame()).await;
+        let reply = request(&mut conn, cmd::Exec::new().into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![Frame::Simple("OK".to_string())]));
+
+        // changed by another client, even to the same value
+        request(&mut conn, cmd::Watch::new(&["balance"]).into_frame()).await;
+        request(&mut other, cmd::Set::new("balance", b("90")).into_frame()).await;
+        request(&mut conn, cmd::Multi::new().into_frame()).await;
+        request(&mut conn, cmd::Set::new("balance", b("80")).into_frame()).await;
+        assert_eq!(request(&mut conn, cmd::Exec::new().into_frame()).await, Frame::NullArray);
+        assert_eq!(request(&mut conn, cmd::Get::new("balance").into_frame()).await, Frame::Bulk(b("90")));
+
+        // EXEC unwatched everything
+        request(&mut conn, cmd::Multi::new().into_frame()).await;
+        request(&mut conn, cmd::Set::new("balance", b("80")).into_frame()).await;
+        let reply = request(&mut conn, cmd::Exec::new().into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![Frame::Simple("OK".to_string())]));
+
+        // so does UNWATCH, and a missing key that is created changed too
+        request(&mut conn, cmd::Watch::new(&["balance"]).into_frame()).await;
+        assert_eq!(request(&mut conn, cmd::Unwatch::new().into_frame()).await, "OK");
+        request(&mut conn, cmd::Watch::new(&["created"]).into_frame()).await;
+        request(&mut other, cmd::Set::new("balance", b("0")).into_frame()).await;
+        request(&mut other, cmd::RPush::new("created", vec![b("a")]).into_frame()).await;
+        request(&mut conn, cmd::Multi::new().into_frame()).await;
+        request(&mut conn, cmd::Get::new("balance").into_frame()).await;
+        assert_eq!(request(&mut conn, cmd::Exec::new().into_frame()).await, Frame::NullArray);
+    });
+}
+
+#[test]
+fn test_watch_ignores_reads_and_no_ops() {
+    new_runtime().block_on(async {
+        let addr = start_server().await;
+        let mut conn = connect(addr).await;
+        let mut other = connect(addr).await;
+        request(&mut conn, cmd::RPush::new("list", vec![b("a")]).into_frame()).await;
+        request(&mut conn, cmd::Watch::new(&["list", "missing"]).into_frame()).await;
+        assert_eq!(request(&mut other, cmd::LPop::new("missing", None).into_frame()).await, Frame::Null);
+        assert_eq!(request(&mut other, cmd::LRem::new("list", 0, b("z")).into_frame()).await, Frame::Integer(0));
+        request(&mut other, cmd::LRange::new("list", 0, -1).into_frame()).await;
+        // nor does a blocking pop that times out
+        let blpop = cmd::BLPop::new(&["missing"], Some(std::time::Duration::from_millis(50)));
+        request(&mut other, blpop.into_frame()).await;
+
+        request(&mut conn, cmd::Multi::new().into_frame()).await;
+        request(&mut conn, cmd::LPop::new("list", None).into_frame()).await;
+        assert_eq!(request(&mut conn, cmd::Exec::new().into_frame()).await, Frame::Array(vec![Frame::Bulk(b("a"))]));
+    });
+}
+
+#[test]
+fn test_watch_expired() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+        request(&mut conn, cmd::Set::new("session", b("v")).into_frame()).await;
+        request(&mut conn, cmd::GetEx::new("session", Some(Expiry::Px(20))).into_frame()).await;
+        request(&mut conn, cmd::Watch::new(&["session"]).into_frame()).await;
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        request(&mut conn, cmd::Multi::new().into_frame()).await;
+        request(&mut conn, cmd::Set::new("session", b("w")).into_frame()).await;
+        assert_eq!(request(&mut conn, cmd::Exec::new().into_frame()).await, Frame::NullArray);
+    });
+}
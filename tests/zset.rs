@@ -0,0 +1,178 @@
+mod common;
+
+use bytes::Bytes;
+use common::{command, connect, new_runtime, request, start_server};
+use miniredis::{
+    cmd::{self, Aggregate, ZRangeBy},
+    frame::Frame,
+    types::zset::{LexBound, LexRange, ScoreRange},
+};
+
+fn b(s: &str) -> Bytes {
+    Bytes::from(s.to_string())
+}
+
+fn bulks(items: &[&str]) -> Frame {
+    Frame::Array(items.iter().map(|item| Frame::Bulk(b(item))).collect())
+}
+
+#[test]
+fn test_zadd_flags() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        let zadd = cmd::ZAdd::new("z").member(1.0, b("a")).member(2.0, b("b"));
+        assert_eq!(request(&mut conn, zadd.into_frame()).await, Frame::Integer(2));
+
+        // NX never updates, XX never adds
+        let zadd = cmd::ZAdd::new("z").nx().member(5.0, b("a")).member(3.0, b("c"));
+        assert_eq!(request(&mut conn, zadd.into_frame()).await, Frame::Integer(1));
+        let zadd = cmd::ZAdd::new("z").xx().ch().member(5.0, b("a")).member(4.0, b("d"));
+        assert_eq!(request(&mut conn, zadd.into_frame()).await, Frame::Integer(1));
+        assert_eq!(request(&mut conn, cmd::ZScore::new("z", b("a")).into_frame()).await, "5");
+        assert_eq!(request(&mut conn, cmd::ZScore::new("z", b("d")).into_frame()).await, Frame::Null);
+
+        // GT and LT only move scores one way, but still add members
+        let zadd = cmd::ZAdd::new("z").gt().ch().member(1.0, b("a")).member(6.0, b("b")).member(0.5, b("e"));
+        assert_eq!(request(&mut conn, zadd.into_frame()).await, Frame::Integer(2));
+        let zadd = cmd::ZAdd::new("z").lt().incr().member(1.0, b("a"));
+        assert_eq!(request(&mut conn, zadd.into_frame()).await, Frame::Null);
+        let zadd = cmd::ZAdd::new("z").incr().member(-1.5, b("a"));
+        assert_eq!(request(&mut conn, zadd.into_frame()).await, "3.5");
+        assert_eq!(request(&mut conn, cmd::ZIncrBy::new("z", 2.0, b("new")).into_frame()).await, "2");
+
+        let reply = request(&mut conn, command(&["zadd", "z", "nx", "xx", "1", "a"])).await;
+        assert!(matches!(reply, Frame::Error(e) if e.contains("not compatible")));
+        let reply = request(&mut conn, command(&["zadd", "z", "incr", "1", "a", "2", "b"])).await;
+        assert!(matches!(reply, Frame::Error(e) if e.contains("single increment-element pair")));
+        let reply = request(&mut conn, command(&["zadd", "z", "nan", "a"])).await;
+        assert!(matches!(reply, Frame::Error(_)));
+
+        let reply = request(&mut conn, cmd::ZMScore::new("z", vec![b("e"), b("x")]).into_frame()).await;
+        assert_eq!(reply, Frame::Array(vec![Frame::Bulk(b("0.5")), Frame::Null]));
+        assert_eq!(request(&mut conn, cmd::ZCard::new("z").into_frame()).await, Frame::Integer(5));
+    });
+}
+
+#[test]
+fn test_ranges_and_ranks() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        let mut zadd = cmd::ZAdd::new("z");
+        for (i, member) in ["a", "b", "c", "d", "e"].iter().enumerate() {
+            zadd = zadd.member(i as f64, b(member));
+        }
+        request(&mut conn, zadd.into_frame()).await;
+
+        assert_eq!(request(&mut conn, cmd::ZRank::new("z", b("c")).into_frame()).await, Frame::Integer(2));
+        assert_eq!(request(&mut conn, cmd::ZRank::new("z", b("a")).rev().into_frame()).await, Frame::Integer(4));
+        assert_eq!(request(&mut conn, cmd::ZRank::new("z", b("x")).into_frame()).await, Frame::Null);
+
+        let zrange = cmd::ZRange::new("z", ZRangeBy::Rank(1, -2));
+        assert_eq!(request(&mut conn, zrange.into_frame()).await, bulks(&["b", "c", "d"]));
+        let zrange = cmd::ZRange::new("z", ZRangeBy::Rank(0, 1)).rev().with_scores();
+        assert_eq!(request(&mut conn, zrange.into_frame()).await, bulks(&["e", "4", "d", "3"]));
+
+        let range = ScoreRange { min_exclusive: true, ..ScoreRange::new(1.0, 4.0) };
+        let zrange = cmd::ZRange::new("z", ZRangeBy::Score(range)).limit(1, 2);
+        assert_eq!(request(&mut conn, zrange.into_frame()).await, bulks(&["d", "e"]));
+        let zrange = cmd::ZRange::new("z", ZRangeBy::Score(range)).rev().limit(0, 1);
+        assert_eq!(request(&mut conn, zrange.into_frame()).await, bulks(&["e"]));
+        assert_eq!(request(&mut conn, cmd::ZCount::new("z", range).into_frame()).await, Frame::Integer(3));
+
+        // the older commands, note the reversed ones take max first
+        let reply = request(&mut conn, command(&["zrangebyscore", "z", "-inf", "(2", "withscores"])).await;
+        assert_eq!(reply, bulks(&["a", "0", "b", "1"]));
+        let reply = request(&mut conn, command(&["zrevrangebyscore", "z", "+inf", "3", "limit", "0", "1"])).await;
+        assert_eq!(reply, bulks(&["e"]));
+        let reply = request(&mut conn, command(&["zrevrange", "z", "0", "0"])).await;
+        assert_eq!(reply, bulks(&["e"]));
+        let reply = request(&mut conn, command(&["zrange", "z", "(4", "2", "byscore", "rev"])).await;
+        assert_eq!(reply, bulks(&["d", "c"]));
+        let reply = request(&mut conn, command(&["zrange", "z", "0", "1", "limit", "0", "1"])).await;
+        assert!(matches!(reply, Frame::Error(_)));
+    });
+}
+
+#[test]
+fn test_lex_ranges() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        let mut zadd = cmd::ZAdd::new("z");
+        for member in ["apple", "banana", "cherry", "date"] {
+            zadd = zadd.member(0.0, b(member));
+        }
+        request(&mut conn, zadd.into_frame()).await;
+
+        let range = LexRange { min: LexBound::Inclusive(b("b")), max: LexBound::Exclusive(b("date")) };
+        let zrange = cmd::ZRange::new("z", ZRangeBy::Lex(range.clone()));
+        assert_eq!(request(&mut conn, zrange.into_frame()).await, bulks(&["banana", "cherry"]));
+        assert_eq!(request(&mut conn, cmd::ZLexCount::new("z", range).into_frame()).await, Frame::Integer(2));
+        let reply = request(&mut conn, command(&["zrevrangebylex", "z", "+", "[c", "limit", "1", "5"])).await;
+        assert_eq!(reply, bulks(&["cherry"]));
+        let reply = request(&mut conn, command(&["zremrangebylex", "z", "-", "(c"])).await;
+        assert_eq!(reply, Frame::Integer(2));
+        let reply = request(&mut conn, command(&["zrangebylex", "z", "a", "+"])).await;
+        assert!(matches!(reply, Frame::Error(e) if e.contains("not valid string range item")));
+    });
+}
+
+#[test]
+fn test_pop_and_remove() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        let zadd = cmd::ZAdd::new("z").member(3.0, b("c")).member(1.0, b("a")).member(2.0, b("b")).member(4.0, b("d"));
+        request(&mut conn, zadd.into_frame()).await;
+
+        assert_eq!(request(&mut conn, cmd::ZPop::min("z", None).into_frame()).await, bulks(&["a", "1"]));
+        assert_eq!(request(&mut conn, cmd::ZPop::max("z", Some(2)).into_frame()).await, bulks(&["d", "4", "c", "3"]));
+        assert_eq!(request(&mut conn, cmd::ZRem::new("z", vec![b("b"), b("x")]).into_frame()).await, Frame::Integer(1));
+        assert_eq!(request(&mut conn, cmd::Exists::new(&["z"]).into_frame()).await, Frame::Integer(0));
+        assert_eq!(request(&mut conn, cmd::ZPop::min("z", None).into_frame()).await, Frame::Array(vec![]));
+
+        let zadd = cmd::ZAdd::new("z").member(1.0, b("a")).member(2.0, b("b")).member(3.0, b("c"));
+        request(&mut conn, zadd.into_frame()).await;
+        let reply = request(&mut conn, cmd::ZRemRange::new("z", ZRangeBy::Rank(0, -2)).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(2));
+        let reply = request(&mut conn, cmd::ZRemRange::new("z", ZRangeBy::Score(ScoreRange::new(3.0, 3.0))).into_frame()).await;
+        assert_eq!(reply, Frame::Integer(1));
+        assert_eq!(request(&mut conn, cmd::Type::new("z").into_frame()).await, Frame::Simple("none".to_string()));
+    });
+}
+
+#[test]
+fn test_union_and_inter_store() {
+    new_runtime().block_on(async {
+        let mut conn = connect(start_server().await).await;
+
+        let zadd = cmd::ZAdd::new("z1").member(1.0, b("a")).member(2.0, b("b")).member(3.0, b("c"));
+        request(&mut conn, zadd.into_frame()).await;
+        let zadd = cmd::ZAdd::new("z2").member(10.0, b("b")).member(20.0, b("c")).member(30.0, b("d"));
+        request(&mut conn, zadd.into_frame()).await;
+        // a plain set counts each member with a score of 1
+        request(&mut conn, cmd::SAdd::new("s", vec![b("c"), b("d")]).into_frame()).await;
+
+        let zstore = cmd::ZStore::union("u", &["z1", "z2", "missing"]).weights(vec![2.0, 1.0, 5.0]);
+        assert_eq!(request(&mut conn, zstore.into_frame()).await, Frame::Integer(4));
+        let zrange = cmd::ZRange::new("u", ZRangeBy::Rank(0, -1)).with_scores();
+        assert_eq!(request(&mut conn, zrange.into_frame()).await, bulks(&["a", "2", "b", "14", "c", "26", "d", "30"]));
+
+        let zstore = cmd::ZStore::inter("i", &["z1", "z2", "s"]).aggregate(Aggregate::Max);
+        assert_eq!(request(&mut conn, zstore.into_frame()).await, Frame::Integer(1));
+        let zrange = cmd::ZRange::new("i", ZRangeBy::Rank(0, -1)).with_scores();
+        assert_eq!(request(&mut conn, zrange.into_frame()).await, bulks(&["c", "20"]));
+
+        let zstore = cmd::ZStore::inter("i", &["z1", "missing"]);
+        assert_eq!(request(&mut conn, zstore.into_frame()).await, Frame::Integer(0));
+        assert_eq!(request(&mut conn, cmd::Exists::new(&["i"]).into_frame()).await, Frame::Integer(0));
+
+        let reply = request(&mut conn, command(&["zunionstore", "u", "0", "z1"])).await;
+        assert!(matches!(reply, Frame::Error(e) if e.contains("at least 1 input key")));
+        request(&mut conn, cmd::Set::new("str", b("x")).into_frame()).await;
+        let reply = request(&mut conn, cmd::ZStore::union("u", &["z1", "str"]).into_frame()).await;
+        assert!(matches!(reply, Frame::Error(e) if e.starts_with("WRONGTYPE")));
+    });
+}